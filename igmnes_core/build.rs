@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+#[path = "src/rom_db/generate.rs"]
+mod generate;
+
+// Builds the game database from the NES 2.0 XML database at NES20DB_PATH if it's set, or from
+// the copy in data/nes20db.xml. Without either, the entries checked in to
+// src/rom_db/entries.rs are used, which only cover a handful of games.
+fn main() {
+    const VENDORED_XML_DIR: &str = "data";
+    const VENDORED_XML_PATH: &str = "data/nes20db.xml";
+    const ENTRIES_PATH: &str = "src/rom_db/entries.rs";
+
+    println!("cargo:rerun-if-env-changed=NES20DB_PATH");
+    println!("cargo:rerun-if-changed={}", VENDORED_XML_DIR);
+    println!("cargo:rerun-if-changed={}", ENTRIES_PATH);
+
+    let xml_path = match std::env::var_os("NES20DB_PATH") {
+        Some(xml_path) => Some(PathBuf::from(xml_path)),
+        None => Some(PathBuf::from(VENDORED_XML_PATH)).filter(|xml_path| xml_path.exists()),
+    };
+
+    let entries = match xml_path {
+        Some(xml_path) => {
+            println!("cargo:rerun-if-changed={}", xml_path.display());
+            let xml = std::fs::read_to_string(&xml_path)
+                .unwrap_or_else(|e| panic!("{}: {}", xml_path.display(), e));
+            generate::generate_rom_db_entries(&xml)
+        }
+        None => {
+            println!(
+                "cargo:warning=No NES 2.0 XML database in {} or NES20DB_PATH, the game database \
+                 only has the entries in {}",
+                VENDORED_XML_PATH, ENTRIES_PATH
+            );
+            std::fs::read_to_string(ENTRIES_PATH).expect(ENTRIES_PATH)
+        }
+    };
+
+    let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR");
+    std::fs::write(Path::new(&out_dir).join("rom_db_entries.rs"), entries)
+        .expect("rom_db_entries.rs");
+}
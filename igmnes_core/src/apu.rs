@@ -1,3 +1,4 @@
+use crate::mappers::{MapperAudio, MapperImpl, MapperIrq};
 use crate::memory::MemMapped;
 use enum_dispatch::enum_dispatch;
 use std::collections::VecDeque;
@@ -914,7 +915,7 @@ impl Apu {
         self.frame_counter.delayed_reset = true;
     }
 
-    fn clock_channel_output(&mut self) {
+    fn clock_channel_output(&mut self, expansion_output: f32) {
         // We add outputs of pulse1 and pulse 2 channels
         // and use that value as an index into the pulse output lookup table
         let pulse_output_index: usize =
@@ -929,7 +930,8 @@ impl Apu {
         let pulse_output = self.pulse_table[pulse_output_index];
         let tnd_output = self.tnd_table[tnd_output_index];
 
-        let output = pulse_output + tnd_output;
+        // Cartridge expansion audio is mixed in linearly
+        let output = pulse_output + tnd_output + expansion_output;
 
         self.nes_samples.push(output);
     }
@@ -1000,7 +1002,7 @@ impl Apu {
         }
     }
 
-    pub fn step(&mut self, cpu_cycles: u64) -> bool {
+    pub fn step(&mut self, cpu_cycles: u64, mapper: &mut MapperImpl) -> bool {
         let cycles_to_run = cpu_cycles - self.cpu_cycles;
         let even_cycle = cpu_cycles % 2 == 0;
 
@@ -1026,7 +1028,12 @@ impl Apu {
 
             self.clock_length_counters(false);
             self.clock_timers();
-            self.clock_channel_output();
+
+            // Mappers with CPU cycle based IRQ counters and expansion audio run in lockstep with the APU
+            mapper.clock_cpu_irq();
+            mapper.clock_audio();
+
+            self.clock_channel_output(mapper.audio_output());
             self.generate_output_samples();
         }
 
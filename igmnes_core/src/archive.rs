@@ -0,0 +1,412 @@
+use crate::checksum::crc32;
+use crate::rom::RomError;
+use miniz_oxide::inflate::decompress_to_vec;
+
+// Compressed ROMs, unpacked in memory at load time
+//
+// Zip archives can hold several files, the ROM is the first entry with a known ROM extension
+// unless one is picked by name. Gzip files hold a single file and are detected by their contents,
+// whatever their extension.
+
+const GZIP_TAG: &[u8] = &[0x1F, 0x8B];
+const GZIP_HEADER_SIZE: usize = 10;
+const GZIP_TRAILER_SIZE: usize = 8;
+const GZIP_METHOD_DEFLATE: u8 = 8;
+const GZIP_FLAG_HEADER_CRC: u8 = 0b0000_0010;
+const GZIP_FLAG_EXTRA: u8 = 0b0000_0100;
+const GZIP_FLAG_NAME: u8 = 0b0000_1000;
+const GZIP_FLAG_COMMENT: u8 = 0b0001_0000;
+
+const ZIP_LOCAL_HEADER_TAG: &[u8] = b"PK\x03\x04";
+const ZIP_LOCAL_HEADER_SIZE: usize = 30;
+const ZIP_CENTRAL_HEADER_TAG: &[u8] = b"PK\x01\x02";
+const ZIP_CENTRAL_HEADER_SIZE: usize = 46;
+const ZIP_END_TAG: &[u8] = b"PK\x05\x06";
+const ZIP_END_SIZE: usize = 22;
+const ZIP_MAX_COMMENT_SIZE: usize = 0xFFFF;
+const ZIP_METHOD_STORED: u16 = 0;
+const ZIP_METHOD_DEFLATE: u16 = 8;
+const ZIP_FLAG_ENCRYPTED: u16 = 0b0000_0001;
+
+const ROM_EXTENSIONS: [&str; 6] = ["nes", "fds", "nsf", "nsfe", "unf", "unif"];
+
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(GZIP_TAG)
+}
+
+pub fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(ZIP_LOCAL_HEADER_TAG) || bytes.starts_with(ZIP_END_TAG)
+}
+
+//  Offset  Size  Contents
+//  $00     2     $1F, $8B
+//  $02     1     Compression method (8 = deflate)
+//  $03     1     Flags
+//  $04     6     Modification time, extra flags and OS
+//  $0A     ...   Optional extra field, name, comment and header CRC-16, deflate data
+//  -$08    4     CRC-32 of the uncompressed data
+//  -$04    4     Uncompressed size, modulo 2^32
+pub fn extract_gzip(bytes: &[u8]) -> Result<Vec<u8>, RomError> {
+    if bytes.len() < GZIP_HEADER_SIZE + GZIP_TRAILER_SIZE || bytes[2] != GZIP_METHOD_DEFLATE {
+        return Err(archive_error("Unsupported or truncated gzip file"));
+    }
+
+    let flags = bytes[3];
+    let mut position = GZIP_HEADER_SIZE;
+    if flags & GZIP_FLAG_EXTRA != 0 {
+        position += 2 + read_u16(bytes, position)? as usize;
+    }
+    for flag in [GZIP_FLAG_NAME, GZIP_FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let string_size = bytes
+                .get(position..)
+                .and_then(|string| string.iter().position(|&byte| byte == 0))
+                .ok_or_else(|| archive_error("Truncated gzip header"))?;
+            position += string_size + 1;
+        }
+    }
+    if flags & GZIP_FLAG_HEADER_CRC != 0 {
+        position += 2;
+    }
+
+    let trailer_start = bytes.len() - GZIP_TRAILER_SIZE;
+    let compressed_bytes = bytes
+        .get(position..trailer_start)
+        .ok_or_else(|| archive_error("Truncated gzip header"))?;
+    let data = inflate(compressed_bytes)?;
+
+    let expected_crc = read_u32(bytes, trailer_start)?;
+    let expected_size = read_u32(bytes, trailer_start + 4)?;
+    if data.len() as u32 != expected_size {
+        return Err(archive_error("Gzip file is corrupted (size mismatch)"));
+    }
+    check_crc(&data, expected_crc)?;
+
+    Ok(data)
+}
+
+struct ZipEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    local_header_offset: usize,
+}
+
+// Names of the entries with a ROM extension, in archive order
+pub fn zip_rom_entries(bytes: &[u8]) -> Result<Vec<String>, RomError> {
+    Ok(read_zip_entries(bytes)?
+        .into_iter()
+        .filter(|entry| is_rom_file_name(&entry.name))
+        .map(|entry| entry.name)
+        .collect())
+}
+
+// Returns the entry name along with its contents
+pub fn extract_zip(bytes: &[u8], entry_name: Option<&str>) -> Result<(String, Vec<u8>), RomError> {
+    let entries = read_zip_entries(bytes)?;
+    let entry = match entry_name {
+        Some(entry_name) => entries
+            .into_iter()
+            .find(|entry| entry.name == entry_name)
+            .ok_or_else(|| archive_error(&format!("No {} in zip archive", entry_name)))?,
+        None => entries
+            .into_iter()
+            .find(|entry| is_rom_file_name(&entry.name))
+            .ok_or_else(|| archive_error("No ROM in zip archive"))?,
+    };
+
+    if entry.flags & ZIP_FLAG_ENCRYPTED != 0 {
+        return Err(archive_error(&format!("{} is encrypted", entry.name)));
+    }
+
+    // The local header repeats the name and has its own extra field, which can differ in size
+    let header = entry.local_header_offset;
+    if bytes.get(header..header + 4) != Some(ZIP_LOCAL_HEADER_TAG) {
+        return Err(archive_error("Corrupted zip archive"));
+    }
+    let data_start = header
+        + ZIP_LOCAL_HEADER_SIZE
+        + read_u16(bytes, header + 26)? as usize
+        + read_u16(bytes, header + 28)? as usize;
+    let compressed_bytes = bytes
+        .get(data_start..data_start + entry.compressed_size)
+        .ok_or_else(|| archive_error("Truncated zip archive"))?;
+
+    let data = match entry.method {
+        ZIP_METHOD_STORED => compressed_bytes.to_vec(),
+        ZIP_METHOD_DEFLATE => inflate(compressed_bytes)?,
+        method => {
+            return Err(archive_error(&format!(
+                "Unsupported zip compression method: {}",
+                method
+            )))
+        }
+    };
+    if data.len() != entry.size {
+        return Err(archive_error("Zip archive is corrupted (size mismatch)"));
+    }
+    check_crc(&data, entry.crc)?;
+
+    Ok((entry.name, data))
+}
+
+// The central directory at the end of the archive lists every entry. It's located by the
+// end of central directory record, which is followed by a comment of up to 64 KiB.
+fn read_zip_entries(bytes: &[u8]) -> Result<Vec<ZipEntry>, RomError> {
+    let search_start = bytes
+        .len()
+        .saturating_sub(ZIP_END_SIZE + ZIP_MAX_COMMENT_SIZE);
+    let end = (search_start..=bytes.len().saturating_sub(ZIP_END_SIZE))
+        .rev()
+        .find(|&position| bytes[position..].starts_with(ZIP_END_TAG))
+        .ok_or_else(|| archive_error("Corrupted zip archive"))?;
+
+    let entry_count = read_u16(bytes, end + 10)? as usize;
+    let mut position = read_u32(bytes, end + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(entry_count);
+    for _ in 0..entry_count {
+        if bytes.get(position..position + 4) != Some(ZIP_CENTRAL_HEADER_TAG) {
+            return Err(archive_error("Corrupted zip archive"));
+        }
+
+        let name_size = read_u16(bytes, position + 28)? as usize;
+        let extra_size = read_u16(bytes, position + 30)? as usize;
+        let comment_size = read_u16(bytes, position + 32)? as usize;
+        let name_start = position + ZIP_CENTRAL_HEADER_SIZE;
+        let name = bytes
+            .get(name_start..name_start + name_size)
+            .ok_or_else(|| archive_error("Truncated zip archive"))?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).to_string(),
+            flags: read_u16(bytes, position + 8)?,
+            method: read_u16(bytes, position + 10)?,
+            crc: read_u32(bytes, position + 16)?,
+            compressed_size: read_u32(bytes, position + 20)? as usize,
+            size: read_u32(bytes, position + 24)? as usize,
+            local_header_offset: read_u32(bytes, position + 42)? as usize,
+        });
+
+        position = name_start + name_size + extra_size + comment_size;
+    }
+
+    Ok(entries)
+}
+
+fn is_rom_file_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    !name.ends_with('/')
+        && ROM_EXTENSIONS
+            .iter()
+            .any(|extension| name.ends_with(&format!(".{}", extension)))
+}
+
+fn inflate(compressed_bytes: &[u8]) -> Result<Vec<u8>, RomError> {
+    decompress_to_vec(compressed_bytes)
+        .map_err(|e| archive_error(&format!("Corrupted compressed data ({})", e)))
+}
+
+fn check_crc(data: &[u8], expected_crc: u32) -> Result<(), RomError> {
+    if crc32(data) == expected_crc {
+        Ok(())
+    } else {
+        Err(archive_error("Archive is corrupted (CRC-32 mismatch)"))
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, RomError> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]))
+        .ok_or_else(|| archive_error("Truncated archive"))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, RomError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .ok_or_else(|| archive_error("Truncated archive"))
+}
+
+fn archive_error(message: &str) -> RomError {
+    RomError::ArchiveError(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::assert_rom_error;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    const ROM: &[u8] = b"NES\x1A rom data rom data rom data";
+
+    // Zip archive of (name, contents, is deflated) entries
+    fn zip_archive(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut central_directory = Vec::new();
+        for &(name, data, is_deflated) in entries {
+            let (method, compressed) = if is_deflated {
+                (ZIP_METHOD_DEFLATE, compress_to_vec(data, 6))
+            } else {
+                (ZIP_METHOD_STORED, data.to_vec())
+            };
+
+            // Flags, method, modification time and date, CRC-32, compressed and uncompressed
+            // sizes, name size
+            let mut fields = vec![0, 0];
+            fields.extend_from_slice(&method.to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            fields.extend_from_slice(&crc32(data).to_le_bytes());
+            fields.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+            central_directory.extend_from_slice(ZIP_CENTRAL_HEADER_TAG);
+            // Versions made by and needed
+            central_directory.extend_from_slice(&[20, 0, 20, 0]);
+            central_directory.extend_from_slice(&fields);
+            // Extra and comment sizes, disk number, attributes
+            central_directory.extend_from_slice(&[0; 12]);
+            central_directory.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(name.as_bytes());
+
+            bytes.extend_from_slice(ZIP_LOCAL_HEADER_TAG);
+            bytes.extend_from_slice(&[20, 0]);
+            bytes.extend_from_slice(&fields);
+            // Extra size
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&compressed);
+        }
+
+        let central_directory_offset = bytes.len() as u32;
+        bytes.extend_from_slice(&central_directory);
+        bytes.extend_from_slice(ZIP_END_TAG);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&central_directory_offset.to_le_bytes());
+        // Comment size
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    fn gzip_file(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = GZIP_TAG.to_vec();
+        bytes.extend_from_slice(&[GZIP_METHOD_DEFLATE, GZIP_FLAG_NAME]);
+        bytes.extend_from_slice(&[0; 6]);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0);
+        bytes.extend(compress_to_vec(data, 6));
+        bytes.extend_from_slice(&crc32(data).to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn first_rom_in_zip_is_extracted() {
+        let bytes = zip_archive(&[
+            ("readme.txt", b"Not a ROM", false),
+            ("roms/", b"", false),
+            ("roms/Game (USA).NES", ROM, true),
+            ("roms/Game (Japan).nes", b"Other ROM", false),
+        ]);
+        assert!(is_zip(&bytes));
+
+        assert_eq!(
+            zip_rom_entries(&bytes).unwrap(),
+            ["roms/Game (USA).NES", "roms/Game (Japan).nes"]
+        );
+        let (name, data) = extract_zip(&bytes, None).unwrap();
+        assert_eq!(name, "roms/Game (USA).NES");
+        assert_eq!(data, ROM);
+    }
+
+    #[test]
+    fn zip_entry_is_extracted_by_name() {
+        let bytes = zip_archive(&[
+            ("Game (USA).nes", ROM, true),
+            ("Game (Japan).nes", b"Other ROM", false),
+        ]);
+
+        let (name, data) = extract_zip(&bytes, Some("Game (Japan).nes")).unwrap();
+        assert_eq!(name, "Game (Japan).nes");
+        assert_eq!(data, b"Other ROM");
+
+        assert_rom_error(
+            extract_zip(&bytes, Some("Game (Europe).nes")),
+            RomError::ArchiveError("No Game (Europe).nes in zip archive".to_string()),
+        );
+    }
+
+    #[test]
+    fn zip_without_rom_is_rejected() {
+        let bytes = zip_archive(&[("readme.txt", b"Not a ROM", true), ("nes/", b"", false)]);
+
+        assert!(zip_rom_entries(&bytes).unwrap().is_empty());
+        assert_rom_error(
+            extract_zip(&bytes, None),
+            RomError::ArchiveError("No ROM in zip archive".to_string()),
+        );
+    }
+
+    #[test]
+    fn corrupted_zip_is_rejected() {
+        let bytes = zip_archive(&[("game.nes", ROM, false)]);
+
+        // Data of the stored entry, after the local header and the name
+        let mut corrupted = bytes.clone();
+        corrupted[ZIP_LOCAL_HEADER_SIZE + "game.nes".len()] ^= 0xFF;
+        assert_rom_error(
+            extract_zip(&corrupted, None),
+            RomError::ArchiveError("Archive is corrupted (CRC-32 mismatch)".to_string()),
+        );
+
+        // Cut off before the end of central directory record
+        let truncated = &bytes[..bytes.len() - ZIP_END_SIZE];
+        assert_rom_error(
+            extract_zip(truncated, None),
+            RomError::ArchiveError("Corrupted zip archive".to_string()),
+        );
+    }
+
+    #[test]
+    fn gzip_is_extracted() {
+        let bytes = gzip_file("game.nes", ROM);
+        assert!(is_gzip(&bytes));
+        assert_eq!(extract_gzip(&bytes).unwrap(), ROM);
+    }
+
+    #[test]
+    fn corrupted_gzip_is_rejected() {
+        let bytes = gzip_file("game.nes", ROM);
+
+        // Uncompressed size in the trailer
+        let mut corrupted = bytes.clone();
+        let size_offset = corrupted.len() - 4;
+        corrupted[size_offset] ^= 0xFF;
+        assert_rom_error(
+            extract_gzip(&corrupted),
+            RomError::ArchiveError("Gzip file is corrupted (size mismatch)".to_string()),
+        );
+
+        // CRC-32 in the trailer
+        let mut corrupted = bytes.clone();
+        let crc_offset = corrupted.len() - GZIP_TRAILER_SIZE;
+        corrupted[crc_offset] ^= 0xFF;
+        assert_rom_error(
+            extract_gzip(&corrupted),
+            RomError::ArchiveError("Archive is corrupted (CRC-32 mismatch)".to_string()),
+        );
+
+        assert_rom_error(
+            extract_gzip(&bytes[..GZIP_HEADER_SIZE]),
+            RomError::ArchiveError("Unsupported or truncated gzip file".to_string()),
+        );
+    }
+}
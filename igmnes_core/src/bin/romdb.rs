@@ -0,0 +1,121 @@
+use igmnes_core::{
+    generate_rom_db_entries, Core, HeaderType, MirroringMode, RomDbConsole, RomLoadOptions,
+};
+use std::path::PathBuf;
+
+// Game database tool
+//
+// `romdb info [--no-rom-db] path_to_rom` prints the header of a ROM as the emulator resolves it,
+// `romdb generate path_to_nes20db.xml` prints the database entries generated from the
+// NES 2.0 XML database, to replace igmnes_core/src/rom_db/entries.rs with. Builds with
+// NES20DB_PATH set generate the database from the XML file directly.
+
+const USAGE: &str =
+    "Usage: romdb info [--no-rom-db] path_to_rom\n       romdb generate path_to_nes20db.xml";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["info", rom_path] => print_info(PathBuf::from(rom_path), false),
+        ["info", "--no-rom-db", rom_path] => print_info(PathBuf::from(rom_path), true),
+        ["generate", xml_path] => generate(PathBuf::from(xml_path)),
+        _ => {
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_info(rom_path: PathBuf, ignore_rom_db: bool) {
+    let options = RomLoadOptions {
+        ignore_rom_db,
+        ..RomLoadOptions::default()
+    };
+    let mut core = match Core::load_rom_with_options(&rom_path, &options) {
+        Ok(core) => core,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let header = core.rom_header();
+    let (crc32, sha1) = core.rom_checksums();
+
+    println!("File:       {}", rom_path.display());
+    println!("CRC-32:     {:08X}", crc32);
+    println!("SHA-1:      {}", sha1);
+    match core.rom_db_entry() {
+        Some(entry) => println!("Database:   {}", entry.title),
+        None if ignore_rom_db => println!("Database:   disabled"),
+        None => println!("Database:   not found"),
+    }
+    println!();
+
+    let header_type = match header.header_type {
+        HeaderType::Standard => "iNES",
+        HeaderType::Extended => "NES 2.0",
+    };
+    let mirroring = if header.four_screen_mode {
+        "Four-screen"
+    } else {
+        match header.mirroring_mode {
+            MirroringMode::Horizontal => "Horizontal",
+            MirroringMode::Vertical => "Vertical",
+            MirroringMode::SingleScreenA | MirroringMode::SingleScreenB => "Single-screen",
+            MirroringMode::FourScreen => "Four-screen",
+        }
+    };
+    let console = if header.is_vs_unisystem {
+        RomDbConsole::VsUniSystem
+    } else if header.is_playchoice_10 {
+        RomDbConsole::PlayChoice10
+    } else {
+        RomDbConsole::Nes
+    };
+
+    println!("Header:     {}", header_type);
+    println!(
+        "Mapper:     {}.{}",
+        header.mapper_number,
+        header.submapper_number()
+    );
+    println!("PRG ROM:    {} KiB", header.prg_rom_size / 1024);
+    println!("CHR ROM:    {} KiB", header.chr_rom_size / 1024);
+    println!(
+        "PRG RAM:    {} KiB{}",
+        header.prg_ram_size / 1024,
+        if header.sram_present {
+            " (battery backed)"
+        } else {
+            ""
+        }
+    );
+    println!("CHR RAM:    {} KiB", header.chr_ram_size / 1024);
+    println!("Mirroring:  {}", mirroring);
+    println!("Trainer:    {}", header.trainer_present);
+    println!("Console:    {:?}", console);
+    if header.is_vs_unisystem {
+        println!("Vs. PPU:    {:?}", header.vs_ppu_type);
+        println!("Vs. board:  {:?}", header.vs_hardware_type);
+    }
+    println!("TV system:  {:?}", header.tv_system);
+    if let Some(title) = core.rom_title() {
+        println!("Title:      {}", title);
+    }
+    for warning in core.rom_warnings() {
+        println!("Warning:    {}", warning);
+    }
+}
+
+fn generate(xml_path: PathBuf) {
+    match std::fs::read_to_string(&xml_path) {
+        Ok(xml) => print!("{}", generate_rom_db_entries(&xml)),
+        Err(e) => {
+            println!("{}: {}", xml_path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use igmnes_core::debug::Tracer;
+use igmnes_core::ppu::palette::PpuPaletteColor;
+use igmnes_core::{
+    crop_frame, frame_to_png, resize_frame, AspectRatio, Core, FrameScaler, NtscFilter,
+    NtscFilterPreset, Overscan, PpuOutputMode, ScalerSettings, NTSC_FRAME_HEIGHT, NTSC_FRAME_WIDTH,
+    PPU_FRAME_WIDTH,
+};
+use std::path::PathBuf;
+
+// Headless screenshots
+//
+// Runs a ROM for a number of frames without a window and saves the last one as a PNG,
+// cropped, scaled and stretched the same way the SDL frontend shows it
+
+const USAGE: &str = "Usage: screenshot [--frames count] [--ntsc composite|svideo|rgb|monochrome] [--overscan top,bottom,left,right] [--aspect 1:1|8:7|4:3] [--scaler none|scale2x|scale3x|xbrz2x|xbrz3x|xbrz4x] [--scanlines strength] [--crt-mask none|aperture|slot] path_to_rom output.png";
+
+const DEFAULT_FRAME_COUNT: usize = 300;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut frame_count = DEFAULT_FRAME_COUNT;
+    let mut ntsc_preset: Option<NtscFilterPreset> = None;
+    let mut overscan = Overscan::default();
+    let mut aspect_ratio = AspectRatio::default();
+    let mut scaler_settings = ScalerSettings::default();
+    let mut paths: Vec<PathBuf> = vec![];
+
+    let mut arg_index = 0;
+    while arg_index < args.len() {
+        let arg = args[arg_index].as_str();
+        let value = args.get(arg_index + 1).map(String::as_str).unwrap_or("");
+        let result = match arg {
+            "--frames" => value.parse().map(|count| frame_count = count).ok(),
+            "--ntsc" => value.parse().map(|preset| ntsc_preset = Some(preset)).ok(),
+            "--overscan" => value.parse().map(|value| overscan = value).ok(),
+            "--aspect" => value.parse().map(|value| aspect_ratio = value).ok(),
+            "--scaler" => value
+                .parse()
+                .map(|scaler| scaler_settings.scaler = scaler)
+                .ok(),
+            "--scanlines" => value
+                .parse()
+                .map(|strength| scaler_settings.scanlines = strength)
+                .ok(),
+            "--crt-mask" => value.parse().map(|mask| scaler_settings.mask = mask).ok(),
+            _ => {
+                paths.push(PathBuf::from(arg));
+                arg_index += 1;
+                continue;
+            }
+        };
+        if result.is_none() {
+            println!("Invalid value for {}: {}", arg, value);
+            std::process::exit(1);
+        }
+        arg_index += 2;
+    }
+
+    let (rom_path, output_path) = match paths.as_slice() {
+        [rom_path, output_path] => (rom_path, output_path),
+        _ => {
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut core = match Core::load_rom(rom_path) {
+        Ok(core) => core,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if ntsc_preset.is_some() {
+        core.set_ppu_output_mode(PpuOutputMode::PaletteIndex);
+    }
+    core.hard_reset();
+
+    let mut tracer = Tracer::default();
+    for _ in 0..frame_count {
+        while !core.is_ppu_frame_ready() {
+            core.step(&mut tracer);
+        }
+        // Clears the ready flag
+        core.ppu_frame();
+    }
+
+    let (frame, frame_width) = match ntsc_preset {
+        Some(preset) => {
+            let mut frame = vec![PpuPaletteColor::default(); NTSC_FRAME_WIDTH * NTSC_FRAME_HEIGHT];
+            let burst_phase = core.ppu_frame_burst_phase();
+            NtscFilter::new(preset.into()).apply(core.ppu_index_frame(), burst_phase, &mut frame);
+            (frame, NTSC_FRAME_WIDTH)
+        }
+        None => (core.ppu_frame().to_vec(), PPU_FRAME_WIDTH),
+    };
+
+    let crop_rect = overscan.crop_rect(frame_width);
+    let cropped = crop_frame(&frame, frame_width, &overscan);
+
+    let mut scaler = FrameScaler::new(scaler_settings);
+    let mut scaled = vec![];
+    scaler.apply(&cropped, crop_rect.width, crop_rect.height, &mut scaled);
+    let (scaled_width, scaled_height) = scaler.output_size(crop_rect.width, crop_rect.height);
+
+    let scale_factor = scaler_settings.scale_factor();
+    let (display_width, display_height) = aspect_ratio.display_size(&overscan);
+    let (width, height) = (display_width * scale_factor, display_height * scale_factor);
+    let image = resize_frame(&scaled, scaled_width, scaled_height, width, height);
+
+    if let Err(e) = std::fs::write(output_path, frame_to_png(&image, width, height)) {
+        println!("Failed to save {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Saved {}x{} {}", width, height, output_path.display());
+}
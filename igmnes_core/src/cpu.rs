@@ -1,9 +1,10 @@
 // 6502
 
 use crate::instructions::*;
-use crate::memory::MemMapped;
+use crate::memory::{CpuBus, MemMapped};
 use std::default::Default;
 use std::fmt::{self, Display};
+use std::ops::Range;
 
 use crate::errors::EmulationError;
 
@@ -122,10 +123,13 @@ impl StatusReg {
     }
 }
 
-#[derive(Default, Copy, Clone)]
-struct CpuInterrupt {
-    is_hardware: bool,
-    is_nmi: bool,
+// Addressing modes go through different cycles depending on what the instruction
+// does with the operand
+#[derive(Copy, Clone, PartialEq)]
+enum MemoryAccess {
+    Read,
+    Write,
+    ReadModifyWrite,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -148,11 +152,13 @@ pub struct Cpu {
     // Cycle count
     pub cycle_count: u64,
 
-    unhandled_interrupt: Option<CpuInterrupt>,
-    pending_interrupt: Option<CpuInterrupt>,
-    instructions_since_last_interrupt: u64,
-
-    is_halted: bool,
+    // Interrupt lines are polled at the end of every cycle. An interrupt is taken after the
+    // current instruction when it was requested before the instruction's last cycle.
+    nmi_line: bool,
+    is_nmi_requested: bool,
+    was_nmi_requested: bool,
+    is_irq_requested: bool,
+    was_irq_requested: bool,
 }
 
 impl Cpu {
@@ -177,10 +183,11 @@ impl Cpu {
             reg_pc: 0,
 
             cycle_count: 0,
-            unhandled_interrupt: None,
-            pending_interrupt: None,
-            instructions_since_last_interrupt: 0,
-            is_halted: false,
+            nmi_line: false,
+            is_nmi_requested: false,
+            was_nmi_requested: false,
+            is_irq_requested: false,
+            was_irq_requested: false,
         };
 
         let entry_point = mem_map.read_word(RESET_PC_VEC);
@@ -210,131 +217,84 @@ impl Cpu {
         self.reg_pc = entry_point;
 
         self.cycle_count = 0;
-    }
 
-    #[inline]
-    pub fn soft_reset(&mut self) {}
-
-    #[inline]
-    pub fn irq(&mut self, mem_map: &mut impl MemMapped) {
-        let interrupt = CpuInterrupt {
-            is_hardware: true,
-            is_nmi: false,
-        };
-        self.interrupt(mem_map, interrupt)
-    }
-
-    #[inline]
-    pub fn nmi(&mut self, mem_map: &mut impl MemMapped) {
-        let interrupt = CpuInterrupt {
-            is_hardware: true,
-            is_nmi: true,
-        };
-        self.interrupt(mem_map, interrupt)
+        self.nmi_line = false;
+        self.is_nmi_requested = false;
+        self.was_nmi_requested = false;
+        self.is_irq_requested = false;
+        self.was_irq_requested = false;
     }
 
     #[inline]
-    fn interrupt(&mut self, mem_map: &mut impl MemMapped, interrupt: CpuInterrupt) {
-        if !self.pending_interrupt.is_none() {
-            return;
-        }
-
-        self.instructions_since_last_interrupt = 0;
-        if interrupt.is_nmi {
-            self.pending_interrupt = Some(interrupt);
-        } else {
-            if !self.reg_status.interrupt_disable {
-                self.perform_irq(mem_map, &interrupt);
-            } else {
-                self.unhandled_interrupt = Some(interrupt);
-            }
-        }
-    }
-
-    pub fn suppress_interrupt(&mut self) {
-        if self.pending_interrupt.is_some() {
-            self.pending_interrupt = None;
-        }
-    }
-
-    pub fn dma(&mut self) {
-        self.is_halted = true
-    }
+    pub fn soft_reset(&mut self) {}
 
     #[inline]
     pub fn step(
         &mut self,
-        mem_map: &mut impl MemMapped,
+        mem_map: &mut impl CpuBus,
         tracer: &mut Tracer,
-    ) -> Result<u8, EmulationError> {
-        if self.is_halted {
-            self.cycle_count += 2;
-            self.is_halted = false;
-            return Ok(2);
-        }
-
-        let result;
+    ) -> Result<u64, EmulationError> {
+        let start_cycle_count = self.cycle_count;
 
-        if let Some(interrupt) = self.pending_interrupt {
-            if (interrupt.is_nmi && self.instructions_since_last_interrupt > 0)
-                || (self.instructions_since_last_interrupt > 1)
-            {
-                if tracer.is_enabled() {
-                    tracer.add_cpu_trace(&self, mem_map);
-                }
-                self.perform_irq(mem_map, &interrupt);
-                self.pending_interrupt = None;
-
-                result = Ok(7);
-            } else {
-                result = self.execute_next_instruction(mem_map, tracer);
-                self.instructions_since_last_interrupt += 1;
+        let result = if self.was_nmi_requested || self.was_irq_requested {
+            if tracer.is_enabled() {
+                tracer.add_cpu_trace(&self, mem_map);
             }
-        } else {
-            result = self.execute_next_instruction(mem_map, tracer);
-            self.instructions_since_last_interrupt += 1;
-        }
 
-        self.unhandled_interrupt = None;
+            // The opcode fetch is thrown away and the PC isn't incremented
+            let reg_pc = self.reg_pc;
+            self.dummy_read(mem_map, reg_pc);
+            self.dummy_read(mem_map, reg_pc);
+            self.perform_interrupt(mem_map, false);
+            Ok(())
+        } else {
+            self.execute_next_instruction(mem_map, tracer)
+        };
 
-        result
+        result.map(|_| self.cycle_count - start_cycle_count)
     }
 
     fn execute_next_instruction(
         &mut self,
-        mem_map: &mut impl MemMapped,
+        mem_map: &mut impl CpuBus,
         tracer: &mut Tracer,
-    ) -> Result<u8, EmulationError> {
+    ) -> Result<(), EmulationError> {
+        use crate::instructions::AddressingMode::*;
+
         if tracer.is_enabled() {
             tracer.add_cpu_trace(&self, mem_map);
         }
 
-        let instruction = Instruction::decode(mem_map, self.reg_pc);
-        let result = match instruction {
+        let reg_pc = self.reg_pc;
+        let start_cycle_count = self.cycle_count;
+        let instruction = Instruction::decode(&mut InstructionFetch::new(self, mem_map), reg_pc);
+        match instruction {
             Ok(mut instr) => {
-                let cycles = self.execute_instruction(&mut instr, mem_map);
+                // Single byte instructions read the next byte and ignore it
+                if let Implicit | Accumulator = instr.addressing_mode {
+                    self.dummy_read(mem_map, reg_pc.wrapping_add(1));
+                }
 
-                self.cycle_count += cycles as u64;
-                Ok(cycles)
+                self.execute_instruction(&mut instr, mem_map, start_cycle_count);
+                Ok(())
             }
             Err(e) => {
                 self.reg_pc = self.reg_pc.wrapping_add(2);
                 Err(e)
             }
-        };
-
-        result
+        }
     }
 
     fn execute_instruction(
         &mut self,
         instruction: &mut Instruction,
-        mem_map: &mut impl MemMapped,
-    ) -> u8 {
+        mem_map: &mut impl CpuBus,
+        start_cycle_count: u64,
+    ) {
         use crate::instructions::InstructionToken::*;
 
         match instruction.token {
-            NOP => self.instr_nop(instruction, mem_map),
+            NOP | IGN => self.instr_nop(instruction, mem_map),
             // Jump instructions
             JMP => self.instr_jmp(instruction, mem_map),
             JSR => self.instr_jsr(instruction, mem_map),
@@ -343,14 +303,14 @@ impl Cpu {
             RTI => self.instr_rti(mem_map),
             RTS => self.instr_rts(mem_map),
             // Branch instructions
-            BPL => self.instr_bpl(instruction),
-            BMI => self.instr_bmi(instruction),
-            BVC => self.instr_bvc(instruction),
-            BVS => self.instr_bvs(instruction),
-            BCC => self.instr_bcc(instruction),
-            BCS => self.instr_bcs(instruction),
-            BNE => self.instr_bne(instruction),
-            BEQ => self.instr_beq(instruction),
+            BPL => self.instr_bpl(instruction, mem_map),
+            BMI => self.instr_bmi(instruction, mem_map),
+            BVC => self.instr_bvc(instruction, mem_map),
+            BVS => self.instr_bvs(instruction, mem_map),
+            BCC => self.instr_bcc(instruction, mem_map),
+            BCS => self.instr_bcs(instruction, mem_map),
+            BNE => self.instr_bne(instruction, mem_map),
+            BEQ => self.instr_beq(instruction, mem_map),
             // Stack instructions
             TXS => self.instr_txs(),
             TSX => self.instr_tsx(),
@@ -405,6 +365,14 @@ impl Cpu {
                     "0x{:04X}: Skipping unimplemented instruction: {}",
                     self.reg_pc, instruction.token
                 );
+
+                // Keeps the timing of the skipped instruction, minus the cycles it already took
+                // to fetch it
+                let spent_cycles = self.cycle_count - start_cycle_count;
+                let reg_pc = self.reg_pc;
+                for _ in spent_cycles..instruction.cycle_count as u64 {
+                    self.dummy_read(mem_map, reg_pc);
+                }
             }
         };
 
@@ -413,15 +381,13 @@ impl Cpu {
                 .reg_pc
                 .wrapping_add(instruction.addressing_mode.byte_count());
         }
-
-        instruction.cycle_count
     }
 
     //
     // NOP
     //
     #[inline]
-    fn instr_nop(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_nop(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let _ = self.read_resolved(instruction, mem_map);
     }
 
@@ -429,7 +395,7 @@ impl Cpu {
     // Jump instructions
     //
     #[inline]
-    fn instr_jmp(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_jmp(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         use crate::instructions::AddressingMode::*;
 
         let addressing_mode = &instruction.addressing_mode;
@@ -455,8 +421,8 @@ impl Cpu {
                 let resolved_low = (addr_high << 8) | addr_low_1 as u16;
                 let resolved_high = (addr_high << 8) | addr_low_2 as u16;
 
-                let target_addr_low = mem_map.read(resolved_low);
-                let target_addr_high = mem_map.read(resolved_high);
+                let target_addr_low = self.read_cycle(mem_map, resolved_low);
+                let target_addr_high = self.read_cycle(mem_map, resolved_high);
 
                 let target_addr = ((target_addr_high as u16) << 8) | target_addr_low as u16;
 
@@ -467,7 +433,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_jsr(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_jsr(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         use crate::instructions::AddressingMode::*;
 
         let addressing_mode = &instruction.addressing_mode;
@@ -479,6 +445,7 @@ impl Cpu {
                 // note the -1
                 let return_destination = reg_pc + addressing_mode.byte_count() - 1;
 
+                self.stack_dummy_read(mem_map);
                 self.stack_push_addr(mem_map, return_destination);
                 self.reg_pc = arg;
             }
@@ -490,19 +457,13 @@ impl Cpu {
     // Break/Return instructions
     //
     #[inline]
-    fn instr_brk(&mut self, mem_map: &mut impl MemMapped) {
-        let interrupt = CpuInterrupt {
-            is_hardware: false,
-            is_nmi: false,
-        };
-        self.perform_irq(mem_map, &interrupt);
+    fn instr_brk(&mut self, mem_map: &mut impl CpuBus) {
+        self.perform_interrupt(mem_map, true);
     }
 
     #[inline]
-    fn instr_rti(&mut self, mem_map: &mut impl MemMapped) {
-        if self.pending_interrupt.is_some() {
-            self.pending_interrupt = None;
-        }
+    fn instr_rti(&mut self, mem_map: &mut impl CpuBus) {
+        self.stack_dummy_read(mem_map);
 
         let status_byte = self.stack_pull(mem_map);
         let new_pc = self.stack_pull_addr(mem_map);
@@ -512,10 +473,12 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_rts(&mut self, mem_map: &mut impl MemMapped) {
+    fn instr_rts(&mut self, mem_map: &mut impl CpuBus) {
+        self.stack_dummy_read(mem_map);
         let mut addr = self.stack_pull_addr(mem_map);
+        self.dummy_read(mem_map, addr);
 
-        addr += 1;
+        addr = addr.wrapping_add(1);
         self.reg_pc = addr;
     }
 
@@ -523,58 +486,58 @@ impl Cpu {
     // Branch instructions
     //
     #[inline]
-    fn instr_bpl(&mut self, instruction: &mut Instruction) {
+    fn instr_bpl(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if !self.reg_status.sign_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bmi(&mut self, instruction: &mut Instruction) {
+    fn instr_bmi(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if self.reg_status.sign_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bvc(&mut self, instruction: &mut Instruction) {
+    fn instr_bvc(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if !self.reg_status.overflow_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bvs(&mut self, instruction: &mut Instruction) {
+    fn instr_bvs(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if self.reg_status.overflow_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bcc(&mut self, instruction: &mut Instruction) {
+    fn instr_bcc(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if !self.reg_status.carry_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bcs(&mut self, instruction: &mut Instruction) {
+    fn instr_bcs(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if self.reg_status.carry_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_bne(&mut self, instruction: &mut Instruction) {
+    fn instr_bne(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if !self.reg_status.zero_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
     #[inline]
-    fn instr_beq(&mut self, instruction: &mut Instruction) {
+    fn instr_beq(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         if self.reg_status.zero_flag {
-            self.branch(instruction);
+            self.branch(instruction, mem_map);
         }
     }
 
@@ -593,34 +556,29 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_pha(&mut self, mem_map: &mut impl MemMapped) {
+    fn instr_pha(&mut self, mem_map: &mut impl CpuBus) {
         let reg_a = self.reg_a;
         self.stack_push(mem_map, reg_a);
     }
 
     #[inline]
-    fn instr_pla(&mut self, mem_map: &mut impl MemMapped) {
+    fn instr_pla(&mut self, mem_map: &mut impl CpuBus) {
+        self.stack_dummy_read(mem_map);
         self.reg_a = self.stack_pull(mem_map);
         self.reg_status.toggle_zero_sign(self.reg_a);
     }
 
     #[inline]
-    fn instr_php(&mut self, mem_map: &mut impl MemMapped) {
+    fn instr_php(&mut self, mem_map: &mut impl CpuBus) {
         let status_byte = self.reg_status.php();
         self.stack_push(mem_map, status_byte);
     }
 
     #[inline]
-    fn instr_plp(&mut self, mem_map: &mut impl MemMapped) {
+    fn instr_plp(&mut self, mem_map: &mut impl CpuBus) {
+        self.stack_dummy_read(mem_map);
         let status_byte = self.stack_pull(mem_map);
         self.reg_status.plp(status_byte);
-
-        if let Some(interrupt) = self.unhandled_interrupt {
-            if !self.reg_status.interrupt_disable {
-                self.instructions_since_last_interrupt = 0;
-                self.pending_interrupt = Some(interrupt);
-            }
-        }
     }
 
     //
@@ -637,23 +595,11 @@ impl Cpu {
 
     #[inline]
     fn instr_cli(&mut self) {
-        if let Some(interrupt) = self.unhandled_interrupt {
-            self.instructions_since_last_interrupt = 0;
-            self.pending_interrupt = Some(interrupt);
-        }
-
         self.reg_status.toggle_interrupt_disable(false);
     }
 
     #[inline]
     fn instr_sei(&mut self) {
-        if let Some(interrupt) = self.unhandled_interrupt {
-            if !self.reg_status.interrupt_disable {
-                self.instructions_since_last_interrupt = 0;
-                self.pending_interrupt = Some(interrupt);
-            }
-        }
-
         self.reg_status.toggle_interrupt_disable(true);
     }
 
@@ -676,37 +622,37 @@ impl Cpu {
     // Store/Load instructions
     //
     #[inline]
-    fn instr_lda(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_lda(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         self.reg_a = self.read_resolved(instruction, mem_map);
         self.reg_status.toggle_zero_sign(self.reg_a);
     }
 
     #[inline]
-    fn instr_ldx(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_ldx(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         self.reg_x = self.read_resolved(instruction, mem_map);
         self.reg_status.toggle_zero_sign(self.reg_x);
     }
 
     #[inline]
-    fn instr_ldy(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_ldy(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         self.reg_y = self.read_resolved(instruction, mem_map);
         self.reg_status.toggle_zero_sign(self.reg_y);
     }
 
     #[inline]
-    fn instr_sta(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_sta(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let reg_a = self.reg_a;
         self.write_resolved(instruction, mem_map, reg_a);
     }
 
     #[inline]
-    fn instr_stx(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_stx(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let reg_x = self.reg_x;
         self.write_resolved(instruction, mem_map, reg_x);
     }
 
     #[inline]
-    fn instr_sty(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_sty(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let reg_y = self.reg_y;
         self.write_resolved(instruction, mem_map, reg_y);
     }
@@ -766,7 +712,7 @@ impl Cpu {
     // ALU instructions
     //
     #[inline]
-    fn instr_ora(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_ora(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         self.reg_a |= byte;
@@ -774,7 +720,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_and(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_and(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         self.reg_a &= byte;
@@ -782,7 +728,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_eor(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_eor(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         self.reg_a ^= byte;
@@ -790,19 +736,19 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_adc(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_adc(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
         self.perform_adc_sbc(byte, false);
     }
 
     #[inline]
-    fn instr_sbc(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_sbc(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
         self.perform_adc_sbc(byte, true);
     }
 
     #[inline]
-    fn instr_cmp(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_cmp(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         if self.reg_a > byte {
@@ -823,7 +769,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_cpx(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_cpx(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         if self.reg_x > byte {
@@ -844,7 +790,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_cpy(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_cpy(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         if self.reg_y > byte {
@@ -865,7 +811,7 @@ impl Cpu {
     }
 
     #[inline]
-    fn instr_bit(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
+    fn instr_bit(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         let byte = self.read_resolved(instruction, mem_map);
 
         let zero = byte & self.reg_a == 0;
@@ -882,81 +828,73 @@ impl Cpu {
     // Read/Modify/Write instructions
     //
     #[inline]
-    fn instr_asl(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
-
-        let carry = (byte >> 7) == 1;
-        self.reg_status.toggle_carry(carry);
-
-        byte = byte << 1;
-        self.reg_status.toggle_zero_sign(byte);
+    fn instr_asl(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let carry = (byte >> 7) == 1;
+            reg_status.toggle_carry(carry);
 
-        self.write_resolved(instruction, mem_map, byte);
+            let byte = byte << 1;
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     #[inline]
-    fn instr_rol(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
+    fn instr_rol(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let old_carry = reg_status.carry_flag as u8;
+            let new_carry = (byte >> 7) == 1;
 
-        let old_carry = self.reg_status.carry_flag as u8;
-        let new_carry = (byte >> 7) == 1;
+            reg_status.toggle_carry(new_carry);
 
-        self.reg_status.toggle_carry(new_carry);
-
-        byte = byte << 1;
-        byte |= old_carry;
-        self.reg_status.toggle_zero_sign(byte);
-
-        self.write_resolved(instruction, mem_map, byte);
+            let byte = (byte << 1) | old_carry;
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     #[inline]
-    fn instr_lsr(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
+    fn instr_lsr(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let carry = (byte & 1) == 1;
+            reg_status.toggle_carry(carry);
 
-        let carry = (byte & 1) == 1;
-        self.reg_status.toggle_carry(carry);
-
-        byte = byte >> 1;
-        self.reg_status.toggle_zero_sign(byte);
-
-        self.write_resolved(instruction, mem_map, byte);
+            let byte = byte >> 1;
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     #[inline]
-    fn instr_ror(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
-
-        let old_carry = self.reg_status.carry_flag as u8;
-        let new_carry = (byte & 1) == 1;
+    fn instr_ror(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let old_carry = reg_status.carry_flag as u8;
+            let new_carry = (byte & 1) == 1;
 
-        self.reg_status.toggle_carry(new_carry);
+            reg_status.toggle_carry(new_carry);
 
-        byte = byte >> 1;
-        byte |= old_carry << 7;
-        self.reg_status.toggle_zero_sign(byte);
-
-        self.write_resolved(instruction, mem_map, byte);
+            let byte = (byte >> 1) | (old_carry << 7);
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     #[inline]
-    fn instr_dec(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
-
-        byte = byte.wrapping_sub(1);
-        self.reg_status.toggle_zero_sign(byte);
-
-        self.write_resolved(instruction, mem_map, byte);
+    fn instr_dec(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let byte = byte.wrapping_sub(1);
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     #[inline]
-    fn instr_inc(&mut self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) {
-        let mut byte = self.read_resolved(instruction, mem_map);
-
-        byte = byte.wrapping_add(1);
-        self.reg_status.toggle_zero_sign(byte);
-
-        self.write_resolved(instruction, mem_map, byte);
+    fn instr_inc(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
+        self.modify_resolved(instruction, mem_map, |reg_status, byte| {
+            let byte = byte.wrapping_add(1);
+            reg_status.toggle_zero_sign(byte);
+            byte
+        });
     }
 
     //////////////
@@ -965,32 +903,35 @@ impl Cpu {
     //
     //////////////
 
+    // Pushes the return address and status and jumps through the vector. An NMI that comes in
+    // before the status is pushed takes over the sequence, even when it was started by BRK or IRQ.
     #[inline]
-    fn perform_irq(&mut self, mem_map: &mut impl MemMapped, interrupt: &CpuInterrupt) {
+    fn perform_interrupt(&mut self, mem_map: &mut impl CpuBus, is_break: bool) {
         let mut new_reg_pc = self.reg_pc;
 
-        if !interrupt.is_hardware {
+        if is_break {
             new_reg_pc = new_reg_pc.wrapping_add(2);
             self.reg_status.toggle_break_executed(true);
         }
 
-        let status_byte = if interrupt.is_hardware {
-            self.reg_status.irq()
-        } else {
-            self.reg_status.php()
-        };
-
         self.stack_push_addr(mem_map, new_reg_pc);
-        self.stack_push(mem_map, status_byte);
 
-        self.reg_pc = if interrupt.is_nmi {
-            mem_map.read_word(NMI_PC_VEC)
+        let is_nmi = self.is_nmi_requested;
+        self.is_nmi_requested = false;
+
+        let status_byte = if is_break {
+            self.reg_status.php()
         } else {
-            mem_map.read_word(BRK_PC_VEC)
+            self.reg_status.irq()
         };
-
+        self.stack_push(mem_map, status_byte);
         self.reg_status.interrupt_disable = true;
-        self.cycle_count += 7;
+
+        let vector = if is_nmi { NMI_PC_VEC } else { BRK_PC_VEC };
+        let addr_low = self.read_cycle(mem_map, vector);
+        let addr_high = self.read_cycle(mem_map, vector + 1);
+
+        self.reg_pc = ((addr_high as u16) << 8) | addr_low as u16;
     }
 
     // Due to the complexity of the ADC/SBC instructions, they are
@@ -1013,30 +954,71 @@ impl Cpu {
         self.reg_status.toggle_zero_sign(self.reg_a);
     }
 
+    //
+    // Bus cycles
+    //
     #[inline]
-    pub fn read_resolved(&self, instruction: &mut Instruction, mem_map: &mut impl MemMapped) -> u8 {
-        use crate::instructions::AddressingMode::*;
+    fn poll_interrupts(&mut self, mem_map: &impl CpuBus) {
+        // NMI is edge sensitive, IRQ is level sensitive
+        self.was_nmi_requested = self.is_nmi_requested;
+        let nmi_line = mem_map.is_nmi_line_asserted();
+        if nmi_line && !self.nmi_line {
+            self.is_nmi_requested = true;
+        }
+        self.nmi_line = nmi_line;
 
-        let addressing_mode = &instruction.addressing_mode;
+        self.was_irq_requested = self.is_irq_requested;
+        self.is_irq_requested =
+            mem_map.is_irq_line_asserted() && !self.reg_status.interrupt_disable;
+    }
 
-        match *addressing_mode {
-            ZeroPageIndexedX(arg) => mem_map.read(arg.wrapping_add(self.reg_x) as u16),
-            ZeroPageIndexedY(arg) => mem_map.read(arg.wrapping_add(self.reg_y) as u16),
-            AbsoluteIndexedX(arg) => {
-                if (arg & 0xFF) + self.reg_x as u16 > 0xFF {
-                    instruction.cycle_count += 1;
-                }
+    #[inline]
+    fn read_cycle(&mut self, mem_map: &mut impl CpuBus, addr: u16) -> u8 {
+        self.cycle_count += mem_map.tick(true);
+        let byte = mem_map.read(addr);
+        self.poll_interrupts(mem_map);
+        byte
+    }
 
-                mem_map.read(arg.wrapping_add(self.reg_x as u16))
-            }
-            AbsoluteIndexedY(arg) => {
-                if (arg & 0xFF) + self.reg_y as u16 > 0xFF {
-                    instruction.cycle_count += 1;
-                }
+    #[inline]
+    fn write_cycle(&mut self, mem_map: &mut impl CpuBus, addr: u16, byte: u8) {
+        self.cycle_count += mem_map.tick(false);
+        mem_map.write(addr, byte);
+        self.poll_interrupts(mem_map);
+    }
+
+    // Reads that only take up a cycle still reach the bus, and can have side effects
+    // on registers like PPUSTATUS and PPUDATA
+    #[inline]
+    fn dummy_read(&mut self, mem_map: &mut impl CpuBus, addr: u16) {
+        let _ = self.read_cycle(mem_map, addr);
+    }
+
+    // Runs the addressing cycles and returns the effective address
+    fn resolve_address(
+        &mut self,
+        instruction: &Instruction,
+        mem_map: &mut impl CpuBus,
+        access: MemoryAccess,
+    ) -> u16 {
+        use crate::instructions::AddressingMode::*;
 
-                mem_map.read(arg.wrapping_add(self.reg_y as u16))
+        match instruction.addressing_mode {
+            ZeroPage(arg) => arg as u16,
+            Absolute(arg) => arg,
+            // The base address is read while the index is added
+            ZeroPageIndexedX(arg) => {
+                self.dummy_read(mem_map, arg as u16);
+                arg.wrapping_add(self.reg_x) as u16
+            }
+            ZeroPageIndexedY(arg) => {
+                self.dummy_read(mem_map, arg as u16);
+                arg.wrapping_add(self.reg_y) as u16
             }
+            AbsoluteIndexedX(arg) => self.resolve_indexed(mem_map, arg, self.reg_x, access),
+            AbsoluteIndexedY(arg) => self.resolve_indexed(mem_map, arg, self.reg_y, access),
             IndexedIndirectX(arg) => {
+                self.dummy_read(mem_map, arg as u16);
                 let arg_plus_x = arg.wrapping_add(self.reg_x);
 
                 // When reading from addresses at page boundaries (0xFF)
@@ -1050,35 +1032,55 @@ impl Cpu {
                 // of the destination address from $00
                 // resulting in the address $0503
 
-                let addr_low = mem_map.read(arg_plus_x as u16);
-                let addr_high = mem_map.read(arg_plus_x.wrapping_add(1) as u16);
+                let addr_low = self.read_cycle(mem_map, arg_plus_x as u16);
+                let addr_high = self.read_cycle(mem_map, arg_plus_x.wrapping_add(1) as u16);
 
-                let addr = ((addr_high as u16) << 8) | addr_low as u16;
-
-                mem_map.read(addr)
+                ((addr_high as u16) << 8) | addr_low as u16
             }
             IndirectIndexedY(arg) => {
-                let addr_low = mem_map.read(arg as u16);
-                let addr_high = mem_map.read(arg.wrapping_add(1) as u16);
+                let addr_low = self.read_cycle(mem_map, arg as u16);
+                let addr_high = self.read_cycle(mem_map, arg.wrapping_add(1) as u16);
                 let arg_resolved = ((addr_high as u16) << 8) | addr_low as u16;
 
-                let addr = arg_resolved.wrapping_add(self.reg_y as u16);
+                self.resolve_indexed(mem_map, arg_resolved, self.reg_y, access)
+            }
+            // Above covers all addressing modes with an address operand
+            _ => unreachable!(),
+        }
+    }
 
-                if (arg_resolved & 0xFF) + self.reg_y as u16 > 0xFF {
-                    instruction.cycle_count += 1;
-                }
+    // The index is added to the low byte first, and the address is read before the carry
+    // into the high byte is fixed up. Reads skip that cycle when no page is crossed,
+    // writes can't since the address could still be wrong.
+    #[inline]
+    fn resolve_indexed(
+        &mut self,
+        mem_map: &mut impl CpuBus,
+        base_addr: u16,
+        index: u8,
+        access: MemoryAccess,
+    ) -> u16 {
+        let addr = base_addr.wrapping_add(index as u16);
+        let is_page_crossed = base_addr & 0xFF00 != addr & 0xFF00;
+        if is_page_crossed || access != MemoryAccess::Read {
+            self.dummy_read(mem_map, (base_addr & 0xFF00) | (addr & 0xFF));
+        }
+        addr
+    }
 
-                mem_map.read(addr)
-            }
+    #[inline]
+    fn read_resolved(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) -> u8 {
+        use crate::instructions::AddressingMode::*;
 
+        match instruction.addressing_mode {
             Immediate(arg) => arg,
             Accumulator => self.reg_a,
-            ZeroPage(arg) => mem_map.read(arg as u16),
-            Absolute(arg) => mem_map.read(arg),
-
-            // Implicit, Relative and Indirect addressing modes are handled
-            // by the instructions themselves
-            _ => 0,
+            // Only the single byte NOPs get here, their dummy read is already done
+            Implicit => 0,
+            _ => {
+                let addr = self.resolve_address(instruction, mem_map, MemoryAccess::Read);
+                self.read_cycle(mem_map, addr)
+            }
         }
     }
 
@@ -1086,61 +1088,90 @@ impl Cpu {
     fn write_resolved(
         &mut self,
         instruction: &mut Instruction,
-        mem_map: &mut impl MemMapped,
+        mem_map: &mut impl CpuBus,
         byte: u8,
     ) {
+        let addr = self.resolve_address(instruction, mem_map, MemoryAccess::Write);
+        self.write_cycle(mem_map, addr, byte);
+    }
+
+    // Read/Modify/Write instructions write back the unmodified value
+    // on the cycle the new value is computed
+    #[inline]
+    fn modify_resolved<F>(
+        &mut self,
+        instruction: &mut Instruction,
+        mem_map: &mut impl CpuBus,
+        modify: F,
+    ) where
+        F: FnOnce(&mut StatusReg, u8) -> u8,
+    {
         use crate::instructions::AddressingMode::*;
 
-        let addressing_mode = &instruction.addressing_mode;
-        match *addressing_mode {
-            ZeroPageIndexedX(arg) => mem_map.write(arg.wrapping_add(self.reg_x) as u16, byte),
-            ZeroPageIndexedY(arg) => mem_map.write(arg.wrapping_add(self.reg_y) as u16, byte),
-            AbsoluteIndexedX(arg) => mem_map.write(arg.wrapping_add(self.reg_x as u16), byte),
-            AbsoluteIndexedY(arg) => mem_map.write(arg.wrapping_add(self.reg_y as u16), byte),
+        match instruction.addressing_mode {
+            Accumulator => self.reg_a = modify(&mut self.reg_status, self.reg_a),
+            _ => {
+                let addr =
+                    self.resolve_address(instruction, mem_map, MemoryAccess::ReadModifyWrite);
+                let byte = self.read_cycle(mem_map, addr);
+                self.write_cycle(mem_map, addr, byte);
+                let byte = modify(&mut self.reg_status, byte);
+                self.write_cycle(mem_map, addr, byte);
+            }
+        }
+    }
+
+    // Value an instruction operates on, for the disassembler. Doesn't take any cycles.
+    pub fn peek_resolved(&self, instruction: &Instruction, mem_map: &mut impl MemMapped) -> u8 {
+        use crate::instructions::AddressingMode::*;
+
+        match instruction.addressing_mode {
+            ZeroPageIndexedX(arg) => mem_map.read(arg.wrapping_add(self.reg_x) as u16),
+            ZeroPageIndexedY(arg) => mem_map.read(arg.wrapping_add(self.reg_y) as u16),
+            AbsoluteIndexedX(arg) => mem_map.read(arg.wrapping_add(self.reg_x as u16)),
+            AbsoluteIndexedY(arg) => mem_map.read(arg.wrapping_add(self.reg_y as u16)),
             IndexedIndirectX(arg) => {
                 let arg_plus_x = arg.wrapping_add(self.reg_x);
 
+                // See comment in the resolve_address function above
                 let addr_low = mem_map.read(arg_plus_x as u16);
                 let addr_high = mem_map.read(arg_plus_x.wrapping_add(1) as u16);
 
-                // See comment in the read_resolved function above
                 let addr = ((addr_high as u16) << 8) | addr_low as u16;
 
-                mem_map.write(addr, byte)
+                mem_map.read(addr)
             }
             IndirectIndexedY(arg) => {
                 let addr_low = mem_map.read(arg as u16);
                 let addr_high = mem_map.read(arg.wrapping_add(1) as u16);
                 let arg_resolved = ((addr_high as u16) << 8) | addr_low as u16;
 
-                // See comment in the read_resolved function above
-                let addr = arg_resolved.wrapping_add(self.reg_y as u16);
-
-                mem_map.write(addr, byte)
+                mem_map.read(arg_resolved.wrapping_add(self.reg_y as u16))
             }
 
-            ZeroPage(arg) => mem_map.write(arg as u16, byte),
-            Absolute(arg) => mem_map.write(arg, byte),
-            Accumulator => {
-                self.reg_a = byte;
-            }
-            // Above covers all addresing modes for writing memory
-            _ => unreachable!(),
+            Immediate(arg) => arg,
+            Accumulator => self.reg_a,
+            ZeroPage(arg) => mem_map.read(arg as u16),
+            Absolute(arg) => mem_map.read(arg),
+
+            // Implicit, Relative and Indirect addressing modes are handled
+            // by the instructions themselves
+            _ => 0,
         }
     }
 
-    fn stack_push(&mut self, mem_map: &mut impl MemMapped, byte: u8) {
+    fn stack_push(&mut self, mem_map: &mut impl CpuBus, byte: u8) {
         //        if self.reg_sp == 0 {
         //            println!("Stack overflow detected! Wrapping...");
         //        }
 
         let addr = 0x100 + (self.reg_sp as u16);
-        mem_map.write(addr, byte);
+        self.write_cycle(mem_map, addr, byte);
 
         self.reg_sp = self.reg_sp.wrapping_sub(1);
     }
 
-    fn stack_pull(&mut self, mem_map: &mut impl MemMapped) -> u8 {
+    fn stack_pull(&mut self, mem_map: &mut impl CpuBus) -> u8 {
         //        if self.reg_sp == 0xFF {
         //            println!("Stack underflow detected! Wrapping...");
         //        }
@@ -1148,10 +1179,16 @@ impl Cpu {
         self.reg_sp = self.reg_sp.wrapping_add(1);
         let addr = 0x100 + self.reg_sp as u16;
 
-        mem_map.read(addr)
+        self.read_cycle(mem_map, addr)
+    }
+
+    // Pulls take an extra cycle to increment the stack pointer, reading the stack before it
+    fn stack_dummy_read(&mut self, mem_map: &mut impl CpuBus) {
+        let addr = 0x100 + self.reg_sp as u16;
+        self.dummy_read(mem_map, addr);
     }
 
-    fn stack_push_addr(&mut self, mem_map: &mut impl MemMapped, addr: u16) {
+    fn stack_push_addr(&mut self, mem_map: &mut impl CpuBus, addr: u16) {
         let addr_high = ((addr & 0xFF00) >> 8) as u8;
         let addr_low = (addr & 0xFF) as u8;
 
@@ -1159,7 +1196,7 @@ impl Cpu {
         self.stack_push(mem_map, addr_low);
     }
 
-    fn stack_pull_addr(&mut self, mem_map: &mut impl MemMapped) -> u16 {
+    fn stack_pull_addr(&mut self, mem_map: &mut impl CpuBus) -> u16 {
         let addr_low = self.stack_pull(mem_map);
         let addr_high = self.stack_pull(mem_map);
 
@@ -1168,29 +1205,37 @@ impl Cpu {
     }
 
     // branch is taken
-    fn branch(&mut self, instruction: &mut Instruction) {
+    fn branch(&mut self, instruction: &mut Instruction, mem_map: &mut impl CpuBus) {
         use crate::instructions::AddressingMode::*;
 
-        // increase cycle count by 1
-        instruction.cycle_count += 1;
-
         // the PC will also be incremented by 2,
         // so the effective final pc address will be
         // reg_pc = reg_pc + offset + 2
 
         match instruction.addressing_mode {
             Relative(offset) => {
+                // A taken branch doesn't poll interrupts on its last cycle
+                // unless it crosses a page, so an IRQ that comes in then waits
+                // until after the next instruction
+                if self.is_irq_requested && !self.was_irq_requested {
+                    self.is_irq_requested = false;
+                }
+
                 // The offset is added AFTER the PC has been incremented by 2
                 // (which happens regardless of whether the branch is being taken or not)
                 // So we need to check for page boundary crossing AFTER the PC has been incremented
                 // by 2
-                let old_reg_pc = self.reg_pc + 2;
+                let old_reg_pc = self.reg_pc.wrapping_add(2);
                 let reg_pc_i32 = self.reg_pc as i32;
                 self.reg_pc = reg_pc_i32.wrapping_add(offset as i32) as u16;
+                let new_reg_pc = self.reg_pc.wrapping_add(2);
 
-                if old_reg_pc & 0xFF00 != self.reg_pc & 0xFF00 {
-                    // moved to previous or next page, increase cycle count by 1
-                    instruction.cycle_count += 1;
+                // The next opcode is read while the offset is added to the low byte of the PC
+                self.dummy_read(mem_map, old_reg_pc);
+
+                if old_reg_pc & 0xFF00 != new_reg_pc & 0xFF00 {
+                    // moved to previous or next page, the high byte takes another cycle
+                    self.dummy_read(mem_map, (old_reg_pc & 0xFF00) | (new_reg_pc & 0xFF));
                 }
             }
             _ => unreachable!(),
@@ -1198,12 +1243,39 @@ impl Cpu {
     }
 }
 
+// Instructions are decoded through the CPU, so that fetching
+// the opcode and each operand byte takes a cycle
+struct InstructionFetch<'a, B: CpuBus> {
+    cpu: &'a mut Cpu,
+    mem_map: &'a mut B,
+}
+
+impl<'a, B: CpuBus> InstructionFetch<'a, B> {
+    fn new(cpu: &'a mut Cpu, mem_map: &'a mut B) -> InstructionFetch<'a, B> {
+        InstructionFetch { cpu, mem_map }
+    }
+}
+
+impl<'a, B: CpuBus> MemMapped for InstructionFetch<'a, B> {
+    fn read(&mut self, index: u16) -> u8 {
+        self.cpu.read_cycle(self.mem_map, index)
+    }
+
+    fn write(&mut self, _index: u16, _byte: u8) {
+        unreachable!()
+    }
+
+    fn read_range(&mut self, _range: Range<u16>) -> &[u8] {
+        unimplemented!()
+    }
+}
+
 impl Display for Cpu {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let status_reg_byte: u8 = self.reg_status.byte();
         write!(
             f,
-            "A:0x{:02X} X:0x{:02X} Y:0x{:02X} P:0x{:02X} SP:0x{:02X} N:{} I:{} UI:{} PI:{} CYC:{}",
+            "A:0x{:02X} X:0x{:02X} Y:0x{:02X} P:0x{:02X} SP:0x{:02X} N:{} I:{} NMI:{} IRQ:{} CYC:{}",
             self.reg_a,
             self.reg_x,
             self.reg_y,
@@ -1211,9 +1283,289 @@ impl Display for Cpu {
             self.reg_sp,
             self.reg_status.sign_flag as u8,
             self.reg_status.interrupt_disable as u8,
-            self.unhandled_interrupt.is_some() as u8,
-            self.pending_interrupt.is_some() as u8,
+            self.is_nmi_requested as u8,
+            self.is_irq_requested as u8,
             self.cycle_count
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum BusAccess {
+        Read(u16),
+        Write(u16, u8),
+    }
+
+    // Flat 64KB of memory that records every access, with interrupt lines that get asserted
+    // from a given cycle on
+    struct TestBus {
+        memory: Vec<u8>,
+        accesses: Vec<BusAccess>,
+        cycles: u64,
+        nmi_cycle: Option<u64>,
+        irq_cycle: Option<u64>,
+    }
+
+    impl TestBus {
+        fn new(program: &[u8]) -> TestBus {
+            let mut memory = vec![0; 0x10000];
+            memory[0x8000..0x8000 + program.len()].copy_from_slice(program);
+            memory[NMI_PC_VEC as usize..].copy_from_slice(&[0x00, 0xA0, 0x00, 0x80, 0x00, 0x90]);
+
+            TestBus {
+                memory,
+                accesses: Vec::new(),
+                cycles: 0,
+                nmi_cycle: None,
+                irq_cycle: None,
+            }
+        }
+    }
+
+    impl MemMapped for TestBus {
+        fn read(&mut self, index: u16) -> u8 {
+            self.accesses.push(BusAccess::Read(index));
+            self.memory[index as usize]
+        }
+
+        fn write(&mut self, index: u16, byte: u8) {
+            self.accesses.push(BusAccess::Write(index, byte));
+            self.memory[index as usize] = byte;
+        }
+
+        fn read_range(&mut self, _range: Range<u16>) -> &[u8] {
+            unimplemented!()
+        }
+    }
+
+    impl CpuBus for TestBus {
+        fn tick(&mut self, _is_read: bool) -> u64 {
+            self.cycles += 1;
+            1
+        }
+
+        fn is_nmi_line_asserted(&self) -> bool {
+            self.nmi_cycle.is_some_and(|cycle| self.cycles >= cycle)
+        }
+
+        fn is_irq_line_asserted(&self) -> bool {
+            self.irq_cycle.is_some_and(|cycle| self.cycles >= cycle)
+        }
+    }
+
+    fn run(cpu: &mut Cpu, bus: &mut TestBus) -> u64 {
+        cpu.step(bus, &mut Tracer::default()).unwrap()
+    }
+
+    fn test_cpu(program: &[u8]) -> (Cpu, TestBus) {
+        let mut bus = TestBus::new(program);
+        let cpu = Cpu::new(&mut bus);
+        bus.accesses.clear();
+        (cpu, bus)
+    }
+
+    #[test]
+    fn indexed_read_crossing_a_page_reads_the_wrong_address_first() {
+        use self::BusAccess::*;
+
+        // LDA $10F0,X
+        let (mut cpu, mut bus) = test_cpu(&[0xBD, 0xF0, 0x10]);
+        cpu.reg_x = 0x20;
+        bus.memory[0x1110] = 0x42;
+
+        assert_eq!(run(&mut cpu, &mut bus), 5);
+        assert_eq!(cpu.reg_a, 0x42);
+        assert_eq!(
+            bus.accesses,
+            [
+                Read(0x8000),
+                Read(0x8001),
+                Read(0x8002),
+                Read(0x1010),
+                Read(0x1110)
+            ]
+        );
+
+        // Without a page crossing the dummy read is skipped
+        let (mut cpu, mut bus) = test_cpu(&[0xBD, 0xF0, 0x10]);
+        cpu.reg_x = 0x05;
+
+        assert_eq!(run(&mut cpu, &mut bus), 4);
+        assert_eq!(
+            bus.accesses,
+            [Read(0x8000), Read(0x8001), Read(0x8002), Read(0x10F5)]
+        );
+    }
+
+    #[test]
+    fn indexed_write_always_does_the_dummy_read() {
+        use self::BusAccess::*;
+
+        // STA $10F0,X
+        let (mut cpu, mut bus) = test_cpu(&[0x9D, 0xF0, 0x10]);
+        cpu.reg_a = 0x42;
+        cpu.reg_x = 0x05;
+
+        assert_eq!(run(&mut cpu, &mut bus), 5);
+        assert_eq!(
+            bus.accesses,
+            [
+                Read(0x8000),
+                Read(0x8001),
+                Read(0x8002),
+                Read(0x10F5),
+                Write(0x10F5, 0x42)
+            ]
+        );
+    }
+
+    #[test]
+    fn read_modify_write_writes_the_old_value_first() {
+        use self::BusAccess::*;
+
+        // INC $10
+        let (mut cpu, mut bus) = test_cpu(&[0xE6, 0x10]);
+        bus.memory[0x10] = 0x41;
+
+        assert_eq!(run(&mut cpu, &mut bus), 5);
+        assert_eq!(
+            bus.accesses,
+            [
+                Read(0x8000),
+                Read(0x8001),
+                Read(0x0010),
+                Write(0x0010, 0x41),
+                Write(0x0010, 0x42)
+            ]
+        );
+
+        // INC $10F0,X
+        let (mut cpu, mut bus) = test_cpu(&[0xFE, 0xF0, 0x10]);
+        cpu.reg_x = 0x20;
+        bus.memory[0x1110] = 0x41;
+
+        assert_eq!(run(&mut cpu, &mut bus), 7);
+        assert_eq!(
+            bus.accesses,
+            [
+                Read(0x8000),
+                Read(0x8001),
+                Read(0x8002),
+                Read(0x1010),
+                Read(0x1110),
+                Write(0x1110, 0x41),
+                Write(0x1110, 0x42)
+            ]
+        );
+    }
+
+    #[test]
+    fn branch_cycles() {
+        use self::BusAccess::*;
+
+        // BNE +$10, not taken
+        let (mut cpu, mut bus) = test_cpu(&[0xD0, 0x10]);
+        cpu.reg_status.zero_flag = true;
+        assert_eq!(run(&mut cpu, &mut bus), 2);
+        assert_eq!(cpu.reg_pc, 0x8002);
+
+        // Taken, the next opcode is read while the offset is added
+        let (mut cpu, mut bus) = test_cpu(&[0xD0, 0x10]);
+        assert_eq!(run(&mut cpu, &mut bus), 3);
+        assert_eq!(cpu.reg_pc, 0x8012);
+        assert_eq!(bus.accesses, [Read(0x8000), Read(0x8001), Read(0x8002)]);
+
+        // Taken across a page, the address without the carry is read as well
+        let (mut cpu, mut bus) = test_cpu(&[]);
+        bus.memory[0x80FD..0x80FF].copy_from_slice(&[0xD0, 0x10]);
+        cpu.reg_pc = 0x80FD;
+        assert_eq!(run(&mut cpu, &mut bus), 4);
+        assert_eq!(cpu.reg_pc, 0x810F);
+        assert_eq!(
+            bus.accesses,
+            [Read(0x80FD), Read(0x80FE), Read(0x80FF), Read(0x800F)]
+        );
+    }
+
+    // Steps until the interrupt is taken, returning the number of instructions run before it
+    fn instructions_before_irq(program: &[u8], pc: u16, irq_cycle: u64) -> usize {
+        let (mut cpu, mut bus) = test_cpu(&[]);
+        bus.memory[pc as usize..pc as usize + program.len()].copy_from_slice(program);
+        cpu.reg_pc = pc;
+        cpu.reg_status.interrupt_disable = false;
+        bus.irq_cycle = Some(irq_cycle);
+
+        let mut instruction_count = 0;
+        while cpu.reg_pc != 0x9000 {
+            run(&mut cpu, &mut bus);
+            instruction_count += 1;
+        }
+        // The interrupt sequence itself
+        instruction_count - 1
+    }
+
+    #[test]
+    fn taken_branch_delays_irq() {
+        // BNE +0, NOP, NOP
+        let program = [0xD0, 0x00, 0xEA, 0xEA];
+
+        // An IRQ seen after the opcode fetch is taken right after the branch
+        assert_eq!(instructions_before_irq(&program, 0x8000, 1), 1);
+        // One seen on the cycle after isn't polled for on the branch's last cycle
+        assert_eq!(instructions_before_irq(&program, 0x8000, 2), 2);
+        // Unless the branch crosses a page, which adds a cycle that polls it
+        // BNE +2 to $8100, NOP, NOP, NOP, NOP
+        let program = [0xD0, 0x02, 0xEA, 0xEA, 0xEA, 0xEA];
+        assert_eq!(instructions_before_irq(&program, 0x80FC, 2), 1);
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        use self::BusAccess::*;
+
+        // NMI seen before the status is pushed: BRK's status with the NMI vector
+        let (mut cpu, mut bus) = test_cpu(&[0x00]);
+        bus.nmi_cycle = Some(4);
+
+        assert_eq!(run(&mut cpu, &mut bus), 7);
+        assert_eq!(cpu.reg_pc, 0xA000);
+        assert_eq!(
+            bus.accesses,
+            [
+                Read(0x8000),
+                Read(0x8001),
+                Write(0x01FD, 0x80),
+                Write(0x01FC, 0x02),
+                Write(0x01FB, 0b0011_0100),
+                Read(0xFFFA),
+                Read(0xFFFB)
+            ]
+        );
+
+        // NMI seen after: the BRK goes through and the NMI is taken after it
+        let (mut cpu, mut bus) = test_cpu(&[0x00]);
+        bus.nmi_cycle = Some(5);
+
+        assert_eq!(run(&mut cpu, &mut bus), 7);
+        assert_eq!(cpu.reg_pc, 0x9000);
+        assert_eq!(bus.accesses[5..], [Read(0xFFFE), Read(0xFFFF)]);
+
+        assert_eq!(run(&mut cpu, &mut bus), 7);
+        assert_eq!(cpu.reg_pc, 0xA000);
+    }
+
+    #[test]
+    fn skipped_instructions_keep_their_timing() {
+        // SLO $10, ALR #$10
+        let (mut cpu, mut bus) = test_cpu(&[0x07, 0x10, 0x4B, 0x10]);
+
+        assert_eq!(run(&mut cpu, &mut bus), 5);
+        assert_eq!(bus.accesses.len(), 5);
+        assert_eq!(run(&mut cpu, &mut bus), 2);
+        assert_eq!(cpu.reg_pc, 0x8004);
+    }
+}
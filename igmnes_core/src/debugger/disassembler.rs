@@ -46,7 +46,7 @@ pub fn disassemble(
     let op_code = instruction.op_code;
     let token = instruction.token.to_string();
 
-    let resolved = cpu.read_resolved(instruction, mem_map);
+    let resolved = cpu.peek_resolved(instruction, mem_map);
     let addressing_mode = &instruction.addressing_mode;
 
     let (args, detail) = match *addressing_mode {
@@ -79,7 +79,7 @@ pub fn disassemble(
             let addr_low = mem_map.read(arg as u16);
             let addr_high = mem_map.read(arg.wrapping_add(1) as u16);
 
-            // See comment in the read_resolved function
+            // See comment in the resolve_address function
             let addr = ((addr_high as u16) << 8) | addr_low as u16;
 
             (
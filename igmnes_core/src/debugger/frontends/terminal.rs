@@ -13,7 +13,7 @@ use crate::debugger::disassembler;
 use crate::debugger::Debugger;
 use crate::dma::Dma;
 use crate::errors::EmulationError;
-use crate::memory::{CpuMemMap, MemMapped};
+use crate::memory::{CpuBus, CpuMemMap, MemMapped};
 use crate::ppu::Ppu;
 use crate::BusOps;
 
@@ -455,7 +455,7 @@ impl BusOps for TerminalDebugger {
         self.mem_map.hard_reset();
     }
 
-    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u8, EmulationError> {
+    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u64, EmulationError> {
         let reg_pc = self.cpu.reg_pc;
 
         if self.breakpoint_set.contains(&reg_pc) {
@@ -507,31 +507,19 @@ impl BusOps for TerminalDebugger {
             res @ _ => res,
         }
     }
+}
 
-    fn step_ppu(&mut self, cpu_cycle_count: u64, tracer: &mut Tracer) -> bool {
-        tracer.set_enabled(self.trace_active);
-        self.mem_map.ppu.step(cpu_cycle_count, tracer)
-    }
-
-    fn step_apu(&mut self, cpu_cycles: u64) -> bool {
-        self.mem_map.apu.step(cpu_cycles)
-    }
-
-    fn step_dma(&mut self) -> bool {
-        // let dma = &mut self.dma();
-        // let cpu_ram = &mut self.mem_map.ram;
-        // let ppu_mem_map = &mut self.mem_map.ppu_mem_map;
-        //
-        // dma.step(cpu_ram, ppu_mem_map)
-        true
+impl<'a> CpuBus for MemMapShim<'a> {
+    fn tick(&mut self, is_read: bool) -> u64 {
+        self.mem_map.tick(is_read)
     }
 
-    fn nmi(&mut self) {
-        self.cpu.nmi(&mut self.mem_map)
+    fn is_nmi_line_asserted(&self) -> bool {
+        self.mem_map.is_nmi_line_asserted()
     }
 
-    fn irq(&mut self) {
-        self.cpu.irq(&mut self.mem_map);
+    fn is_irq_line_asserted(&self) -> bool {
+        self.mem_map.is_irq_line_asserted()
     }
 }
 
@@ -1,9 +1,6 @@
-use crate::memory::{CpuMemMap, MemMapped};
-
 #[derive(Default)]
 pub struct Dma {
     page_index: u8,
-    dma_cycle_count: usize,
 
     pub dma_type: Option<DmaType>,
 }
@@ -20,24 +17,16 @@ impl Dma {
     pub fn start_dma(&mut self, dma_type: DmaType, page_index: u8) {
         self.dma_type = Some(dma_type);
         self.page_index = page_index;
-        self.dma_cycle_count = 0;
     }
 
-    pub fn step(&mut self, mem_map: &mut CpuMemMap) {
-        if self.dma_type.is_none() {
-            return;
-        }
-
-        if self.dma_cycle_count == 0 {
-            let range_start = self.page_index as u16 * 0x100;
-            let range_end = range_start + 0x100;
-            let cpu_mem = mem_map.ram.read_range(range_start..range_end);
-            mem_map.ppu.ppu_mem_map.oam_table.write(cpu_mem);
-        }
-        self.dma_cycle_count += 2;
-
-        if self.dma_cycle_count == 514 {
-            self.dma_type = None;
+    // The page of a pending OAM DMA, which is run by the bus on the CPU's next read cycle
+    pub fn take_oam_page(&mut self) -> Option<u8> {
+        match self.dma_type {
+            Some(DmaType::OAM) => {
+                self.dma_type = None;
+                Some(self.page_index)
+            }
+            _ => None,
         }
     }
 
@@ -0,0 +1,217 @@
+// Famicom Disk System disk sides, in the layouts images come in and the one the drive sees
+//
+// - .fds: the blocks back to back, without gaps or CRCs, padded to 65500 bytes
+// - QD: the blocks back to back, each followed by its CRC, padded to 65536 bytes (the size
+//   of the Quick Disks the FDS disks are based on)
+// - Raw: what the drive streams, a gap of zeros, a start mark, the block and its CRC for
+//   each block
+//
+// A side holds a disk info block (1), a file amount block (2), then a file header (3) and
+// file data (4) block for each file. The size of a file data block is in the file header
+// before it.
+
+pub const FDS_SIDE_SIZE: usize = 65500;
+pub const QD_SIDE_SIZE: usize = 65536;
+
+// 28300 bits of gap before the first block, 976 bits between blocks
+pub const LEADING_GAP_SIZE: usize = 28300 / 8;
+const BLOCK_GAP_SIZE: usize = 976 / 8;
+const BLOCK_START_MARK: u8 = 0x80;
+const CRC_SIZE: usize = 2;
+
+// Finds the blocks of a side that has them back to back, with crc_size bytes after each.
+// Stops at the first byte that doesn't start a block, or at a block cut off by the end.
+fn split_blocks(side: &[u8], crc_size: usize) -> Vec<(&[u8], &[u8])> {
+    let mut blocks = Vec::new();
+    let mut position = 0;
+    let mut file_size = 0;
+
+    while position < side.len() {
+        let block_size = match block_size(side[position], file_size) {
+            Some(block_size) => block_size,
+            None => break,
+        };
+        if position + block_size + crc_size > side.len() {
+            break;
+        }
+
+        let block = &side[position..position + block_size];
+        if let Some(size) = file_data_size(block) {
+            file_size = size;
+        }
+        let crc = &side[position + block_size..position + block_size + crc_size];
+        blocks.push((block, crc));
+
+        position += block_size + crc_size;
+    }
+
+    blocks
+}
+
+fn block_size(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        // Disk info
+        1 => Some(56),
+        // File amount
+        2 => Some(2),
+        // File header
+        3 => Some(16),
+        // File data
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+// Size of the file data block that follows a file header
+fn file_data_size(block: &[u8]) -> Option<usize> {
+    match block {
+        [3, .., _, _, _] if block.len() == 16 => {
+            Some(u16::from_le_bytes([block[13], block[14]]) as usize)
+        }
+        _ => None,
+    }
+}
+
+// Adds the gaps, start marks and CRCs that the .fds format leaves out
+pub fn fds_to_raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw_side = vec![0; LEADING_GAP_SIZE];
+    for (block, _) in split_blocks(side, 0) {
+        raw_side.push(BLOCK_START_MARK);
+        raw_side.extend_from_slice(block);
+        raw_side.extend_from_slice(&block_crc(block).to_le_bytes());
+        raw_side.resize(raw_side.len() + BLOCK_GAP_SIZE, 0);
+    }
+
+    raw_side
+}
+
+// Takes the blocks back out of the drive's stream, for saving in .fds format. Whatever
+// follows the first block that doesn't parse is left out.
+pub fn raw_to_fds_side(raw_side: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut position = 0;
+    let mut file_size = 0;
+
+    loop {
+        // The gap ends with the start mark
+        while position < raw_side.len() && raw_side[position] == 0 {
+            position += 1;
+        }
+        if raw_side.get(position) != Some(&BLOCK_START_MARK) {
+            break;
+        }
+        position += 1;
+        if position >= raw_side.len() {
+            break;
+        }
+
+        let block_size = match block_size(raw_side[position], file_size) {
+            Some(block_size) if position + block_size + CRC_SIZE <= raw_side.len() => block_size,
+            _ => break,
+        };
+        let block = &raw_side[position..position + block_size];
+        if let Some(size) = file_data_size(block) {
+            file_size = size;
+        }
+        side.extend_from_slice(block);
+
+        position += block_size + CRC_SIZE;
+    }
+
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
+
+// Checks and strips the CRCs of a QD side. Returns the index of the first block with a bad
+// CRC on failure.
+pub fn qd_to_fds_side(qd_side: &[u8]) -> Result<Vec<u8>, usize> {
+    let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+    for (index, (block, crc)) in split_blocks(qd_side, CRC_SIZE).into_iter().enumerate() {
+        if crc != block_crc(block).to_le_bytes() {
+            return Err(index);
+        }
+        side.extend_from_slice(block);
+    }
+
+    side.resize(FDS_SIDE_SIZE, 0);
+    Ok(side)
+}
+
+// CRC of a block as the drive computes it, over the start mark, the block and two zeros
+pub fn block_crc(block: &[u8]) -> u16 {
+    block
+        .iter()
+        .chain(&[0, 0])
+        .fold(update_crc(0, BLOCK_START_MARK), |crc, &byte| {
+            update_crc(crc, byte)
+        })
+}
+
+// CRC-16/KERMIT, shifted in LSB first like the drive does
+pub fn update_crc(mut crc: u16, byte: u8) -> u16 {
+    for bit in 0..8 {
+        let carry = crc & 1 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if byte & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Disk info, file amount, and a file header and its 3 bytes of data
+    fn fds_side() -> Vec<u8> {
+        let mut side = vec![1];
+        side.extend_from_slice(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        let mut file_header = vec![3; 16];
+        file_header[13..15].copy_from_slice(&3u16.to_le_bytes());
+        side.extend_from_slice(&file_header);
+        side.extend_from_slice(&[4, 0xAA, 0xBB, 0xCC]);
+        side.resize(FDS_SIDE_SIZE, 0);
+        side
+    }
+
+    fn qd_side(side: &[u8]) -> Vec<u8> {
+        let mut qd_side = Vec::new();
+        for (block, _) in split_blocks(side, 0) {
+            qd_side.extend_from_slice(block);
+            qd_side.extend_from_slice(&block_crc(block).to_le_bytes());
+        }
+        qd_side.resize(QD_SIDE_SIZE, 0);
+        qd_side
+    }
+
+    #[test]
+    fn raw_side_converts_back_to_fds() {
+        let side = fds_side();
+        let raw_side = fds_to_raw_side(&side);
+        assert_eq!(
+            raw_side.len(),
+            LEADING_GAP_SIZE + 4 * (1 + CRC_SIZE + BLOCK_GAP_SIZE) + 78
+        );
+        assert_eq!(raw_to_fds_side(&raw_side), side);
+    }
+
+    #[test]
+    fn qd_side_converts_to_fds() {
+        let side = fds_side();
+        assert_eq!(qd_to_fds_side(&qd_side(&side)), Ok(side));
+    }
+
+    #[test]
+    fn qd_side_with_bad_crc_is_rejected() {
+        let mut qd_side = qd_side(&fds_side());
+        // Data of the file data block, the 4th one
+        qd_side[56 + 2 + 2 + 2 + 16 + 2 + 1] ^= 0xFF;
+        assert_eq!(qd_to_fds_side(&qd_side), Err(3));
+    }
+}
@@ -2,17 +2,24 @@
 extern crate nom;
 
 pub mod apu;
+mod archive;
+mod checksum;
 mod controller;
 pub mod cpu;
 pub mod debug;
 pub mod debugger;
 mod dma;
 mod errors;
+mod fds_disk;
 mod instructions;
 mod mappers;
 mod memory;
+mod nsf;
+mod patch;
 pub mod ppu;
 mod rom;
+mod rom_db;
+mod vs_system;
 
 use self::apu::Apu;
 use self::cpu::Cpu;
@@ -26,16 +33,38 @@ use crate::debug::Tracer;
 use crate::debugger::frontends::terminal::TerminalDebugger;
 use crate::debugger::{Debugger, DebuggerFrontend};
 use crate::dma::Dma;
-use crate::mappers::MapperIrq;
+use crate::mappers::MapperBattery;
 use crate::ppu::palette::PpuPaletteColor;
 use crate::rom::RomError;
 use enum_dispatch::enum_dispatch;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 pub use crate::controller::{ControllerButton, ControllerButtonState, ControllerIndex};
-pub use crate::ppu::PpuFrame;
+pub use crate::mappers::MapperBankState;
+pub use crate::nsf::{Nsf, NsfExpansionChips, NsfTrack};
+pub use crate::patch::PatchError;
+pub use crate::ppu::events::{
+    PpuEventLog, PpuEventRecording, PpuRegisterState, PpuScanlineEvent, PpuWriteEvent,
+};
+pub use crate::ppu::frame::{
+    crop_frame, display_frame, frame_to_png, frame_to_rgb24, resize_frame, AspectRatio, FrameRect,
+    Overscan, PPU_FRAME_HEIGHT, PPU_FRAME_WIDTH,
+};
+pub use crate::ppu::ntsc::{
+    NtscFilter, NtscFilterPreset, NtscFilterSettings, NTSC_FRAME_HEIGHT, NTSC_FRAME_WIDTH,
+};
+pub use crate::ppu::palette::{PpuPaletteColors, PpuPalettePreset, PpuPaletteSettings};
+pub use crate::ppu::scaler::{CrtMask, FrameScaler, Scaler, ScalerSettings};
+pub use crate::ppu::viewer::{
+    OamSprite, NAMETABLES_VIEW_HEIGHT, NAMETABLES_VIEW_WIDTH, OAM_VIEW_HEIGHT, OAM_VIEW_WIDTH,
+    PATTERN_TABLES_VIEW_HEIGHT, PATTERN_TABLES_VIEW_WIDTH,
+};
+pub use crate::ppu::{PpuDisplayOptions, PpuFrame, PpuIndexFrame, PpuOutputMode};
+pub use crate::rom::{Header, HeaderType, MirroringMode, RomLoadOptions, TVSystem};
+pub use crate::rom_db::{generate_rom_db_entries, RomDbConsole, RomDbEntry};
+pub use crate::vs_system::{VsHardwareType, VsPpuType};
 
 pub const MASTER_CLOCK_NTSC: f32 = 21.477272_E6_f32;
 // 21.477272 MHz
@@ -62,13 +91,9 @@ pub trait BusOps {
 
     fn controllers(&mut self) -> &mut [Controller; 2];
 
-    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u8, EmulationError>;
-    fn step_ppu(&mut self, cpu_cycles: u64, tracer: &mut Tracer) -> bool;
-    fn step_apu(&mut self, cpu_cycles: u64) -> bool;
-    fn step_dma(&mut self) -> bool;
-
-    fn nmi(&mut self);
-    fn irq(&mut self);
+    // Runs the next instruction or interrupt, clocking the rest of the system along with
+    // every bus access, and returns the CPU cycles it took
+    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u64, EmulationError>;
 
     fn hard_reset(&mut self);
 }
@@ -131,39 +156,10 @@ impl BusOps for DefaultBus {
     }
 
     #[inline]
-    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u8, EmulationError> {
+    fn step_cpu(&mut self, tracer: &mut Tracer) -> Result<u64, EmulationError> {
         self.cpu.step(&mut self.mem_map, tracer)
     }
 
-    #[inline]
-    fn step_ppu(&mut self, cpu_cycle_count: u64, tracer: &mut Tracer) -> bool {
-        self.mem_map.ppu.step(cpu_cycle_count, tracer)
-    }
-
-    #[inline]
-    fn step_apu(&mut self, cpu_cycles: u64) -> bool {
-        self.mem_map.apu.step(cpu_cycles)
-    }
-
-    fn step_dma(&mut self) -> bool {
-        let mut dma = std::mem::take(&mut self.mem_map.dma);
-        let mem_map = &mut self.mem_map;
-        dma.step(mem_map);
-        let result = dma.is_dma_active();
-        self.mem_map.dma = dma;
-        result
-    }
-
-    #[inline]
-    fn nmi(&mut self) {
-        self.cpu.nmi(&mut self.mem_map)
-    }
-
-    #[inline]
-    fn irq(&mut self) {
-        self.cpu.irq(&mut self.mem_map);
-    }
-
     fn hard_reset(&mut self) {
         self.mem_map.hard_reset();
         let entry_point_addr = self.mem_map.read_word(cpu::RESET_PC_VEC);
@@ -199,17 +195,32 @@ pub enum CoreError {
 impl Core {
     pub fn load_rom(file_path: &Path) -> Result<Core, CoreError> {
         let rom = Rom::load_rom(file_path)?;
+        Ok(Core::with_rom(rom))
+    }
+
+    pub fn load_rom_with_options(
+        file_path: &Path,
+        options: &RomLoadOptions,
+    ) -> Result<Core, CoreError> {
+        let rom = Rom::load_rom_with_options(file_path, options)?;
+        Ok(Core::with_rom(rom))
+    }
+
+    // Names of the ROMs in a zip archive, empty for other files
+    pub fn list_archive_entries(file_path: &Path) -> Result<Vec<String>, CoreError> {
+        Ok(Rom::list_archive_entries(file_path)?)
+    }
+
+    fn with_rom(rom: Rom) -> Core {
         let mut mem_map = CpuMemMap::new(rom);
 
         let cpu = Cpu::new(&mut mem_map);
         let bus = DefaultBus::new(cpu, mem_map);
 
-        let core = Core {
+        Core {
             bus: Bus::from(bus),
             is_debugger_attached: false,
-        };
-
-        Ok(core)
+        }
     }
 
     #[inline]
@@ -229,6 +240,41 @@ impl Core {
         self.bus.ppu().get_frame()
     }
 
+    // Frames of 9-bit palette indices, for PpuOutputMode::PaletteIndex
+    pub fn ppu_index_frame(&mut self) -> PpuIndexFrame<'_> {
+        self.bus.ppu().get_index_frame()
+    }
+
+    pub fn ppu_frame_burst_phase(&mut self) -> u8 {
+        self.bus.ppu().frame_burst_phase()
+    }
+
+    pub fn ppu_output_mode(&mut self) -> PpuOutputMode {
+        self.bus.ppu().output_mode()
+    }
+
+    pub fn set_ppu_output_mode(&mut self, output_mode: PpuOutputMode) {
+        self.bus.ppu().set_output_mode(output_mode)
+    }
+
+    pub fn ppu_display_options(&mut self) -> PpuDisplayOptions {
+        self.bus.ppu().display_options()
+    }
+
+    // Sprite limit removal and hidden layers, which don't affect emulation
+    pub fn set_ppu_display_options(&mut self, display_options: PpuDisplayOptions) {
+        self.bus.ppu().set_display_options(display_options)
+    }
+
+    // The colors used for PpuOutputMode::Rgb, and to convert index frames the same way
+    pub fn palette_colors(&mut self) -> &PpuPaletteColors {
+        self.bus.ppu().ppu_mem_map.palette.colors()
+    }
+
+    pub fn set_palette_colors(&mut self, colors: PpuPaletteColors) {
+        self.bus.ppu().ppu_mem_map.palette.set_colors(colors)
+    }
+
     pub fn is_apu_output_ready(&mut self) -> bool {
         self.bus.apu().is_output_ready()
     }
@@ -274,32 +320,236 @@ impl Core {
         self.bus.hard_reset()
     }
 
-    pub fn get_background_color(&mut self) -> PpuPaletteColor {
-        self.bus.ppu().ppu_mem_map.palette.get_transparent_color()
+    pub fn has_battery(&mut self) -> bool {
+        self.bus.mem_map().mapper.battery_data().is_some()
     }
 
-    pub fn step(&mut self, tracer: &mut Tracer) {
-        tracer.start_new_trace();
+    // Battery backed memory of the loaded cart, to be persisted by the frontend
+    pub fn battery_data(&mut self) -> Option<Vec<u8>> {
+        self.bus
+            .mem_map()
+            .mapper
+            .battery_data()
+            .map(|data| data.into_owned())
+    }
 
-        let current_cycle_count = self.bus.cpu().cycle_count;
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        self.bus.mem_map().mapper.load_battery_data(data)
+    }
+
+    // Title stored in the ROM file, for formats that have one
+    pub fn rom_title(&mut self) -> Option<String> {
+        self.bus.mem_map().rom().title.clone()
+    }
+
+    // Header of the loaded ROM, after corrections from the game database
+    pub fn rom_header(&mut self) -> Header {
+        self.bus.mem_map().rom().header.clone()
+    }
+
+    // Game database entry of the loaded ROM, None if it isn't in the database
+    pub fn rom_db_entry(&mut self) -> Option<&'static RomDbEntry> {
+        self.bus.mem_map().rom().db_entry
+    }
 
-        let nmi = self.bus.step_ppu(current_cycle_count, tracer);
-        if nmi {
-            self.bus.ppu().clear_nmi();
-            self.bus.nmi();
+    // CRC-32 and SHA-1 of the PRG and CHR ROM, which identify the game in the game database
+    pub fn rom_checksums(&mut self) -> (u32, String) {
+        rom_db::rom_checksums(self.bus.mem_map().rom())
+    }
+
+    // Problems with the loaded ROM that don't prevent it from running
+    pub fn rom_warnings(&mut self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        // Only NES 2.0 headers and the game database know the PPU and protection hardware
+        let rom = self.bus.mem_map().rom();
+        if rom.header.is_vs_unisystem
+            && matches!(rom.header.header_type, HeaderType::Standard)
+            && rom.db_entry.is_none()
+            && rom.vs_game.is_none()
+        {
+            warnings.push(
+                "Vs. UniSystem game with an iNES header and no database entry, its PPU and \
+                 protection hardware may be wrong"
+                    .to_string(),
+            );
         }
+        warnings
+    }
+
+    // Patch that was applied to the ROM when it was loaded
+    pub fn patch_path(&mut self) -> Option<PathBuf> {
+        self.bus.mem_map().rom().patch_path.clone()
+    }
 
-        let mapper_irq = self.bus.mem_map().mapper.irq_pending();
-        let apu_irq = self.bus.step_apu(current_cycle_count);
-        let irq = apu_irq || mapper_irq;
-        if irq && !nmi {
-            self.bus.irq();
+    // Number of disk sides of the loaded Famicom Disk System image, None for cartridges
+    pub fn fds_disk_side_count(&mut self) -> Option<usize> {
+        self.bus
+            .mem_map()
+            .mapper
+            .fds()
+            .map(|fds| fds.disk_side_count())
+    }
+
+    // Inserted disk side, None if the drive is empty or the loaded ROM is a cartridge
+    pub fn fds_disk_side(&mut self) -> Option<usize> {
+        self.bus
+            .mem_map()
+            .mapper
+            .fds()
+            .and_then(|fds| fds.disk_side())
+    }
+
+    // Ejects the current disk side and inserts another one. The drive stays empty for a moment,
+    // so the BIOS notices the swap
+    pub fn fds_insert_disk_side(&mut self, side: usize) {
+        if let Some(fds) = self.bus.mem_map().mapper.fds() {
+            fds.insert_disk_side(side);
+        }
+    }
+
+    pub fn fds_eject_disk(&mut self) {
+        if let Some(fds) = self.bus.mem_map().mapper.fds() {
+            fds.eject_disk();
+        }
+    }
+
+    // Metadata of the loaded NSF tune, None for cartridges
+    pub fn nsf(&mut self) -> Option<&Nsf> {
+        self.bus
+            .mem_map()
+            .mapper
+            .nsf_player()
+            .map(|nsf_player| nsf_player.nsf())
+    }
+
+    // Song that is playing, 0 based
+    pub fn nsf_song(&mut self) -> Option<usize> {
+        self.bus
+            .mem_map()
+            .mapper
+            .nsf_player()
+            .map(|nsf_player| nsf_player.song())
+    }
+
+    // Restarts playback with another song
+    pub fn nsf_select_song(&mut self, song: usize) {
+        if let Some(nsf_player) = self.bus.mem_map().mapper.nsf_player() {
+            nsf_player.select_song(song);
+            self.hard_reset();
+        }
+    }
+
+    // Whether the loaded ROM is a Vs. UniSystem arcade game, with coin slots and DIP switches
+    pub fn is_vs_system(&mut self) -> bool {
+        self.bus.mem_map().vs_system.is_some()
+    }
+
+    // DIP switches 1 to 8 in bits 0 to 7, 0 for other games
+    pub fn vs_dip_switches(&mut self) -> u8 {
+        self.bus
+            .mem_map()
+            .vs_system
+            .as_ref()
+            .map_or(0, |vs_system| vs_system.dip_switches())
+    }
+
+    // Games read the DIP switches at boot, so changes usually take effect after a reset
+    pub fn vs_set_dip_switches(&mut self, dip_switches: u8) {
+        if let Some(vs_system) = &mut self.bus.mem_map().vs_system {
+            vs_system.set_dip_switches(dip_switches);
+        }
+    }
+
+    // Slot 0 or 1. Games count a coin when it's released, so hold it for a few frames
+    pub fn vs_set_coin_inserted(&mut self, slot: usize, is_inserted: bool) {
+        if let Some(vs_system) = &mut self.bus.mem_map().vs_system {
+            vs_system.set_coin_inserted(slot, is_inserted);
         }
+    }
 
-        let dma = self.bus.dma().is_dma_active();
-        if dma {
-            self.bus.step_dma();
-            self.bus.cpu().dma();
+    pub fn vs_set_service_button(&mut self, is_pressed: bool) {
+        if let Some(vs_system) = &mut self.bus.mem_map().vs_system {
+            vs_system.set_service_button_pressed(is_pressed);
+        }
+    }
+
+    // Last value the game wrote to the coin counter register at $4020, 0 for other games
+    pub fn vs_coin_counter(&mut self) -> u8 {
+        self.bus
+            .mem_map()
+            .vs_system
+            .as_ref()
+            .map_or(0, |vs_system| vs_system.coin_counter())
+    }
+
+    // Coins counted by the coin counter since the game was loaded
+    pub fn vs_coin_count(&mut self) -> u32 {
+        self.bus
+            .mem_map()
+            .vs_system
+            .as_ref()
+            .map_or(0, |vs_system| vs_system.coin_count())
+    }
+
+    pub fn get_background_color(&mut self) -> PpuPaletteColor {
+        self.bus.ppu().ppu_mem_map.palette.get_transparent_color()
+    }
+
+    // PPU viewers, see ppu/viewer.rs. Outputs are NAMETABLES_VIEW_WIDTH * NAMETABLES_VIEW_HEIGHT
+    // colors and so on
+    pub fn render_nametables_view(&mut self, output: &mut [PpuPaletteColor]) {
+        self.peek_ppu(|ppu| ppu.render_nametables_view(output))
+    }
+
+    // Palettes 0-3 are the background palettes, 4-7 the sprite palettes
+    pub fn render_pattern_tables_view(
+        &mut self,
+        palette_index: u8,
+        output: &mut [PpuPaletteColor],
+    ) {
+        self.peek_ppu(|ppu| ppu.render_pattern_tables_view(palette_index, output))
+    }
+
+    pub fn render_oam_view(&mut self, output: &mut [PpuPaletteColor]) {
+        self.peek_ppu(|ppu| ppu.render_oam_view(output))
+    }
+
+    pub fn oam_sprites(&mut self) -> [OamSprite; 64] {
+        self.bus.ppu().oam_sprites()
+    }
+
+    pub fn palette_view(&mut self) -> [PpuPaletteColor; 32] {
+        self.bus.ppu().palette_view()
+    }
+
+    pub fn ppu_event_recording(&mut self) -> PpuEventRecording {
+        self.bus.ppu().event_recorder.recording()
+    }
+
+    // Starts or stops recording PPU events, clearing the logs
+    pub fn set_ppu_event_recording(&mut self, recording: PpuEventRecording) {
+        self.bus.ppu().event_recorder.set_recording(recording)
+    }
+
+    // PPU events of the last complete frame
+    pub fn ppu_event_log(&mut self) -> &PpuEventLog {
+        self.bus.ppu().event_recorder.last_log()
+    }
+
+    fn peek_ppu<T>(&mut self, peek: impl FnOnce(&mut Ppu) -> T) -> T {
+        let mem_map = self.bus.mem_map();
+        mem_map.set_is_mutating_read(false);
+        let result = peek(&mut mem_map.ppu);
+        mem_map.set_is_mutating_read(true);
+        result
+    }
+
+    pub fn step(&mut self, tracer: &mut Tracer) {
+        tracer.start_new_trace();
+
+        if tracer.is_enabled() {
+            tracer.add_ppu_trace(self.bus.ppu());
         }
 
         if let Some(debugger) = self.bus.debugger() {
@@ -311,15 +561,7 @@ impl Core {
         let result = self.bus.step_cpu(tracer);
 
         match result {
-            Ok(_) => {
-                if self.bus.ppu().should_suppress_nmi() {
-                    self.bus.cpu().suppress_interrupt();
-                } else if self.bus.ppu().nmi_pending {
-                    // Needs PPU to track it's own cycles in order to be more accurate
-                    self.bus.ppu().clear_nmi();
-                    self.bus.nmi();
-                }
-            }
+            Ok(_) => (),
             Err(error) => match error {
                 EmulationError::DebuggerBreakpoint(_addr)
                 | EmulationError::DebuggerWatchpoint(_addr) => {
@@ -0,0 +1,137 @@
+// Nintendo MMC2/MMC4 CHR latches
+//
+// Each 4 KB pattern table half has two CHR bank registers and a latch selecting between them.
+// The latch is flipped by the PPU itself: fetching tile $FD or $FE from a pattern table half
+// selects the $FD or $FE bank for that half, starting with the next fetch.
+//
+//     PPU $0FD8: latch 0 = $FD      PPU $1FD8-$1FDF: latch 1 = $FD
+//     PPU $0FE8: latch 0 = $FE      PPU $1FE8-$1FEF: latch 1 = $FE
+//
+// MMC4 decodes latch 0 the same way as latch 1 ($0FD8-$0FDF and $0FE8-$0FEF),
+// while MMC2 only reacts to the first row of the tile.
+
+const CHR_BANK_SIZE: usize = 0x1000; // 4 KB
+
+#[derive(Clone, Copy, PartialEq)]
+enum Latch {
+    FD,
+    FE,
+}
+
+#[derive(Clone)]
+pub struct ChrLatch {
+    // [$0000 FD, $0000 FE, $1000 FD, $1000 FE]
+    chr_banks: [u8; 4],
+    latches: [Latch; 2],
+    is_latch_0_row_decoded: bool,
+}
+
+impl ChrLatch {
+    pub fn new(is_latch_0_row_decoded: bool) -> ChrLatch {
+        ChrLatch {
+            chr_banks: [0; 4],
+            latches: [Latch::FE; 2],
+            is_latch_0_row_decoded,
+        }
+    }
+
+    pub fn write_chr_bank(&mut self, register: usize, byte: u8) {
+        self.chr_banks[register] = byte & 0x1F;
+    }
+
+    pub fn get_chr_index(&self, index: u16, chr_len: usize) -> usize {
+        let half = (index as usize >> 12) & 1;
+        let register = half * 2
+            + match self.latches[half] {
+                Latch::FD => 0,
+                Latch::FE => 1,
+            };
+
+        let chr_banks = chr_len / CHR_BANK_SIZE;
+        let bank_index = self.chr_banks[register] as usize % chr_banks;
+        bank_index * CHR_BANK_SIZE + (index as usize & 0xFFF)
+    }
+
+    pub fn observe_pattern_fetch(&mut self, addr: u16) {
+        if addr > 0x1FFF {
+            return;
+        }
+
+        let half = (addr as usize >> 12) & 1;
+        let row_mask = if half == 0 && !self.is_latch_0_row_decoded {
+            0xFFF
+        } else {
+            0xFF8
+        };
+
+        match addr & row_mask {
+            0xFD8 => self.latches[half] = Latch::FD,
+            0xFE8 => self.latches[half] = Latch::FE,
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHR_LEN: usize = 4 * CHR_BANK_SIZE;
+
+    fn chr_latch(is_latch_0_row_decoded: bool) -> ChrLatch {
+        let mut chr_latch = ChrLatch::new(is_latch_0_row_decoded);
+        for register in 0..4 {
+            chr_latch.write_chr_bank(register, register as u8);
+        }
+        chr_latch
+    }
+
+    fn bank(chr_latch: &ChrLatch, index: u16) -> usize {
+        chr_latch.get_chr_index(index, CHR_LEN) / CHR_BANK_SIZE
+    }
+
+    #[test]
+    fn latches_select_banks_per_half() {
+        let mut chr_latch = chr_latch(true);
+        assert_eq!((bank(&chr_latch, 0x0000), bank(&chr_latch, 0x1000)), (1, 3));
+
+        chr_latch.observe_pattern_fetch(0x0FD8);
+        assert_eq!((bank(&chr_latch, 0x0000), bank(&chr_latch, 0x1000)), (0, 3));
+        chr_latch.observe_pattern_fetch(0x1FDF);
+        assert_eq!((bank(&chr_latch, 0x0000), bank(&chr_latch, 0x1000)), (0, 2));
+        chr_latch.observe_pattern_fetch(0x1FE8);
+        assert_eq!((bank(&chr_latch, 0x0000), bank(&chr_latch, 0x1000)), (0, 3));
+
+        // Other tiles, the low plane and nametable fetches don't touch the latches
+        for &addr in &[0x0FC8, 0x0FD0, 0x0FE0, 0x1FF8, 0x2FD8] {
+            chr_latch.observe_pattern_fetch(addr);
+        }
+        assert_eq!((bank(&chr_latch, 0x0000), bank(&chr_latch, 0x1000)), (0, 3));
+    }
+
+    #[test]
+    fn mmc2_latch_0_only_reacts_to_the_first_row() {
+        let mut chr_latch = chr_latch(false);
+        chr_latch.observe_pattern_fetch(0x0FDB);
+        assert_eq!(bank(&chr_latch, 0x0000), 1);
+        chr_latch.observe_pattern_fetch(0x0FD8);
+        assert_eq!(bank(&chr_latch, 0x0000), 0);
+        chr_latch.observe_pattern_fetch(0x0FEF);
+        assert_eq!(bank(&chr_latch, 0x0000), 0);
+        chr_latch.observe_pattern_fetch(0x0FE8);
+        assert_eq!(bank(&chr_latch, 0x0000), 1);
+
+        // Latch 1 reacts to every row
+        chr_latch.observe_pattern_fetch(0x1FDB);
+        assert_eq!(bank(&chr_latch, 0x1000), 2);
+    }
+
+    #[test]
+    fn mmc4_latch_0_reacts_to_every_row() {
+        let mut chr_latch = chr_latch(true);
+        chr_latch.observe_pattern_fetch(0x0FDB);
+        assert_eq!(bank(&chr_latch, 0x0000), 0);
+        chr_latch.observe_pattern_fetch(0x0FEF);
+        assert_eq!(bank(&chr_latch, 0x0000), 1);
+    }
+}
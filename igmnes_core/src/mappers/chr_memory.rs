@@ -0,0 +1,132 @@
+use crate::rom::Rom;
+
+// CHR memory of a cart: CHR ROM, CHR RAM, or both on boards like TQROM
+//
+// Mappers translate PPU addresses into an offset within the CHR memory through their banking
+// registers. Offsets wrap around the memory size, like unconnected upper address lines would.
+// The CHR memory used for regular reads and writes is CHR ROM if the cart has any, CHR RAM
+// otherwise. Boards with both access CHR RAM explicitly through the `*_ram` methods.
+
+const DEFAULT_CHR_RAM_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct ChrMemory {
+    rom_bytes: Vec<u8>,
+    ram_bytes: Vec<u8>,
+}
+
+impl ChrMemory {
+    // CHR ROM from the cart, plus CHR RAM if the header asks for it
+    // or an 8 KB CHR RAM if the cart has no CHR ROM
+    pub fn new(rom: &Rom) -> ChrMemory {
+        ChrMemory::with_ram_size(rom, DEFAULT_CHR_RAM_SIZE)
+    }
+
+    // Same as `new`, for boards that come with a different amount of CHR RAM
+    pub fn with_ram_size(rom: &Rom, default_ram_size: usize) -> ChrMemory {
+        let ram_size = match rom.header.chr_ram_size {
+            0 if rom.chr_rom_bytes.is_empty() => default_ram_size,
+            ram_size => ram_size,
+        };
+
+        ChrMemory {
+            rom_bytes: rom.chr_rom_bytes.clone(),
+            ram_bytes: vec![0; ram_size],
+        }
+    }
+
+    // Boards that carry CHR RAM alongside CHR ROM, even if the header doesn't specify it
+    pub fn with_rom_and_ram(rom: &Rom, default_ram_size: usize) -> ChrMemory {
+        let ram_size = match rom.header.chr_ram_size {
+            0 => default_ram_size,
+            ram_size => ram_size,
+        };
+
+        ChrMemory {
+            rom_bytes: rom.chr_rom_bytes.clone(),
+            ram_bytes: vec![0; ram_size],
+        }
+    }
+
+    #[inline]
+    pub fn is_ram(&self) -> bool {
+        self.rom_bytes.is_empty()
+    }
+
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        if self.is_ram() {
+            &self.ram_bytes
+        } else {
+            &self.rom_bytes
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.bytes().len()
+    }
+
+    #[inline]
+    pub fn read(&self, offset: usize) -> u8 {
+        let bytes = self.bytes();
+        bytes[offset % bytes.len()]
+    }
+
+    // A range that runs past the end of the memory is cut off there, the rest of it wraps
+    // around to the start and has to be read separately
+    #[inline]
+    pub fn read_range(&self, offset: usize, len: usize) -> &[u8] {
+        let bytes = self.bytes();
+        let start = offset % bytes.len();
+        &bytes[start..(start + len).min(bytes.len())]
+    }
+
+    // Writes to CHR ROM are ignored
+    #[inline]
+    pub fn write(&mut self, offset: usize, byte: u8) {
+        if self.is_ram() {
+            self.write_ram(offset, byte);
+        }
+    }
+
+    #[inline]
+    pub fn read_ram(&self, offset: usize) -> u8 {
+        self.ram_bytes[offset % self.ram_bytes.len()]
+    }
+
+    #[inline]
+    pub fn read_ram_range(&self, offset: usize, len: usize) -> &[u8] {
+        let start = offset % self.ram_bytes.len();
+        &self.ram_bytes[start..(start + len).min(self.ram_bytes.len())]
+    }
+
+    #[inline]
+    pub fn write_ram(&mut self, offset: usize, byte: u8) {
+        let len = self.ram_bytes.len();
+        self.ram_bytes[offset % len] = byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::test_rom;
+
+    #[test]
+    fn range_is_cut_off_at_the_end_of_chr_ram() {
+        let mut rom = test_rom(0, 0x4000, 1, 0, 0);
+        rom.header.chr_ram_size = 0x800;
+        let mut chr = ChrMemory::new(&rom);
+        for offset in 0..0x800 {
+            chr.write(offset, offset as u8);
+        }
+
+        assert_eq!(chr.read_range(0x7F0, 16), &chr.ram_bytes[0x7F0..0x800]);
+        assert_eq!(chr.read_range(0x7F8, 16), &chr.ram_bytes[0x7F8..0x800]);
+        assert_eq!(chr.read_ram_range(0xFFC, 16), &chr.ram_bytes[0x7FC..0x800]);
+        // Offsets wrap around like for single reads
+        assert_eq!(chr.read_range(0x1008, 8), &chr.ram_bytes[0x008..0x010]);
+        assert_eq!(chr.read(0x1FFF), 0xFF);
+    }
+}
@@ -0,0 +1,303 @@
+// Famicom Disk System expansion audio, a single wavetable channel with frequency modulation
+//
+// The channel steps through a 64 entry table of 6-bit samples. Its pitch is bent by the
+// modulation unit, which steps through a 64 entry table of 3-bit adjustments to a signed
+// sweep counter. Volume and modulation depth each come from an envelope unit.
+//
+// Registers
+//     $4040-$407F: wavetable (writable only while $4089 bit 7 is set)
+//     $4080: volume envelope        $4084: modulation envelope
+//     $4082: wave frequency low     $4086: modulation frequency low
+//     $4083: wave frequency high    $4087: modulation frequency high
+//     $4085: modulation counter     $4088: modulation table write
+//     $4089: wavetable write enable, master volume
+//     $408A: envelope speed
+//     $4090: volume gain (read)     $4092: modulation gain (read)
+
+// Expansion audio level relative to the APU output, a full volume wave is roughly
+// two and a half times as loud as a single pulse channel
+const AUDIO_GAIN: f32 = 0.36;
+
+// One-pole lowpass around 2 kHz, applied to every CPU cycle's output
+const LOWPASS_ALPHA: f32 = 0.007;
+
+const WAVE_MAX: f32 = 63.0 * 32.0;
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+// Adjustments to the modulation counter, None resets it
+const MOD_ADJUSTMENTS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+const DEFAULT_ENVELOPE_SPEED: u8 = 0xE8;
+
+#[derive(Clone, Default)]
+struct Envelope {
+    // 7  bit  0
+    // ---- ----
+    // MDSS SSSS
+    // |||| ||||
+    // ||++-++++- Envelope speed, or gain when the envelope is disabled
+    // |+-------- Direction (0: decrease, 1: increase)
+    // +--------- Envelope disable
+    is_disabled: bool,
+    is_increasing: bool,
+    speed: u8,
+
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, byte: u8, master_speed: u8) {
+        self.is_disabled = byte & 0x80 != 0;
+        self.is_increasing = byte & 0x40 != 0;
+        self.speed = byte & 0x3F;
+
+        if self.is_disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.is_disabled {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        } else {
+            self.reset_timer(master_speed);
+            if self.is_increasing && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.is_increasing && self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    is_wave_write_enabled: bool,
+    wave_frequency: u16,
+    wave_accumulator: u32,
+    wave_position: usize,
+    is_wave_halted: bool,
+    volume_envelope: Envelope,
+    // Volume is latched at the start of each wave period
+    output_volume: u8,
+    master_volume: usize,
+
+    mod_table: [u8; 64],
+    mod_table_position: usize,
+    mod_frequency: u16,
+    mod_accumulator: u32,
+    mod_counter: i8,
+    is_mod_halted: bool,
+    mod_envelope: Envelope,
+
+    are_envelopes_halted: bool,
+    envelope_speed: u8,
+
+    output: f32,
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave_table: [0; 64],
+            is_wave_write_enabled: false,
+            wave_frequency: 0,
+            wave_accumulator: 0,
+            wave_position: 0,
+            is_wave_halted: true,
+            volume_envelope: Envelope::default(),
+            output_volume: 0,
+            master_volume: 0,
+
+            mod_table: [0; 64],
+            mod_table_position: 0,
+            mod_frequency: 0,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            is_mod_halted: true,
+            mod_envelope: Envelope::default(),
+
+            are_envelopes_halted: false,
+            envelope_speed: DEFAULT_ENVELOPE_SPEED,
+
+            output: 0.0,
+        }
+    }
+
+    pub fn read(&self, index: u16) -> u8 {
+        match index {
+            0x4040..=0x407F => self.wave_table[(index & 0x3F) as usize],
+            0x4090 => self.volume_envelope.gain,
+            0x4092 => self.mod_envelope.gain,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x4040..=0x407F if self.is_wave_write_enabled => {
+                self.wave_table[(index & 0x3F) as usize] = byte & 0x3F;
+            }
+            0x4080 => self.volume_envelope.write(byte, self.envelope_speed),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | byte as u16,
+            0x4083 => {
+                // 7  bit  0
+                // ---- ----
+                // HE.. FFFF
+                // ||   ||||
+                // ||   ++++- Wave frequency high 4 bits
+                // |+-------- Halt envelopes
+                // +--------- Halt wave and reset its phase
+                self.wave_frequency = (self.wave_frequency & 0x00FF) | ((byte as u16 & 0x0F) << 8);
+                self.are_envelopes_halted = byte & 0x40 != 0;
+                self.is_wave_halted = byte & 0x80 != 0;
+
+                if self.is_wave_halted {
+                    self.wave_accumulator = 0;
+                    self.wave_position = 0;
+                }
+                if self.are_envelopes_halted {
+                    self.volume_envelope.reset_timer(self.envelope_speed);
+                    self.mod_envelope.reset_timer(self.envelope_speed);
+                }
+            }
+            0x4084 => self.mod_envelope.write(byte, self.envelope_speed),
+            0x4085 => self.mod_counter = Self::wrap_mod_counter(byte as i16),
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | byte as u16,
+            0x4087 => {
+                // 7  bit  0
+                // ---- ----
+                // H... FFFF
+                // |    ||||
+                // |    ++++- Modulation frequency high 4 bits
+                // +--------- Halt modulation and reset its accumulator
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((byte as u16 & 0x0F) << 8);
+                self.is_mod_halted = byte & 0x80 != 0;
+
+                if self.is_mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // Each write fills two consecutive entries of the table
+            0x4088 if self.is_mod_halted => {
+                for _ in 0..2 {
+                    self.mod_table[self.mod_table_position] = byte & 0b111;
+                    self.mod_table_position = (self.mod_table_position + 1) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.is_wave_write_enabled = byte & 0x80 != 0;
+                self.master_volume = (byte & 0b11) as usize;
+            }
+            0x408A => self.envelope_speed = byte,
+            _ => (),
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.are_envelopes_halted && !self.is_wave_halted && self.envelope_speed != 0 {
+            self.volume_envelope.clock(self.envelope_speed);
+            self.mod_envelope.clock(self.envelope_speed);
+        }
+
+        self.clock_modulation();
+
+        if !self.is_wave_halted && !self.is_wave_write_enabled {
+            self.wave_accumulator += self.get_pitch() as u32;
+            if self.wave_accumulator > 0xFFFF {
+                self.wave_accumulator &= 0xFFFF;
+                self.wave_position = (self.wave_position + 1) & 0x3F;
+
+                if self.wave_position == 0 {
+                    self.output_volume = self.volume_envelope.gain.min(32);
+                }
+            }
+        }
+
+        // The output holds its last value while the wavetable is being written
+        let wave = (self.wave_table[self.wave_position] as f32 * self.output_volume as f32)
+            / WAVE_MAX
+            * MASTER_VOLUME[self.master_volume];
+        self.output += (wave - self.output) * LOWPASS_ALPHA;
+    }
+
+    #[inline]
+    pub fn output(&self) -> f32 {
+        self.output * AUDIO_GAIN
+    }
+
+    fn clock_modulation(&mut self) {
+        if self.is_mod_halted || self.mod_frequency == 0 {
+            return;
+        }
+
+        self.mod_accumulator += self.mod_frequency as u32;
+        if self.mod_accumulator > 0xFFFF {
+            self.mod_accumulator &= 0xFFFF;
+
+            let entry = self.mod_table[self.mod_table_position];
+            self.mod_table_position = (self.mod_table_position + 1) & 0x3F;
+            self.mod_counter = match MOD_ADJUSTMENTS[entry as usize] {
+                Some(adjustment) => {
+                    Self::wrap_mod_counter(self.mod_counter as i16 + adjustment as i16)
+                }
+                None => 0,
+            };
+        }
+    }
+
+    // The modulation counter is a 7-bit signed value
+    fn wrap_mod_counter(value: i16) -> i8 {
+        (((value & 0x7F) << 9) >> 9) as i8
+    }
+
+    // Wave frequency bent by the modulation unit, per the nesdev wiki formula
+    fn get_pitch(&self) -> i32 {
+        let pitch = self.wave_frequency as i32;
+        if self.is_mod_halted {
+            return pitch;
+        }
+
+        let mut temp = self.mod_counter as i32 * self.mod_envelope.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (pitch + temp).max(0)
+    }
+}
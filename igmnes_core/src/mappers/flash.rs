@@ -0,0 +1,98 @@
+// Microchip SST39SF040 (512 KB NOR flash), used by self-flashable homebrew boards
+//
+// The flash is read like a regular ROM. Writes are interpreted as command sequences,
+// with the command addresses decoded from flash address lines A14-A0:
+//
+//     Byte program:      $5555=$AA, $2AAA=$55, $5555=$A0, addr=data
+//     Sector erase (4K): $5555=$AA, $2AAA=$55, $5555=$80, $5555=$AA, $2AAA=$55, sector=$30
+//     Chip erase:        $5555=$AA, $2AAA=$55, $5555=$80, $5555=$AA, $2AAA=$55, $5555=$10
+//     Software ID entry: $5555=$AA, $2AAA=$55, $5555=$90
+//     Software ID exit:  $5555=$AA, $2AAA=$55, $5555=$F0 or any address=$F0
+//
+// Programming can only clear bits, erasing sets every byte of a sector (or the chip) to $FF.
+// Program and erase operations complete instantly, so status polling (toggle bit) always
+// succeeds on the first read.
+
+const SECTOR_SIZE: usize = 0x1000; // 4 KB
+
+const MANUFACTURER_ID: u8 = 0xBF;
+const DEVICE_ID: u8 = 0xB7;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlashState {
+    Read,
+    Unlock1,
+    Unlock2,
+    ByteProgram,
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+#[derive(Clone)]
+pub struct Sst39sf040 {
+    state: FlashState,
+    is_software_id_mode: bool,
+}
+
+impl Sst39sf040 {
+    pub fn new() -> Sst39sf040 {
+        Sst39sf040 {
+            state: FlashState::Read,
+            is_software_id_mode: false,
+        }
+    }
+
+    #[inline]
+    pub fn read(&self, bytes: &[u8], address: usize) -> u8 {
+        if self.is_software_id_mode {
+            match address & 1 {
+                0 => MANUFACTURER_ID,
+                _ => DEVICE_ID,
+            }
+        } else {
+            bytes[address % bytes.len()]
+        }
+    }
+
+    pub fn write(&mut self, bytes: &mut [u8], address: usize, byte: u8) {
+        use FlashState::*;
+
+        let command_address = address & 0x7FFF;
+
+        if byte == 0xF0 && self.state != ByteProgram {
+            self.state = Read;
+            self.is_software_id_mode = false;
+            return;
+        }
+
+        self.state = match (self.state, command_address, byte) {
+            (Read, 0x5555, 0xAA) => Unlock1,
+            (Unlock1, 0x2AAA, 0x55) => Unlock2,
+            (Unlock2, 0x5555, 0xA0) => ByteProgram,
+            (Unlock2, 0x5555, 0x80) => Erase,
+            (Unlock2, 0x5555, 0x90) => {
+                self.is_software_id_mode = true;
+                Read
+            }
+            (ByteProgram, _, _) => {
+                let index = address % bytes.len();
+                bytes[index] &= byte;
+                Read
+            }
+            (Erase, 0x5555, 0xAA) => EraseUnlock1,
+            (EraseUnlock1, 0x2AAA, 0x55) => EraseUnlock2,
+            (EraseUnlock2, _, 0x30) => {
+                let sector_start = (address % bytes.len()) & !(SECTOR_SIZE - 1);
+                let sector_end = (sector_start + SECTOR_SIZE).min(bytes.len());
+                bytes[sector_start..sector_end].fill(0xFF);
+                Read
+            }
+            (EraseUnlock2, 0x5555, 0x10) => {
+                bytes.fill(0xFF);
+                Read
+            }
+            _ => Read,
+        };
+    }
+}
@@ -1,31 +1,32 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
-use crate::rom::{MirroringMode, Rom};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
 use std::ops::Range;
 
 #[derive(Clone)]
 pub struct NRom {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
     prg_ram_bytes: Vec<u8>,
-    mirroring_mode: MirroringMode,
 }
 
 impl NRom {
     pub fn new(rom: &Rom) -> NRom {
         let prg_rom_bytes = rom.prg_rom_bytes.clone(); // TODO use references!
-        let chr_rom_bytes = rom.chr_rom_bytes.clone();
 
         let prg_ram_size = rom.header.prg_ram_size;
         let prg_ram_bytes: Vec<u8> = vec![0; prg_ram_size as usize];
 
         NRom {
-            vram: Ram::default(),
+            nametables: Nametables::new(rom),
             prg_rom_bytes,
-            chr_rom_bytes,
+            chr: ChrMemory::new(rom),
             prg_ram_bytes,
-            mirroring_mode: rom.header.mirroring_mode,
         }
     }
 
@@ -44,7 +45,8 @@ impl NRom {
     fn get_prg_ram_index(&self, index: u16) -> usize {
         // CPU memory map maps the cart address space from 0x4020 to 0xFFFF
         // NROM starts mapping RAM at 0x6000, so there's nothing mapped between 0x4020 and 0x6000
-        (index - 0x6000) as usize
+        // Smaller RAM (e.g. Family BASIC's 2 KiB) is mirrored across the window
+        (index - 0x6000) as usize % self.prg_ram_bytes.len()
     }
 }
 
@@ -52,6 +54,15 @@ impl Mapper for NRom {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = NRom::new(rom);
     }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| index as usize,
+        )
+    }
 }
 
 impl CpuMapper for NRom {
@@ -63,79 +74,60 @@ impl CpuMapper for NRom {
 
     #[inline(always)]
     fn read_prg_ram(&self, index: u16) -> u8 {
+        if self.prg_ram_bytes.is_empty() {
+            return 0;
+        }
         let index: usize = self.get_prg_ram_index(index);
         self.prg_ram_bytes[index]
     }
 
     #[inline(always)]
     fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        if self.prg_ram_bytes.is_empty() {
+            return;
+        }
         let index: usize = self.get_prg_ram_index(index);
         self.prg_ram_bytes[index] = byte;
     }
-}
 
-impl PpuMapper for NRom {
-    #[inline(always)]
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        if self.chr_rom_bytes.is_empty() {
-            0
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.prg_ram_bytes.is_empty() {
+            None
         } else {
-            self.chr_rom_bytes[index as usize]
+            Some(&mut self.prg_ram_bytes)
         }
     }
+}
 
+impl PpuMapper for NRom {
     #[inline(always)]
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        if self.chr_rom_bytes.len() == 0 {
-            // Mainly for test roms that don't contain CHR
-            &[]
-        } else {
-            &self.chr_rom_bytes[range.start as usize..range.end as usize]
-        }
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
     }
 
-    fn read_chr_ram(&self, index: u16) -> u8 {
-        panic!(
-            "Attempted read from non-existent CHR RAM index (untranslated): 0x{:X}",
-            index
-        )
-    }
-
-    fn read_chr_ram_range(&self, range: Range<u16>) -> &[u8] {
-        panic!(
-            "Attempted read from non-existent CHR RAM range (untranslated): 0x{:?}",
-            range
-        )
-    }
-
-    fn write_chr_ram(&mut self, index: u16, _byte: u8) {
-        panic!(
-            "Attempted read from non-existent CHR RAM index (untranslated): 0x{:X}",
-            index
-        )
+    #[inline(always)]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        self.chr.read_range(range.start as usize, range.len())
     }
 
     #[inline(always)]
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        match self.mirroring_mode {
-            MirroringMode::Horizontal => ((index / 0x800) * 0x400) + (index % 0x400),
-            MirroringMode::Vertical => index % 0x800,
-        }
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
     }
 }
 
 impl MapperIrq for NRom {}
 
+impl MapperAudio for NRom {}
+
+impl MapperBattery for NRom {}
+
 impl MemMapped for NRom {
     #[inline(always)]
     fn read(&mut self, index: u16) -> u8 {
         match index {
-            0..=0x1FFF => self.read_chr_rom(index),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
-            }
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x2FFF => self.nametables.read(index),
             0x6000..=0x7FFF => self.read_prg_ram(index),
             0x8000..=0xFFFF => self.read_prg_rom(index),
             _ => {
@@ -148,10 +140,8 @@ impl MemMapped for NRom {
     #[inline(always)]
     fn write(&mut self, index: u16, byte: u8) {
         match index {
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x2FFF => self.nametables.write(index, byte),
             0x6000..=0x7FFF => self.write_prg_ram(index, byte),
             _ => return,
         }
@@ -160,7 +150,7 @@ impl MemMapped for NRom {
     #[inline(always)]
     fn read_range(&mut self, range: Range<u16>) -> &[u8] {
         match range.start {
-            0..=0x1FFF => self.read_chr_rom_range(range),
+            0..=0x1FFF => self.read_chr_range(range),
             _ => unimplemented!(),
         }
     }
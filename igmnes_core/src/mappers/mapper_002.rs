@@ -1,17 +1,21 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
-use crate::rom::{MirroringMode, Rom};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    has_bus_conflicts, resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState,
+    MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
 use std::ops::Range;
 
 const BANK_SIZE_BYTES: usize = 16_384;
-const CHR_RAM_SIZE: usize = 8_192;
 
 #[derive(Clone)]
 pub struct UxROM {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_ram_bytes: Vec<u8>,
-    mirroring_mode: MirroringMode,
+    chr: ChrMemory,
+    has_bus_conflicts: bool,
 
     bank_index: usize,
 }
@@ -19,12 +23,11 @@ pub struct UxROM {
 impl UxROM {
     pub fn new(rom: &Rom) -> UxROM {
         let prg_rom_bytes = rom.prg_rom_bytes.clone(); // TODO use references!
-        let chr_ram_bytes: Vec<u8> = vec![0; CHR_RAM_SIZE];
         UxROM {
-            vram: Ram::default(),
+            nametables: Nametables::new(rom),
             prg_rom_bytes,
-            chr_ram_bytes,
-            mirroring_mode: rom.header.mirroring_mode,
+            chr: ChrMemory::new(rom),
+            has_bus_conflicts: has_bus_conflicts(rom, true),
             bank_index: 0,
         }
     }
@@ -46,7 +49,8 @@ impl UxROM {
         }
     }
 
-    fn select_bank(&mut self, byte: u8) {
+    fn select_bank(&mut self, index: u16, byte: u8) {
+        let byte = resolve_bus_conflict(self, self.has_bus_conflicts, index, byte);
         self.bank_index = byte as usize;
     }
 }
@@ -55,6 +59,15 @@ impl Mapper for UxROM {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = UxROM::new(rom);
     }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| index as usize,
+        )
+    }
 }
 
 impl CpuMapper for UxROM {
@@ -71,51 +84,31 @@ impl CpuMapper for UxROM {
 }
 
 impl PpuMapper for UxROM {
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        panic!(
-            "Attempted read from non-existent CHR ROM index (untranslated): 0x{:X}",
-            index
-        )
-    }
-
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        panic!(
-            "Attempted read from non-existent CHR ROM range (untranslated): 0x{:?}",
-            range
-        )
-    }
-
-    fn read_chr_ram(&self, index: u16) -> u8 {
-        self.chr_ram_bytes[index as usize]
-    }
-
-    fn read_chr_ram_range(&self, range: Range<u16>) -> &[u8] {
-        &self.chr_ram_bytes[range.start as usize..range.end as usize]
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
     }
 
-    fn write_chr_ram(&mut self, index: u16, byte: u8) {
-        self.chr_ram_bytes[index as usize] = byte;
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = range.start as usize;
+        self.chr.read_range(start, range.len())
     }
 
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        match self.mirroring_mode {
-            MirroringMode::Horizontal => ((index / 0x800) * 0x400) + (index % 0x400),
-            MirroringMode::Vertical => index % 0x800,
-        }
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
     }
 }
 
 impl MapperIrq for UxROM {}
 
+impl MapperAudio for UxROM {}
+
+impl MapperBattery for UxROM {}
+
 impl MemMapped for UxROM {
     fn read(&mut self, index: u16) -> u8 {
         match index {
-            0..=0x1FFF => self.read_chr_ram(index),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
-            }
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x2FFF => self.nametables.read(index),
             0x8000..=0xFFFF => self.read_prg_rom(index),
             _ => {
                 println!("Attempted read from unmapped address: 0x{:X}", index);
@@ -126,19 +119,16 @@ impl MemMapped for UxROM {
 
     fn write(&mut self, index: u16, byte: u8) {
         match index {
-            0..=0x1FFF => self.write_chr_ram(index, byte),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
-            0x8000..=0xFFFF => self.select_bank(byte),
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x2FFF => self.nametables.write(index, byte),
+            0x8000..=0xFFFF => self.select_bank(index, byte),
             _ => return,
         }
     }
 
     fn read_range(&mut self, range: Range<u16>) -> &[u8] {
         match range.start {
-            0..=0x1FFF => self.read_chr_ram_range(range),
+            0..=0x1FFF => self.read_chr_range(range),
             _ => unimplemented!(),
         }
     }
@@ -1,16 +1,21 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
-use crate::rom::{MirroringMode, Rom};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    has_bus_conflicts, resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState,
+    MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
 use std::ops::Range;
 
 const BANK_SIZE_BYTES: usize = 8_192;
 
 #[derive(Clone)]
 pub struct CNROM {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_rom_bytes: Vec<u8>,
-    mirroring_mode: MirroringMode,
+    chr: ChrMemory,
+    has_bus_conflicts: bool,
 
     bank_index: usize,
 }
@@ -18,12 +23,11 @@ pub struct CNROM {
 impl CNROM {
     pub fn new(rom: &Rom) -> CNROM {
         let prg_rom_bytes = rom.prg_rom_bytes.clone(); // TODO use references!
-        let chr_rom_bytes = rom.chr_rom_bytes.clone();
         CNROM {
-            vram: Ram::default(),
+            nametables: Nametables::new(rom),
             prg_rom_bytes,
-            chr_rom_bytes,
-            mirroring_mode: rom.header.mirroring_mode,
+            chr: ChrMemory::new(rom),
+            has_bus_conflicts: has_bus_conflicts(rom, true),
             bank_index: 0,
         }
     }
@@ -35,7 +39,7 @@ impl CNROM {
         (index - 0x8000) as usize
     }
 
-    fn get_chr_rom_index(&self, index: u16) -> usize {
+    fn get_chr_index(&self, index: u16) -> usize {
         // Banks
         //      CHR capacity: Up to 2048 KiB ROM
         //      CHR bank size: 8 KiB
@@ -43,9 +47,8 @@ impl CNROM {
     }
 
     fn select_bank(&mut self, index: u16, byte: u8) {
-        let byte_in_rom = self.read_prg_rom(index);
-        let resulting_byte = (byte & 0b11) & byte_in_rom;
-        self.bank_index = resulting_byte as usize;
+        let byte = resolve_bus_conflict(self, self.has_bus_conflicts, index, byte);
+        self.bank_index = (byte & 0b11) as usize;
     }
 }
 
@@ -53,6 +56,15 @@ impl Mapper for CNROM {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = CNROM::new(rom);
     }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
 }
 
 impl CpuMapper for CNROM {
@@ -69,52 +81,33 @@ impl CpuMapper for CNROM {
 }
 
 impl PpuMapper for CNROM {
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        let index = self.get_chr_rom_index(index);
-        self.chr_rom_bytes[index]
-    }
-
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        let adjusted_range_start_index = self.get_chr_rom_index(range.start);
-        let adjusted_range = adjusted_range_start_index..adjusted_range_start_index + range.len();
-        &self.chr_rom_bytes[adjusted_range]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
     }
 
-    fn read_chr_ram(&self, index: u16) -> u8 {
-        panic!(
-            "Attempted read from non-existent CHR RAM index (untranslated): 0x{:X}",
-            index
-        )
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
     }
 
-    fn read_chr_ram_range(&self, range: Range<u16>) -> &[u8] {
-        panic!(
-            "Attempted read from non-existent CHR RAM range (untranslated): 0x{:?}",
-            range
-        )
-    }
-
-    fn write_chr_ram(&mut self, _index: u16, _byte: u8) {}
-
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        match self.mirroring_mode {
-            MirroringMode::Horizontal => ((index / 0x800) * 0x400) + (index % 0x400),
-            MirroringMode::Vertical => index % 0x800,
-        }
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
     }
 }
 
 impl MapperIrq for CNROM {}
 
+impl MapperAudio for CNROM {}
+
+impl MapperBattery for CNROM {}
+
 impl MemMapped for CNROM {
     fn read(&mut self, index: u16) -> u8 {
         match index {
-            0..=0x1FFF => self.read_chr_rom(index),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
-            }
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x2FFF => self.nametables.read(index),
             0x8000..=0xFFFF => self.read_prg_rom(index),
             _ => {
                 println!("Attempted read from unmapped address: 0x{:X}", index);
@@ -125,11 +118,8 @@ impl MemMapped for CNROM {
 
     fn write(&mut self, index: u16, byte: u8) {
         match index {
-            0..=0x1FFF => self.write_chr_ram(index, byte),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x2FFF => self.nametables.write(index, byte),
             0x8000..=0xFFFF => self.select_bank(index, byte),
             _ => (),
         }
@@ -137,7 +127,7 @@ impl MemMapped for CNROM {
 
     fn read_range(&mut self, range: Range<u16>) -> &[u8] {
         match range.start {
-            0..=0x1FFF => self.read_chr_rom_range(range),
+            0..=0x1FFF => self.read_chr_range(range),
             _ => unimplemented!(),
         }
     }
@@ -1,19 +1,27 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
 use crate::rom::{MirroringMode, Rom};
+use std::borrow::Cow;
 use std::ops::Range;
 
 // MMC3 Bank Sizes
 const PRG_BANK_SIZE: usize = 0x2000; // 8 KB
 const CHR_BANK_SIZE_1KB: usize = 0x0400; // 1 KB
 const PRG_RAM_SIZE: usize = 0x2000; // 8 KB (if present)
+const TQROM_CHR_RAM_SIZE: usize = 0x2000; // 8 KB
 
 #[derive(Clone)]
 pub struct Mmc3 {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
     prg_ram_bytes: Vec<u8>,
+    is_battery_backed: bool,
+    is_tqrom: bool,
 
     bank_index: u8,
     r: [u8; 8],
@@ -21,8 +29,6 @@ pub struct Mmc3 {
     prg_bank_mode: u8,
     chr_inversion: bool,
 
-    mirroring_mode: MirroringMode,
-
     irq_latch: u8,
     irq_counter: u8,
     irq_enabled: bool,
@@ -42,11 +48,21 @@ impl Mmc3 {
         };
         let prg_ram_bytes: Vec<u8> = vec![0; prg_ram_size as usize];
 
+        // TQROM (mapper 119) carries 8 KB CHR RAM alongside the CHR ROM
+        let is_tqrom = rom.header.mapper_number == 119;
+        let chr = if is_tqrom {
+            ChrMemory::with_rom_and_ram(rom, TQROM_CHR_RAM_SIZE)
+        } else {
+            ChrMemory::new(rom)
+        };
+
         Mmc3 {
-            vram: Ram::default(),
+            nametables: Nametables::new(rom),
             prg_rom_bytes: rom.prg_rom_bytes.clone(),
-            chr_rom_bytes: rom.chr_rom_bytes.clone(),
+            chr,
             prg_ram_bytes,
+            is_battery_backed: rom.header.sram_present,
+            is_tqrom,
 
             bank_index: 0,
             r: [0; 8],
@@ -54,8 +70,6 @@ impl Mmc3 {
             prg_bank_mode: 0,
             chr_inversion: false,
 
-            mirroring_mode: MirroringMode::Vertical,
-
             irq_latch: 0,
             irq_counter: 0,
             irq_enabled: false,
@@ -101,9 +115,9 @@ impl Mmc3 {
         (index - 0x6000) as usize % self.prg_ram_bytes.len()
     }
 
-    fn get_chr_rom_index(&self, index: u16) -> usize {
+    // Returns the selected 1 KB bank and the offset within it
+    fn get_chr_bank(&self, index: u16) -> (usize, usize) {
         let addr = index as usize;
-        let chr_banks = self.chr_rom_bytes.len() / CHR_BANK_SIZE_1KB;
 
         let (bank_register_value, addr_offset_mask) = if self.chr_inversion {
             match addr {
@@ -139,10 +153,27 @@ impl Mmc3 {
             bank_register_value as usize // Full 8 bits used for 1KB bank
         };
 
-        let bank_start_offset = (bank_index % chr_banks) * CHR_BANK_SIZE_1KB;
-        let addr_offset = addr & addr_offset_mask;
+        // The upper half of a 2KB bank is the next 1KB bank
+        let bank_index = bank_index | ((addr & addr_offset_mask) >> 10);
+        let addr_offset = addr & 0x03FF;
 
-        (bank_start_offset + addr_offset) % self.chr_rom_bytes.len()
+        (bank_index, addr_offset)
+    }
+
+    // TQROM selects CHR RAM instead of CHR ROM with bit 6 of the bank number
+    #[inline(always)]
+    fn is_chr_ram_bank(&self, bank_index: usize) -> bool {
+        self.is_tqrom && bank_index & 0x40 != 0
+    }
+
+    fn get_chr_index(&self, bank_index: usize, addr_offset: usize) -> usize {
+        if self.is_chr_ram_bank(bank_index) {
+            // CHR RAM offsets wrap around its size, leaving only the low bank bits
+            bank_index * CHR_BANK_SIZE_1KB + addr_offset
+        } else {
+            let chr_banks = self.chr.size() / CHR_BANK_SIZE_1KB;
+            (bank_index % chr_banks) * CHR_BANK_SIZE_1KB + addr_offset
+        }
     }
 }
 
@@ -150,6 +181,18 @@ impl Mapper for Mmc3 {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = Mmc3::new(rom);
     }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| {
+                let (bank_index, addr_offset) = self.get_chr_bank(index);
+                self.get_chr_index(bank_index, addr_offset)
+            },
+        )
+    }
 }
 
 impl CpuMapper for Mmc3 {
@@ -170,41 +213,44 @@ impl CpuMapper for Mmc3 {
         let index = self.get_prg_ram_index(index);
         self.prg_ram_bytes[index] = byte;
     }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram_bytes)
+    }
 }
 
 impl PpuMapper for Mmc3 {
     #[inline(always)]
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        let index = self.get_chr_rom_index(index);
-        self.chr_rom_bytes[index]
+    fn read_chr(&self, index: u16) -> u8 {
+        let (bank_index, addr_offset) = self.get_chr_bank(index);
+        let index = self.get_chr_index(bank_index, addr_offset);
+        if self.is_chr_ram_bank(bank_index) {
+            self.chr.read_ram(index)
+        } else {
+            self.chr.read(index)
+        }
     }
 
     #[inline(always)]
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        // panic!("MMC3 range reads must check bank boundaries!");
-        let start_physical_index = self.get_chr_rom_index(range.start);
-        let len = (range.end - range.start) as usize;
-        let end_physical_index = start_physical_index + len;
-
-        &self.chr_rom_bytes[start_physical_index..end_physical_index]
-    }
-
-    fn read_chr_ram(&self, _index: u16) -> u8 {
-        panic!("MMC3 does not support CHR RAM at this level.")
-    }
-    fn read_chr_ram_range(&self, _range: Range<u16>) -> &[u8] {
-        panic!("MMC3 does not support CHR RAM at this level.")
-    }
-    fn write_chr_ram(&mut self, _index: u16, _byte: u8) {
-        panic!("MMC3 does not support CHR RAM at this level.")
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        // Range reads don't cross 1 KB bank boundaries
+        let (bank_index, addr_offset) = self.get_chr_bank(range.start);
+        let start = self.get_chr_index(bank_index, addr_offset);
+        if self.is_chr_ram_bank(bank_index) {
+            self.chr.read_ram_range(start, range.len())
+        } else {
+            self.chr.read_range(start, range.len())
+        }
     }
 
     #[inline(always)]
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        match self.mirroring_mode {
-            MirroringMode::Horizontal => ((index / 0x800) * 0x400) + (index % 0x400),
-            MirroringMode::Vertical => index & 0x7FF,
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let (bank_index, addr_offset) = self.get_chr_bank(index);
+        let index = self.get_chr_index(bank_index, addr_offset);
+        if self.is_chr_ram_bank(bank_index) {
+            self.chr.write_ram(index, byte)
+        } else {
+            self.chr.write(index, byte)
         }
     }
 }
@@ -224,6 +270,24 @@ impl MapperIrq for Mmc3 {
     }
 }
 
+impl MapperAudio for Mmc3 {}
+
+impl MapperBattery for Mmc3 {
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        if self.is_battery_backed {
+            Some(Cow::Borrowed(&self.prg_ram_bytes))
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_data(&mut self, data: &[u8]) {
+        if self.is_battery_backed && data.len() == self.prg_ram_bytes.len() {
+            self.prg_ram_bytes.copy_from_slice(data);
+        }
+    }
+}
+
 impl Mmc3 {
     fn irq_tick(&mut self) {
         if self.irq_counter == 0 || self.irq_reload {
@@ -244,12 +308,9 @@ impl MemMapped for Mmc3 {
         match index {
             0x0000..=0x1FFF => {
                 self.clock_irq(index);
-                self.read_chr_rom(index)
-            }
-            0x2000..=0x3FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
+                self.read_chr(index)
             }
+            0x2000..=0x3FFF => self.nametables.read(index),
             0x6000..=0x7FFF => self.read_prg_ram(index),
             // MMC3 registers are write-only from 0x8000-0xFFFF, so this is PRG ROM read
             0x8000..=0xFFFF => self.read_prg_rom(index),
@@ -260,11 +321,8 @@ impl MemMapped for Mmc3 {
     #[inline(always)]
     fn write(&mut self, index: u16, byte: u8) {
         match index {
-            0x0000..=0x1FFF => return,
-            0x2000..=0x3FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
+            0x0000..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
             0x6000..=0x7FFF => self.write_prg_ram(index, byte),
 
             0x8000..=0xFFFF => {
@@ -278,11 +336,11 @@ impl MemMapped for Mmc3 {
                         self.r[self.bank_index as usize] = byte;
                     }
                     0xA000..=0xBFFF if index % 2 == 0 => {
-                        self.mirroring_mode = match byte & 1 {
+                        self.nametables.set_mirroring(match byte & 1 {
                             1 => MirroringMode::Horizontal,
                             0 => MirroringMode::Vertical,
                             _ => unreachable!(),
-                        }
+                        });
                     }
                     0xA001 | 0xA003 | 0xA005 | 0xA007 => {
                         // Note: MMC3 games rarely rely on the protection bits (6, 7), focusing only on enable.
@@ -312,13 +370,13 @@ impl MemMapped for Mmc3 {
 
     #[inline(always)]
     fn read_range(&mut self, range: Range<u16>) -> &[u8] {
-        // Only the CHR ROM range (0x0000-0x1FFF) is currently supported for range reads.
+        // Only the CHR range (0x0000-0x1FFF) is currently supported for range reads.
         match range.start {
             0x0000..=0x1FFF => {
                 for addr in range.start..range.end {
                     self.clock_irq(addr);
                 }
-                self.read_chr_rom_range(range)
+                self.read_chr_range(range)
             }
             _ => unimplemented!(),
         }
@@ -1,31 +1,35 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
-use crate::rom::Rom;
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    has_bus_conflicts, resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState,
+    MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
 use std::ops::Range;
 
 const BANK_SIZE_BYTES: usize = 32_768;
-const CHR_RAM_SIZE: usize = 8_192;
 
 #[derive(Clone)]
 pub struct AxROM {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_ram_bytes: Vec<u8>,
+    chr: ChrMemory,
+    has_bus_conflicts: bool,
 
     bank_index: usize,
-    nametable_index: usize,
 }
 
 impl AxROM {
     pub fn new(rom: &Rom) -> AxROM {
         let prg_rom_bytes = rom.prg_rom_bytes.clone(); // TODO use references!
-        let chr_ram_bytes: Vec<u8> = vec![0; CHR_RAM_SIZE];
         AxROM {
-            vram: Ram::default(),
+            nametables: Nametables::with_mirroring(MirroringMode::SingleScreenA),
             prg_rom_bytes,
-            chr_ram_bytes,
+            chr: ChrMemory::new(rom),
+            // Most AxROM boards (ANROM, AMROM) prevent bus conflicts, AOROM does not
+            has_bus_conflicts: has_bus_conflicts(rom, false),
             bank_index: 0,
-            nametable_index: 0,
         }
     }
 
@@ -35,9 +39,13 @@ impl AxROM {
         (self.bank_index * BANK_SIZE_BYTES) + (index as usize & 0x7FFF)
     }
 
-    fn select_bank(&mut self, byte: u8) {
+    fn select_bank(&mut self, index: u16, byte: u8) {
+        let byte = resolve_bus_conflict(self, self.has_bus_conflicts, index, byte);
         self.bank_index = (byte & 0b111) as usize;
-        self.nametable_index = ((byte >> 4) & 0x1) as usize;
+        self.nametables.set_mirroring(match (byte >> 4) & 0x1 {
+            0 => MirroringMode::SingleScreenA,
+            _ => MirroringMode::SingleScreenB,
+        });
     }
 }
 
@@ -58,56 +66,43 @@ impl Mapper for AxROM {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = AxROM::new(rom);
     }
-}
 
-impl PpuMapper for AxROM {
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        panic!(
-            "Attempted read from non-existent CHR ROM index (untranslated): 0x{:X}",
-            index
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| index as usize,
         )
     }
+}
 
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        panic!(
-            "Attempted read from non-existent CHR ROM range (untranslated): 0x{:?}",
-            range
-        )
-    }
-
-    fn read_chr_ram(&self, index: u16) -> u8 {
-        self.chr_ram_bytes[index as usize]
-    }
-
-    fn read_chr_ram_range(&self, range: Range<u16>) -> &[u8] {
-        &self.chr_ram_bytes[range.start as usize..range.end as usize]
+impl PpuMapper for AxROM {
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
     }
 
-    fn write_chr_ram(&mut self, index: u16, byte: u8) {
-        self.chr_ram_bytes[index as usize] = byte;
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = range.start as usize;
+        self.chr.read_range(start, range.len())
     }
 
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        let index = match self.nametable_index {
-            0 => index % 0x400,
-            1 => index % 0x400 + 0x400,
-            _ => unreachable!(),
-        };
-        index
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
     }
 }
 
 impl MapperIrq for AxROM {}
 
+impl MapperAudio for AxROM {}
+
+impl MapperBattery for AxROM {}
+
 impl MemMapped for AxROM {
     fn read(&mut self, index: u16) -> u8 {
         match index {
-            0..=0x1FFF => self.read_chr_ram(index),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
-            }
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x2FFF => self.nametables.read(index),
             0x8000..=0xFFFF => self.read_prg_rom(index),
             _ => {
                 println!("Attempted read from unmapped address: 0x{:X}", index);
@@ -118,19 +113,16 @@ impl MemMapped for AxROM {
 
     fn write(&mut self, index: u16, byte: u8) {
         match index {
-            0..=0x1FFF => self.write_chr_ram(index, byte),
-            0x2000..=0x2FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
-            0x8000..=0xFFFF => self.select_bank(byte),
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x2FFF => self.nametables.write(index, byte),
+            0x8000..=0xFFFF => self.select_bank(index, byte),
             _ => (),
         }
     }
 
     fn read_range(&mut self, range: Range<u16>) -> &[u8] {
         match range.start {
-            0..=0x1FFF => self.read_chr_ram_range(range),
+            0..=0x1FFF => self.read_chr_range(range),
             _ => unimplemented!(),
         }
     }
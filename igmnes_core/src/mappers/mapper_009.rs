@@ -0,0 +1,162 @@
+use crate::mappers::chr_latch::ChrLatch;
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::ops::Range;
+
+// Nintendo MMC2 (mapper 9), used by Punch-Out!!
+//
+// Banks
+//     CPU $8000-$9FFF: 8 KB switchable PRG ROM bank
+//     CPU $A000-$FFFF: three 8 KB PRG ROM banks, fixed to the last three banks
+//     PPU $0000-$0FFF: two 4 KB switchable CHR ROM banks, selected by latch 0
+//     PPU $1000-$1FFF: two 4 KB switchable CHR ROM banks, selected by latch 1
+
+const PRG_BANK_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct Mmc2 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    prg_bank_index: usize,
+    chr_latch: ChrLatch,
+}
+
+impl Mmc2 {
+    pub fn new(rom: &Rom) -> Mmc2 {
+        Mmc2 {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+
+            prg_bank_index: 0,
+            chr_latch: ChrLatch::new(false),
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = self.prg_rom_bytes.len() / PRG_BANK_SIZE;
+        let bank_index = match index {
+            0x8000..=0x9FFF => self.prg_bank_index % prg_banks,
+            0xA000..=0xBFFF => prg_banks - 3,
+            0xC000..=0xDFFF => prg_banks - 2,
+            0xE000..=0xFFFF => prg_banks - 1,
+            _ => unreachable!(),
+        };
+
+        bank_index * PRG_BANK_SIZE + (index as usize & 0x1FFF)
+    }
+
+    fn write_register(&mut self, index: u16, byte: u8) {
+        match index {
+            0xA000..=0xAFFF => self.prg_bank_index = (byte & 0x0F) as usize,
+            0xB000..=0xBFFF => self.chr_latch.write_chr_bank(0, byte),
+            0xC000..=0xCFFF => self.chr_latch.write_chr_bank(1, byte),
+            0xD000..=0xDFFF => self.chr_latch.write_chr_bank(2, byte),
+            0xE000..=0xEFFF => self.chr_latch.write_chr_bank(3, byte),
+            0xF000..=0xFFFF => {
+                self.nametables.set_mirroring(match byte & 1 {
+                    0 => MirroringMode::Vertical,
+                    1 => MirroringMode::Horizontal,
+                    _ => unreachable!(),
+                });
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Mmc2::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.chr_latch.get_chr_index(index, self.chr.size()),
+        )
+    }
+}
+
+impl CpuMapper for Mmc2 {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Mmc2 {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.chr_latch.get_chr_index(index, self.chr.size());
+        self.chr.read(index)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.chr_latch.get_chr_index(range.start, self.chr.size());
+        self.chr.read_range(start, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.chr_latch.get_chr_index(index, self.chr.size());
+        self.chr.write(index, byte)
+    }
+
+    #[inline]
+    fn observe_pattern_fetch(&mut self, addr: u16) {
+        self.chr_latch.observe_pattern_fetch(addr);
+    }
+}
+
+impl MapperIrq for Mmc2 {}
+
+impl MapperAudio for Mmc2 {}
+
+impl MapperBattery for Mmc2 {}
+
+impl MemMapped for Mmc2 {
+    #[inline]
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0x0000..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x8000..=0xFFFF => self.write_register(index, byte),
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0x0000..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery,
+    MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
+use std::ops::Range;
+
+// CPROM (mapper 13), used by Videomation
+//
+// Banks
+//     CPU $8000-$FFFF: 32 KB PRG ROM, not bankswitched
+//     PPU $0000-$0FFF: 4 KB CHR RAM bank, fixed to the first bank
+//     PPU $1000-$1FFF: 4 KB switchable CHR RAM bank
+//
+// Bank select ($8000-$FFFF), with bus conflicts
+//     7  bit  0
+//     ---- ----
+//     xxxx xxCC
+//            ||
+//            ++- Select 4 KB CHR RAM bank for PPU $1000-$1FFF
+
+const CHR_BANK_SIZE: usize = 0x1000; // 4 KB
+const CHR_RAM_SIZE: usize = 0x4000; // 16 KB
+
+#[derive(Clone)]
+pub struct Cprom {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    chr_bank_index: usize,
+}
+
+impl Cprom {
+    pub fn new(rom: &Rom) -> Cprom {
+        Cprom {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::with_ram_size(rom, CHR_RAM_SIZE),
+
+            chr_bank_index: 0,
+        }
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        let bank_index = match index {
+            0x0000..=0x0FFF => 0,
+            _ => self.chr_bank_index,
+        };
+
+        bank_index * CHR_BANK_SIZE + (index as usize & 0xFFF)
+    }
+
+    fn select_bank(&mut self, index: u16, byte: u8) {
+        let byte = resolve_bus_conflict(self, true, index, byte);
+        self.chr_bank_index = (byte & 0b11) as usize;
+    }
+}
+
+impl Mapper for Cprom {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Cprom::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| index as usize - 0x8000,
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Cprom {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        self.prg_rom_bytes[(index as usize - 0x8000) % self.prg_rom_bytes.len()]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Cprom {
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Cprom {}
+
+impl MapperAudio for Cprom {}
+
+impl MapperBattery for Cprom {}
+
+impl MemMapped for Cprom {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x8000..=0xFFFF => self.select_bank(index, byte),
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::test_rom;
+
+    #[test]
+    fn bank_select_switches_upper_chr_ram_bank() {
+        let mut mapper = Cprom::new(&test_rom(13, 0x8000, 1, 0, 0));
+        for bank in 0..4 {
+            mapper.write(0x8001, bank);
+            mapper.write(0x1000, 0x10 + bank);
+        }
+
+        mapper.write(0x8001, 2);
+        assert_eq!(mapper.read(0x1000), 0x12);
+        // The lower half is fixed to the first bank
+        assert_eq!(mapper.read(0x0000), 0x10);
+        mapper.write(0x8001, 0xFF);
+        assert_eq!(mapper.read(0x1000), 0x13);
+    }
+}
@@ -0,0 +1,553 @@
+use crate::fds_disk::{self, FDS_SIDE_SIZE, LEADING_GAP_SIZE};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::fds_audio::FdsAudio;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{CpuMapper, Mapper, MapperAudio, MapperBattery, MapperIrq, PpuMapper};
+use crate::memory::{MemMapConfig, MemMapped};
+use crate::rom::{MirroringMode, Rom};
+use std::borrow::Cow;
+use std::ops::Range;
+
+// Famicom Disk System (mapper 20)
+//
+// Banks
+//     CPU $4020-$4092: disk drive, timer IRQ and expansion audio registers
+//     CPU $6000-$DFFF: 32 KB PRG RAM, where the BIOS loads the game files
+//     CPU $E000-$FFFF: 8 KB BIOS ROM
+//     PPU $0000-$1FFF: 8 KB CHR RAM
+//
+// The disk drive is a serial device that streams one byte roughly every 150 CPU cycles
+// while the motor is running. Disk sides are kept in the format the drive sees: each block
+// is preceded by a gap of zeros and a start mark, and followed by its CRC. The .fds format
+// leaves these out, so they are added when the image is loaded and taken back out when the
+// disk is saved.
+
+const BIOS_SIZE: usize = 0x2000; // 8 KB
+const PRG_RAM_SIZE: usize = 0x8000; // 32 KB
+
+const CYCLES_PER_BYTE: u32 = 150;
+// Time it takes the head to move back to the start of the disk
+const HEAD_RESET_DELAY: u32 = 50_000;
+// Time the drive stays empty when swapping disk sides, so the BIOS notices the swap
+const DISK_SWAP_DELAY: u32 = 1_000_000;
+
+#[derive(Clone)]
+pub struct Fds {
+    nametables: Nametables,
+    bios_bytes: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram_bytes: Vec<u8>,
+
+    // All disk sides, back to back, with gaps and CRCs
+    disk_bytes: Vec<u8>,
+    disk_side_size: usize,
+    disk_side_count: usize,
+    disk_side: Option<usize>,
+    next_disk_side: Option<usize>,
+    disk_swap_delay: u32,
+
+    // Timer IRQ
+    irq_reload_value: u16,
+    irq_counter: u16,
+    is_irq_repeat_enabled: bool,
+    is_irq_enabled: bool,
+    timer_irq: bool,
+
+    // $4023
+    is_disk_io_enabled: bool,
+    is_sound_io_enabled: bool,
+
+    // $4025
+    //   7  bit  0
+    //   ---- ----
+    //   IS.C MRTM
+    //   || | ||||
+    //   || | |||+- Motor on
+    //   || | ||+-- Transfer reset
+    //   || | |+--- Transfer mode (0: write, 1: read)
+    //   || | +---- Mirroring (0: vertical, 1: horizontal)
+    //   || +------ CRC control (write the CRC of the current block)
+    //   |+-------- Transfer start
+    //   +--------- Disk IRQ enable
+    is_motor_on: bool,
+    is_transfer_reset: bool,
+    is_read_mode: bool,
+    is_crc_control: bool,
+    is_transfer_start: bool,
+    is_disk_irq_enabled: bool,
+
+    read_data: u8,
+    write_data: u8,
+    ext_connector_output: u8,
+
+    // Drive state
+    head_position: usize,
+    head_delay: u32,
+    is_end_of_head: bool,
+    is_scanning: bool,
+    is_gap_ended: bool,
+    is_byte_transferred: bool,
+    was_crc_control: bool,
+    crc: u16,
+    disk_irq: bool,
+
+    audio: Box<FdsAudio>,
+
+    mem_map_config: MemMapConfig,
+}
+
+impl Fds {
+    pub fn new(rom: &Rom) -> Fds {
+        let sides: Vec<Vec<u8>> = rom
+            .disk_sides
+            .iter()
+            .map(|side| fds_disk::fds_to_raw_side(side))
+            .collect();
+        let disk_side_size = sides
+            .iter()
+            .map(|side| side.len())
+            .max()
+            .unwrap_or(0)
+            .max(LEADING_GAP_SIZE + FDS_SIDE_SIZE);
+
+        let mut disk_bytes = Vec::with_capacity(disk_side_size * sides.len());
+        for mut side in sides {
+            side.resize(disk_side_size, 0);
+            disk_bytes.extend_from_slice(&side);
+        }
+
+        Fds {
+            nametables: Nametables::with_mirroring(MirroringMode::Horizontal),
+            bios_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+            prg_ram_bytes: vec![0; PRG_RAM_SIZE],
+
+            disk_bytes,
+            disk_side_size,
+            disk_side_count: rom.disk_sides.len(),
+            disk_side: if rom.disk_sides.is_empty() {
+                None
+            } else {
+                Some(0)
+            },
+            next_disk_side: None,
+            disk_swap_delay: 0,
+
+            irq_reload_value: 0,
+            irq_counter: 0,
+            is_irq_repeat_enabled: false,
+            is_irq_enabled: false,
+            timer_irq: false,
+
+            is_disk_io_enabled: false,
+            is_sound_io_enabled: false,
+
+            is_motor_on: false,
+            is_transfer_reset: false,
+            is_read_mode: true,
+            is_crc_control: false,
+            is_transfer_start: false,
+            is_disk_irq_enabled: false,
+
+            read_data: 0,
+            write_data: 0,
+            ext_connector_output: 0,
+
+            head_position: 0,
+            head_delay: 0,
+            is_end_of_head: true,
+            is_scanning: false,
+            is_gap_ended: false,
+            is_byte_transferred: false,
+            was_crc_control: false,
+            crc: 0,
+            disk_irq: false,
+
+            audio: Box::new(FdsAudio::new()),
+
+            mem_map_config: MemMapConfig::default(),
+        }
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.disk_side_count
+    }
+
+    // Side that is inserted, or about to be inserted if a swap is in progress
+    pub fn disk_side(&self) -> Option<usize> {
+        self.next_disk_side.or(self.disk_side)
+    }
+
+    pub fn insert_disk_side(&mut self, side: usize) {
+        if side < self.disk_side_count {
+            self.disk_side = None;
+            self.next_disk_side = Some(side);
+            self.disk_swap_delay = DISK_SWAP_DELAY;
+        }
+    }
+
+    pub fn eject_disk(&mut self) {
+        self.disk_side = None;
+        self.next_disk_side = None;
+        self.disk_swap_delay = 0;
+    }
+
+    fn get_prg_ram_index(&self, index: u16) -> usize {
+        (index - 0x6000) as usize
+    }
+
+    fn read_register(&mut self, index: u16) -> u8 {
+        match index {
+            0x4030 => {
+                // 7  bit  0
+                // ---- ----
+                // .E.C ..BT
+                //  | |   ||
+                //  | |   |+- Timer IRQ occurred
+                //  | |   +-- Byte transferred
+                //  | +------ CRC error (never reported)
+                //  +-------- End of head
+                let status = self.timer_irq as u8
+                    | (self.is_byte_transferred as u8) << 1
+                    | (self.is_end_of_head as u8) << 6;
+
+                if self.is_mutating_read() {
+                    self.is_byte_transferred = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+                status
+            }
+            0x4031 => {
+                if self.is_mutating_read() {
+                    self.is_byte_transferred = false;
+                    self.disk_irq = false;
+                }
+                self.read_data
+            }
+            0x4032 => {
+                // 7  bit  0
+                // ---- ----
+                // .... .WRI
+                //       |||
+                //       ||+- Disk not inserted
+                //       |+-- Disk not ready
+                //       +--- Disk write protected
+                let is_inserted = self.disk_side.is_some();
+                !is_inserted as u8
+                    | ((!is_inserted || !self.is_scanning) as u8) << 1
+                    | (!is_inserted as u8) << 2
+            }
+            // Bit 7 is the battery status, which reads good while the BIOS drives it high
+            0x4033 => self.ext_connector_output & 0x80,
+            0x4040..=0x4092 => self.audio.read(index),
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, index: u16, byte: u8) {
+        match index {
+            0x4020 => self.irq_reload_value = (self.irq_reload_value & 0xFF00) | byte as u16,
+            0x4021 => {
+                self.irq_reload_value = (self.irq_reload_value & 0x00FF) | ((byte as u16) << 8)
+            }
+            0x4022 => {
+                self.is_irq_repeat_enabled = byte & 0x01 != 0;
+                self.is_irq_enabled = byte & 0x02 != 0 && self.is_disk_io_enabled;
+
+                if self.is_irq_enabled {
+                    self.irq_counter = self.irq_reload_value;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.is_disk_io_enabled = byte & 0x01 != 0;
+                self.is_sound_io_enabled = byte & 0x02 != 0;
+
+                if !self.is_disk_io_enabled {
+                    self.is_irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024..=0x4026 if !self.is_disk_io_enabled => (),
+            0x4024 => {
+                self.write_data = byte;
+                self.is_byte_transferred = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.is_motor_on = byte & 0x01 != 0;
+                self.is_transfer_reset = byte & 0x02 != 0;
+                self.is_read_mode = byte & 0x04 != 0;
+                self.nametables.set_mirroring(if byte & 0x08 != 0 {
+                    MirroringMode::Horizontal
+                } else {
+                    MirroringMode::Vertical
+                });
+                self.is_crc_control = byte & 0x10 != 0;
+                self.is_transfer_start = byte & 0x40 != 0;
+                self.is_disk_irq_enabled = byte & 0x80 != 0;
+
+                self.disk_irq = false;
+            }
+            0x4026 => self.ext_connector_output = byte,
+            0x4040..=0x4092 if !self.is_sound_io_enabled => (),
+            0x4040..=0x4092 => self.audio.write(index, byte),
+            _ => (),
+        }
+    }
+
+    fn clock_timer_irq(&mut self) {
+        if !self.is_irq_enabled {
+            return;
+        }
+
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload_value;
+            if !self.is_irq_repeat_enabled {
+                self.is_irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_disk_swap(&mut self) {
+        if self.disk_swap_delay > 0 {
+            self.disk_swap_delay -= 1;
+            if self.disk_swap_delay == 0 {
+                self.disk_side = self.next_disk_side.take();
+            }
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        let disk_side = match self.disk_side {
+            Some(disk_side) if self.is_motor_on => disk_side,
+            _ => {
+                self.is_end_of_head = true;
+                self.is_scanning = false;
+                return;
+            }
+        };
+
+        if self.is_transfer_reset && !self.is_scanning {
+            return;
+        }
+
+        if self.is_end_of_head {
+            self.head_delay = HEAD_RESET_DELAY;
+            self.is_end_of_head = false;
+            self.head_position = 0;
+            self.is_gap_ended = false;
+            return;
+        }
+
+        if self.head_delay > 0 {
+            self.head_delay -= 1;
+            return;
+        }
+
+        self.is_scanning = true;
+
+        let disk_index = disk_side * self.disk_side_size + self.head_position;
+        if self.is_read_mode {
+            let disk_byte = self.disk_bytes[disk_index];
+            let mut should_irq = self.is_disk_irq_enabled;
+
+            if !self.is_transfer_start {
+                self.is_gap_ended = false;
+            } else if disk_byte != 0 && !self.is_gap_ended {
+                // The start mark ends the gap, but isn't reported as data
+                self.is_gap_ended = true;
+                should_irq = false;
+            }
+
+            if self.is_gap_ended {
+                self.is_byte_transferred = true;
+                self.read_data = disk_byte;
+                if should_irq {
+                    self.disk_irq = true;
+                }
+            }
+        } else {
+            if !self.is_crc_control {
+                self.is_byte_transferred = true;
+                if self.is_disk_irq_enabled {
+                    self.disk_irq = true;
+                }
+            }
+
+            let disk_byte = if !self.is_transfer_start {
+                self.crc = 0;
+                0
+            } else if self.is_crc_control {
+                if !self.was_crc_control {
+                    self.crc = fds_disk::update_crc(fds_disk::update_crc(self.crc, 0), 0);
+                }
+                let crc_byte = self.crc as u8;
+                self.crc >>= 8;
+                crc_byte
+            } else {
+                self.crc = fds_disk::update_crc(self.crc, self.write_data);
+                self.write_data
+            };
+
+            self.disk_bytes[disk_index] = disk_byte;
+            self.is_gap_ended = false;
+        }
+        self.was_crc_control = self.is_crc_control;
+
+        self.head_position += 1;
+        if self.head_position >= self.disk_side_size {
+            self.is_motor_on = false;
+        } else {
+            self.head_delay = CYCLES_PER_BYTE;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Fds::new(rom);
+    }
+}
+
+impl CpuMapper for Fds {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        self.bios_bytes[index as usize % BIOS_SIZE]
+    }
+
+    #[inline]
+    fn read_prg_ram(&self, index: u16) -> u8 {
+        let index = self.get_prg_ram_index(index);
+        self.prg_ram_bytes[index]
+    }
+
+    #[inline]
+    fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        let index = self.get_prg_ram_index(index);
+        self.prg_ram_bytes[index] = byte;
+    }
+
+    // The disk and sound registers that can be read, and RAM at $6000-$DFFF
+    fn is_open_bus(&mut self, index: u16) -> bool {
+        match index {
+            0x4030..=0x4033 | 0x4040..=0x407F | 0x4090 | 0x4092 => false,
+            0x4020..=0x5FFF => true,
+            _ => false,
+        }
+    }
+}
+
+impl PpuMapper for Fds {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        self.chr.read_range(range.start as usize, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
+    }
+}
+
+impl MapperIrq for Fds {
+    #[inline]
+    fn clock_cpu_irq(&mut self) {
+        self.clock_timer_irq();
+        self.clock_disk_swap();
+        self.clock_drive();
+    }
+
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+}
+
+impl MapperAudio for Fds {
+    #[inline]
+    fn clock_audio(&mut self) {
+        self.audio.clock();
+    }
+
+    #[inline]
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+// Disk contents are persisted like battery backed memory, so saved games survive. They are
+// saved as a headerless .fds image, which other emulators and tools can open.
+impl MapperBattery for Fds {
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        let fds_bytes = self
+            .disk_bytes
+            .chunks(self.disk_side_size)
+            .flat_map(fds_disk::raw_to_fds_side)
+            .collect();
+        Some(Cow::Owned(fds_bytes))
+    }
+
+    fn load_battery_data(&mut self, data: &[u8]) {
+        if data.len() == self.disk_side_count * FDS_SIDE_SIZE {
+            for (index, side) in data.chunks(FDS_SIDE_SIZE).enumerate() {
+                let mut raw_side = fds_disk::fds_to_raw_side(side);
+                raw_side.resize(self.disk_side_size, 0);
+                let start = index * self.disk_side_size;
+                self.disk_bytes[start..start + self.disk_side_size].copy_from_slice(&raw_side);
+            }
+        }
+    }
+}
+
+impl MemMapped for Fds {
+    #[inline]
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0x0000..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x4020..=0x5FFF => self.read_register(index),
+            0x6000..=0xDFFF => self.read_prg_ram(index),
+            0xE000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x0000..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x4020..=0x5FFF => self.write_register(index, byte),
+            0x6000..=0xDFFF => self.write_prg_ram(index, byte),
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0x0000..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn is_mutating_read(&self) -> bool {
+        self.mem_map_config.is_mutating_read
+    }
+
+    fn set_is_mutating_read(&mut self, is_mutating_read: bool) {
+        self.mem_map_config.is_mutating_read = is_mutating_read;
+    }
+}
@@ -0,0 +1,206 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::ops::Range;
+
+// Action 53 multicart (mapper 28)
+//
+// Banks
+//     CPU $8000-$FFFF: 16 KB PRG ROM banks, combined from an outer (game) and inner bank
+//     PPU $0000-$1FFF: 8 KB switchable CHR RAM bank (of 32 KB)
+//
+// Register select ($5000-$5FFF), register data ($8000-$FFFF)
+//     $00: CHR bank          ...M ..CC  CHR RAM bank, one-screen page (M)
+//     $01: Inner bank        ...M PPPP  Inner PRG bank, one-screen page (M)
+//     $80: Mode              ..SS PPMM  Outer bank size, PRG bank mode, mirroring
+//     $81: Outer bank        PPPP PPPP  Outer 32 KB PRG bank
+//
+// PRG bank modes: 0-1: 32 KB, 2: $8000 fixed to the first bank of the outer bank,
+// 3: $C000 fixed to the last bank of the outer bank.
+// Mirroring: 0: one-screen lower bank, 1: one-screen upper bank, 2: vertical, 3: horizontal.
+// Writes to $00 and $01 also set the one-screen page, if a one-screen mode is selected.
+
+const PRG_BANK_SIZE: usize = 0x4000; // 16 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+const CHR_RAM_SIZE: usize = 0x8000; // 32 KB
+
+#[derive(Clone)]
+pub struct Action53 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    register_index: u8,
+    chr_bank_index: usize,
+    inner_bank: u8,
+    mode: u8,
+    outer_bank: u8,
+}
+
+impl Action53 {
+    pub fn new(rom: &Rom) -> Action53 {
+        Action53 {
+            nametables: Nametables::with_mirroring(MirroringMode::SingleScreenA),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::with_ram_size(rom, CHR_RAM_SIZE),
+
+            register_index: 0,
+            chr_bank_index: 0,
+            inner_bank: 0,
+            mode: 0,
+            // The menu lives in the last bank, which is selected at power on
+            outer_bank: 0xFF,
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let is_upper_half = index & 0x4000 != 0;
+        let bank_mode = (self.mode >> 2) & 0b11;
+        let game_size = (self.mode >> 4) & 0b11;
+
+        let inner_bank = match bank_mode {
+            // 32 KB
+            0 | 1 => (self.inner_bank << 1) | is_upper_half as u8,
+            // Fixed first bank at $8000
+            2 if !is_upper_half => 0,
+            // Fixed last bank at $C000
+            3 if is_upper_half => 0xFF,
+            _ => self.inner_bank,
+        };
+
+        // The game size selects how many bank bits come from the inner bank
+        let inner_mask = (2u16 << game_size) - 1;
+        let outer_bank = (self.outer_bank as u16) << 1;
+        let bank_index = (outer_bank & !inner_mask) | (inner_bank as u16 & inner_mask);
+
+        let prg_banks = self.prg_rom_bytes.len() / PRG_BANK_SIZE;
+        (bank_index as usize % prg_banks) * PRG_BANK_SIZE + (index as usize & 0x3FFF)
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        self.chr_bank_index * CHR_BANK_SIZE + (index as usize & 0x1FFF)
+    }
+
+    fn set_one_screen_page(&mut self, byte: u8) {
+        if self.mode & 0b10 == 0 {
+            self.mode = (self.mode & !0b1) | ((byte >> 4) & 0b1);
+            self.update_mirroring();
+        }
+    }
+
+    fn update_mirroring(&mut self) {
+        self.nametables.set_mirroring(match self.mode & 0b11 {
+            0 => MirroringMode::SingleScreenA,
+            1 => MirroringMode::SingleScreenB,
+            2 => MirroringMode::Vertical,
+            3 => MirroringMode::Horizontal,
+            _ => unreachable!(),
+        });
+    }
+
+    fn write_register(&mut self, byte: u8) {
+        match self.register_index {
+            0x00 => {
+                self.chr_bank_index = (byte & 0b11) as usize;
+                self.set_one_screen_page(byte);
+            }
+            0x01 => {
+                self.inner_bank = byte & 0x0F;
+                self.set_one_screen_page(byte);
+            }
+            0x80 => {
+                self.mode = byte & 0x3F;
+                self.update_mirroring();
+            }
+            0x81 => self.outer_bank = byte,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Mapper for Action53 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Action53::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Action53 {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Action53 {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Action53 {}
+
+impl MapperAudio for Action53 {}
+
+impl MapperBattery for Action53 {}
+
+impl MemMapped for Action53 {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x5000..=0x5FFF => self.register_index = byte & 0x81,
+            0x8000..=0xFFFF => self.write_register(byte),
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::flash::Sst39sf040;
+use crate::mappers::nametables::{NametablePage, Nametables};
+use crate::mappers::{
+    resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery,
+    MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::borrow::Cow;
+use std::ops::Range;
+
+// RetroUSB/InfiniteNESLives UNROM 512 (mapper 30)
+//
+// Banks
+//     CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
+//     CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank
+//     PPU $0000-$1FFF: 8 KB switchable CHR RAM bank (of 32 KB)
+//
+// Bank select
+//     7  bit  0
+//     ---- ----
+//     MCCP PPPP
+//     |||+-++++- Select 16 KB PRG ROM bank for CPU $8000-$BFFF
+//     |++------- Select 8 KB CHR RAM bank for PPU $0000-$1FFF
+//     +--------- Select one-screen nametable (one-screen mirroring only)
+//
+// Nametable mirroring is set by the header (flags 6 bits 3 and 0):
+//     %00: horizontal, %01: vertical, %10: one-screen, switchable
+//     %11: four-screen, using the last 8 KB of CHR RAM as nametable RAM
+//
+// Self-flashable boards (header battery bit set) map the bank select register to $C000-$FFFF
+// and route writes to $8000-$BFFF to the flash. The PRG ROM contents are persisted as
+// battery data. Non-flashable boards map the register to $8000-$FFFF, with bus conflicts.
+
+const PRG_BANK_SIZE: usize = 0x4000; // 16 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+const CHR_RAM_SIZE: usize = 0x8000; // 32 KB
+const FOUR_SCREEN_NAMETABLE_OFFSET: usize = 0x6000;
+const NAMETABLE_PAGE_SIZE: usize = 0x400; // 1 KB
+
+#[derive(Clone)]
+pub struct Unrom512 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+    is_one_screen: bool,
+
+    prg_bank_index: usize,
+    chr_bank_index: usize,
+
+    is_flashable: bool,
+    flash: Sst39sf040,
+}
+
+impl Unrom512 {
+    pub fn new(rom: &Rom) -> Unrom512 {
+        let is_one_screen =
+            rom.header.four_screen_mode && rom.header.mirroring_mode == MirroringMode::Horizontal;
+
+        let nametables = match (rom.header.four_screen_mode, rom.header.mirroring_mode) {
+            (false, mirroring_mode) => Nametables::with_mirroring(mirroring_mode),
+            (true, MirroringMode::Horizontal) => {
+                Nametables::with_mirroring(MirroringMode::SingleScreenA)
+            }
+            (true, _) => {
+                // Four-screen nametables live in CHR RAM, mapped by the mapper itself
+                let mut nametables = Nametables::with_mirroring(MirroringMode::SingleScreenA);
+                for nametable_index in 0..4 {
+                    nametables.set_page(nametable_index, NametablePage::Mapper(nametable_index));
+                }
+                nametables
+            }
+        };
+
+        Unrom512 {
+            nametables,
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::with_ram_size(rom, CHR_RAM_SIZE),
+            is_one_screen,
+
+            prg_bank_index: 0,
+            chr_bank_index: 0,
+
+            is_flashable: rom.header.sram_present,
+            flash: Sst39sf040::new(),
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = self.prg_rom_bytes.len() / PRG_BANK_SIZE;
+        let bank_index = match index {
+            0x8000..=0xBFFF => self.prg_bank_index % prg_banks,
+            0xC000..=0xFFFF => prg_banks - 1,
+            _ => unreachable!(),
+        };
+
+        bank_index * PRG_BANK_SIZE + (index as usize & 0x3FFF)
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        self.chr_bank_index * CHR_BANK_SIZE + (index as usize & 0x1FFF)
+    }
+
+    fn get_nametable_ram_index(page: usize, offset: usize) -> usize {
+        FOUR_SCREEN_NAMETABLE_OFFSET + page * NAMETABLE_PAGE_SIZE + offset
+    }
+
+    fn select_bank(&mut self, byte: u8) {
+        self.prg_bank_index = (byte & 0x1F) as usize;
+        self.chr_bank_index = ((byte >> 5) & 0b11) as usize;
+
+        if self.is_one_screen {
+            self.nametables.set_mirroring(match byte >> 7 {
+                0 => MirroringMode::SingleScreenA,
+                _ => MirroringMode::SingleScreenB,
+            });
+        }
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Unrom512::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Unrom512 {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.flash.read(&self.prg_rom_bytes, index)
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Unrom512 {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Unrom512 {}
+
+impl MapperAudio for Unrom512 {}
+
+impl MapperBattery for Unrom512 {
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        if self.is_flashable {
+            Some(Cow::Borrowed(&self.prg_rom_bytes))
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_data(&mut self, data: &[u8]) {
+        if self.is_flashable && data.len() == self.prg_rom_bytes.len() {
+            self.prg_rom_bytes.copy_from_slice(data);
+        }
+    }
+}
+
+impl MemMapped for Unrom512 {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => match self.nametables.lookup(index) {
+                (NametablePage::Mapper(page), offset) => self
+                    .chr
+                    .read_ram(Unrom512::get_nametable_ram_index(page, offset)),
+                _ => self.nametables.read(index),
+            },
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => match self.nametables.lookup(index) {
+                (NametablePage::Mapper(page), offset) => {
+                    let index = Unrom512::get_nametable_ram_index(page, offset);
+                    self.chr.write_ram(index, byte)
+                }
+                _ => self.nametables.write(index, byte),
+            },
+            0x8000..=0xBFFF if self.is_flashable => {
+                let flash_address = self.get_prg_rom_index(index);
+                self.flash
+                    .write(&mut self.prg_rom_bytes, flash_address, byte);
+            }
+            0xC000..=0xFFFF if self.is_flashable => self.select_bank(byte),
+            0x8000..=0xFFFF => {
+                let byte = resolve_bus_conflict(self, true, index, byte);
+                self.select_bank(byte);
+            }
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
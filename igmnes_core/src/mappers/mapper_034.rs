@@ -0,0 +1,229 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery,
+    MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
+use std::ops::Range;
+
+// Mapper 34 covers two unrelated boards:
+//
+// BNROM (submapper 2)
+//     CPU $8000-$FFFF: 32 KB switchable PRG ROM bank, selected by writes to $8000-$FFFF
+//     PPU $0000-$1FFF: 8 KB CHR RAM
+//     Has bus conflicts
+//
+// AVE NINA-001 (submapper 1)
+//     CPU $6000-$7FFF: 8 KB PRG RAM
+//     CPU $8000-$FFFF: 32 KB switchable PRG ROM bank, selected by writes to $7FFD
+//     PPU $0000-$0FFF: 4 KB switchable CHR ROM bank, selected by writes to $7FFE
+//     PPU $1000-$1FFF: 4 KB switchable CHR ROM bank, selected by writes to $7FFF
+//
+// iNES headers don't distinguish the two, so boards with more than 8 KB of CHR ROM are
+// assumed to be NINA-001 (BNROM only ever uses CHR RAM).
+
+const PRG_BANK_SIZE: usize = 0x8000; // 32 KB
+const CHR_BANK_SIZE: usize = 0x1000; // 4 KB
+const PRG_RAM_SIZE: usize = 0x2000; // 8 KB
+const CHR_RAM_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct Mapper034 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram_bytes: Vec<u8>,
+    is_nina_001: bool,
+
+    prg_bank_index: usize,
+    chr_bank_indices: [usize; 2],
+}
+
+impl Mapper034 {
+    pub fn new(rom: &Rom) -> Mapper034 {
+        let is_nina_001 = match rom.header.submapper_number() {
+            1 => true,
+            2 => false,
+            _ => rom.chr_rom_bytes.len() > CHR_RAM_SIZE,
+        };
+
+        Mapper034 {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+            prg_ram_bytes: vec![0; PRG_RAM_SIZE],
+            is_nina_001,
+
+            prg_bank_index: 0,
+            chr_bank_indices: [0, 1],
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = (self.prg_rom_bytes.len() / PRG_BANK_SIZE).max(1);
+        let bank_offset = (self.prg_bank_index % prg_banks) * PRG_BANK_SIZE;
+        (bank_offset + (index as usize & 0x7FFF)) % self.prg_rom_bytes.len()
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        if self.is_nina_001 {
+            let chr_banks = self.chr.size() / CHR_BANK_SIZE;
+            let bank_index = self.chr_bank_indices[(index as usize >> 12) & 1] % chr_banks;
+            bank_index * CHR_BANK_SIZE + (index as usize & 0xFFF)
+        } else {
+            index as usize % self.chr.size()
+        }
+    }
+
+    fn get_prg_ram_index(&self, index: u16) -> usize {
+        (index - 0x6000) as usize % self.prg_ram_bytes.len()
+    }
+}
+
+impl Mapper for Mapper034 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Mapper034::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Mapper034 {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, index: u16) -> u8 {
+        if self.is_nina_001 {
+            let index = self.get_prg_ram_index(index);
+            self.prg_ram_bytes[index]
+        } else {
+            0
+        }
+    }
+
+    fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        if !self.is_nina_001 {
+            return;
+        }
+
+        match index {
+            0x7FFD => self.prg_bank_index = (byte & 0b1) as usize,
+            0x7FFE => self.chr_bank_indices[0] = (byte & 0x0F) as usize,
+            0x7FFF => self.chr_bank_indices[1] = (byte & 0x0F) as usize,
+            _ => (),
+        }
+
+        // The bank registers don't prevent the write from reaching PRG RAM
+        let index = self.get_prg_ram_index(index);
+        self.prg_ram_bytes[index] = byte;
+    }
+
+    // BNROM has no PRG RAM, only the NINA-001 board does
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.is_nina_001 {
+            Some(&mut self.prg_ram_bytes)
+        } else {
+            None
+        }
+    }
+}
+
+impl PpuMapper for Mapper034 {
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Mapper034 {}
+
+impl MapperAudio for Mapper034 {}
+
+impl MapperBattery for Mapper034 {}
+
+impl MemMapped for Mapper034 {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x6000..=0x7FFF => self.read_prg_ram(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x6000..=0x7FFF => self.write_prg_ram(index, byte),
+            0x8000..=0xFFFF if !self.is_nina_001 => {
+                let byte = resolve_bus_conflict(self, true, index, byte);
+                self.prg_bank_index = byte as usize;
+            }
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::test_rom;
+
+    #[test]
+    fn bnrom_switches_prg_rom_bank() {
+        let mut mapper = Mapper034::new(&test_rom(34, PRG_BANK_SIZE, 4, 0, 0));
+        mapper.write(0x8001, 3);
+        assert_eq!(mapper.read(0x8000), 3);
+
+        // NINA-001 registers are ignored
+        mapper.write(0x7FFD, 1);
+        assert_eq!(mapper.read(0x8000), 3);
+    }
+
+    #[test]
+    fn nina_001_switches_prg_rom_and_chr_rom_banks() {
+        let mut mapper = Mapper034::new(&test_rom(34, PRG_BANK_SIZE, 2, CHR_BANK_SIZE, 8));
+        mapper.write(0x7FFD, 1);
+        mapper.write(0x7FFE, 5);
+        mapper.write(0x7FFF, 6);
+        assert_eq!(mapper.read(0x8000), 1);
+        assert_eq!(mapper.read(0x0000), 5);
+        assert_eq!(mapper.read(0x1000), 6);
+        // The registers are written to PRG RAM as well
+        assert_eq!(mapper.read(0x7FFE), 5);
+
+        // BNROM register is ignored
+        mapper.write(0x8001, 0);
+        assert_eq!(mapper.read(0x8000), 1);
+    }
+}
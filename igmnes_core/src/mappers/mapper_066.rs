@@ -0,0 +1,162 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    resolve_bus_conflict, CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery,
+    MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
+use std::ops::Range;
+
+// GxROM and MHROM (mapper 66)
+//
+// Banks
+//     CPU $8000-$FFFF: 32 KB switchable PRG ROM bank
+//     PPU $0000-$1FFF: 8 KB switchable CHR ROM bank
+//
+// Bank select ($8000-$FFFF), with bus conflicts
+//     7  bit  0
+//     ---- ----
+//     xxPP xxCC
+//       ||   ||
+//       ||   ++- Select 8 KB CHR ROM bank for PPU $0000-$1FFF
+//       ++------ Select 32 KB PRG ROM bank for CPU $8000-$FFFF
+
+const PRG_BANK_SIZE: usize = 0x8000; // 32 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct GxROM {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    prg_bank_index: usize,
+    chr_bank_index: usize,
+}
+
+impl GxROM {
+    pub fn new(rom: &Rom) -> GxROM {
+        GxROM {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+
+            prg_bank_index: 0,
+            chr_bank_index: 0,
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = (self.prg_rom_bytes.len() / PRG_BANK_SIZE).max(1);
+        let bank_offset = (self.prg_bank_index % prg_banks) * PRG_BANK_SIZE;
+        (bank_offset + (index as usize & 0x7FFF)) % self.prg_rom_bytes.len()
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        let chr_banks = self.chr.size() / CHR_BANK_SIZE;
+        (self.chr_bank_index % chr_banks) * CHR_BANK_SIZE + index as usize
+    }
+
+    fn select_bank(&mut self, index: u16, byte: u8) {
+        let byte = resolve_bus_conflict(self, true, index, byte);
+        self.prg_bank_index = ((byte >> 4) & 0b11) as usize;
+        self.chr_bank_index = (byte & 0b11) as usize;
+    }
+}
+
+impl Mapper for GxROM {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = GxROM::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for GxROM {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for GxROM {
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for GxROM {}
+
+impl MapperAudio for GxROM {}
+
+impl MapperBattery for GxROM {}
+
+impl MemMapped for GxROM {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x8000..=0xFFFF => self.select_bank(index, byte),
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::test_rom;
+
+    #[test]
+    fn bank_select_switches_prg_and_chr() {
+        let mut mapper = GxROM::new(&test_rom(66, PRG_BANK_SIZE, 4, CHR_BANK_SIZE, 4));
+        mapper.write(0x8001, 0x23);
+        assert_eq!(mapper.read(0x8000), 2);
+        assert_eq!(mapper.read(0x0000), 3);
+
+        // The written value is ANDed with the PRG ROM byte, the bank number here
+        mapper.write(0x8000, 0x11);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0x0000), 0);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::ops::Range;
+
+// Camerica/Codemasters BF909x (mapper 71)
+//
+// Banks
+//     CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
+//     CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank
+//     PPU $0000-$1FFF: 8 KB CHR RAM
+//
+// Registers
+//     $C000-$FFFF: PRG ROM bank select
+//     $8000-$9FFF: one-screen mirroring select (bit 4), only on the BF9097 board used by
+//                  Fire Hawk (submapper 1). Other boards have fixed mirroring and never write
+//                  to $9000-$9FFF, so without a NES 2.0 submapper the first such write
+//                  switches to BF9097 mode.
+
+const PRG_BANK_SIZE: usize = 0x4000; // 16 KB
+const CHR_RAM_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct Camerica {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    bank_index: usize,
+
+    is_one_screen_mirroring: bool,
+    // Whether the board is detected from the writes, as the header doesn't say
+    is_board_detected: bool,
+}
+
+impl Camerica {
+    pub fn new(rom: &Rom) -> Camerica {
+        let is_one_screen_mirroring = rom.header.submapper_number() == 1;
+        let nametables = if is_one_screen_mirroring {
+            Nametables::with_mirroring(MirroringMode::SingleScreenA)
+        } else {
+            Nametables::new(rom)
+        };
+
+        Camerica {
+            nametables,
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::with_ram_size(rom, CHR_RAM_SIZE),
+
+            bank_index: 0,
+
+            is_one_screen_mirroring,
+            is_board_detected: rom.header.extension.is_none(),
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = self.prg_rom_bytes.len() / PRG_BANK_SIZE;
+        let bank_index = match index {
+            0x8000..=0xBFFF => self.bank_index % prg_banks,
+            0xC000..=0xFFFF => prg_banks - 1,
+            _ => unreachable!(),
+        };
+
+        bank_index * PRG_BANK_SIZE + (index as usize & 0x3FFF)
+    }
+
+    fn select_one_screen_page(&mut self, byte: u8) {
+        self.nametables.set_mirroring(match (byte >> 4) & 0b1 {
+            0 => MirroringMode::SingleScreenA,
+            _ => MirroringMode::SingleScreenB,
+        });
+    }
+}
+
+impl Mapper for Camerica {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Camerica::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| index as usize,
+        )
+    }
+}
+
+impl CpuMapper for Camerica {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Camerica {
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = range.start as usize;
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
+    }
+}
+
+impl MapperIrq for Camerica {}
+
+impl MapperAudio for Camerica {}
+
+impl MapperBattery for Camerica {}
+
+impl MemMapped for Camerica {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x8000..=0x9FFF if self.is_one_screen_mirroring => self.select_one_screen_page(byte),
+            0x9000..=0x9FFF if self.is_board_detected => {
+                self.is_one_screen_mirroring = true;
+                self.select_one_screen_page(byte);
+            }
+            0xC000..=0xFFFF => self.bank_index = byte as usize,
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::nametables::NametablePage;
+    use crate::mappers::test_rom;
+    use crate::rom::HeaderExtension;
+
+    fn camerica(submapper_number: Option<u8>) -> Camerica {
+        let mut rom = test_rom(71, PRG_BANK_SIZE, 8, 0, 0);
+        rom.header.mirroring_mode = MirroringMode::Vertical;
+        rom.header.extension = submapper_number.map(|submapper_number| HeaderExtension {
+            mapper_number: 71,
+            submapper_number,
+        });
+        Camerica::new(&rom)
+    }
+
+    #[test]
+    fn bank_select_switches_first_prg_rom_bank() {
+        let mut mapper = camerica(None);
+        assert_eq!(mapper.read(0xC000), 7);
+        mapper.write(0xC000, 5);
+        assert_eq!(mapper.read(0x8000), 5);
+        assert_eq!(mapper.read(0xC000), 7);
+    }
+
+    #[test]
+    fn ines_board_switches_to_bf9097_on_mirroring_write() {
+        let mut mapper = camerica(None);
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(1));
+        mapper.write(0x8000, 0x10);
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(1));
+
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.nametables.lookup(0x2000).0, NametablePage::Ciram(1));
+        mapper.write(0x8000, 0x00);
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(0));
+    }
+
+    #[test]
+    fn nes2_submapper_selects_board() {
+        let mut mapper = camerica(Some(0));
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.nametables.lookup(0x2000).0, NametablePage::Ciram(0));
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(1));
+
+        let mut mapper = camerica(Some(1));
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(0));
+        mapper.write(0x8000, 0x10);
+        assert_eq!(mapper.nametables.lookup(0x2400).0, NametablePage::Ciram(1));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::ops::Range;
+
+// AVE NINA-03/NINA-06 (mapper 79) and Sachen/Hacker multicarts (mapper 113)
+//
+// Banks
+//     CPU $8000-$FFFF: 32 KB switchable PRG ROM bank
+//     PPU $0000-$1FFF: 8 KB switchable CHR ROM bank
+//
+// Bank select ($4100-$5FFF, mirrored where A8 is set and A13-A15 are clear)
+//     Mapper 79         Mapper 113
+//     7  bit  0         7  bit  0
+//     ---- ----         ---- ----
+//     xxxx PCCC         MCPP PCCC
+//          ||||         |||| ||||
+//          |+++- CHR    |||| |+++- CHR bank (low bits)
+//          +---- PRG    |||+-+---- PRG bank
+//                       |+-------- CHR bank (high bit)
+//                       +--------- Mirroring (0: horizontal, 1: vertical)
+
+const PRG_BANK_SIZE: usize = 0x8000; // 32 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+
+#[derive(Clone)]
+pub struct Nina0306 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+    is_mapper_113: bool,
+
+    prg_bank_index: usize,
+    chr_bank_index: usize,
+}
+
+impl Nina0306 {
+    pub fn new(rom: &Rom) -> Nina0306 {
+        Nina0306 {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+            is_mapper_113: rom.header.mapper_number == 113,
+
+            prg_bank_index: 0,
+            chr_bank_index: 0,
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = (self.prg_rom_bytes.len() / PRG_BANK_SIZE).max(1);
+        let bank_offset = (self.prg_bank_index % prg_banks) * PRG_BANK_SIZE;
+        (bank_offset + (index as usize & 0x7FFF)) % self.prg_rom_bytes.len()
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        let chr_banks = self.chr.size() / CHR_BANK_SIZE;
+        (self.chr_bank_index % chr_banks) * CHR_BANK_SIZE + index as usize
+    }
+
+    fn select_bank(&mut self, byte: u8) {
+        if self.is_mapper_113 {
+            self.prg_bank_index = ((byte >> 3) & 0b111) as usize;
+            self.chr_bank_index = ((byte & 0b111) | ((byte >> 3) & 0b1000)) as usize;
+            self.nametables.set_mirroring(match byte >> 7 {
+                0 => MirroringMode::Horizontal,
+                _ => MirroringMode::Vertical,
+            });
+        } else {
+            self.prg_bank_index = ((byte >> 3) & 0b1) as usize;
+            self.chr_bank_index = (byte & 0b111) as usize;
+        }
+    }
+}
+
+impl Mapper for Nina0306 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Nina0306::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Nina0306 {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Nina0306 {
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Nina0306 {}
+
+impl MapperAudio for Nina0306 {}
+
+impl MapperBattery for Nina0306 {}
+
+impl MemMapped for Nina0306 {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x4100..=0x5FFF if index & 0xE100 == 0x4100 => self.select_bank(byte),
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::nametables::NametablePage;
+    use crate::mappers::test_rom;
+
+    #[test]
+    fn nina_03_06_switches_prg_and_chr() {
+        let mut mapper = Nina0306::new(&test_rom(79, PRG_BANK_SIZE, 2, CHR_BANK_SIZE, 8));
+        mapper.write(0x4100, 0b1101);
+        assert_eq!(mapper.read(0x8000), 1);
+        assert_eq!(mapper.read(0x0000), 5);
+
+        // Mirrored where A8 is set and A13-A15 are clear
+        mapper.write(0x5F00, 0b0010);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0x0000), 2);
+        mapper.write(0x4000, 0b1111);
+        mapper.write(0x6100, 0b1111);
+        assert_eq!(mapper.read(0x0000), 2);
+    }
+
+    #[test]
+    fn mapper_113_switches_prg_chr_and_mirroring() {
+        let mut mapper = Nina0306::new(&test_rom(113, PRG_BANK_SIZE, 8, CHR_BANK_SIZE, 16));
+        // Vertical mirroring, CHR bank 0b1011, PRG bank 0b101
+        mapper.write(0x4100, 0b1110_1011);
+        assert_eq!(mapper.read(0x8000), 5);
+        assert_eq!(mapper.read(0x0000), 0b1011);
+        assert_eq!(mapper.nametables.lookup(0x2800).0, NametablePage::Ciram(0));
+
+        mapper.write(0x4100, 0);
+        assert_eq!(mapper.nametables.lookup(0x2800).0, NametablePage::Ciram(1));
+    }
+}
@@ -0,0 +1,378 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::opll::{Opll, CPU_CYCLES_PER_SAMPLE};
+use crate::mappers::vrc_irq::VrcIrq;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::{MirroringMode, Rom};
+use std::borrow::Cow;
+use std::ops::Range;
+
+// Konami VRC7 (mapper 85)
+//
+// Banks
+//     CPU $6000-$7FFF: 8 KB PRG RAM bank
+//     CPU $8000-$9FFF: 8 KB switchable PRG ROM bank
+//     CPU $A000-$BFFF: 8 KB switchable PRG ROM bank
+//     CPU $C000-$DFFF: 8 KB switchable PRG ROM bank
+//     CPU $E000-$FFFF: 8 KB PRG ROM bank, fixed to the last bank
+//     PPU $0000-$1FFF: eight 1 KB switchable CHR ROM/RAM banks
+//
+// The two board variants connect different CPU address lines to the register select input:
+// VRC7a (Lagrange Point) uses A4 and VRC7b (Tiny Toon Adventures 2) uses A3.
+// Both are accepted here by folding A3 into A4.
+
+const PRG_BANK_SIZE: usize = 0x2000; // 8 KB
+const CHR_BANK_SIZE: usize = 0x0400; // 1 KB
+const PRG_RAM_SIZE: usize = 0x2000; // 8 KB
+
+// Expansion audio level relative to the APU output, one full volume channel is roughly
+// as loud as a single pulse channel
+pub const AUDIO_GAIN: f32 = 0.72;
+
+#[derive(Clone)]
+pub struct Vrc7 {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram_bytes: Vec<u8>,
+    is_battery_backed: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+
+    is_prg_ram_enabled: bool,
+
+    irq: VrcIrq,
+
+    opll: Box<Opll>,
+    is_audio_silenced: bool,
+    audio_cycles: u8,
+}
+
+impl Vrc7 {
+    pub fn new(rom: &Rom) -> Vrc7 {
+        Vrc7 {
+            nametables: Nametables::with_mirroring(MirroringMode::Vertical),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
+            prg_ram_bytes: vec![0; PRG_RAM_SIZE],
+            is_battery_backed: rom.header.sram_present,
+
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+
+            is_prg_ram_enabled: false,
+
+            irq: VrcIrq::new(),
+
+            opll: Box::new(Opll::new()),
+            is_audio_silenced: false,
+            audio_cycles: 0,
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = self.prg_rom_bytes.len() / PRG_BANK_SIZE;
+        let bank_index = match index {
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF => self.prg_banks[2] as usize,
+            0xE000..=0xFFFF => prg_banks - 1,
+            _ => unreachable!(),
+        };
+
+        (bank_index % prg_banks) * PRG_BANK_SIZE + (index as usize & 0x1FFF)
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        let chr_banks = self.chr.size() / CHR_BANK_SIZE;
+        let bank_index = self.chr_banks[(index as usize / CHR_BANK_SIZE) & 0b111] as usize;
+
+        (bank_index % chr_banks) * CHR_BANK_SIZE + (index as usize & 0x3FF)
+    }
+
+    fn get_prg_ram_index(&self, index: u16) -> usize {
+        (index - 0x6000) as usize % self.prg_ram_bytes.len()
+    }
+
+    fn write_register(&mut self, index: u16, byte: u8) {
+        // Fold A3 (VRC7b) into A4 (VRC7a)
+        let register = (index & 0xF000) | if index & 0x18 != 0 { 0x10 } else { 0 };
+
+        match register {
+            0x8000 => self.prg_banks[0] = byte & 0x3F,
+            0x8010 => self.prg_banks[1] = byte & 0x3F,
+            0x9000 => self.prg_banks[2] = byte & 0x3F,
+            0x9010 => {
+                // Audio register select is at $9010, audio data at $9030
+                if index & 0x20 != 0 {
+                    self.opll.write_data(byte);
+                } else {
+                    self.opll.select_register(byte);
+                }
+            }
+            0xA000 => self.chr_banks[0] = byte,
+            0xA010 => self.chr_banks[1] = byte,
+            0xB000 => self.chr_banks[2] = byte,
+            0xB010 => self.chr_banks[3] = byte,
+            0xC000 => self.chr_banks[4] = byte,
+            0xC010 => self.chr_banks[5] = byte,
+            0xD000 => self.chr_banks[6] = byte,
+            0xD010 => self.chr_banks[7] = byte,
+            0xE000 => {
+                // 7  bit  0
+                // ---- ----
+                // RS.. ..MM
+                // ||     ||
+                // ||     ++- Mirroring
+                // |+-------- Sound reset (silences and resets the expansion audio)
+                // +--------- WRAM enable
+                self.nametables.set_mirroring(match byte & 0b11 {
+                    0 => MirroringMode::Vertical,
+                    1 => MirroringMode::Horizontal,
+                    2 => MirroringMode::SingleScreenA,
+                    3 => MirroringMode::SingleScreenB,
+                    _ => unreachable!(),
+                });
+                self.is_prg_ram_enabled = byte & 0x80 != 0;
+
+                let is_audio_silenced = byte & 0x40 != 0;
+                if is_audio_silenced {
+                    self.opll.reset();
+                }
+                self.is_audio_silenced = is_audio_silenced;
+            }
+            0xE010 => self.irq.write_latch(byte),
+            0xF000 => self.irq.write_control(byte),
+            0xF010 => self.irq.acknowledge(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Vrc7::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Vrc7 {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    #[inline]
+    fn read_prg_ram(&self, index: u16) -> u8 {
+        if self.is_prg_ram_enabled {
+            let index = self.get_prg_ram_index(index);
+            self.prg_ram_bytes[index]
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        if self.is_prg_ram_enabled {
+            let index = self.get_prg_ram_index(index);
+            self.prg_ram_bytes[index] = byte;
+        }
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram_bytes)
+    }
+
+    // Disabled PRG RAM doesn't answer reads
+    fn is_open_bus(&mut self, index: u16) -> bool {
+        match index {
+            0x4020..=0x5FFF => true,
+            0x6000..=0x7FFF => !self.is_prg_ram_enabled,
+            _ => false,
+        }
+    }
+}
+
+impl PpuMapper for Vrc7 {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Vrc7 {
+    #[inline]
+    fn clock_cpu_irq(&mut self) {
+        self.irq.clock();
+    }
+
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq.is_pending()
+    }
+}
+
+impl MapperAudio for Vrc7 {
+    #[inline]
+    fn clock_audio(&mut self) {
+        if self.is_audio_silenced {
+            return;
+        }
+
+        self.audio_cycles += 1;
+        if self.audio_cycles == CPU_CYCLES_PER_SAMPLE {
+            self.audio_cycles = 0;
+            self.opll.clock();
+        }
+    }
+
+    #[inline]
+    fn audio_output(&self) -> f32 {
+        if self.is_audio_silenced {
+            0.0
+        } else {
+            self.opll.output() * AUDIO_GAIN
+        }
+    }
+}
+
+impl MapperBattery for Vrc7 {
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        if self.is_battery_backed {
+            Some(Cow::Borrowed(&self.prg_ram_bytes))
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_data(&mut self, data: &[u8]) {
+        if self.is_battery_backed && data.len() == self.prg_ram_bytes.len() {
+            self.prg_ram_bytes.copy_from_slice(data);
+        }
+    }
+}
+
+impl MemMapped for Vrc7 {
+    #[inline]
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0x0000..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x6000..=0x7FFF => self.read_prg_ram(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x0000..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x6000..=0x7FFF => self.write_prg_ram(index, byte),
+            0x8000..=0xFFFF => self.write_register(index, byte),
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0x0000..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::nametables::NametablePage;
+    use crate::mappers::test_rom;
+
+    fn vrc7() -> Vrc7 {
+        Vrc7::new(&test_rom(85, PRG_BANK_SIZE, 16, CHR_BANK_SIZE, 16))
+    }
+
+    #[test]
+    fn vrc7a_and_vrc7b_registers_are_decoded() {
+        // VRC7a selects the second register of a pair with A4, VRC7b with A3
+        for &(second_register_offset, audio_data_offset) in &[(0x10, 0x30), (0x08, 0x28)] {
+            let mut vrc7 = vrc7();
+            vrc7.write(0x8000, 3);
+            vrc7.write(0x8000 + second_register_offset, 4);
+            vrc7.write(0x9000, 5);
+            vrc7.write(0xD000 + second_register_offset, 9);
+            assert_eq!(vrc7.read(0x8000), 3);
+            assert_eq!(vrc7.read(0xA000), 4);
+            assert_eq!(vrc7.read(0xC000), 5);
+            assert_eq!(vrc7.read(0xE000), 15);
+            assert_eq!(vrc7.read(0x1C00), 9);
+
+            vrc7.write(0x9000 + second_register_offset, 0x30);
+            vrc7.write(0x9000 + audio_data_offset, 0x52);
+            assert_eq!(vrc7.opll.register(0x30), 0x52);
+        }
+    }
+
+    #[test]
+    fn control_register_sets_mirroring_and_prg_ram() {
+        let mut vrc7 = vrc7();
+        vrc7.write(0xE000, 0b1000_0001);
+        // Horizontal mirroring
+        assert_eq!(vrc7.nametables.lookup(0x2400).0, NametablePage::Ciram(0));
+        assert_eq!(vrc7.nametables.lookup(0x2800).0, NametablePage::Ciram(1));
+        vrc7.write(0x6000, 0x42);
+        assert!(!vrc7.is_open_bus(0x6000));
+        assert_eq!(vrc7.read(0x6000), 0x42);
+
+        // Disabled PRG RAM ignores writes and leaves reads to open bus
+        vrc7.write(0xE000, 0b0000_0011);
+        assert_eq!(vrc7.nametables.lookup(0x2000).0, NametablePage::Ciram(1));
+        vrc7.write(0x6000, 0x17);
+        assert!(vrc7.is_open_bus(0x6000));
+        vrc7.write(0xE000, 0b1000_0000);
+        assert_eq!(vrc7.read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn sound_reset_silences_audio() {
+        let mut vrc7 = vrc7();
+        vrc7.write(0x9010, 0x30);
+        vrc7.write(0x9030, 0x10);
+        vrc7.write(0xE000, 0b0100_0000);
+        assert!(vrc7.is_audio_silenced);
+        assert_eq!(vrc7.opll.register(0x30), 0);
+        assert_eq!(vrc7.audio_output(), 0.0);
+
+        vrc7.write(0xE000, 0);
+        assert!(!vrc7.is_audio_silenced);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
+use std::ops::Range;
+
+// Vs. UniSystem (mapper 99)
+//
+// Banks
+//     CPU $6000-$7FFF: 2 KB PRG RAM, mirrored
+//     CPU $8000-$FFFF: 32 KB PRG ROM. With 40 KB (Vs. Gumshoe), $8000-$9FFF is switchable
+//     PPU $0000-$1FFF: 8 KB switchable CHR ROM bank
+//
+// Bank select ($4016 write, shared with the controller strobe)
+//     7  bit  0
+//     ---- ----
+//     xxxx xBxx
+//          |
+//          +--- Select 8 KB CHR ROM bank for PPU $0000-$1FFF,
+//               and 8 KB PRG ROM bank for CPU $8000-$9FFF (0: bank 0, 1: bank 4)
+
+const PRG_ROM_SIZE: usize = 0x8000; // 32 KB
+const PRG_BANK_SIZE: usize = 0x2000; // 8 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+const PRG_RAM_SIZE: usize = 0x800; // 2 KB
+
+#[derive(Clone)]
+pub struct VsUniSystem {
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    prg_ram_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    bank_index: usize,
+}
+
+impl VsUniSystem {
+    pub fn new(rom: &Rom) -> VsUniSystem {
+        VsUniSystem {
+            nametables: Nametables::new(rom),
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            prg_ram_bytes: vec![0; PRG_RAM_SIZE],
+            chr: ChrMemory::new(rom),
+
+            bank_index: 0,
+        }
+    }
+
+    // Called on $4016 writes
+    pub fn select_bank(&mut self, byte: u8) {
+        self.bank_index = ((byte >> 2) & 0b1) as usize;
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let index = index as usize & 0x7FFF;
+        if self.prg_rom_bytes.len() > PRG_ROM_SIZE && index < PRG_BANK_SIZE {
+            (self.bank_index * 4 * PRG_BANK_SIZE + index) % self.prg_rom_bytes.len()
+        } else {
+            index % self.prg_rom_bytes.len()
+        }
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        let chr_banks = (self.chr.size() / CHR_BANK_SIZE).max(1);
+        (self.bank_index % chr_banks) * CHR_BANK_SIZE + index as usize
+    }
+}
+
+impl Mapper for VsUniSystem {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = VsUniSystem::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for VsUniSystem {
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.prg_rom_bytes[index]
+    }
+
+    fn read_prg_ram(&self, index: u16) -> u8 {
+        self.prg_ram_bytes[index as usize % PRG_RAM_SIZE]
+    }
+
+    fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        self.prg_ram_bytes[index as usize % PRG_RAM_SIZE] = byte;
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram_bytes)
+    }
+}
+
+impl PpuMapper for VsUniSystem {
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for VsUniSystem {}
+
+impl MapperAudio for VsUniSystem {}
+
+impl MapperBattery for VsUniSystem {}
+
+impl MemMapped for VsUniSystem {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x6000..=0x7FFF => self.read_prg_ram(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x6000..=0x7FFF => self.write_prg_ram(index, byte),
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
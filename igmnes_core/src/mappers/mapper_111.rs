@@ -0,0 +1,189 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::flash::Sst39sf040;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
+use crate::rom::Rom;
+use std::borrow::Cow;
+use std::ops::Range;
+
+// Membler Industries GTROM/Cheapocabra (mapper 111)
+//
+// Banks
+//     CPU $8000-$FFFF: 32 KB switchable PRG flash bank
+//     PPU $0000-$1FFF: 8 KB switchable CHR RAM bank (of 16 KB)
+//     PPU $2000-$3EFF: 8 KB switchable nametable RAM bank (of 16 KB), four-screen
+//
+// Bank select ($5000-$5FFF, $7000-$7FFF)
+//     7  bit  0
+//     ---- ----
+//     GRNC PPPP
+//     |||| ||||
+//     |||| ++++- Select 32 KB PRG flash bank for CPU $8000-$FFFF
+//     |||+------ Select 8 KB CHR RAM bank for PPU $0000-$1FFF
+//     ||+------- Select 8 KB nametable RAM bank for PPU $2000-$3EFF
+//     |+-------- Red LED (not emulated)
+//     +--------- Green LED (not emulated)
+//
+// The board has a single 32 KB RAM chip: CHR RAM occupies the lower half and nametable RAM
+// the upper half. The PRG flash is always writable and persisted as battery data.
+
+const PRG_BANK_SIZE: usize = 0x8000; // 32 KB
+const CHR_BANK_SIZE: usize = 0x2000; // 8 KB
+const NAMETABLE_BANK_SIZE: usize = 0x2000; // 8 KB
+const NAMETABLE_RAM_OFFSET: usize = 0x4000;
+const RAM_SIZE: usize = 0x8000; // 32 KB
+
+#[derive(Clone)]
+pub struct Gtrom {
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+
+    prg_bank_index: usize,
+    chr_bank_index: usize,
+    nametable_bank_index: usize,
+
+    flash: Sst39sf040,
+}
+
+impl Gtrom {
+    pub fn new(rom: &Rom) -> Gtrom {
+        Gtrom {
+            prg_rom_bytes: rom.prg_rom_bytes.clone(),
+            chr: ChrMemory::with_ram_size(rom, RAM_SIZE),
+
+            prg_bank_index: 0,
+            chr_bank_index: 0,
+            nametable_bank_index: 0,
+
+            flash: Sst39sf040::new(),
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let prg_banks = (self.prg_rom_bytes.len() / PRG_BANK_SIZE).max(1);
+        let bank_offset = (self.prg_bank_index % prg_banks) * PRG_BANK_SIZE;
+        (bank_offset + (index as usize & 0x7FFF)) % self.prg_rom_bytes.len()
+    }
+
+    fn get_chr_index(&self, index: u16) -> usize {
+        self.chr_bank_index * CHR_BANK_SIZE + (index as usize & 0x1FFF)
+    }
+
+    // The nametable RAM bank covers all of PPU $2000-$3FFF, without mirroring
+    fn get_nametable_index(&self, index: u16) -> usize {
+        NAMETABLE_RAM_OFFSET
+            + self.nametable_bank_index * NAMETABLE_BANK_SIZE
+            + (index as usize & 0x1FFF)
+    }
+
+    fn select_bank(&mut self, byte: u8) {
+        self.prg_bank_index = (byte & 0x0F) as usize;
+        self.chr_bank_index = ((byte >> 4) & 0b1) as usize;
+        self.nametable_bank_index = ((byte >> 5) & 0b1) as usize;
+    }
+}
+
+impl Mapper for Gtrom {
+    fn hard_reset(&mut self, rom: &Rom) {
+        *self = Gtrom::new(rom);
+    }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
+}
+
+impl CpuMapper for Gtrom {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        let index = self.get_prg_rom_index(index);
+        self.flash.read(&self.prg_rom_bytes, index)
+    }
+
+    fn read_prg_ram(&self, _index: u16) -> u8 {
+        0
+    }
+
+    fn write_prg_ram(&mut self, _index: u16, _byte: u8) {}
+}
+
+impl PpuMapper for Gtrom {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
+    }
+}
+
+impl MapperIrq for Gtrom {}
+
+impl MapperAudio for Gtrom {}
+
+impl MapperBattery for Gtrom {
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_rom_bytes))
+    }
+
+    fn load_battery_data(&mut self, data: &[u8]) {
+        if data.len() == self.prg_rom_bytes.len() {
+            self.prg_rom_bytes.copy_from_slice(data);
+        }
+    }
+}
+
+impl MemMapped for Gtrom {
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => {
+                let index = self.get_nametable_index(index);
+                self.chr.read_ram(index)
+            }
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => {
+                let index = self.get_nametable_index(index);
+                self.chr.write_ram(index, byte);
+            }
+            0x5000..=0x5FFF | 0x7000..=0x7FFF => self.select_bank(byte),
+            0x8000..=0xFFFF => {
+                let flash_address = self.get_prg_rom_index(index);
+                self.flash
+                    .write(&mut self.prg_rom_bytes, flash_address, byte);
+            }
+            _ => (),
+        }
+    }
+
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+}
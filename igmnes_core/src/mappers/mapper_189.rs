@@ -1,5 +1,9 @@
-use crate::mappers::{CpuMapper, Mapper, MapperIrq, PpuMapper};
-use crate::memory::{MemMapped, Ram};
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::{
+    CpuMapper, Mapper, MapperAudio, MapperBankState, MapperBattery, MapperIrq, PpuMapper,
+};
+use crate::memory::MemMapped;
 use crate::rom::{MirroringMode, Rom};
 use std::ops::Range;
 
@@ -11,9 +15,9 @@ const PRG_RAM_SIZE: usize = 0x2000; // 8 KB (if present)
 // These boards are modified MMC3 boards that bank PRG-ROM in 32 KiB amounts, like AxROM, BNROM and GNROM
 #[derive(Clone)]
 pub struct Mapper189 {
-    vram: Ram,
+    nametables: Nametables,
     prg_rom_bytes: Vec<u8>,
-    chr_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
     prg_ram_bytes: Vec<u8>,
 
     prg_bank_index: usize,
@@ -21,7 +25,6 @@ pub struct Mapper189 {
     r: [u8; 8],
 
     chr_inversion: bool,
-    mirroring_mode: MirroringMode,
 
     irq_latch: u8,
     irq_counter: u8,
@@ -42,9 +45,9 @@ impl Mapper189 {
         let prg_ram_bytes: Vec<u8> = vec![0; prg_ram_size as usize];
 
         Mapper189 {
-            vram: Ram::default(),
+            nametables: Nametables::new(rom),
             prg_rom_bytes: prg_rom_bytes,
-            chr_rom_bytes: rom.chr_rom_bytes.clone(),
+            chr: ChrMemory::new(rom),
             prg_ram_bytes: prg_ram_bytes,
 
             prg_bank_index: 0,
@@ -53,8 +56,6 @@ impl Mapper189 {
 
             chr_inversion: false,
 
-            mirroring_mode: MirroringMode::Vertical,
-
             irq_latch: 0,
             irq_counter: 0,
             irq_enabled: false,
@@ -74,9 +75,9 @@ impl Mapper189 {
         (index - 0x6000) as usize % self.prg_ram_bytes.len()
     }
 
-    fn get_chr_rom_index(&self, index: u16) -> usize {
+    fn get_chr_index(&self, index: u16) -> usize {
         let addr = index as usize;
-        let chr_banks = self.chr_rom_bytes.len() / CHR_BANK_SIZE_1KB;
+        let chr_banks = self.chr.size() / CHR_BANK_SIZE_1KB;
 
         let (bank_register_value, addr_offset_mask) = if self.chr_inversion {
             match addr {
@@ -115,7 +116,7 @@ impl Mapper189 {
         let bank_start_offset = (bank_index % chr_banks) * CHR_BANK_SIZE_1KB;
         let addr_offset = addr & addr_offset_mask;
 
-        (bank_start_offset + addr_offset) % self.chr_rom_bytes.len()
+        (bank_start_offset + addr_offset) % self.chr.size()
     }
 
     fn set_prg_bank_index(&mut self, byte: u8) {
@@ -127,6 +128,15 @@ impl Mapper for Mapper189 {
     fn hard_reset(&mut self, rom: &Rom) {
         *self = Mapper189::new(rom);
     }
+
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::from_indices(
+            self.prg_rom_bytes.len(),
+            self.chr.size(),
+            |index| self.get_prg_rom_index(index),
+            |index| self.get_chr_index(index),
+        )
+    }
 }
 
 impl CpuMapper for Mapper189 {
@@ -147,42 +157,29 @@ impl CpuMapper for Mapper189 {
         let index = self.get_prg_ram_index(index);
         self.prg_ram_bytes[index] = byte;
     }
-}
 
-impl PpuMapper for Mapper189 {
-    #[inline(always)]
-    fn read_chr_rom(&self, index: u16) -> u8 {
-        let index = self.get_chr_rom_index(index);
-        self.chr_rom_bytes[index]
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram_bytes)
     }
+}
 
-    #[inline(always)]
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8] {
-        // panic!("MMC3 range reads must check bank boundaries!");
-        let start_physical_index = self.get_chr_rom_index(range.start);
-        let len = (range.end - range.start) as usize;
-        let end_physical_index = start_physical_index + len;
-
-        &self.chr_rom_bytes[start_physical_index..end_physical_index]
+impl PpuMapper for Mapper189 {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        let index = self.get_chr_index(index);
+        self.chr.read(index)
     }
 
-    fn read_chr_ram(&self, _index: u16) -> u8 {
-        panic!("MMC3 does not support CHR RAM at this level.")
-    }
-    fn read_chr_ram_range(&self, _range: Range<u16>) -> &[u8] {
-        panic!("MMC3 does not support CHR RAM at this level.")
-    }
-    fn write_chr_ram(&mut self, _index: u16, _byte: u8) {
-        panic!("MMC3 does not support CHR RAM at this level.")
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        let start = self.get_chr_index(range.start);
+        self.chr.read_range(start, range.len())
     }
 
-    #[inline(always)]
-    fn get_mirrored_index(&self, index: u16) -> u16 {
-        let index = index - 0x2000;
-        match self.mirroring_mode {
-            MirroringMode::Horizontal => ((index / 0x800) * 0x400) + (index % 0x400),
-            MirroringMode::Vertical => index & 0x7FF,
-        }
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        let index = self.get_chr_index(index);
+        self.chr.write(index, byte)
     }
 }
 
@@ -201,6 +198,10 @@ impl MapperIrq for Mapper189 {
     }
 }
 
+impl MapperAudio for Mapper189 {}
+
+impl MapperBattery for Mapper189 {}
+
 impl Mapper189 {
     fn irq_tick(&mut self) {
         if self.irq_counter == 0 || self.irq_reload {
@@ -221,12 +222,9 @@ impl MemMapped for Mapper189 {
         match index {
             0x0000..=0x1FFF => {
                 self.clock_irq(index);
-                self.read_chr_rom(index)
-            }
-            0x2000..=0x3FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.read(index)
+                self.read_chr(index)
             }
+            0x2000..=0x3FFF => self.nametables.read(index),
             0x6000..=0x7FFF => self.read_prg_ram(index),
             // MMC3 registers are write-only from 0x8000-0xFFFF, so this is PRG ROM read
             0x8000..=0xFFFF => self.read_prg_rom(index),
@@ -238,10 +236,7 @@ impl MemMapped for Mapper189 {
     fn write(&mut self, index: u16, byte: u8) {
         match index {
             0x0000..=0x1FFF => return,
-            0x2000..=0x3FFF => {
-                let index = self.get_mirrored_index(index);
-                self.vram.write(index, byte)
-            }
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
             0x4020..=0x5FFF => {
                 self.set_prg_bank_index(byte);
             }
@@ -259,11 +254,11 @@ impl MemMapped for Mapper189 {
                         self.r[self.bank_index as usize] = byte;
                     }
                     0xA000..=0xBFFF if index % 2 == 0 => {
-                        self.mirroring_mode = match byte & 1 {
+                        self.nametables.set_mirroring(match byte & 1 {
                             1 => MirroringMode::Horizontal,
                             0 => MirroringMode::Vertical,
                             _ => unreachable!(),
-                        }
+                        });
                     }
                     0xA001 | 0xA003 | 0xA005 | 0xA007 => {
                         // Note: MMC3 games rarely rely on the protection bits (6, 7), focusing only on enable.
@@ -299,7 +294,7 @@ impl MemMapped for Mapper189 {
                 for addr in range.start..range.end {
                     self.clock_irq(addr);
                 }
-                self.read_chr_rom_range(range)
+                self.read_chr_range(range)
             }
             _ => unimplemented!(),
         }
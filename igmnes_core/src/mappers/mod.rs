@@ -1,23 +1,106 @@
+mod chr_latch;
+mod chr_memory;
+mod fds_audio;
+mod flash;
 mod mapper_000;
 mod mapper_002;
 mod mapper_003;
 mod mapper_004;
 mod mapper_007;
+mod mapper_009;
+mod mapper_010;
+mod mapper_011;
+mod mapper_013;
+mod mapper_020;
+mod mapper_028;
+mod mapper_030;
+mod mapper_034;
+mod mapper_066;
+mod mapper_071;
+mod mapper_079;
+mod mapper_085;
+mod mapper_099;
+mod mapper_111;
 mod mapper_189;
+mod nametables;
+mod nsf_player;
+mod opll;
+mod vrc_irq;
 
 use self::mapper_000::NRom;
 use crate::mappers::mapper_003::CNROM;
 use crate::mappers::mapper_004::Mmc3;
 use crate::mappers::mapper_007::AxROM;
+use crate::mappers::mapper_009::Mmc2;
+use crate::mappers::mapper_010::Mmc4;
+use crate::mappers::mapper_011::ColorDreams;
+use crate::mappers::mapper_013::Cprom;
+use crate::mappers::mapper_020::Fds;
+use crate::mappers::mapper_028::Action53;
+use crate::mappers::mapper_030::Unrom512;
+use crate::mappers::mapper_034::Mapper034;
+use crate::mappers::mapper_066::GxROM;
+use crate::mappers::mapper_071::Camerica;
+use crate::mappers::mapper_079::Nina0306;
+use crate::mappers::mapper_085::Vrc7;
+use crate::mappers::mapper_099::VsUniSystem;
+use crate::mappers::mapper_111::Gtrom;
+use crate::mappers::nsf_player::NsfPlayer;
 use crate::mappers::{mapper_002::UxROM, mapper_189::Mapper189};
 use crate::memory::MemMapped;
 use crate::rom::Rom;
 use enum_dispatch::enum_dispatch;
+use std::array;
+use std::borrow::Cow;
 use std::ops::{Deref, DerefMut, Range};
 
 #[enum_dispatch]
 pub trait Mapper: Sized {
     fn hard_reset(&mut self, rom: &Rom);
+
+    // Banks currently mapped, for debugging tools. Boards without PRG ROM (FDS, NSF) leave
+    // them at 0
+    fn bank_state(&self) -> MapperBankState {
+        MapperBankState::default()
+    }
+}
+
+// PRG ROM and CHR banks currently mapped, counted in 8 KB units of PRG ROM and 1 KB units of
+// CHR whatever the board's own bank sizes are
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct MapperBankState {
+    // At $8000, $A000, $C000 and $E000
+    pub prg_banks: [u16; 4],
+    // At $0000, $0400, ... $1C00
+    pub chr_banks: [u16; 8],
+}
+
+impl MapperBankState {
+    // From the functions a board resolves PRG ROM and CHR addresses to offsets with
+    pub fn from_indices<P, C>(
+        prg_rom_len: usize,
+        chr_len: usize,
+        prg_rom_index: P,
+        chr_index: C,
+    ) -> MapperBankState
+    where
+        P: Fn(u16) -> usize,
+        C: Fn(u16) -> usize,
+    {
+        let bank = |offset: usize, len: usize, bank_size: usize| {
+            ((offset % len.max(1)) / bank_size) as u16
+        };
+        MapperBankState {
+            prg_banks: array::from_fn(|slot| {
+                let offset = prg_rom_index(0x8000 + slot as u16 * 0x2000);
+                bank(offset, prg_rom_len, 0x2000)
+            }),
+            chr_banks: array::from_fn(|slot| {
+                let offset = chr_index(slot as u16 * 0x400);
+                bank(offset, chr_len, 0x400)
+            }),
+        }
+    }
 }
 
 #[enum_dispatch]
@@ -27,47 +110,174 @@ pub trait CpuMapper: MemMapped {
     // Reads/Writes to PRG RAM
     fn read_prg_ram(&self, index: u16) -> u8;
     fn write_prg_ram(&mut self, index: u16, byte: u8);
+
+    // PRG RAM mapped at $6000-$7FFF, None if the cart has none there
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // Whether a CPU read from $4020-$FFFF is left unanswered, returning the last value on the
+    // data bus. By default that's $4020-$5FFF and $6000-$7FFF without PRG RAM
+    fn is_open_bus(&mut self, index: u16) -> bool {
+        match index {
+            0x4020..=0x5FFF => true,
+            0x6000..=0x7FFF => self.prg_ram_mut().is_none(),
+            _ => false,
+        }
+    }
 }
 
 #[enum_dispatch]
 pub trait PpuMapper: MemMapped {
-    // Reads from CHR ROM
-    fn read_chr_rom(&self, index: u16) -> u8;
-    fn read_chr_rom_range(&self, range: Range<u16>) -> &[u8];
-
-    // Reads/Writes to CHR RAM
-    fn read_chr_ram(&self, index: u16) -> u8;
-    fn read_chr_ram_range(&self, range: Range<u16>) -> &[u8];
-    fn write_chr_ram(&mut self, index: u16, byte: u8);
+    // Reads/Writes to CHR ROM/RAM, through the mapper's CHR banking
+    fn read_chr(&self, index: u16) -> u8;
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8];
+    fn write_chr(&mut self, index: u16, byte: u8);
 
-    fn get_mirrored_index(&self, index: u16) -> u16;
+    // Called after the PPU fetches a background tile or sprite pattern,
+    // with the address of the high bit plane byte
+    fn observe_pattern_fetch(&mut self, _addr: u16) {}
 }
 
 #[enum_dispatch]
 pub trait MapperIrq {
     fn clock_irq(&mut self, _addr: u16) {}
+    // Called once per CPU cycle, for mappers with cycle based IRQ counters
+    fn clock_cpu_irq(&mut self) {}
     fn irq_pending(&self) -> bool {
         false
     }
 }
 
-#[enum_dispatch(Mapper, CpuMapper, PpuMapper, MapperIrq, MemMapped)]
+#[enum_dispatch]
+pub trait MapperAudio {
+    // Called once per CPU cycle, for mappers with expansion audio
+    fn clock_audio(&mut self) {}
+    // Expansion audio output, mixed with the APU output
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+}
+
+#[enum_dispatch]
+pub trait MapperBattery {
+    // Contents of battery backed PRG RAM (or self-flashed PRG ROM) that should persist
+    // between sessions, None if the cart doesn't have any
+    fn battery_data(&self) -> Option<Cow<'_, [u8]>> {
+        None
+    }
+    fn load_battery_data(&mut self, _data: &[u8]) {}
+}
+
+#[enum_dispatch(
+    Mapper,
+    CpuMapper,
+    PpuMapper,
+    MapperIrq,
+    MapperAudio,
+    MapperBattery,
+    MemMapped
+)]
 pub enum MapperImpl {
     Mapper000(NRom),
     Mapper002(UxROM),
     Mapper003(CNROM),
     Mapper004(Mmc3),
     Mapper007(AxROM),
+    Mapper009(Mmc2),
+    Mapper010(Mmc4),
+    Mapper011(ColorDreams),
+    Mapper013(Cprom),
+    // Famicom Disk System
+    Mapper020(Fds),
+    Mapper028(Action53),
+    Mapper030(Unrom512),
+    Mapper034(Mapper034),
+    Mapper066(GxROM),
+    Mapper071(Camerica),
+    // Also used for mapper 113
+    Mapper079(Nina0306),
+    Mapper085(Vrc7),
+    Mapper099(VsUniSystem),
+    Mapper111(Gtrom),
     Mapper189(Mapper189),
+    // NSF music player, not an actual cart
+    Nsf(NsfPlayer),
+}
+
+// Discrete logic boards without bus conflict prevention see the written value ANDed with the
+// PRG ROM byte at the same address, as both drive the data bus during the write
+#[inline]
+pub fn resolve_bus_conflict<M: CpuMapper>(
+    mapper: &M,
+    has_bus_conflicts: bool,
+    index: u16,
+    byte: u8,
+) -> u8 {
+    if has_bus_conflicts {
+        byte & mapper.read_prg_rom(index)
+    } else {
+        byte
+    }
+}
+
+// NES 2.0 submappers of UxROM, CNROM and AxROM: 1 - no bus conflicts, 2 - AND-type bus conflicts
+pub fn has_bus_conflicts(rom: &Rom, default: bool) -> bool {
+    match rom.header.submapper_number() {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
+}
+
+impl MapperImpl {
+    pub fn fds(&mut self) -> Option<&mut Fds> {
+        match self {
+            MapperImpl::Mapper020(fds) => Some(fds),
+            _ => None,
+        }
+    }
+
+    pub fn nsf_player(&mut self) -> Option<&mut NsfPlayer> {
+        match self {
+            MapperImpl::Nsf(nsf_player) => Some(nsf_player),
+            _ => None,
+        }
+    }
+
+    pub fn vs_unisystem(&mut self) -> Option<&mut VsUniSystem> {
+        match self {
+            MapperImpl::Mapper099(vs_unisystem) => Some(vs_unisystem),
+            _ => None,
+        }
+    }
 }
 
 pub fn load_mapper_for_rom(rom: &Rom) -> Result<MapperImpl, String> {
+    if rom.nsf.is_some() {
+        return Ok(NsfPlayer::new(rom).into());
+    }
+
     let mapper: MapperImpl = match rom.header.mapper_number {
         0 => NRom::new(rom).into(),
         2 => UxROM::new(rom).into(),
         3 => CNROM::new(rom).into(),
-        4 => Mmc3::new(rom).into(),
+        4 | 119 => Mmc3::new(rom).into(),
         7 => AxROM::new(rom).into(),
+        9 => Mmc2::new(rom).into(),
+        10 => Mmc4::new(rom).into(),
+        11 => ColorDreams::new(rom).into(),
+        13 => Cprom::new(rom).into(),
+        20 => Fds::new(rom).into(),
+        28 => Action53::new(rom).into(),
+        30 => Unrom512::new(rom).into(),
+        34 => Mapper034::new(rom).into(),
+        66 => GxROM::new(rom).into(),
+        71 => Camerica::new(rom).into(),
+        79 | 113 => Nina0306::new(rom).into(),
+        85 => Vrc7::new(rom).into(),
+        99 => VsUniSystem::new(rom).into(),
+        111 => Gtrom::new(rom).into(),
         189 => Mapper189::new(rom).into(),
         mapper_num @ _ => return Err(format!("Unsupported mapper number: {}", mapper_num)),
     };
@@ -122,3 +332,77 @@ pub fn default_mapper() -> MapperImpl {
     let def_rom = Rom::default();
     NRom::new(&def_rom).into()
 }
+
+// Cart whose PRG ROM banks start with their bank number and are filled with $FF, so that writes
+// past the first byte don't lose bits to bus conflicts, and whose CHR ROM banks are filled with
+// their bank number. No CHR ROM banks makes it a CHR RAM cart.
+#[cfg(test)]
+pub fn test_rom(
+    mapper_number: u16,
+    prg_bank_size: usize,
+    prg_banks: usize,
+    chr_bank_size: usize,
+    chr_banks: usize,
+) -> Rom {
+    use crate::rom::Header;
+
+    let prg_rom_bytes = (0..prg_banks)
+        .flat_map(|bank| {
+            let mut bank_bytes = vec![0xFF; prg_bank_size];
+            bank_bytes[0] = bank as u8;
+            bank_bytes
+        })
+        .collect::<Vec<u8>>();
+    let chr_rom_bytes = (0..chr_banks)
+        .flat_map(|bank| vec![bank as u8; chr_bank_size])
+        .collect::<Vec<u8>>();
+
+    Rom {
+        header: Header {
+            prg_rom_size: prg_rom_bytes.len(),
+            chr_rom_size: chr_rom_bytes.len(),
+            mapper_number,
+            ..Header::default()
+        },
+        prg_rom_bytes,
+        chr_rom_bytes,
+        ..Rom::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::HeaderExtension;
+
+    #[test]
+    fn bus_conflicts_and_the_written_value_with_prg_rom() {
+        let mut rom = test_rom(0, 0x4000, 2, 0x2000, 1);
+        rom.prg_rom_bytes[0x1234] = 0b1010_1010;
+        let nrom = NRom::new(&rom);
+
+        assert_eq!(
+            resolve_bus_conflict(&nrom, true, 0x9234, 0b1100_1100),
+            0b1000_1000
+        );
+        assert_eq!(
+            resolve_bus_conflict(&nrom, false, 0x9234, 0b1100_1100),
+            0b1100_1100
+        );
+    }
+
+    #[test]
+    fn bus_conflicts_follow_the_submapper() {
+        let mut rom = test_rom(2, 0x4000, 2, 0, 0);
+        assert!(has_bus_conflicts(&rom, true));
+        assert!(!has_bus_conflicts(&rom, false));
+
+        for &(submapper_number, expected) in &[(0, true), (1, false), (2, true)] {
+            rom.header.extension = Some(HeaderExtension {
+                mapper_number: 2,
+                submapper_number,
+            });
+            assert_eq!(has_bus_conflicts(&rom, true), expected);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::rom::{MirroringMode, Rom};
+
+// Nametable memory, PPU $2000-$2FFF (mirrored at $3000-$3EFF)
+//
+// The console has 2 KB of internal VRAM (CIRAM), enough for two of the four 1 KB nametables.
+// The cart decides which page of memory each nametable maps to: a CIRAM page, a page of extra
+// VRAM on four-screen carts, or memory supplied by the mapper (CHR banks, expansion RAM).
+//
+// Mappers either select one of the standard mirroring modes or map pages individually.
+// Carts with four-screen VRAM are hardwired, so mapping changes made by the mapper are ignored.
+
+const PAGE_SIZE: usize = 0x400; // 1 KB
+const CIRAM_SIZE: usize = 0x800; // 2 KB
+const FOUR_SCREEN_VRAM_SIZE: usize = 0x1000; // 4 KB
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NametablePage {
+    // Page of the console's internal VRAM, 0 (A) or 1 (B)
+    Ciram(usize),
+    // Page of the four-screen VRAM on the cart, 0-3
+    CartVram(usize),
+    // Page of memory supplied by the mapper, the index is mapper defined
+    Mapper(usize),
+}
+
+#[derive(Clone)]
+pub struct Nametables {
+    ciram: [u8; CIRAM_SIZE],
+    cart_vram: Vec<u8>,
+    pages: [NametablePage; 4],
+    is_hardwired: bool,
+}
+
+impl Nametables {
+    // Mirroring set by the header, or four-screen VRAM if the header asks for it
+    pub fn new(rom: &Rom) -> Nametables {
+        let mut nametables = if rom.header.four_screen_mode {
+            Nametables::with_mirroring(MirroringMode::FourScreen)
+        } else {
+            Nametables::with_mirroring(rom.header.mirroring_mode)
+        };
+        nametables.is_hardwired = rom.header.four_screen_mode;
+        nametables
+    }
+
+    // Mirroring set by the mapper, for boards that don't use the header mirroring
+    pub fn with_mirroring(mirroring_mode: MirroringMode) -> Nametables {
+        let mut nametables = Nametables {
+            ciram: [0xFF; CIRAM_SIZE],
+            cart_vram: Vec::new(),
+            pages: [NametablePage::Ciram(0); 4],
+            is_hardwired: false,
+        };
+        nametables.map_pages(mirroring_mode);
+        nametables
+    }
+
+    pub fn set_mirroring(&mut self, mirroring_mode: MirroringMode) {
+        if !self.is_hardwired {
+            self.map_pages(mirroring_mode);
+        }
+    }
+
+    // Maps a single nametable (0-3) to a page
+    pub fn set_page(&mut self, nametable_index: usize, page: NametablePage) {
+        if !self.is_hardwired {
+            self.pages[nametable_index & 0b11] = page;
+        }
+    }
+
+    fn map_pages(&mut self, mirroring_mode: MirroringMode) {
+        use NametablePage::*;
+
+        self.pages = match mirroring_mode {
+            MirroringMode::Horizontal => [Ciram(0), Ciram(0), Ciram(1), Ciram(1)],
+            MirroringMode::Vertical => [Ciram(0), Ciram(1), Ciram(0), Ciram(1)],
+            MirroringMode::SingleScreenA => [Ciram(0); 4],
+            MirroringMode::SingleScreenB => [Ciram(1); 4],
+            MirroringMode::FourScreen => {
+                if self.cart_vram.is_empty() {
+                    self.cart_vram = vec![0; FOUR_SCREEN_VRAM_SIZE];
+                }
+                [CartVram(0), CartVram(1), CartVram(2), CartVram(3)]
+            }
+        };
+    }
+
+    // The page a PPU address maps to, and the offset within the page
+    #[inline]
+    pub fn lookup(&self, index: u16) -> (NametablePage, usize) {
+        let index = index as usize & 0xFFF;
+        (self.pages[index / PAGE_SIZE], index % PAGE_SIZE)
+    }
+
+    // Pages supplied by the mapper read as 0 and ignore writes here,
+    // mappers that use them should `lookup` the page first
+    #[inline]
+    pub fn read(&self, index: u16) -> u8 {
+        match self.lookup(index) {
+            (NametablePage::Ciram(page), offset) => self.ciram[(page & 1) * PAGE_SIZE + offset],
+            (NametablePage::CartVram(page), offset) => {
+                self.cart_vram[(page & 0b11) * PAGE_SIZE + offset]
+            }
+            (NametablePage::Mapper(_), _) => 0,
+        }
+    }
+
+    #[inline]
+    pub fn write(&mut self, index: u16, byte: u8) {
+        match self.lookup(index) {
+            (NametablePage::Ciram(page), offset) => {
+                self.ciram[(page & 1) * PAGE_SIZE + offset] = byte
+            }
+            (NametablePage::CartVram(page), offset) => {
+                self.cart_vram[(page & 0b11) * PAGE_SIZE + offset] = byte
+            }
+            (NametablePage::Mapper(_), _) => (),
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use crate::mappers::chr_memory::ChrMemory;
+use crate::mappers::fds_audio::FdsAudio;
+use crate::mappers::mapper_085::AUDIO_GAIN as VRC7_AUDIO_GAIN;
+use crate::mappers::nametables::Nametables;
+use crate::mappers::opll::{Opll, CPU_CYCLES_PER_SAMPLE};
+use crate::mappers::{CpuMapper, Mapper, MapperAudio, MapperBattery, MapperIrq, PpuMapper};
+use crate::memory::{MemMapConfig, MemMapped};
+use crate::nsf::{Nsf, NsfExpansionChips};
+use crate::rom::{MirroringMode, Rom};
+use crate::CPU_CLOCK_RATIO_NTSC;
+use std::ops::Range;
+
+// NSF player, a virtual cart that plays NSF music rips
+//
+// Banks
+//     CPU $4100-$41FF: player driver and registers
+//     CPU $5FF8-$5FFF: bank registers for the 4 KB banks at $8000-$FFFF
+//     CPU $6000-$7FFF: 8 KB PRG RAM
+//     CPU $8000-$FFFF: eight 4 KB switchable banks of tune code and data
+//     PPU $0000-$1FFF: 8 KB CHR RAM (unused)
+//
+// The vectors point into a small driver that initializes the APU, calls the tune's init routine
+// with the selected song, and then calls the play routine whenever the play timer expires.
+//
+// Tunes using the FDS expansion audio get RAM at $6000-$FFFF instead, and the bank registers
+// (including $5FF6 and $5FF7 for $6000-$7FFF) copy banks of tune data into it.
+
+const BANK_SIZE: usize = 0x1000; // 4 KB
+const PRG_RAM_SIZE: usize = 0x2000; // 8 KB
+const FDS_RAM_SIZE: usize = 0xA000; // 40 KB
+
+const DRIVER_ADDR: u16 = 0x4100;
+const INTERRUPT_HANDLER_ADDR: u16 = 0x4130;
+const INIT_TRAMPOLINE_ADDR: u16 = 0x41F0;
+const PLAY_TRAMPOLINE_ADDR: u16 = 0x41F3;
+const SONG_REG_ADDR: u16 = 0x41F8;
+const REGION_REG_ADDR: u16 = 0x41F9;
+const PLAY_PENDING_REG_ADDR: u16 = 0x41FA;
+
+const DRIVER_CODE: [u8; 0x30] = [
+    0x78, //             SEI
+    0xD8, //             CLD
+    0xA2, 0xFF, //       LDX #$FF
+    0x9A, //             TXS
+    0xA9, 0x00, //       LDA #$00
+    0xA2, 0x13, //       LDX #$13
+    0x9D, 0x00, 0x40, // STA $4000,X    ; Silence the APU channels
+    0xCA, //             DEX
+    0x10, 0xFA, //       BPL $4109
+    0x8D, 0x15, 0x40, // STA $4015
+    0xA9, 0x0F, //       LDA #$0F
+    0x8D, 0x15, 0x40, // STA $4015      ; Enable the APU channels, except for DMC
+    0xA9, 0x40, //       LDA #$40
+    0x8D, 0x17, 0x40, // STA $4017      ; Disable the frame counter IRQ
+    0xAD, 0xF8, 0x41, // LDA $41F8      ; Song number
+    0xAE, 0xF9, 0x41, // LDX $41F9      ; Region (0: NTSC)
+    0x20, 0xF0, 0x41, // JSR $41F0      ; Init routine
+    0x2C, 0xFA, 0x41, // BIT $41FA      ; Wait for the play timer
+    0x10, 0xFB, //       BPL $4125
+    0x20, 0xF3, 0x41, // JSR $41F3      ; Play routine
+    0x4C, 0x25, 0x41, // JMP $4125
+];
+
+#[derive(Clone)]
+pub struct NsfPlayer {
+    nsf: Nsf,
+    nametables: Nametables,
+    prg_rom_bytes: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram_bytes: Vec<u8>,
+
+    // $6000-$FFFF in 4 KB banks, only the last 8 are switchable unless the tune uses FDS audio
+    banks: [u8; 10],
+    is_fds: bool,
+    fds_ram_bytes: Vec<u8>,
+
+    song: usize,
+    play_period: u32,
+    play_counter: u32,
+    is_play_pending: bool,
+
+    opll: Option<Box<Opll>>,
+    audio_cycles: u8,
+    fds_audio: Option<Box<FdsAudio>>,
+
+    mem_map_config: MemMapConfig,
+}
+
+impl NsfPlayer {
+    pub fn new(rom: &Rom) -> NsfPlayer {
+        let nsf = rom.nsf.clone().unwrap_or_default();
+        let is_fds = nsf.expansion_chips.contains(NsfExpansionChips::FDS);
+
+        // Tunes that aren't bankswitched are loaded at the load address, bankswitched ones
+        // at the load address within the first bank
+        let (padding, banks) = if nsf.is_bankswitched() {
+            let mut banks = [0; 10];
+            banks[2..].copy_from_slice(&nsf.initial_banks);
+            banks[0] = nsf.initial_banks[6];
+            banks[1] = nsf.initial_banks[7];
+            (nsf.load_addr as usize & (BANK_SIZE - 1), banks)
+        } else {
+            let first_addr = if is_fds { 0x6000 } else { 0x8000 };
+            let first_bank = (first_addr - 0x6000) / BANK_SIZE;
+            let mut banks = [0; 10];
+            for (index, bank) in banks.iter_mut().enumerate().skip(first_bank) {
+                *bank = (index - first_bank) as u8;
+            }
+            (nsf.load_addr as usize - first_addr, banks)
+        };
+
+        let mut prg_rom_bytes = vec![0; padding];
+        prg_rom_bytes.extend_from_slice(&rom.prg_rom_bytes);
+        let bank_count = prg_rom_bytes.len().div_ceil(BANK_SIZE);
+        prg_rom_bytes.resize(bank_count.max(1) * BANK_SIZE, 0);
+
+        let play_period = (nsf.play_speed as f32 * CPU_CLOCK_RATIO_NTSC / 1_000_000.0) as u32;
+
+        let mut player = NsfPlayer {
+            nametables: Nametables::with_mirroring(MirroringMode::Horizontal),
+            prg_rom_bytes,
+            chr: ChrMemory::new(rom),
+            prg_ram_bytes: vec![0; PRG_RAM_SIZE],
+
+            banks,
+            is_fds,
+            fds_ram_bytes: if is_fds {
+                vec![0; FDS_RAM_SIZE]
+            } else {
+                Vec::new()
+            },
+
+            song: nsf.starting_song,
+            play_period,
+            play_counter: 0,
+            is_play_pending: false,
+
+            opll: if nsf.expansion_chips.contains(NsfExpansionChips::VRC7) {
+                Some(Box::new(Opll::new()))
+            } else {
+                None
+            },
+            audio_cycles: 0,
+            fds_audio: if is_fds {
+                Some(Box::new(FdsAudio::new()))
+            } else {
+                None
+            },
+
+            mem_map_config: MemMapConfig::default(),
+
+            nsf,
+        };
+
+        if is_fds {
+            for slot in 0..player.banks.len() {
+                player.copy_bank_to_fds_ram(slot);
+            }
+        }
+
+        player
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    // 0 based
+    pub fn song(&self) -> usize {
+        self.song
+    }
+
+    // Takes effect on the next reset, which runs the init routine again
+    pub fn select_song(&mut self, song: usize) {
+        if song < self.nsf.song_count() {
+            self.song = song;
+        }
+    }
+
+    fn get_prg_rom_index(&self, index: u16) -> usize {
+        let slot = (index as usize - 0x6000) / BANK_SIZE;
+        self.get_bank_index(self.banks[slot]) + (index as usize & (BANK_SIZE - 1))
+    }
+
+    fn get_bank_index(&self, bank: u8) -> usize {
+        let bank_count = self.prg_rom_bytes.len() / BANK_SIZE;
+        (bank as usize % bank_count) * BANK_SIZE
+    }
+
+    fn copy_bank_to_fds_ram(&mut self, slot: usize) {
+        let bank_index = self.get_bank_index(self.banks[slot]);
+        let ram_index = slot * BANK_SIZE;
+        self.fds_ram_bytes[ram_index..ram_index + BANK_SIZE]
+            .copy_from_slice(&self.prg_rom_bytes[bank_index..bank_index + BANK_SIZE]);
+    }
+
+    fn read_driver(&mut self, index: u16) -> u8 {
+        let [init_lo, init_hi] = self.nsf.init_addr.to_le_bytes();
+        let [play_lo, play_hi] = self.nsf.play_addr.to_le_bytes();
+
+        match index {
+            INIT_TRAMPOLINE_ADDR => 0x4C, // JMP
+            0x41F1 => init_lo,
+            0x41F2 => init_hi,
+            PLAY_TRAMPOLINE_ADDR => 0x4C, // JMP
+            0x41F4 => play_lo,
+            0x41F5 => play_hi,
+            SONG_REG_ADDR => self.song as u8,
+            REGION_REG_ADDR => 0,
+            PLAY_PENDING_REG_ADDR => {
+                let is_play_pending = self.is_play_pending;
+                if self.is_mutating_read() {
+                    self.is_play_pending = false;
+                }
+                (is_play_pending as u8) << 7
+            }
+            INTERRUPT_HANDLER_ADDR => 0x40, // RTI
+            _ => DRIVER_CODE
+                .get((index - DRIVER_ADDR) as usize)
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    fn read_vector(&self, index: u16) -> u8 {
+        let [reset_lo, reset_hi] = DRIVER_ADDR.to_le_bytes();
+        let [handler_lo, handler_hi] = INTERRUPT_HANDLER_ADDR.to_le_bytes();
+
+        match index {
+            0xFFFC => reset_lo,
+            0xFFFD => reset_hi,
+            0xFFFA | 0xFFFE => handler_lo,
+            _ => handler_hi,
+        }
+    }
+
+    fn write_bank_register(&mut self, index: u16, byte: u8) {
+        let slot = (index - 0x5FF6) as usize;
+        if self.is_fds {
+            self.banks[slot] = byte;
+            self.copy_bank_to_fds_ram(slot);
+        } else if slot >= 2 {
+            self.banks[slot] = byte;
+        }
+    }
+}
+
+impl Mapper for NsfPlayer {
+    // The selected song survives a reset
+    fn hard_reset(&mut self, rom: &Rom) {
+        let song = self.song;
+        *self = NsfPlayer::new(rom);
+        self.song = song;
+    }
+}
+
+impl CpuMapper for NsfPlayer {
+    #[inline]
+    fn read_prg_rom(&self, index: u16) -> u8 {
+        if self.is_fds {
+            self.fds_ram_bytes[(index - 0x6000) as usize]
+        } else {
+            self.prg_rom_bytes[self.get_prg_rom_index(index)]
+        }
+    }
+
+    #[inline]
+    fn read_prg_ram(&self, index: u16) -> u8 {
+        if self.is_fds {
+            self.fds_ram_bytes[(index - 0x6000) as usize]
+        } else {
+            self.prg_ram_bytes[(index - 0x6000) as usize]
+        }
+    }
+
+    #[inline]
+    fn write_prg_ram(&mut self, index: u16, byte: u8) {
+        if self.is_fds {
+            self.fds_ram_bytes[(index - 0x6000) as usize] = byte;
+        } else {
+            self.prg_ram_bytes[(index - 0x6000) as usize] = byte;
+        }
+    }
+
+    // The driver at $4100-$41FF, FDS sound registers and RAM at $6000-$7FFF
+    fn is_open_bus(&mut self, index: u16) -> bool {
+        match index {
+            0x4040..=0x407F | 0x4090 | 0x4092 => self.fds_audio.is_none(),
+            0x4100..=0x41FF => false,
+            0x4020..=0x5FFF => true,
+            _ => false,
+        }
+    }
+}
+
+impl PpuMapper for NsfPlayer {
+    #[inline]
+    fn read_chr(&self, index: u16) -> u8 {
+        self.chr.read(index as usize)
+    }
+
+    #[inline]
+    fn read_chr_range(&self, range: Range<u16>) -> &[u8] {
+        self.chr.read_range(range.start as usize, range.len())
+    }
+
+    #[inline]
+    fn write_chr(&mut self, index: u16, byte: u8) {
+        self.chr.write(index as usize, byte)
+    }
+}
+
+// The play timer isn't an IRQ, the driver polls it
+impl MapperIrq for NsfPlayer {
+    #[inline]
+    fn clock_cpu_irq(&mut self) {
+        self.play_counter += 1;
+        if self.play_counter >= self.play_period {
+            self.play_counter = 0;
+            self.is_play_pending = true;
+        }
+    }
+}
+
+impl MapperAudio for NsfPlayer {
+    #[inline]
+    fn clock_audio(&mut self) {
+        if let Some(opll) = self.opll.as_mut() {
+            self.audio_cycles += 1;
+            if self.audio_cycles == CPU_CYCLES_PER_SAMPLE {
+                self.audio_cycles = 0;
+                opll.clock();
+            }
+        }
+
+        if let Some(fds_audio) = self.fds_audio.as_mut() {
+            fds_audio.clock();
+        }
+    }
+
+    #[inline]
+    fn audio_output(&self) -> f32 {
+        let vrc7_output = self
+            .opll
+            .as_ref()
+            .map_or(0.0, |opll| opll.output() * VRC7_AUDIO_GAIN);
+        let fds_output = self
+            .fds_audio
+            .as_ref()
+            .map_or(0.0, |fds_audio| fds_audio.output());
+
+        vrc7_output + fds_output
+    }
+}
+
+impl MapperBattery for NsfPlayer {}
+
+impl MemMapped for NsfPlayer {
+    #[inline]
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0x0000..=0x1FFF => self.read_chr(index),
+            0x2000..=0x3FFF => self.nametables.read(index),
+            0x4040..=0x4092 => self
+                .fds_audio
+                .as_ref()
+                .map_or(0, |fds_audio| fds_audio.read(index)),
+            0x4100..=0x41FF => self.read_driver(index),
+            0x6000..=0x7FFF => self.read_prg_ram(index),
+            0xFFFA..=0xFFFF => self.read_vector(index),
+            0x8000..=0xFFFF => self.read_prg_rom(index),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, index: u16, byte: u8) {
+        match index {
+            0x0000..=0x1FFF => self.write_chr(index, byte),
+            0x2000..=0x3FFF => self.nametables.write(index, byte),
+            0x4040..=0x4092 => {
+                if let Some(fds_audio) = self.fds_audio.as_mut() {
+                    fds_audio.write(index, byte);
+                }
+            }
+            0x5FF6..=0x5FFF => self.write_bank_register(index, byte),
+            0x6000..=0x7FFF => self.write_prg_ram(index, byte),
+            0x8000..=0xFFFF => {
+                if self.is_fds {
+                    self.fds_ram_bytes[(index - 0x6000) as usize] = byte;
+                }
+
+                // VRC7 audio register select is at $9010, audio data at $9030
+                if let Some(opll) = self.opll.as_mut() {
+                    match index {
+                        0x9010 => opll.select_register(byte),
+                        0x9030 => opll.write_data(byte),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn read_range(&mut self, range: Range<u16>) -> &[u8] {
+        match range.start {
+            0x0000..=0x1FFF => self.read_chr_range(range),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn is_mutating_read(&self) -> bool {
+        self.mem_map_config.is_mutating_read
+    }
+
+    fn set_is_mutating_read(&mut self, is_mutating_read: bool) {
+        self.mem_map_config.is_mutating_read = is_mutating_read;
+    }
+}
@@ -0,0 +1,619 @@
+// Yamaha YM2413 (OPLL) FM synthesizer, in the cut-down form found inside the Konami VRC7
+//
+// The VRC7 variant has 6 melodic channels (no rhythm mode) and its own set of 15 built-in
+// instrument patches. Each channel is a 2-operator FM voice: a modulator slot that phase-modulates
+// a carrier slot. The chip produces one sample every 72 clocks of its 3.579545 MHz input,
+// which gives the internal 49716 Hz sample rate (exactly one sample per 36 NES CPU cycles).
+//
+// The envelope and phase generator model follows the structure of the real chip:
+// attenuation is accumulated in dB (envelope, total level, key scaling, tremolo) and converted
+// to linear amplitude only at the output of each slot.
+
+use std::f32::consts::PI;
+
+pub const OPLL_SAMPLE_RATE: f32 = 49_716.0;
+pub const CPU_CYCLES_PER_SAMPLE: u8 = 36;
+
+const CHANNEL_COUNT: usize = 6;
+
+const SINE_TABLE_BITS: u32 = 10;
+const SINE_TABLE_LEN: usize = 1 << SINE_TABLE_BITS;
+
+// Phase accumulator is 18 bits wide; the top 10 bits index the sine table
+const PHASE_BITS: u32 = 18;
+const PHASE_MASK: u32 = (1 << PHASE_BITS) - 1;
+
+// Envelope generator phase is 22 bits wide; the top 7 bits are the envelope level (0.375 dB steps)
+const EG_PHASE_BITS: u32 = 22;
+const EG_BITS: u32 = 7;
+const EG_PHASE_MAX: u32 = 1 << EG_PHASE_BITS;
+const EG_LEVEL_MAX: u8 = (1 << EG_BITS) - 1;
+const EG_STEP_DB: f32 = 0.375;
+
+// Anything quieter than this is treated as silence
+const MAX_ATTENUATION_DB: f32 = 96.0;
+
+// Tremolo: 4.875 dB deep at ~3.7 Hz, vibrato: 14 cents deep at ~6.4 Hz
+const AM_DEPTH_DB: f32 = 4.875;
+const AM_FREQUENCY: f32 = 3.7;
+const PM_DEPTH_CENTS: f32 = 14.0;
+const PM_FREQUENCY: f32 = 6.4;
+
+// Frequency multiplier, doubled so that the 1/2 multiplier stays integral
+const MULTIPLIER_X2: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// Key scale level attenuation (dB) for the top 4 bits of the F-number at block 7
+const KSL_TABLE_DB: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+    42.0,
+];
+
+// 0, 1.5, 3 and 6 dB/octave
+const KSL_SCALE: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+// Built-in VRC7 instrument patches (patch 0 is the user-defined instrument)
+const VRC7_PATCHES: [[u8; 8]; 16] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+#[derive(Debug, Default, Clone, Copy)]
+struct OperatorPatch {
+    is_tremolo_enabled: bool,
+    is_vibrato_enabled: bool,
+    // Sustained (1) or percussive (0) envelope
+    is_sustained: bool,
+    is_key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    // Half-sine (rectified) waveform
+    is_rectified: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Patch {
+    modulator: OperatorPatch,
+    carrier: OperatorPatch,
+    // Modulator total level
+    total_level: u8,
+    feedback: u8,
+}
+
+impl From<&[u8; 8]> for Patch {
+    fn from(bytes: &[u8; 8]) -> Self {
+        // $00/$01: AM, VIB, EG type, KSR, multiplier
+        // $02:     modulator KSL, modulator total level
+        // $03:     carrier KSL, carrier/modulator rectified waveform, feedback
+        // $04/$05: attack rate, decay rate
+        // $06/$07: sustain level, release rate
+        let operator = |index: usize, key_scale_level: u8, is_rectified: bool| OperatorPatch {
+            is_tremolo_enabled: bytes[index] & 0x80 != 0,
+            is_vibrato_enabled: bytes[index] & 0x40 != 0,
+            is_sustained: bytes[index] & 0x20 != 0,
+            is_key_scale_rate: bytes[index] & 0x10 != 0,
+            multiplier: bytes[index] & 0x0F,
+            key_scale_level,
+            is_rectified,
+            attack_rate: bytes[4 + index] >> 4,
+            decay_rate: bytes[4 + index] & 0x0F,
+            sustain_level: bytes[6 + index] >> 4,
+            release_rate: bytes[6 + index] & 0x0F,
+        };
+
+        Patch {
+            modulator: operator(0, bytes[2] >> 6, bytes[3] & 0x08 != 0),
+            carrier: operator(1, bytes[3] >> 6, bytes[3] & 0x10 != 0),
+            total_level: bytes[2] & 0x3F,
+            feedback: bytes[3] & 0x07,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    // Sustained envelope holding at the sustain level
+    SustainHold,
+    // Percussive envelope decaying at the release rate after reaching the sustain level
+    Sustain,
+    Release,
+    #[default]
+    Finished,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    phase: u32,
+    eg_state: EnvelopeState,
+    eg_phase: u32,
+    eg_level: u8,
+
+    // Last two outputs, used for modulator self-feedback
+    output: [f32; 2],
+}
+
+impl Slot {
+    fn key_on(&mut self) {
+        self.eg_state = EnvelopeState::Attack;
+        self.eg_phase = 0;
+        self.phase = 0;
+    }
+
+    fn key_off(&mut self, attack_curve: &[u8; 128]) {
+        if self.eg_state == EnvelopeState::Attack {
+            // Continue releasing from the current (exponential) attack level
+            let level = attack_curve[(self.eg_phase >> (EG_PHASE_BITS - EG_BITS)) as usize];
+            self.eg_phase = (level as u32) << (EG_PHASE_BITS - EG_BITS);
+        }
+        self.eg_state = EnvelopeState::Release;
+    }
+
+    fn clock_envelope(
+        &mut self,
+        operator: &OperatorPatch,
+        key_scale_rate: u8,
+        is_sustain_on: bool,
+        attack_curve: &[u8; 128],
+    ) {
+        use EnvelopeState::*;
+
+        let level_shift = EG_PHASE_BITS - EG_BITS;
+
+        match self.eg_state {
+            Attack => {
+                self.eg_level = attack_curve[(self.eg_phase >> level_shift) as usize];
+                self.eg_phase += attack_phase_increment(operator.attack_rate, key_scale_rate);
+                if self.eg_phase >= EG_PHASE_MAX || operator.attack_rate == 15 {
+                    self.eg_level = 0;
+                    self.eg_phase = 0;
+                    self.eg_state = Decay;
+                }
+            }
+            Decay => {
+                self.eg_level = (self.eg_phase >> level_shift) as u8;
+                self.eg_phase += decay_phase_increment(operator.decay_rate, key_scale_rate);
+                let sustain_phase = (operator.sustain_level as u32 * 8) << level_shift;
+                if self.eg_phase >= sustain_phase {
+                    self.eg_phase = sustain_phase;
+                    self.eg_state = if operator.is_sustained {
+                        SustainHold
+                    } else {
+                        Sustain
+                    };
+                }
+            }
+            SustainHold => {
+                self.eg_level = (self.eg_phase >> level_shift) as u8;
+            }
+            Sustain | Release => {
+                self.eg_level = (self.eg_phase >> level_shift).min(EG_LEVEL_MAX as u32) as u8;
+                let rate = if self.eg_state == Sustain {
+                    operator.release_rate
+                } else if is_sustain_on {
+                    5
+                } else if operator.is_sustained {
+                    operator.release_rate
+                } else {
+                    7
+                };
+                self.eg_phase += decay_phase_increment(rate, key_scale_rate);
+                if self.eg_phase >= EG_PHASE_MAX {
+                    self.eg_state = Finished;
+                    self.eg_level = EG_LEVEL_MAX;
+                }
+            }
+            Finished => {
+                self.eg_level = EG_LEVEL_MAX;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Channel {
+    f_number: u16,
+    block: u8,
+    is_key_on: bool,
+    is_sustain_on: bool,
+    instrument: u8,
+    volume: u8,
+
+    modulator: Slot,
+    carrier: Slot,
+}
+
+impl Channel {
+    fn key_scale_rate(&self, operator: &OperatorPatch) -> u8 {
+        let rate = (self.block << 1) | (self.f_number >> 8) as u8;
+        if operator.is_key_scale_rate {
+            rate
+        } else {
+            rate >> 2
+        }
+    }
+
+    fn key_scale_level_db(&self, operator: &OperatorPatch) -> f32 {
+        let base = KSL_TABLE_DB[(self.f_number >> 5) as usize] - 6.0 * (7 - self.block) as f32;
+        base.max(0.0) * KSL_SCALE[operator.key_scale_level as usize]
+    }
+
+    fn phase_increment(&self, operator: &OperatorPatch, vibrato: f32) -> u32 {
+        let base = (((self.f_number as u32) << self.block)
+            * MULTIPLIER_X2[operator.multiplier as usize])
+            >> 2;
+        if operator.is_vibrato_enabled {
+            (base as f32 * vibrato) as u32
+        } else {
+            base
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Opll {
+    registers: [u8; 0x40],
+    selected_register: u8,
+
+    custom_patch: Patch,
+    channels: [Channel; CHANNEL_COUNT],
+
+    sine_table: Box<[f32; SINE_TABLE_LEN]>,
+    attack_curve: [u8; 128],
+
+    lfo_sample_index: u32,
+
+    output: f32,
+}
+
+impl Default for Opll {
+    fn default() -> Self {
+        Opll::new()
+    }
+}
+
+impl Opll {
+    pub fn new() -> Opll {
+        let mut sine_table = Box::new([0.0; SINE_TABLE_LEN]);
+        for (index, value) in sine_table.iter_mut().enumerate() {
+            *value = (2.0 * PI * index as f32 / SINE_TABLE_LEN as f32).sin();
+        }
+
+        // The attack phase follows an exponential curve towards 0 dB
+        let mut attack_curve = [0u8; 128];
+        for (index, value) in attack_curve.iter_mut().enumerate() {
+            *value = if index == 0 {
+                EG_LEVEL_MAX
+            } else {
+                let level =
+                    EG_LEVEL_MAX as f32 - EG_LEVEL_MAX as f32 * (index as f32).ln() / 128f32.ln();
+                level.max(0.0) as u8
+            };
+        }
+
+        Opll {
+            registers: [0; 0x40],
+            selected_register: 0,
+            custom_patch: Patch::default(),
+            channels: [Channel::default(); CHANNEL_COUNT],
+            sine_table,
+            attack_curve,
+            lfo_sample_index: 0,
+            output: 0.0,
+        }
+    }
+
+    // Silences the chip and clears its registers, the lookup tables don't change
+    pub fn reset(&mut self) {
+        self.registers = [0; 0x40];
+        self.selected_register = 0;
+        self.custom_patch = Patch::default();
+        self.channels = [Channel::default(); CHANNEL_COUNT];
+        self.lfo_sample_index = 0;
+        self.output = 0.0;
+    }
+
+    #[cfg(test)]
+    pub fn register(&self, register: u8) -> u8 {
+        self.registers[register as usize]
+    }
+
+    pub fn select_register(&mut self, byte: u8) {
+        self.selected_register = byte;
+    }
+
+    pub fn write_data(&mut self, byte: u8) {
+        let register = self.selected_register as usize;
+        if register >= self.registers.len() {
+            return;
+        }
+        self.registers[register] = byte;
+
+        match register {
+            0x00..=0x07 => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&self.registers[0x00..0x08]);
+                self.custom_patch = Patch::from(&bytes);
+            }
+            0x10..=0x15 => {
+                let channel = &mut self.channels[register - 0x10];
+                channel.f_number = (channel.f_number & 0x100) | byte as u16;
+            }
+            0x20..=0x25 => {
+                let attack_curve = self.attack_curve;
+                let channel = &mut self.channels[register - 0x20];
+                channel.f_number = (channel.f_number & 0xFF) | (((byte & 0x01) as u16) << 8);
+                channel.block = (byte >> 1) & 0x07;
+                channel.is_sustain_on = byte & 0x20 != 0;
+
+                let is_key_on = byte & 0x10 != 0;
+                if is_key_on && !channel.is_key_on {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !is_key_on && channel.is_key_on {
+                    channel.modulator.key_off(&attack_curve);
+                    channel.carrier.key_off(&attack_curve);
+                }
+                channel.is_key_on = is_key_on;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[register - 0x30];
+                channel.instrument = byte >> 4;
+                channel.volume = byte & 0x0F;
+            }
+            _ => (),
+        }
+    }
+
+    fn patch(&self, instrument: u8) -> Patch {
+        if instrument == 0 {
+            self.custom_patch
+        } else {
+            Patch::from(&VRC7_PATCHES[instrument as usize])
+        }
+    }
+
+    #[inline]
+    fn wave(&self, phase: u32, is_rectified: bool) -> f32 {
+        let index = (phase & PHASE_MASK) >> (PHASE_BITS - SINE_TABLE_BITS);
+        let value = self.sine_table[index as usize];
+        if is_rectified && value < 0.0 {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    // Produces one sample at the chip's internal 49716 Hz rate
+    pub fn clock(&mut self) {
+        let lfo_time = self.lfo_sample_index as f32 / OPLL_SAMPLE_RATE;
+        self.lfo_sample_index = self.lfo_sample_index.wrapping_add(1);
+
+        // Tremolo is a triangle wave between 0 dB and the full depth
+        let am_phase = (lfo_time * AM_FREQUENCY).fract();
+        let tremolo_db = AM_DEPTH_DB * (1.0 - (2.0 * am_phase - 1.0).abs());
+        let vibrato =
+            2f32.powf(PM_DEPTH_CENTS * (2.0 * PI * PM_FREQUENCY * lfo_time).sin() / 1200.0);
+
+        let mut mix = 0.0;
+        for index in 0..CHANNEL_COUNT {
+            let mut channel = self.channels[index];
+            let patch = self.patch(channel.instrument);
+
+            // Modulator
+            let modulator = &patch.modulator;
+            let rate = channel.key_scale_rate(modulator);
+            channel.modulator.clock_envelope(
+                modulator,
+                rate,
+                channel.is_sustain_on,
+                &self.attack_curve,
+            );
+            let modulator_db = patch.total_level as f32 * 0.75
+                + channel.key_scale_level_db(modulator)
+                + self.envelope_db(&channel.modulator, modulator, tremolo_db);
+
+            let feedback_offset = if patch.feedback > 0 {
+                let average = (channel.modulator.output[0] + channel.modulator.output[1]) / 2.0;
+                // Full scale output corresponds to 4π of phase modulation
+                average * 2.0 / (1 << (7 - patch.feedback)) as f32
+            } else {
+                0.0
+            };
+            let modulator_phase = channel
+                .modulator
+                .phase
+                .wrapping_add(phase_from_cycles(feedback_offset));
+            let modulator_output =
+                self.wave(modulator_phase, modulator.is_rectified) * db_to_amplitude(modulator_db);
+            channel.modulator.output = [channel.modulator.output[1], modulator_output];
+            channel.modulator.phase = (channel.modulator.phase
+                + channel.phase_increment(modulator, vibrato))
+                & PHASE_MASK;
+
+            // Carrier
+            let carrier = &patch.carrier;
+            let rate = channel.key_scale_rate(carrier);
+            channel.carrier.clock_envelope(
+                carrier,
+                rate,
+                channel.is_sustain_on,
+                &self.attack_curve,
+            );
+            let carrier_db = channel.volume as f32 * 3.0
+                + channel.key_scale_level_db(carrier)
+                + self.envelope_db(&channel.carrier, carrier, tremolo_db);
+
+            let carrier_phase = channel
+                .carrier
+                .phase
+                .wrapping_add(phase_from_cycles(modulator_output * 2.0));
+            let carrier_output =
+                self.wave(carrier_phase, carrier.is_rectified) * db_to_amplitude(carrier_db);
+            channel.carrier.output = [channel.carrier.output[1], carrier_output];
+            channel.carrier.phase =
+                (channel.carrier.phase + channel.phase_increment(carrier, vibrato)) & PHASE_MASK;
+
+            self.channels[index] = channel;
+            mix += carrier_output;
+        }
+
+        self.output = mix / CHANNEL_COUNT as f32;
+    }
+
+    fn envelope_db(&self, slot: &Slot, operator: &OperatorPatch, tremolo_db: f32) -> f32 {
+        if slot.eg_state == EnvelopeState::Finished {
+            return MAX_ATTENUATION_DB;
+        }
+        let tremolo_db = if operator.is_tremolo_enabled {
+            tremolo_db
+        } else {
+            0.0
+        };
+        slot.eg_level as f32 * EG_STEP_DB + tremolo_db
+    }
+
+    // Output of all channels, in -1.0..=1.0
+    #[inline]
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+#[inline]
+fn db_to_amplitude(db: f32) -> f32 {
+    if db >= MAX_ATTENUATION_DB {
+        0.0
+    } else {
+        10f32.powf(-db / 20.0)
+    }
+}
+
+#[inline]
+fn phase_from_cycles(cycles: f32) -> u32 {
+    (cycles * (1 << PHASE_BITS) as f32) as i32 as u32
+}
+
+fn attack_phase_increment(rate: u8, key_scale_rate: u8) -> u32 {
+    match rate {
+        0 | 15 => 0,
+        _ => {
+            let (rate_high, rate_low) = effective_rate(rate, key_scale_rate);
+            (3 * (rate_low + 4)) << (rate_high + 1)
+        }
+    }
+}
+
+fn decay_phase_increment(rate: u8, key_scale_rate: u8) -> u32 {
+    match rate {
+        0 => 0,
+        _ => {
+            let (rate_high, rate_low) = effective_rate(rate, key_scale_rate);
+            (rate_low + 4) << (rate_high - 1)
+        }
+    }
+}
+
+fn effective_rate(rate: u8, key_scale_rate: u8) -> (u32, u32) {
+    let rate_high = (rate as u32 + (key_scale_rate as u32 >> 2)).min(15);
+    let rate_low = key_scale_rate as u32 & 0b11;
+    (rate_high, rate_low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(opll: &mut Opll, register: u8, byte: u8) {
+        opll.select_register(register);
+        opll.write_data(byte);
+    }
+
+    #[test]
+    fn channel_registers_are_decoded() {
+        let mut opll = Opll::new();
+        write(&mut opll, 0x12, 0xA5);
+        write(&mut opll, 0x22, 0b0011_1011);
+        write(&mut opll, 0x32, 0x7C);
+
+        let channel = &opll.channels[2];
+        assert_eq!(channel.f_number, 0x1A5);
+        assert_eq!(channel.block, 0b101);
+        assert!(channel.is_key_on);
+        assert!(channel.is_sustain_on);
+        assert_eq!(channel.instrument, 7);
+        assert_eq!(channel.volume, 0x0C);
+        assert_eq!(channel.carrier.eg_state, EnvelopeState::Attack);
+
+        // Key off moves the slots to the release phase, the F-number high bit is cleared
+        write(&mut opll, 0x22, 0b0000_1010);
+        let channel = &opll.channels[2];
+        assert_eq!(channel.f_number, 0x0A5);
+        assert!(!channel.is_key_on);
+        assert_eq!(channel.carrier.eg_state, EnvelopeState::Release);
+    }
+
+    #[test]
+    fn custom_patch_registers_are_decoded() {
+        let mut opll = Opll::new();
+        for (register, &byte) in VRC7_PATCHES[1].iter().enumerate() {
+            write(&mut opll, register as u8, byte);
+        }
+
+        let patch = opll.patch(0);
+        // Percussive modulator with multiplier 3, sustained carrier with multiplier 1
+        assert!(!patch.modulator.is_sustained && patch.carrier.is_sustained);
+        assert_eq!(patch.modulator.multiplier, 3);
+        assert_eq!(patch.carrier.multiplier, 1);
+        assert_eq!(patch.total_level, 0x05);
+        assert_eq!(patch.feedback, 6);
+        assert!(!patch.modulator.is_rectified && !patch.carrier.is_rectified);
+        assert_eq!(patch.modulator.attack_rate, 0xE);
+        assert_eq!(patch.modulator.decay_rate, 0x8);
+        assert_eq!(patch.carrier.attack_rate, 0x8);
+        assert_eq!(patch.carrier.decay_rate, 0x1);
+        assert_eq!(patch.modulator.sustain_level, 0x4);
+        assert_eq!(patch.carrier.release_rate, 0x7);
+    }
+
+    #[test]
+    fn unused_registers_are_ignored() {
+        let mut opll = Opll::new();
+        write(&mut opll, 0x16, 0xFF);
+        write(&mut opll, 0x40, 0xFF);
+        assert!(opll.channels.iter().all(|channel| channel.f_number == 0));
+    }
+
+    #[test]
+    fn reset_clears_channels_and_keeps_tables() {
+        let mut opll = Opll::new();
+        let sine_table = opll.sine_table.clone();
+        write(&mut opll, 0x00, 0xFF);
+        write(&mut opll, 0x20, 0x10);
+        opll.clock();
+
+        opll.reset();
+        assert!(!opll.channels[0].is_key_on);
+        assert_eq!(opll.registers, [0; 0x40]);
+        assert_eq!(opll.output(), 0.0);
+        assert_eq!(opll.sine_table, sine_table);
+    }
+}
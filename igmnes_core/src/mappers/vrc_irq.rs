@@ -0,0 +1,141 @@
+// Konami VRC IRQ counter, shared by VRC4, VRC6 and VRC7
+//
+// The counter is an 8-bit up-counter that is reloaded from the latch and raises an IRQ
+// when it overflows past 0xFF. In scanline mode it is clocked through a prescaler
+// that approximates one scanline (341 PPU dots, or 113.667 CPU cycles).
+// In cycle mode it is clocked on every CPU cycle.
+
+const PRESCALER_RELOAD: i16 = 341;
+const PRESCALER_STEP: i16 = 3;
+
+#[derive(Clone, Default)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+
+    // Control register
+    //   7  bit  0
+    //   ---- ----
+    //   .... .MEA
+    //         |||
+    //         ||+- IRQ enable after acknowledgement
+    //         |+-- IRQ enable
+    //         +--- IRQ mode (0: scanline mode, 1: CPU cycle mode)
+    enable_after_ack: bool,
+    enabled: bool,
+    is_cycle_mode: bool,
+
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> VrcIrq {
+        VrcIrq::default()
+    }
+
+    pub fn write_latch(&mut self, byte: u8) {
+        self.latch = byte;
+    }
+
+    pub fn write_control(&mut self, byte: u8) {
+        self.enable_after_ack = byte & 0b001 != 0;
+        self.enabled = byte & 0b010 != 0;
+        self.is_cycle_mode = byte & 0b100 != 0;
+
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_RELOAD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    #[inline]
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.is_cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= PRESCALER_STEP;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_RELOAD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_until_pending(irq: &mut VrcIrq) -> u32 {
+        let mut cycles = 0;
+        while !irq.is_pending() {
+            irq.clock();
+            cycles += 1;
+            assert!(cycles < 100_000, "The IRQ never fired");
+        }
+        cycles
+    }
+
+    #[test]
+    fn scanline_mode_clocks_every_113_667_cycles() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFD);
+        irq.write_control(0b010);
+
+        // The prescaler counts 114, 114 and 113 cycles per scanline
+        assert_eq!(clock_until_pending(&mut irq), 114 + 114 + 113);
+    }
+
+    #[test]
+    fn cycle_mode_clocks_every_cycle() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFC);
+        irq.write_control(0b110);
+        assert_eq!(clock_until_pending(&mut irq), 4);
+    }
+
+    #[test]
+    fn acknowledge_restores_enable_after_ack() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFF);
+        irq.write_control(0b111);
+        assert_eq!(clock_until_pending(&mut irq), 1);
+
+        // Enabled again with the E bit, counting on from the reloaded counter
+        irq.acknowledge();
+        assert!(!irq.is_pending());
+        assert_eq!(clock_until_pending(&mut irq), 1);
+
+        irq.write_control(0b100);
+        irq.acknowledge();
+        for _ in 0..1000 {
+            irq.clock();
+        }
+        assert!(!irq.is_pending());
+    }
+}
@@ -1,15 +1,19 @@
 use crate::apu::Apu;
 use crate::controller::Controller;
 use crate::dma::{Dma, DmaType};
-use crate::mappers::{self, Mapper, MapperImpl, SharedMapper};
+use crate::mappers::{self, CpuMapper, Mapper, MapperBattery, MapperImpl, MapperIrq, SharedMapper};
 use crate::ppu::{memory::PpuMemMap, Ppu};
 use crate::rom::Rom;
+use crate::vs_system::VsSystem;
 use enum_dispatch::enum_dispatch;
 
 use std::default::Default;
 use std::ops::Range;
 
 const RAM_SIZE: usize = 0x800;
+// Offset of $7000 in PRG RAM
+const TRAINER_PRG_RAM_OFFSET: usize = 0x1000;
+const TRAINER_RAM_SIZE: usize = 0x2000;
 
 #[enum_dispatch]
 pub trait MemMapped {
@@ -34,6 +38,17 @@ pub trait MemMapped {
     fn set_is_mutating_read(&mut self, _: bool) {}
 }
 
+// The CPU side of the bus. Every read and write the CPU makes, including its dummy accesses,
+// takes one cycle and the rest of the system is clocked before it happens
+pub trait CpuBus: MemMapped {
+    // Runs the next CPU cycle, returning how many cycles went by, which is more than one
+    // when a DMA halted the CPU before a read
+    fn tick(&mut self, is_read: bool) -> u64;
+
+    fn is_nmi_line_asserted(&self) -> bool;
+    fn is_irq_line_asserted(&self) -> bool;
+}
+
 #[derive(Copy, Clone)]
 pub struct MemMapConfig {
     pub is_mutating_read: bool,
@@ -88,6 +103,17 @@ pub struct CpuMemMap {
     pub dma: Dma,
     pub controllers: [Controller; 2],
     pub mapper: Box<MapperImpl>,
+    // Coin slots, DIP switches and protection of Vs. UniSystem games
+    pub vs_system: Option<VsSystem>,
+    // RAM at $6000-$7FFF for trainers of mappers that don't have any PRG RAM there,
+    // empty otherwise
+    trainer_ram: Vec<u8>,
+
+    cpu_cycles: u64,
+    is_apu_irq_asserted: bool,
+    // The last value on the CPU data bus, read back from addresses nothing answers
+    open_bus: u8,
+    mem_map_config: MemMapConfig,
 }
 
 impl Default for CpuMemMap {
@@ -102,6 +128,12 @@ impl Default for CpuMemMap {
             dma: Dma::default(),
             controllers: [Controller::default(); 2],
             mapper: Box::new(def_mapper),
+            vs_system: None,
+            trainer_ram: Vec::new(),
+            cpu_cycles: 0,
+            is_apu_irq_asserted: false,
+            open_bus: 0,
+            mem_map_config: MemMapConfig::default(),
         }
     }
 }
@@ -113,29 +145,155 @@ impl CpuMemMap {
 
         let shared_mapper = SharedMapper::new(&mut mapper_box);
         let ppu_mem_map = PpuMemMap::new(shared_mapper);
-        let mem_map = CpuMemMap {
+        let mut ppu = Ppu::new(ppu_mem_map);
+        if let Some(ppu_type) = rom.header.rgb_ppu_type() {
+            ppu.set_rgb_ppu(ppu_type);
+        }
+        let vs_system = rom
+            .header
+            .is_vs_unisystem
+            .then(|| VsSystem::new(rom.header.vs_hardware_type));
+
+        let mut mem_map = CpuMemMap {
             rom,
             ram: Ram::new(),
             apu: Apu::new(),
-            ppu: Ppu::new(ppu_mem_map),
+            ppu,
             dma: Dma::new(),
             controllers: [Controller::new(); 2],
             mapper: mapper_box,
+            vs_system,
+            trainer_ram: Vec::new(),
+            cpu_cycles: 0,
+            is_apu_irq_asserted: false,
+            open_bus: 0,
+            mem_map_config: MemMapConfig::default(),
         };
+        mem_map.load_trainer();
 
         mem_map
     }
 
+    pub fn rom(&self) -> &Rom {
+        &self.rom
+    }
+
     pub fn hard_reset(&mut self) {
         self.ram = Ram::new();
+        self.dma = Dma::new();
+        self.cpu_cycles = 0;
+        self.is_apu_irq_asserted = false;
+        self.open_bus = 0;
         self.apu.hard_reset();
         self.ppu.hard_reset();
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.hard_reset();
+        }
+
+        // Battery backed memory survives a power cycle
+        let battery_data = self.mapper.battery_data().map(|data| data.into_owned());
         self.mapper.hard_reset(&self.rom);
+        if let Some(battery_data) = battery_data {
+            self.mapper.load_battery_data(&battery_data);
+        }
+
+        self.load_trainer();
+    }
+
+    // Copiers loaded the trainer into PRG RAM at $7000-$71FF before starting the game. They had
+    // their own 8KB of RAM at $6000-$7FFF, so boards without PRG RAM get it too
+    fn load_trainer(&mut self) {
+        let trainer_bytes = match &self.rom.trainer_bytes {
+            Some(trainer_bytes) => trainer_bytes,
+            None => return,
+        };
+
+        let prg_ram = match self.mapper.prg_ram_mut() {
+            Some(prg_ram) => prg_ram,
+            None => {
+                self.trainer_ram = vec![0; TRAINER_RAM_SIZE];
+                &mut self.trainer_ram
+            }
+        };
+        let start = TRAINER_PRG_RAM_OFFSET % prg_ram.len();
+        prg_ram[start..start + trainer_bytes.len()].copy_from_slice(trainer_bytes);
+    }
+
+    // Controllers only drive the low 5 bits, the Vs. UniSystem drives all of them
+    fn read_controller(&mut self, index: u16) -> u8 {
+        let controller_index = (index - 0x4016) as usize;
+        let value = self.controllers[controller_index].read(index);
+        match self.vs_system {
+            Some(_) => value | self.read_vs_system(index),
+            None => value | (self.open_bus & 0b1110_0000),
+        }
+    }
+
+    fn read_vs_system(&mut self, index: u16) -> u8 {
+        self.vs_system
+            .as_mut()
+            .map_or(0, |vs_system| vs_system.read(index))
+    }
+
+    #[inline]
+    fn clock(&mut self) {
+        self.cpu_cycles += 1;
+        self.ppu.step(self.cpu_cycles);
+        self.is_apu_irq_asserted = self.apu.step(self.cpu_cycles, &mut self.mapper);
+    }
+
+    // OAM DMA halts the CPU on its next read, waits a cycle to get aligned to a read cycle
+    // if needed, then copies the page through OAMDATA with alternating read and write cycles.
+    // That's 513 or 514 cycles in total.
+    fn run_oam_dma(&mut self, page_index: u8) -> u64 {
+        let start_cycles = self.cpu_cycles;
+
+        self.clock();
+        if self.cpu_cycles % 2 == 1 {
+            self.clock();
+        }
+
+        let page_start = page_index as u16 * 0x100;
+        for offset in 0..0x100 {
+            self.clock();
+            let byte = self.read(page_start + offset);
+            self.clock();
+            self.ppu.write(4, byte);
+        }
+
+        self.cpu_cycles - start_cycles
     }
 }
 
 //
 
+impl CpuBus for CpuMemMap {
+    #[inline]
+    fn tick(&mut self, is_read: bool) -> u64 {
+        // Writes don't halt the CPU, so a DMA waits for the next read
+        let dma_cycles = match is_read {
+            true => self
+                .dma
+                .take_oam_page()
+                .map_or(0, |page_index| self.run_oam_dma(page_index)),
+            false => 0,
+        };
+
+        self.clock();
+        dma_cycles + 1
+    }
+
+    #[inline]
+    fn is_nmi_line_asserted(&self) -> bool {
+        self.ppu.is_nmi_asserted()
+    }
+
+    #[inline]
+    fn is_irq_line_asserted(&self) -> bool {
+        self.is_apu_irq_asserted || self.mapper.irq_pending()
+    }
+}
+
 impl MemMapped for CpuMemMap {
     //        Address range	Size	Device
     //        $0000-$07FF	$0800	2KB internal RAM
@@ -149,7 +307,7 @@ impl MemMapped for CpuMemMap {
     //        $4020-$FFFF	$BFE0	Cartridge space: PRG ROM, PRG RAM, and mapper registers (See Note)
     #[inline]
     fn read(&mut self, index: u16) -> u8 {
-        match index {
+        let value = match index {
             // RAM
             0..=0x1FFF => {
                 let index = index % 0x800;
@@ -160,25 +318,32 @@ impl MemMapped for CpuMemMap {
                 let index = index % 0x8;
                 self.ppu.read(index)
             }
-            // APU
-            0x4000..=0x4013 | 0x4015 => self.apu.read(index),
-            // OAM DMA register (write-only)
-            0x4014 => 0,
-            // I/O
-            0x4016 => self.controllers[0].read(index),
-            // I/O, Apu: This address is shared by both the APU and I/O so we can from read either one
-            0x4017 => self.controllers[1].read(index),
-            0x4018..=0x401f => {
-                // let _index = index % 0x4018;
-                //println!("Attempted unimplemented read from CPU Test Register: 0x{:04X}", index);
-                0
+            // APU status, bit 5 is open bus. The CPU reads it internally, so the bus keeps its value
+            0x4015 => return (self.apu.read(index) & !0b0010_0000) | (self.open_bus & 0b0010_0000),
+            // I/O, with the coin slots and DIP switches of the Vs. UniSystem in the upper bits
+            0x4016 | 0x4017 => self.read_controller(index),
+            // Write-only APU and OAM DMA registers, and the CPU test registers,
+            // which are normally disabled
+            0x4000..=0x4014 | 0x4018..=0x401F => self.open_bus,
+            // RAM the trainer was loaded into
+            0x6000..=0x7FFF if !self.trainer_ram.is_empty() => {
+                self.trainer_ram[(index - 0x6000) as usize]
             }
-            0x4020..=0xFFFF => self.mapper.read(index),
+            0x4020..=0xFFFF => match &mut self.vs_system {
+                Some(vs_system) if vs_system.is_protection_address(index) => vs_system.read(index),
+                _ if self.mapper.is_open_bus(index) => self.open_bus,
+                _ => self.mapper.read(index),
+            },
+        };
+        if self.is_mutating_read() {
+            self.open_bus = value;
         }
+        value
     }
 
     #[inline]
     fn write(&mut self, index: u16, byte: u8) {
+        self.open_bus = byte;
         match index {
             // RAM
             0..=0x1FFF => {
@@ -205,6 +370,9 @@ impl MemMapped for CpuMemMap {
                     self.controllers[0].stop_polling();
                     self.controllers[1].stop_polling();
                 }
+                if let Some(vs_unisystem) = self.mapper.vs_unisystem() {
+                    vs_unisystem.select_bank(byte);
+                }
             }
             // This address is shared by both APU and I/O so we need to write the value to both
             0x4017 => {
@@ -218,7 +386,14 @@ impl MemMapped for CpuMemMap {
                     index
                 );
             }
-            0x4020..=0xFFFF => self.mapper.write(index, byte),
+            // RAM the trainer was loaded into
+            0x6000..=0x7FFF if !self.trainer_ram.is_empty() => {
+                self.trainer_ram[(index - 0x6000) as usize] = byte;
+            }
+            0x4020..=0xFFFF => match &mut self.vs_system {
+                Some(vs_system) if index == 0x4020 => vs_system.write(index, byte),
+                _ => self.mapper.write(index, byte),
+            },
         }
     }
 
@@ -227,10 +402,128 @@ impl MemMapped for CpuMemMap {
         self.ram.read_range(range)
     }
 
+    fn is_mutating_read(&self) -> bool {
+        self.mem_map_config.is_mutating_read
+    }
+
     fn set_is_mutating_read(&mut self, is_mutating_read: bool) {
+        self.mem_map_config.is_mutating_read = is_mutating_read;
         self.ppu.set_is_mutating_read(is_mutating_read);
         for controller in self.controllers.iter_mut() {
             controller.set_is_mutating_read(is_mutating_read);
         }
+        self.mapper.set_is_mutating_read(is_mutating_read);
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.set_is_mutating_read(is_mutating_read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::test_rom;
+
+    fn trainer_rom(mapper_number: u16, prg_ram_size: usize) -> Rom {
+        let mut rom = test_rom(mapper_number, 0x4000, 2, 0, 0);
+        rom.header.prg_ram_size = prg_ram_size;
+        rom.trainer_bytes = Some((0..0x200).map(|i| i as u8).collect());
+        rom
+    }
+
+    #[test]
+    fn trainer_is_loaded_at_7000() {
+        let mut mem_map = CpuMemMap::new(trainer_rom(0, 0x2000));
+
+        assert_eq!(mem_map.read(0x6FFF), 0);
+        assert_eq!(mem_map.read(0x7000), 0x00);
+        assert_eq!(mem_map.read(0x7001), 0x01);
+        assert_eq!(mem_map.read(0x71FF), 0xFF);
+        assert_eq!(mem_map.read(0x7200), 0);
+    }
+
+    #[test]
+    fn trainer_gets_its_own_ram_without_prg_ram() {
+        // UxROM has nothing at $6000-$7FFF
+        let mut mem_map = CpuMemMap::new(trainer_rom(2, 0));
+
+        assert_eq!(mem_map.read(0x7000), 0x00);
+        assert_eq!(mem_map.read(0x7001), 0x01);
+        assert_eq!(mem_map.read(0x71FF), 0xFF);
+
+        mem_map.write(0x6000, 0x42);
+        assert_eq!(mem_map.read(0x6000), 0x42);
+
+        // A power cycle clears the RAM and loads the trainer again
+        mem_map.write(0x7000, 0x42);
+        mem_map.hard_reset();
+        assert_eq!(mem_map.read(0x6000), 0);
+        assert_eq!(mem_map.read(0x7000), 0x00);
+        assert_eq!(mem_map.read(0x7001), 0x01);
+    }
+
+    #[test]
+    fn oam_dma_takes_513_or_514_cycles() {
+        let mut mem_map = CpuMemMap::new(test_rom(0, 0x4000, 2, 0x2000, 1));
+        for offset in 0..0x100 {
+            mem_map.write(0x0200 + offset, offset as u8);
+        }
+
+        // Started on an odd cycle, plus the read that was halted
+        mem_map.tick(true);
+        mem_map.write(0x4014, 0x02);
+        assert_eq!(mem_map.tick(true), 513 + 1);
+
+        // Started on an even cycle, it waits one more to get aligned. Writes don't halt
+        // the CPU, so it starts on the next read
+        mem_map.write(0x4014, 0x02);
+        assert_eq!(mem_map.tick(false), 1);
+        assert_eq!(mem_map.tick(true), 514 + 1);
+
+        mem_map.write(0x2003, 0x05);
+        assert_eq!(mem_map.read(0x2004), 0x05);
+    }
+
+    // CPU cycles until the PPU sets the vblank flag of the first frame
+    const VBLANK_CPU_CYCLE: u64 = (241 * 341 + 1) / 3 + 1;
+
+    // Ticks up to the given cycle, reads $2002 on it like the CPU would, then runs the rest of
+    // the frame. Returns the value read and whether the CPU saw the NMI line asserted.
+    fn read_ppu_status_on_cycle(cycle: u64) -> (u8, bool) {
+        let mut mem_map = CpuMemMap::new(test_rom(0, 0x4000, 2, 0x2000, 1));
+        mem_map.write(0x2000, 0x80);
+
+        let mut is_nmi_seen = false;
+        let mut value = 0;
+        for current_cycle in 1..VBLANK_CPU_CYCLE + 100 {
+            mem_map.tick(true);
+            if current_cycle == cycle {
+                value = mem_map.read(0x2002);
+            }
+            is_nmi_seen |= mem_map.is_nmi_line_asserted();
+        }
+        (value & 0x80, is_nmi_seen)
+    }
+
+    #[test]
+    fn ppu_status_read_races_vblank() {
+        // Two or more PPU clocks before, the flag reads clear and the NMI fires as usual
+        assert_eq!(read_ppu_status_on_cycle(VBLANK_CPU_CYCLE - 2), (0, true));
+        // One PPU clock before, the flag reads clear and isn't set for this frame
+        assert_eq!(read_ppu_status_on_cycle(VBLANK_CPU_CYCLE - 1), (0, false));
+        // On the cycle it's set, the flag reads set and the NMI is suppressed
+        assert_eq!(read_ppu_status_on_cycle(VBLANK_CPU_CYCLE), (0x80, false));
+        // After the CPU has seen it, the NMI still happens
+        assert_eq!(read_ppu_status_on_cycle(VBLANK_CPU_CYCLE + 1), (0x80, true));
+    }
+
+    #[test]
+    fn no_trainer_ram_without_a_trainer() {
+        let mut mem_map = CpuMemMap::new(test_rom(2, 0x4000, 2, 0, 0));
+
+        mem_map.write(0x6000, 0x42);
+        // Open bus, left over from the read at $8001
+        assert_eq!(mem_map.read(0x8001), 0xFF);
+        assert_eq!(mem_map.read(0x6000), 0xFF);
     }
 }
@@ -0,0 +1,539 @@
+use crate::rom::RomError;
+use bitflags::bitflags;
+
+// NSF and NSFe music rips
+//
+// An NSF holds the sound driver and music data ripped from a game, along with the addresses
+// of its init and play routines. The init routine sets up a song and the play routine is
+// called at a fixed rate afterwards, usually once per frame.
+//
+// NSFe is a chunked variant of the same data, which adds per-track labels, lengths and fades.
+
+pub const NSF_HEADER_TAG: &[u8] = b"NESM\x1A";
+pub const NSFE_TAG: &[u8] = b"NSFE";
+
+const NSF_HEADER_SIZE: usize = 0x80;
+
+// 60.1 Hz, in microseconds
+const DEFAULT_PLAY_SPEED: u16 = 16639;
+
+bitflags! {
+    #[derive(Debug, Default, Copy, Clone, PartialEq)]
+    pub struct NsfExpansionChips: u8 {
+        const VRC6  = 0b0000_0001;
+        const VRC7  = 0b0000_0010;
+        const FDS   = 0b0000_0100;
+        const MMC5  = 0b0000_1000;
+        const N163  = 0b0001_0000;
+        const S5B   = 0b0010_0000;
+    }
+}
+
+impl NsfExpansionChips {
+    // Expansion audio chips that the player can emulate
+    pub fn supported() -> NsfExpansionChips {
+        NsfExpansionChips::VRC7 | NsfExpansionChips::FDS
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        const NAMES: [(NsfExpansionChips, &str); 6] = [
+            (NsfExpansionChips::VRC6, "VRC6"),
+            (NsfExpansionChips::VRC7, "VRC7"),
+            (NsfExpansionChips::FDS, "FDS"),
+            (NsfExpansionChips::MMC5, "MMC5"),
+            (NsfExpansionChips::N163, "N163"),
+            (NsfExpansionChips::S5B, "Sunsoft 5B"),
+        ];
+
+        NAMES
+            .iter()
+            .filter(|(chip, _)| self.contains(*chip))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct NsfTrack {
+    pub label: Option<String>,
+    pub length_ms: Option<u32>,
+    pub fade_ms: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: String,
+
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    // Play routine call period, in microseconds
+    pub play_speed: u16,
+    // Initial values of the $5FF8-$5FFF bank registers, all zero if the tune isn't bankswitched
+    pub initial_banks: [u8; 8],
+    pub expansion_chips: NsfExpansionChips,
+
+    // 0 based
+    pub starting_song: usize,
+    pub tracks: Vec<NsfTrack>,
+}
+
+impl Nsf {
+    pub fn song_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_bankswitched(&self) -> bool {
+        self.initial_banks.iter().any(|&bank| bank != 0)
+    }
+}
+
+pub fn is_nsf_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(NSF_HEADER_TAG) || bytes.starts_with(NSFE_TAG)
+}
+
+// Returns the tune metadata and its code and data, which get loaded at the load address
+pub fn parse_nsf_file(bytes: &[u8]) -> Result<(Nsf, Vec<u8>), RomError> {
+    if bytes.starts_with(NSFE_TAG) {
+        parse_nsfe(bytes)
+    } else {
+        parse_nsf(bytes)
+    }
+}
+
+//  Offset  Size  Contents
+//  $00     5     "NESM", $1A
+//  $05     1     Version
+//  $06     1     Total songs
+//  $07     1     Starting song (1 based)
+//  $08     2     Load address
+//  $0A     2     Init address
+//  $0C     2     Play address
+//  $0E     32    Song name
+//  $2E     32    Artist
+//  $4E     32    Copyright
+//  $6E     2     NTSC play speed
+//  $70     8     Bankswitch init values
+//  $78     2     PAL play speed
+//  $7A     1     PAL/NTSC bits
+//  $7B     1     Expansion chips
+//  $7C     4     Reserved (NSF2)
+fn parse_nsf(bytes: &[u8]) -> Result<(Nsf, Vec<u8>), RomError> {
+    if bytes.len() < NSF_HEADER_SIZE {
+        return Err(RomError::ParseError("NSF header is truncated".to_string()));
+    }
+    check_tv_system(bytes[0x7A])?;
+
+    let mut initial_banks = [0; 8];
+    initial_banks.copy_from_slice(&bytes[0x70..0x78]);
+
+    let nsf = Nsf {
+        title: read_string(&bytes[0x0E..0x2E]),
+        artist: read_string(&bytes[0x2E..0x4E]),
+        copyright: read_string(&bytes[0x4E..0x6E]),
+        ripper: String::new(),
+
+        load_addr: read_word(bytes, 0x08),
+        init_addr: read_word(bytes, 0x0A),
+        play_addr: read_word(bytes, 0x0C),
+        play_speed: play_speed_or_default(read_word(bytes, 0x6E)),
+        initial_banks,
+        expansion_chips: NsfExpansionChips::from_bits_truncate(bytes[0x7B]),
+
+        starting_song: (bytes[0x07] as usize).saturating_sub(1),
+        tracks: vec![NsfTrack::default(); bytes[0x06] as usize],
+    };
+
+    validate(nsf, bytes[NSF_HEADER_SIZE..].to_vec())
+}
+
+// Chunks are a 4 byte little endian length, a 4 byte ID and the chunk data
+fn parse_nsfe(bytes: &[u8]) -> Result<(Nsf, Vec<u8>), RomError> {
+    let mut nsf = Nsf {
+        play_speed: DEFAULT_PLAY_SPEED,
+        ..Nsf::default()
+    };
+    let mut data: Option<Vec<u8>> = None;
+    let mut has_info = false;
+
+    let mut position = NSFE_TAG.len();
+    while position + 8 <= bytes.len() {
+        let length = u32::from_le_bytes([
+            bytes[position],
+            bytes[position + 1],
+            bytes[position + 2],
+            bytes[position + 3],
+        ]) as usize;
+        let id = &bytes[position + 4..position + 8];
+        let chunk_start = position + 8;
+        let chunk_end = chunk_start
+            .checked_add(length)
+            .filter(|&chunk_end| chunk_end <= bytes.len())
+            .ok_or_else(|| RomError::ParseError("NSFe chunk is truncated".to_string()))?;
+        let chunk = &bytes[chunk_start..chunk_end];
+
+        match id {
+            b"INFO" => {
+                // Load, init and play addresses, PAL/NTSC bits, expansion chips,
+                // and optionally the total song count and the (0 based) starting song
+                if chunk.len() < 8 {
+                    return Err(RomError::ParseError(
+                        "NSFe INFO chunk is truncated".to_string(),
+                    ));
+                }
+                check_tv_system(chunk[6])?;
+
+                nsf.load_addr = read_word(chunk, 0);
+                nsf.init_addr = read_word(chunk, 2);
+                nsf.play_addr = read_word(chunk, 4);
+                nsf.expansion_chips = NsfExpansionChips::from_bits_truncate(chunk[7]);
+                let song_count = chunk.get(8).map_or(1, |&count| count as usize);
+                nsf.tracks = vec![NsfTrack::default(); song_count];
+                nsf.starting_song = chunk.get(9).map_or(0, |&song| song as usize);
+                has_info = true;
+            }
+            b"DATA" => data = Some(chunk.to_vec()),
+            b"BANK" => {
+                for (bank, &byte) in nsf.initial_banks.iter_mut().zip(chunk) {
+                    *bank = byte;
+                }
+            }
+            b"RATE" if chunk.len() >= 2 => {
+                nsf.play_speed = play_speed_or_default(read_word(chunk, 0))
+            }
+            b"auth" => {
+                let mut strings = chunk.split(|&byte| byte == 0).map(read_string);
+                nsf.title = strings.next().unwrap_or_default();
+                nsf.artist = strings.next().unwrap_or_default();
+                nsf.copyright = strings.next().unwrap_or_default();
+                nsf.ripper = strings.next().unwrap_or_default();
+            }
+            b"tlbl" => {
+                for (track, label) in nsf.tracks.iter_mut().zip(chunk.split(|&byte| byte == 0)) {
+                    track.label = Some(read_string(label)).filter(|label| !label.is_empty());
+                }
+            }
+            b"time" => {
+                for (track, length) in nsf.tracks.iter_mut().zip(read_durations(chunk)) {
+                    track.length_ms = length;
+                }
+            }
+            b"fade" => {
+                for (track, fade) in nsf.tracks.iter_mut().zip(read_durations(chunk)) {
+                    track.fade_ms = fade;
+                }
+            }
+            b"NEND" => break,
+            // Chunks with an uppercase first letter are required to play the file correctly
+            id if id[0].is_ascii_uppercase() => {
+                return Err(RomError::ParseError(format!(
+                    "Unsupported NSFe chunk: {}",
+                    String::from_utf8_lossy(id)
+                )))
+            }
+            _ => (),
+        }
+
+        position = chunk_end;
+    }
+
+    match data {
+        Some(data) if has_info => validate(nsf, data),
+        _ => Err(RomError::ParseError(
+            "NSFe file is missing the INFO or DATA chunk".to_string(),
+        )),
+    }
+}
+
+fn validate(mut nsf: Nsf, data: Vec<u8>) -> Result<(Nsf, Vec<u8>), RomError> {
+    if nsf.tracks.is_empty() {
+        return Err(RomError::ParseError("NSF file has no songs".to_string()));
+    }
+
+    // FDS tunes can be loaded into the RAM at $6000-$7FFF
+    let lowest_load_addr = if nsf.expansion_chips.contains(NsfExpansionChips::FDS) {
+        0x6000
+    } else {
+        0x8000
+    };
+    if nsf.load_addr < lowest_load_addr {
+        return Err(RomError::ParseError(format!(
+            "Invalid NSF load address: ${:04X}",
+            nsf.load_addr
+        )));
+    }
+
+    if nsf.starting_song >= nsf.song_count() {
+        nsf.starting_song = 0;
+    }
+
+    Ok((nsf, data))
+}
+
+// The player only runs NTSC tunes, dual compatible ones play at their NTSC rate
+fn check_tv_system(tv_system_bits: u8) -> Result<(), RomError> {
+    if tv_system_bits & 0b11 == 0b01 {
+        Err(RomError::ParseError(
+            "Unsupported system type: PAL".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn play_speed_or_default(play_speed: u16) -> u16 {
+    if play_speed == 0 {
+        DEFAULT_PLAY_SPEED
+    } else {
+        play_speed
+    }
+}
+
+fn read_word(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+// Zero terminated (or zero padded) strings
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// Signed 32-bit millisecond counts, negative values mean the default duration
+fn read_durations(chunk: &[u8]) -> impl Iterator<Item = Option<u32>> + '_ {
+    chunk.chunks_exact(4).map(|duration| {
+        let duration = i32::from_le_bytes([duration[0], duration[1], duration[2], duration[3]]);
+        if duration >= 0 {
+            Some(duration as u32)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::assert_rom_error;
+
+    fn nsf_header() -> Vec<u8> {
+        let mut header = vec![0; NSF_HEADER_SIZE];
+        header[..5].copy_from_slice(NSF_HEADER_TAG);
+        header[0x05] = 1;
+        // 3 songs, starting with the 2nd
+        header[0x06] = 3;
+        header[0x07] = 2;
+        header[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        header[0x0A..0x0C].copy_from_slice(&0x8003u16.to_le_bytes());
+        header[0x0C..0x0E].copy_from_slice(&0x8006u16.to_le_bytes());
+        header[0x0E..0x13].copy_from_slice(b"Title");
+        header[0x6E..0x70].copy_from_slice(&16666u16.to_le_bytes());
+        header
+    }
+
+    fn nsfe_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn nsfe_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = NSFE_TAG.to_vec();
+        for chunk in chunks {
+            bytes.extend_from_slice(chunk);
+        }
+        bytes.extend(nsfe_chunk(b"NEND", &[]));
+        bytes
+    }
+
+    // Load, init and play addresses, NTSC, no expansion chips, 2 songs
+    const NSFE_INFO: [u8; 10] = [0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0, 0, 2, 1];
+
+    #[test]
+    fn nsf_header_is_parsed() {
+        let mut bytes = nsf_header();
+        bytes.extend_from_slice(&[0x60, 0xEA, 0xEA]);
+
+        let (nsf, data) = parse_nsf_file(&bytes).unwrap();
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.load_addr, 0x8000);
+        assert_eq!(nsf.init_addr, 0x8003);
+        assert_eq!(nsf.play_addr, 0x8006);
+        assert_eq!(nsf.play_speed, 16666);
+        assert_eq!(nsf.song_count(), 3);
+        assert_eq!(nsf.starting_song, 1);
+        assert!(!nsf.is_bankswitched());
+        assert_eq!(data, [0x60, 0xEA, 0xEA]);
+    }
+
+    #[test]
+    fn nsf_bankswitch_init_values_are_parsed() {
+        let mut bytes = nsf_header();
+        bytes[0x70..0x78].copy_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7]);
+
+        let (nsf, _) = parse_nsf_file(&bytes).unwrap();
+        assert!(nsf.is_bankswitched());
+        assert_eq!(nsf.initial_banks, [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn nsf_expansion_chips_are_parsed() {
+        let mut bytes = nsf_header();
+        bytes[0x7B] = 0b0000_0110;
+        // FDS tunes can load below $8000
+        bytes[0x08..0x0A].copy_from_slice(&0x6000u16.to_le_bytes());
+
+        let (nsf, _) = parse_nsf_file(&bytes).unwrap();
+        assert_eq!(
+            nsf.expansion_chips,
+            NsfExpansionChips::VRC7 | NsfExpansionChips::FDS
+        );
+        assert_eq!(nsf.expansion_chips.names(), ["VRC7", "FDS"]);
+        assert_eq!(nsf.load_addr, 0x6000);
+    }
+
+    #[test]
+    fn nsf_load_address_below_8000_is_rejected() {
+        let mut bytes = nsf_header();
+        bytes[0x08..0x0A].copy_from_slice(&0x6000u16.to_le_bytes());
+
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("Invalid NSF load address: $6000".to_string()),
+        );
+    }
+
+    #[test]
+    fn pal_only_nsf_is_rejected() {
+        let mut bytes = nsf_header();
+        bytes[0x7A] = 0b01;
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("Unsupported system type: PAL".to_string()),
+        );
+
+        // Dual compatible tunes play at their NTSC rate
+        bytes[0x7A] = 0b10;
+        assert!(parse_nsf_file(&bytes).is_ok());
+    }
+
+    #[test]
+    fn truncated_nsf_header_is_rejected() {
+        let bytes = &nsf_header()[..0x40];
+        assert_rom_error(
+            parse_nsf_file(bytes),
+            RomError::ParseError("NSF header is truncated".to_string()),
+        );
+    }
+
+    #[test]
+    fn nsfe_chunks_are_parsed() {
+        let bytes = nsfe_file(&[
+            nsfe_chunk(b"INFO", &NSFE_INFO),
+            nsfe_chunk(b"BANK", &[0, 1, 2, 3]),
+            nsfe_chunk(b"RATE", &16666u16.to_le_bytes()),
+            nsfe_chunk(b"auth", b"Title\0Artist\0Copyright\0Ripper\0"),
+            nsfe_chunk(b"tlbl", b"First\0Second\0"),
+            nsfe_chunk(
+                b"time",
+                &[1000i32.to_le_bytes(), (-1i32).to_le_bytes()].concat(),
+            ),
+            nsfe_chunk(
+                b"fade",
+                &[500i32.to_le_bytes(), 0i32.to_le_bytes()].concat(),
+            ),
+            nsfe_chunk(b"DATA", &[0x60]),
+        ]);
+
+        let (nsf, data) = parse_nsf_file(&bytes).unwrap();
+        assert_eq!(nsf.load_addr, 0x8000);
+        assert_eq!(nsf.init_addr, 0x8003);
+        assert_eq!(nsf.play_addr, 0x8006);
+        assert_eq!(nsf.play_speed, 16666);
+        assert_eq!(nsf.initial_banks, [0, 1, 2, 3, 0, 0, 0, 0]);
+        assert_eq!(nsf.starting_song, 1);
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.ripper, "Ripper");
+
+        assert_eq!(nsf.song_count(), 2);
+        assert_eq!(nsf.tracks[0].label.as_deref(), Some("First"));
+        assert_eq!(nsf.tracks[0].length_ms, Some(1000));
+        assert_eq!(nsf.tracks[0].fade_ms, Some(500));
+        assert_eq!(nsf.tracks[1].length_ms, None);
+        assert_eq!(nsf.tracks[1].fade_ms, Some(0));
+        assert_eq!(data, [0x60]);
+    }
+
+    #[test]
+    fn nsfe_expansion_chips_are_parsed() {
+        let mut info = NSFE_INFO;
+        info[7] = 0b0010_0001;
+        let bytes = nsfe_file(&[nsfe_chunk(b"INFO", &info), nsfe_chunk(b"DATA", &[0x60])]);
+
+        let (nsf, _) = parse_nsf_file(&bytes).unwrap();
+        assert_eq!(
+            nsf.expansion_chips,
+            NsfExpansionChips::VRC6 | NsfExpansionChips::S5B
+        );
+        assert!(!NsfExpansionChips::supported().contains(nsf.expansion_chips));
+    }
+
+    #[test]
+    fn pal_only_nsfe_is_rejected() {
+        let mut info = NSFE_INFO;
+        info[6] = 0b01;
+        let bytes = nsfe_file(&[nsfe_chunk(b"INFO", &info), nsfe_chunk(b"DATA", &[0x60])]);
+
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("Unsupported system type: PAL".to_string()),
+        );
+    }
+
+    #[test]
+    fn nsfe_without_data_is_rejected() {
+        let bytes = nsfe_file(&[nsfe_chunk(b"INFO", &NSFE_INFO)]);
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("NSFe file is missing the INFO or DATA chunk".to_string()),
+        );
+    }
+
+    #[test]
+    fn unknown_required_nsfe_chunk_is_rejected() {
+        let bytes = nsfe_file(&[
+            nsfe_chunk(b"INFO", &NSFE_INFO),
+            nsfe_chunk(b"NSF2", &[0]),
+            nsfe_chunk(b"DATA", &[0x60]),
+        ]);
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("Unsupported NSFe chunk: NSF2".to_string()),
+        );
+
+        // Optional chunks are skipped
+        let bytes = nsfe_file(&[
+            nsfe_chunk(b"INFO", &NSFE_INFO),
+            nsfe_chunk(b"text", b"Notes"),
+            nsfe_chunk(b"DATA", &[0x60]),
+        ]);
+        assert!(parse_nsf_file(&bytes).is_ok());
+    }
+
+    #[test]
+    fn truncated_nsfe_chunk_is_rejected() {
+        let mut bytes = nsfe_file(&[nsfe_chunk(b"INFO", &NSFE_INFO)]);
+        bytes.truncate(NSFE_TAG.len() + 8 + 4);
+        assert_rom_error(
+            parse_nsf_file(&bytes),
+            RomError::ParseError("NSFe chunk is truncated".to_string()),
+        );
+    }
+}
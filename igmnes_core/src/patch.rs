@@ -0,0 +1,526 @@
+use crate::checksum::crc32;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// ROM patches (IPS, UPS and BPS), applied to the raw file bytes at load time
+//
+// IPS is a list of (offset, bytes) records with no checksums, so it applies to any file.
+// UPS and BPS carry the sizes and CRC-32s of the source and target files, which are checked
+// to make sure the patch is applied to the ROM it was made for.
+
+const IPS_TAG: &[u8] = b"PATCH";
+const IPS_EOF_TAG: &[u8] = b"EOF";
+const UPS_TAG: &[u8] = b"UPS1";
+const BPS_TAG: &[u8] = b"BPS1";
+
+// Source, target and patch CRC-32s at the end of UPS and BPS patches
+const CHECKSUMS_SIZE: usize = 12;
+
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
+#[derive(Error, Debug)]
+pub enum PatchError {
+    #[error("Unrecognized patch format")]
+    UnknownFormat,
+
+    #[error("Patch is truncated or malformed")]
+    Malformed,
+
+    #[error("Patch is corrupted (CRC-32 is {actual:08X}, expected {expected:08X})")]
+    PatchChecksumMismatch { expected: u32, actual: u32 },
+
+    #[error(
+        "Patch was made for a different ROM (CRC-32 is {actual:08X}, expected {expected:08X})"
+    )]
+    SourceChecksumMismatch { expected: u32, actual: u32 },
+
+    #[error("Patch was made for a different ROM (size is {actual} bytes, expected {expected})")]
+    SourceSizeMismatch { expected: usize, actual: usize },
+
+    #[error("Patched ROM is invalid (CRC-32 is {actual:08X}, expected {expected:08X})")]
+    TargetChecksumMismatch { expected: u32, actual: u32 },
+}
+
+// A patch next to the ROM with the same name, e.g. game.ips for game.nes
+pub fn find_patch_for_rom(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|patch_path| patch_path.is_file())
+}
+
+pub fn apply_patch(rom_bytes: &[u8], patch_bytes: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch_bytes.starts_with(IPS_TAG) {
+        apply_ips(rom_bytes, patch_bytes)
+    } else if patch_bytes.starts_with(UPS_TAG) {
+        apply_ups(rom_bytes, patch_bytes)
+    } else if patch_bytes.starts_with(BPS_TAG) {
+        apply_bps(rom_bytes, patch_bytes)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+struct PatchReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> PatchReader<'a> {
+        PatchReader { bytes, position }
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(PatchError::Malformed)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_be(&mut self, count: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .read_bytes(count)?
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | byte as usize))
+    }
+
+    // Variable length number used by UPS and BPS: 7 bits per byte, least significant first,
+    // with the top bit marking the last byte. Each continuation also adds one, so that
+    // every number has a single encoding.
+    fn read_number(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_byte()?;
+            value = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|data| value.checked_add(data))
+                .ok_or(PatchError::Malformed)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::Malformed)?;
+            value = value.checked_add(shift).ok_or(PatchError::Malformed)?;
+        }
+    }
+}
+
+// "PATCH", then records of a 3 byte offset and a 2 byte size followed by the data.
+// A size of 0 is a run of a 2 byte count of a single byte. The list ends with "EOF",
+// optionally followed by a 3 byte size to truncate the file to.
+fn apply_ips(rom_bytes: &[u8], patch_bytes: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = rom_bytes.to_vec();
+    let mut reader = PatchReader::new(patch_bytes, IPS_TAG.len());
+
+    loop {
+        if reader.read_bytes(IPS_EOF_TAG.len())? == IPS_EOF_TAG {
+            break;
+        }
+        reader.position -= IPS_EOF_TAG.len();
+
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+        if size > 0 {
+            let data = reader.read_bytes(size)?;
+            write_ips_record(&mut target, offset, data.iter().copied(), size);
+        } else {
+            let count = reader.read_be(2)?;
+            let byte = reader.read_byte()?;
+            write_ips_record(&mut target, offset, std::iter::repeat(byte), count);
+        }
+    }
+
+    if let Ok(truncated_size) = reader.read_be(3) {
+        target.truncate(truncated_size);
+    }
+
+    Ok(target)
+}
+
+fn write_ips_record(
+    target: &mut Vec<u8>,
+    offset: usize,
+    data: impl Iterator<Item = u8>,
+    size: usize,
+) {
+    if target.len() < offset + size {
+        target.resize(offset + size, 0);
+    }
+    for (target_byte, byte) in target[offset..offset + size].iter_mut().zip(data) {
+        *target_byte = byte;
+    }
+}
+
+// "UPS1", the source and target sizes, then hunks of a number of bytes to skip followed by
+// bytes to XOR with the source, terminated by a zero byte. The checksums follow at the end.
+fn apply_ups(rom_bytes: &[u8], patch_bytes: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let checksums = read_checksums(patch_bytes)?;
+    let mut reader = PatchReader::new(patch_bytes, UPS_TAG.len());
+
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    check_source(rom_bytes, source_size, checksums.source)?;
+
+    let mut target = rom_bytes.to_vec();
+    target.resize(target_size.max(rom_bytes.len()), 0);
+
+    let mut position = 0;
+    let hunks_end = patch_bytes.len() - CHECKSUMS_SIZE;
+    while reader.position < hunks_end {
+        position += reader.read_number()?;
+        loop {
+            let byte = reader.read_byte()?;
+            if position >= target.len() {
+                target.resize(position + 1, 0);
+            }
+            target[position] ^= byte;
+            position += 1;
+
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+    target.truncate(target_size);
+
+    check_target(&target, checksums.target)?;
+    Ok(target)
+}
+
+// "BPS1", the source, target and metadata sizes, the metadata, then actions that build the
+// target: copies from the source at the same offset, literal bytes from the patch, and copies
+// from relative offsets in the source or in the target built so far. The checksums follow.
+fn apply_bps(rom_bytes: &[u8], patch_bytes: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let checksums = read_checksums(patch_bytes)?;
+    let mut reader = PatchReader::new(patch_bytes, BPS_TAG.len());
+
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    check_source(rom_bytes, source_size, checksums.source)?;
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    let actions_end = patch_bytes.len() - CHECKSUMS_SIZE;
+    while reader.position < actions_end {
+        let action = reader.read_number()?;
+        let length = (action >> 2) + 1;
+
+        match action & 0b11 {
+            // Source read
+            0 => {
+                let start = target.len();
+                let bytes = rom_bytes
+                    .get(start..start + length)
+                    .ok_or(PatchError::Malformed)?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read
+            1 => target.extend_from_slice(reader.read_bytes(length)?),
+            // Source copy
+            2 => {
+                source_offset = read_relative_offset(&mut reader, source_offset)?;
+                let bytes = rom_bytes
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::Malformed)?;
+                target.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // Target copy, byte by byte as the copied range can overlap the bytes being written
+            3 => {
+                target_offset = read_relative_offset(&mut reader, target_offset)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::Malformed)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(PatchError::Malformed);
+    }
+
+    check_target(&target, checksums.target)?;
+    Ok(target)
+}
+
+// The lowest bit is the sign, the rest is the distance
+fn read_relative_offset(reader: &mut PatchReader, offset: usize) -> Result<usize, PatchError> {
+    let data = reader.read_number()?;
+    let distance = data >> 1;
+    if data & 1 != 0 {
+        offset.checked_sub(distance)
+    } else {
+        offset.checked_add(distance)
+    }
+    .ok_or(PatchError::Malformed)
+}
+
+struct PatchChecksums {
+    source: u32,
+    target: u32,
+}
+
+// The patch checksum covers everything but itself
+fn read_checksums(patch_bytes: &[u8]) -> Result<PatchChecksums, PatchError> {
+    if patch_bytes.len() < BPS_TAG.len() + CHECKSUMS_SIZE {
+        return Err(PatchError::Malformed);
+    }
+
+    let checksums_start = patch_bytes.len() - CHECKSUMS_SIZE;
+    let read_crc = |offset: usize| {
+        let start = checksums_start + offset;
+        u32::from_le_bytes([
+            patch_bytes[start],
+            patch_bytes[start + 1],
+            patch_bytes[start + 2],
+            patch_bytes[start + 3],
+        ])
+    };
+
+    let expected = read_crc(8);
+    let actual = crc32(&patch_bytes[..patch_bytes.len() - 4]);
+    if expected != actual {
+        return Err(PatchError::PatchChecksumMismatch { expected, actual });
+    }
+
+    Ok(PatchChecksums {
+        source: read_crc(0),
+        target: read_crc(4),
+    })
+}
+
+fn check_source(rom_bytes: &[u8], size: usize, checksum: u32) -> Result<(), PatchError> {
+    if rom_bytes.len() != size {
+        return Err(PatchError::SourceSizeMismatch {
+            expected: size,
+            actual: rom_bytes.len(),
+        });
+    }
+
+    let actual = crc32(rom_bytes);
+    if actual != checksum {
+        return Err(PatchError::SourceChecksumMismatch {
+            expected: checksum,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_target(target: &[u8], checksum: u32) -> Result<(), PatchError> {
+    let actual = crc32(target);
+    if actual != checksum {
+        return Err(PatchError::TargetChecksumMismatch {
+            expected: checksum,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+
+    fn encode_number(mut number: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let data = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(data | 0x80);
+                return bytes;
+            }
+            bytes.push(data);
+            number -= 1;
+        }
+    }
+
+    // Header of a UPS or BPS patch: the tag and the source and target sizes
+    fn patch_header(tag: &[u8], target_size: usize) -> Vec<u8> {
+        let mut patch = tag.to_vec();
+        patch.extend(encode_number(SOURCE.len()));
+        patch.extend(encode_number(target_size));
+        patch
+    }
+
+    fn append_checksums(mut patch: Vec<u8>, target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(SOURCE).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn numbers_have_a_single_encoding() {
+        for number in [0, 1, 0x7F, 0x80, 0x407F, 0x4080, 1 << 20] {
+            let bytes = encode_number(number);
+            assert_eq!(PatchReader::new(&bytes, 0).read_number().unwrap(), number);
+        }
+        assert_eq!(encode_number(0x80), [0x00, 0x80]);
+    }
+
+    #[test]
+    fn ips_records_are_applied() {
+        let mut patch = IPS_TAG.to_vec();
+        // 2 bytes at 1
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, b'x', b'y']);
+        // Run of 3 'z' at 5
+        patch.extend_from_slice(&[0, 0, 5, 0, 0, 0, 3, b'z']);
+        patch.extend_from_slice(IPS_EOF_TAG);
+
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), b"AxyDEzzz");
+    }
+
+    #[test]
+    fn ips_records_past_the_end_grow_the_file() {
+        let mut patch = IPS_TAG.to_vec();
+        patch.extend_from_slice(&[0, 0, 10, 0, 0, 0, 2, b'z']);
+        patch.extend_from_slice(IPS_EOF_TAG);
+
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), b"ABCDEFGH\0\0zz");
+    }
+
+    #[test]
+    fn ips_truncation_extension_is_applied() {
+        let mut patch = IPS_TAG.to_vec();
+        patch.extend_from_slice(&[0, 0, 0, 0, 1, b'a']);
+        patch.extend_from_slice(IPS_EOF_TAG);
+        patch.extend_from_slice(&[0, 0, 4]);
+
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), b"aBCD");
+    }
+
+    #[test]
+    fn truncated_ips_is_rejected() {
+        let mut patch = IPS_TAG.to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 4, b'x']);
+
+        assert!(matches!(
+            apply_patch(SOURCE, &patch),
+            Err(PatchError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn ups_xor_runs_are_applied() {
+        let target = b"AbcDEFgH!";
+        let mut patch = patch_header(UPS_TAG, target.len());
+        // Skip 1, XOR "BC" to "bc"
+        patch.extend(encode_number(1));
+        patch.extend_from_slice(&[0x20, 0x20, 0]);
+        // The terminator covered "D", skip "EF" and XOR "G" to "g"
+        patch.extend(encode_number(2));
+        patch.extend_from_slice(&[0x20, 0]);
+        // Past the end of the source, XOR with zeros
+        patch.extend(encode_number(0));
+        patch.extend_from_slice(&[b'!', 0]);
+        let patch = append_checksums(patch, target);
+
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_actions_are_applied() {
+        let target = b"ABCDxyEFEFEFAB";
+        let mut patch = patch_header(BPS_TAG, target.len());
+        // No metadata
+        patch.extend(encode_number(0));
+        // Source read of "ABCD"
+        patch.extend(encode_number((4 - 1) << 2));
+        // Target read of "xy"
+        patch.extend(encode_number(((2 - 1) << 2) | 1));
+        patch.extend_from_slice(b"xy");
+        // Source copy of "EF", 4 forward
+        patch.extend(encode_number(((2 - 1) << 2) | 2));
+        patch.extend(encode_number(4 << 1));
+        // Target copy of "EFEF", overlapping what it writes, 6 forward
+        patch.extend(encode_number(((4 - 1) << 2) | 3));
+        patch.extend(encode_number(6 << 1));
+        // Source copy of "AB", 6 back
+        patch.extend(encode_number(((2 - 1) << 2) | 2));
+        patch.extend(encode_number((6 << 1) | 1));
+        let patch = append_checksums(patch, target);
+
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn corrupted_patch_is_rejected() {
+        let target = b"ABCDEFGH";
+        let mut patch = patch_header(BPS_TAG, target.len());
+        patch.extend(encode_number(0));
+        patch.extend(encode_number((8 - 1) << 2));
+        let mut patch = append_checksums(patch, target);
+        patch[BPS_TAG.len()] ^= 1;
+
+        assert!(matches!(
+            apply_patch(SOURCE, &patch),
+            Err(PatchError::PatchChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn patch_for_a_different_rom_is_rejected() {
+        let target = b"ABCDEFGH";
+        let mut patch = patch_header(UPS_TAG, target.len());
+        patch.extend(encode_number(0));
+        patch.push(0);
+        let patch = append_checksums(patch, target);
+
+        assert!(matches!(
+            apply_patch(b"ABCDEFGX", &patch),
+            Err(PatchError::SourceChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            apply_patch(b"ABCDEFG", &patch),
+            Err(PatchError::SourceSizeMismatch {
+                expected: 8,
+                actual: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_patched_rom_is_rejected() {
+        let mut patch = patch_header(UPS_TAG, SOURCE.len());
+        patch.extend(encode_number(0));
+        patch.extend_from_slice(&[1, 0]);
+        // Checksum of the unpatched ROM
+        let patch = append_checksums(patch, SOURCE);
+
+        let expected = crc32(SOURCE);
+        assert!(matches!(
+            apply_patch(SOURCE, &patch),
+            Err(PatchError::TargetChecksumMismatch { expected: crc, .. }) if crc == expected
+        ));
+    }
+
+    #[test]
+    fn unknown_patch_format_is_rejected() {
+        assert!(matches!(
+            apply_patch(SOURCE, b"NOTAPATCH"),
+            Err(PatchError::UnknownFormat)
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+// PPU event recorder, logging the PPU's registers on each scanline and the CPU's writes to
+// them, for tools that show where raster effects happen in a frame.
+//
+// Events are collected while a frame is rendered and handed over as a whole when the PPU
+// wraps around to scanline 0, so the last log always covers one complete frame.
+use crate::mappers::MapperBankState;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PpuEventRecording {
+    #[default]
+    Disabled,
+    // Register state at the start of each scanline and the writes to $2000-$2007
+    Scanlines,
+    // Same, with the register state after each write as well
+    ScanlinesAndWrites,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PpuRegisterState {
+    pub reg_v: u16,
+    pub reg_t: u16,
+    pub reg_x: u8,
+    // PPUCTRL and PPUMASK as last written
+    pub ctrl: u8,
+    pub mask: u8,
+}
+
+// Recorded on dot 1, before the scanline's first pixel
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PpuScanlineEvent {
+    // 0-239 visible, 240 post-render, 241-260 vblank, 261 pre-render
+    pub scanline: u16,
+    pub registers: PpuRegisterState,
+    pub mapper_banks: MapperBankState,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PpuWriteEvent {
+    pub scanline: u16,
+    // 0-340
+    pub dot: u16,
+    // $2000-$2007
+    pub address: u16,
+    pub value: u8,
+    // Only with PpuEventRecording::ScanlinesAndWrites
+    pub registers: Option<PpuRegisterState>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PpuEventLog {
+    pub scanlines: Vec<PpuScanlineEvent>,
+    pub writes: Vec<PpuWriteEvent>,
+}
+
+impl PpuEventLog {
+    fn clear(&mut self) {
+        self.scanlines.clear();
+        self.writes.clear();
+    }
+}
+
+#[derive(Default)]
+pub struct PpuEventRecorder {
+    recording: PpuEventRecording,
+    curr_log: PpuEventLog,
+    last_log: PpuEventLog,
+}
+
+impl PpuEventRecorder {
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.recording != PpuEventRecording::Disabled
+    }
+
+    pub fn recording(&self) -> PpuEventRecording {
+        self.recording
+    }
+
+    pub fn set_recording(&mut self, recording: PpuEventRecording) {
+        self.recording = recording;
+        self.clear();
+    }
+
+    // The last complete frame
+    pub fn last_log(&self) -> &PpuEventLog {
+        &self.last_log
+    }
+
+    pub fn clear(&mut self) {
+        self.curr_log.clear();
+        self.last_log.clear();
+    }
+
+    pub fn record_scanline(&mut self, event: PpuScanlineEvent) {
+        self.curr_log.scanlines.push(event);
+    }
+
+    pub fn record_write(&mut self, mut event: PpuWriteEvent) {
+        if self.recording != PpuEventRecording::ScanlinesAndWrites {
+            event.registers = None;
+        }
+        self.curr_log.writes.push(event);
+    }
+
+    // Reuses the old log's buffers for the next frame
+    pub fn finish_frame(&mut self) {
+        std::mem::swap(&mut self.curr_log, &mut self.last_log);
+        self.curr_log.clear();
+    }
+}
@@ -0,0 +1,236 @@
+// Frame conversion helpers, for frontends and for screenshots and recordings that should look
+// the same as what's on screen.
+//
+// TVs hide some of the picture behind the edges of the screen (the overscan), which games
+// rely on to hide scrolling artifacts, so most of the time the top and bottom 8 scanlines
+// are cropped. PPU pixels also aren't square: at NTSC's dot clock a pixel is 8/7 as wide as
+// it is tall, and a TV stretches the picture to its own 4:3 screen.
+use crate::checksum::crc32;
+use crate::ppu::palette::PpuPaletteColor;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+use std::str::FromStr;
+
+pub const PPU_FRAME_WIDTH: usize = 256;
+pub const PPU_FRAME_HEIGHT: usize = 240;
+
+// Pixels cropped from each edge, in PPU pixels
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Overscan {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+impl Default for Overscan {
+    fn default() -> Self {
+        Overscan {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+// Either a single value for all edges, or "top,bottom,left,right"
+impl FromStr for Overscan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid overscan: {}", s);
+        let edges = s
+            .split(',')
+            .map(|edge| edge.trim().parse::<u8>().map_err(|_| error()))
+            .collect::<Result<Vec<u8>, String>>()?;
+
+        let overscan = match edges[..] {
+            [all] => Overscan {
+                top: all,
+                bottom: all,
+                left: all,
+                right: all,
+            },
+            [top, bottom, left, right] => Overscan {
+                top,
+                bottom,
+                left,
+                right,
+            },
+            _ => return Err(error()),
+        };
+
+        if overscan.width() == 0 || overscan.height() == 0 {
+            return Err(error());
+        }
+        Ok(overscan)
+    }
+}
+
+impl Overscan {
+    pub const NONE: Overscan = Overscan {
+        top: 0,
+        bottom: 0,
+        left: 0,
+        right: 0,
+    };
+
+    // Size of the picture that's left, in PPU pixels
+    pub fn width(&self) -> usize {
+        PPU_FRAME_WIDTH.saturating_sub(self.left as usize + self.right as usize)
+    }
+
+    pub fn height(&self) -> usize {
+        PPU_FRAME_HEIGHT.saturating_sub(self.top as usize + self.bottom as usize)
+    }
+
+    // The part of a frame that's left after cropping. Frames wider than the PPU's, like
+    // the NTSC filter's, have their left and right edges scaled to match.
+    pub fn crop_rect(&self, frame_width: usize) -> FrameRect {
+        let to_frame_x = |x: usize| x * frame_width / PPU_FRAME_WIDTH;
+        let x = to_frame_x(self.left as usize);
+        let width = to_frame_x(PPU_FRAME_WIDTH - self.right as usize).saturating_sub(x);
+
+        FrameRect {
+            x,
+            y: self.top as usize,
+            width,
+            height: self.height(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct FrameRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum AspectRatio {
+    // One PPU pixel per screen pixel
+    #[default]
+    Square,
+    // NTSC pixels, 8/7 as wide as they are tall
+    Pixel8x7,
+    // The cropped picture stretched to fill a 4:3 screen
+    Display4x3,
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1:1" | "square" => Ok(AspectRatio::Square),
+            "8:7" | "ntsc" => Ok(AspectRatio::Pixel8x7),
+            "4:3" | "tv" => Ok(AspectRatio::Display4x3),
+            _ => Err(format!("Unknown aspect ratio: {}", s)),
+        }
+    }
+}
+
+impl AspectRatio {
+    // Size the cropped picture is shown at, keeping its height and stretching its width
+    pub fn display_size(&self, overscan: &Overscan) -> (usize, usize) {
+        let (width, height) = (overscan.width(), overscan.height());
+        let display_width = match self {
+            AspectRatio::Square => width,
+            AspectRatio::Pixel8x7 => (width * 8 + 3) / 7,
+            AspectRatio::Display4x3 => (height * 4 + 1) / 3,
+        };
+
+        (display_width, height)
+    }
+}
+
+// Copies the cropped part of a frame, which works for any kind of pixel (RGB colors,
+// palette indices, ...)
+pub fn crop_frame<T: Copy>(frame: &[T], frame_width: usize, overscan: &Overscan) -> Vec<T> {
+    let rect = overscan.crop_rect(frame_width);
+    let mut cropped = Vec::with_capacity(rect.width * rect.height);
+    for row in frame
+        .chunks_exact(frame_width)
+        .skip(rect.y)
+        .take(rect.height)
+    {
+        cropped.extend_from_slice(&row[rect.x..rect.x + rect.width]);
+    }
+
+    cropped
+}
+
+// Nearest neighbor scaling, the same as what frontends stretch their textures with
+pub fn resize_frame<T: Copy>(
+    frame: &[T],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+) -> Vec<T> {
+    let mut resized = Vec::with_capacity(new_width * new_height);
+    for y in 0..new_height {
+        let row = &frame[(y * height / new_height) * width..][..width];
+        resized.extend((0..new_width).map(|x| row[x * width / new_width]));
+    }
+
+    resized
+}
+
+// The frame as it's shown on screen, cropped and stretched to its aspect ratio. Returns
+// the pixels along with their width and height.
+pub fn display_frame(
+    frame: &[PpuPaletteColor],
+    frame_width: usize,
+    overscan: &Overscan,
+    aspect_ratio: AspectRatio,
+) -> (Vec<PpuPaletteColor>, usize, usize) {
+    let rect = overscan.crop_rect(frame_width);
+    let cropped = crop_frame(frame, frame_width, overscan);
+    let (width, height) = aspect_ratio.display_size(overscan);
+
+    let resized = resize_frame(&cropped, rect.width, rect.height, width, height);
+    (resized, width, height)
+}
+
+// Packed 24-bit RGB, the layout image encoders and video textures expect
+pub fn frame_to_rgb24(frame: &[PpuPaletteColor]) -> Vec<u8> {
+    frame
+        .iter()
+        .flat_map(|color| [color.red, color.green, color.blue])
+        .collect()
+}
+
+// PNG file of a frame, 8-bit RGB without filtering, for screenshots
+pub fn frame_to_png(frame: &[PpuPaletteColor], width: usize, height: usize) -> Vec<u8> {
+    let mut image_data = Vec::with_capacity((width * 3 + 1) * height);
+    for row in frame.chunks_exact(width).take(height) {
+        // Filter type of the scanline
+        image_data.push(0);
+        image_data.extend(frame_to_rgb24(row));
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth, RGB, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    let mut write_chunk = |chunk_type: &[u8; 4], data: &[u8]| {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let chunk_start = png.len();
+        png.extend_from_slice(chunk_type);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[chunk_start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    };
+    write_chunk(b"IHDR", &header);
+    write_chunk(b"IDAT", &compress_to_vec_zlib(&image_data, 6));
+    write_chunk(b"IEND", &[]);
+
+    png
+}
@@ -1,7 +1,7 @@
 // PpuMemMap
 
 use crate::errors::EmulationError;
-use crate::mappers::SharedMapper;
+use crate::mappers::{PpuMapper, SharedMapper};
 use crate::memory::MemMapped;
 use crate::ppu::palette::PpuPalette;
 use crate::ppu::OamTable;
@@ -71,24 +71,47 @@ impl PpuMemMap {
 
         let pattern_table_byte_low = self.read(pattern_table_addr_low);
         let pattern_table_byte_high = self.read(pattern_table_addr_high);
+        self.mapper.observe_pattern_fetch(pattern_table_addr_high);
         Ok([pattern_table_byte_low, pattern_table_byte_high])
     }
 
+    // The whole tile is read, but the PPU only fetches the planes of the pixel_y row,
+    // which is the address that mappers watching the PPU bus see
     pub fn fetch_sprite_pattern(
         &mut self,
         pattern_table_index: u8,
         pattern_entry_index: u8,
+        pixel_y: u16,
+    ) -> [u8; 16] {
+        let result = self.peek_sprite_pattern(pattern_table_index, pattern_entry_index);
+        let pattern_table_addr_high: u16 = (pattern_table_index as u16) << 12
+            | (pattern_entry_index as u16) << 4
+            | 1 << 3
+            | pixel_y;
+        self.mapper.observe_pattern_fetch(pattern_table_addr_high);
+        result
+    }
+
+    // Without the side effects of a fetch on mappers that watch the PPU bus
+    pub fn peek_sprite_pattern(
+        &self,
+        pattern_table_index: u8,
+        pattern_entry_index: u8,
     ) -> [u8; 16] {
         let base_addr = (pattern_table_index as u16) << 12;
         let pattern_entry_addr = base_addr + (pattern_entry_index as u16 * 16);
 
-        let byte_slice = self.read_range(pattern_entry_addr..pattern_entry_addr + 16);
-        let result: [u8; 16] = if byte_slice.len() == 0 {
+        let byte_slice = self
+            .mapper
+            .read_chr_range(pattern_entry_addr..pattern_entry_addr + 16);
+        if byte_slice.len() == 0 {
             [0; 16]
+        } else if byte_slice.len() < 16 {
+            // Cut off by the end of CHR memory, reads of the rest wrap around to the start
+            array::from_fn(|index| self.mapper.read_chr(pattern_entry_addr + index as u16))
         } else {
             array::from_fn(|index| byte_slice[index])
-        };
-        result
+        }
     }
 }
 
@@ -158,3 +181,61 @@ impl MemMapped for PpuMemMap {
         self.mapper.read_range(range)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::{load_mapper_for_rom, test_rom, MapperImpl};
+
+    // MMC2 (9) or MMC4 (10) with CHR bank 1 selected by $FD and bank 2 by $FE in both halves
+    fn mmc2_or_mmc4(mapper_number: u16) -> MapperImpl {
+        let mut mapper =
+            load_mapper_for_rom(&test_rom(mapper_number, 0x2000, 8, 0x1000, 4)).unwrap();
+        for &(register, bank) in &[(0xB000, 1), (0xC000, 2), (0xD000, 1), (0xE000, 2)] {
+            mapper.write(register, bank);
+        }
+        mapper
+    }
+
+    // The bank that the latch of the pattern table selects
+    fn fetch_sprite_row(mapper: &mut MapperImpl, tile: u8, pixel_y: u16) -> u8 {
+        let mut ppu_mem_map = PpuMemMap::new(SharedMapper::new(mapper));
+        ppu_mem_map.fetch_sprite_pattern(0, tile, pixel_y);
+        ppu_mem_map.read(0x0000)
+    }
+
+    #[test]
+    fn sprite_fetch_reports_the_fetched_row() {
+        let mut mmc4 = mmc2_or_mmc4(10);
+        assert_eq!(fetch_sprite_row(&mut mmc4, 0xFD, 5), 1);
+        assert_eq!(fetch_sprite_row(&mut mmc4, 0xFE, 7), 2);
+
+        // MMC2 only reacts to the first row of the tiles in the first pattern table
+        let mut mmc2 = mmc2_or_mmc4(9);
+        assert_eq!(fetch_sprite_row(&mut mmc2, 0xFD, 5), 2);
+        assert_eq!(fetch_sprite_row(&mut mmc2, 0xFD, 0), 1);
+    }
+
+    #[test]
+    fn sprite_pattern_wraps_around_the_end_of_chr_memory() {
+        // A bad dump with 8 bytes past the first 2 KB of CHR ROM
+        let mut rom = test_rom(0, 0x4000, 1, 0x808, 1);
+        for (offset, byte) in rom.chr_rom_bytes.iter_mut().enumerate() {
+            *byte = offset as u8;
+        }
+        let mut nrom = load_mapper_for_rom(&rom).unwrap();
+        let ppu_mem_map = PpuMemMap::new(SharedMapper::new(&mut nrom));
+
+        let pattern = ppu_mem_map.peek_sprite_pattern(0, 0x80);
+        assert_eq!(pattern[..8], rom.chr_rom_bytes[0x800..0x808]);
+        assert_eq!(pattern[8..], rom.chr_rom_bytes[0x000..0x008]);
+    }
+
+    #[test]
+    fn sprite_peek_leaves_latches_alone() {
+        let mut mmc4 = mmc2_or_mmc4(10);
+        let mut ppu_mem_map = PpuMemMap::new(SharedMapper::new(&mut mmc4));
+        ppu_mem_map.peek_sprite_pattern(0, 0xFD);
+        assert_eq!(ppu_mem_map.read(0x0000), 2);
+    }
+}
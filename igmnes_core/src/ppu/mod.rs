@@ -1,22 +1,55 @@
+pub mod events;
+pub mod frame;
 pub mod memory;
+pub mod ntsc;
 pub mod palette;
+pub mod scaler;
+pub mod viewer;
 
 use bitflags::bitflags;
 
 use std::fmt::Display;
 use std::{array, fmt};
 
-use crate::debug::Tracer;
-
-use crate::mappers::MapperIrq;
+use crate::mappers::{Mapper, MapperIrq};
 use crate::memory::{MemMapConfig, MemMapped};
+use crate::ppu::events::{PpuEventRecorder, PpuRegisterState, PpuScanlineEvent, PpuWriteEvent};
 use crate::ppu::memory::PpuMemMap;
-use crate::ppu::palette::PpuPaletteColor;
+use crate::ppu::palette::{PpuPaletteColor, PpuPaletteColors};
+use crate::vs_system::VsPpuType;
+use crate::CPU_CLOCK_RATIO_NTSC;
 
 const BIT_MASK: u8 = 0b0000_0001;
 const BIT_MASK_2: u8 = 0b0000_0011;
 
+// Bits of the I/O latch decay to 0 after about 600 ms without being refreshed
+const IO_LATCH_DECAY_CPU_CYCLES: u64 = (CPU_CLOCK_RATIO_NTSC * 0.6) as u64;
+
 pub type PpuFrame<'a> = &'a [PpuPaletteColor];
+// 9-bit output values, see PpuPaletteColors
+pub type PpuIndexFrame<'a> = &'a [u16];
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PpuOutputMode {
+    // Colors from the PPU's palette, with greyscale and emphasis applied
+    #[default]
+    Rgb,
+    // 9-bit values with the palette entry in bits 0-5 and the emphasis bits in bits 6-8,
+    // for frontends that convert them with their own palette or filter
+    PaletteIndex,
+}
+
+// Changes to what's drawn that the game can't see: sprite 0 hit, sprite overflow and the
+// mapper's view of the PPU bus stay as they would be without them
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PpuDisplayOptions {
+    // Draws every sprite in range of a scanline instead of the first 8
+    pub is_sprite_limit_disabled: bool,
+    pub is_background_hidden: bool,
+    pub are_sprites_hidden: bool,
+    // Bit n hides sprite n of OAM
+    pub hidden_sprites: u64,
+}
 
 // We use a whole byte for now, to avoid bit-packing, this type is merely for clarification
 trait BitOps {
@@ -185,7 +218,8 @@ impl Into<u8> for OamSpriteAttributes {
 }
 impl OamSpriteAttributes {
     fn write(&mut self, byte: u8) {
-        self.palette_index = byte & 0b00001111;
+        // Bits 2-4 aren't stored and read back as 0
+        self.palette_index = byte & 0b11;
         self.priority = OamAttributePriority::from(byte.get_bit_u8(5));
         self.is_flipped_horizontally = byte.get_bit(6);
         self.is_flipped_vertically = byte.get_bit(7);
@@ -285,6 +319,8 @@ impl OamTable {
 struct SecondaryOamEntry {
     oam_entry: OamEntry,
     sprite_index: usize,
+    // The first sprite evaluated, which is sprite 0 unless OAMADDR was misaligned
+    is_sprite_0: bool,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -293,6 +329,22 @@ struct SecondaryOam {
     count: usize,
 }
 
+// Sprite evaluation of the next scanline, stepped a dot at a time, see step_sprite_evaluation
+#[derive(Default, Copy, Clone)]
+struct SpriteEvaluation {
+    secondary_oam: [u8; 32],
+    secondary_oam_addr: usize,
+    // OAM index of the sprite in each secondary OAM slot
+    sprite_indices: [u8; 8],
+    // The byte last read from OAM or secondary OAM, which OAMDATA returns during rendering
+    oam_latch: u8,
+    is_sprite_in_range: bool,
+    is_sprite_0_in_range: bool,
+    is_done: bool,
+    // Bytes read after the overflowing sprite's Y before evaluation stops
+    overflow_bytes_left: u8,
+}
+
 #[derive(Default, Copy, Clone)]
 struct SpriteOutputUnit {
     secondary_oam_entry: SecondaryOamEntry,
@@ -303,31 +355,39 @@ struct SpriteOutputUnit {
 struct SpriteOutputUnits {
     units: [SpriteOutputUnit; 8],
     count: usize,
+    // Past the 8 sprite limit, see PpuDisplayOptions
+    extra_units: Vec<SpriteOutputUnit>,
 }
 
 #[derive(Default)]
 struct SpritePixel {
-    color: PpuPaletteColor,
+    palette_entry: u8,
     priority: OamAttributePriority,
-    sprite_index: usize,
+    is_sprite_0: bool,
     is_transparent: bool,
 }
 
-#[derive(Default)]
+#[derive(Default, Copy, Clone)]
 struct BackgroundPixel {
-    color: PpuPaletteColor,
+    palette_entry: u8,
     is_transparent: bool,
 }
 
+// Only the buffer of the current output mode is written to
 #[derive(Clone)]
 pub struct PpuOutput {
     pub data: Box<[PpuPaletteColor; 256 * 240]>,
+    pub indices: Box<[u16; 256 * 240]>,
+    // Color subcarrier phase at the start of the frame, see ntsc.rs
+    pub burst_phase: u8,
 }
 
 impl Default for PpuOutput {
     fn default() -> Self {
         PpuOutput {
             data: Box::new([PpuPaletteColor::default(); 256 * 240]),
+            indices: Box::new([0; 256 * 240]),
+            burst_phase: 0,
         }
     }
 }
@@ -390,6 +450,8 @@ pub struct Ppu {
 
     is_odd_frame: bool,
     is_address_latch_on: bool,
+    // 0-2, advances by one every frame, or by two when the last dot of an odd frame is skipped
+    burst_phase: u8,
 
     //
     // Internal Data
@@ -398,7 +460,6 @@ pub struct Ppu {
     curr_scanline_cycle: u16,
 
     cpu_cycles: u64,
-    pub nmi_pending: bool,
 
     pub ppu_mem_map: PpuMemMap,
     mem_map_config: MemMapConfig,
@@ -406,13 +467,21 @@ pub struct Ppu {
     // Rendering data
     shift_regs: PpuShiftRegisters,
     secondary_oam: SecondaryOam,
+    sprite_evaluation: SpriteEvaluation,
+    // OAM rows (8 bytes each) overwritten with row 0 when rendering is enabled again,
+    // see set_oam_corruption
+    corrupted_oam_rows: u32,
     sprite_output_units: SpriteOutputUnits,
 
     curr_frame: PpuOutput,
     output_frame: PpuOutput,
+    output_mode: PpuOutputMode,
+    display_options: PpuDisplayOptions,
 
     is_frame_ready: bool,
 
+    pub event_recorder: PpuEventRecorder,
+
     // Quirks
 
     // Reading $2002 within a few PPU clocks of when VBL is set results in special-case behavior.
@@ -423,6 +492,16 @@ pub struct Ppu {
     // (CPU inputs like NMI are sampled each clock.)
     should_skip_vbl: bool,
     read_buffer: u8,
+
+    // RC2C05 Vs. PPUs have PPUCTRL and PPUMASK swapped, and return an ID in the low bits of PPUSTATUS
+    is_ctrl_mask_swapped: bool,
+    status_id: u8,
+
+    // The PPU's data bus to the CPU, which holds the last value written to or read from any
+    // register. Reads of write-only registers and of bits a register doesn't drive return it.
+    io_latch: u8,
+    // When each bit was last refreshed, in CPU cycles
+    io_latch_refresh_cycles: [u64; 8],
 }
 
 impl Ppu {
@@ -435,6 +514,17 @@ impl Ppu {
         ppu
     }
 
+    // Arcade RGB PPUs, which have their own palettes instead of generating NTSC colors
+    pub fn set_rgb_ppu(&mut self, ppu_type: VsPpuType) {
+        self.ppu_mem_map
+            .palette
+            .set_colors(PpuPaletteColors::with_rgb_emphasis(
+                &ppu_type.palette_colors(),
+            ));
+        self.is_ctrl_mask_swapped = ppu_type.is_ctrl_mask_swapped();
+        self.status_id = ppu_type.status_id();
+    }
+
     #[inline]
     fn coarse_x_scroll(&self) -> u16 {
         self.reg_v & 0b1_1111
@@ -477,6 +567,30 @@ impl Ppu {
             || self.reg_mask.contains(PpuMaskReg::IS_SHOW_SPRITES_ENABLED)
     }
 
+    fn io_latch(&mut self) -> u8 {
+        for bit in 0..8 {
+            if self.cpu_cycles - self.io_latch_refresh_cycles[bit] > IO_LATCH_DECAY_CPU_CYCLES {
+                self.io_latch &= !(1 << bit);
+            }
+        }
+        self.io_latch
+    }
+
+    fn refresh_io_latch(&mut self, value: u8, refreshed_bits: u8) {
+        self.io_latch = (self.io_latch & !refreshed_bits) | (value & refreshed_bits);
+        for bit in 0..8 {
+            if refreshed_bits & (1 << bit) != 0 {
+                self.io_latch_refresh_cycles[bit] = self.cpu_cycles;
+            }
+        }
+    }
+
+    // OAM is busy with sprite evaluation on rendering scanlines
+    #[inline]
+    fn is_rendering_oam(&self) -> bool {
+        self.is_rendering_enabled() && (self.curr_scanline < 240 || self.curr_scanline == 261)
+    }
+
     #[inline(always)]
     fn increment_addr_read(&mut self) {
         self.reg_v = if self.reg_ctrl.contains(PpuCtrlReg::IS_INCREMENT_MODE_32) {
@@ -535,9 +649,11 @@ impl Ppu {
         }
     }
 
+    // The PPU's /NMI output, which the CPU watches for a falling edge
     #[inline(always)]
-    pub fn clear_nmi(&mut self) {
-        self.nmi_pending = false;
+    pub fn is_nmi_asserted(&self) -> bool {
+        self.reg_status.contains(PpuStatusReg::IS_IN_VBLANK)
+            && self.reg_ctrl.contains(PpuCtrlReg::IS_NMI_ENABLED)
     }
 
     #[inline(always)]
@@ -558,6 +674,7 @@ impl Ppu {
 
         self.is_address_latch_on = false;
         self.is_odd_frame = false;
+        self.burst_phase = 0;
 
         self.curr_scanline = 0;
         self.curr_scanline_cycle = 0;
@@ -565,11 +682,11 @@ impl Ppu {
 
         self.output_frame = PpuOutput::default();
         self.curr_frame = PpuOutput::default();
-    }
-
-    #[inline(always)]
-    pub fn should_suppress_nmi(&self) -> bool {
-        self.should_skip_vbl
+        self.sprite_evaluation = SpriteEvaluation::default();
+        self.corrupted_oam_rows = 0;
+        self.io_latch = 0;
+        self.io_latch_refresh_cycles = [0; 8];
+        self.event_recorder.clear();
     }
 
     #[inline(always)]
@@ -634,7 +751,7 @@ impl Ppu {
             (self.shift_regs.palette_index_low << 1) | self.shift_regs.attribute_latch_low as u8;
     }
 
-    pub fn step(&mut self, cpu_cycles: u64, tracer: &mut Tracer) -> bool {
+    pub fn step(&mut self, cpu_cycles: u64) {
         let cycles_to_run = (cpu_cycles - self.cpu_cycles) * 3;
 
         for _ in 0..cycles_to_run {
@@ -642,34 +759,36 @@ impl Ppu {
             let curr_scanline = self.curr_scanline;
             let curr_scanline_cycle = self.curr_scanline_cycle;
 
+            if curr_scanline_cycle == 1 && self.event_recorder.is_enabled() {
+                self.record_scanline_event();
+            }
+
             // Rendering scanlines & cycles
             let pixel_x = curr_scanline_cycle.wrapping_sub(1) as usize;
             let pixel_y = curr_scanline as usize;
             if is_rendering_enabled && pixel_y < 240 && pixel_x < 256 {
                 let background_pixel = self.get_background_pixel(pixel_x, pixel_y);
-                let sprite_pixel = self.get_sprite_pixel(pixel_x, pixel_y);
-
-                let output_color = match (
-                    sprite_pixel.priority,
-                    sprite_pixel.is_transparent,
-                    background_pixel.is_transparent,
-                ) {
-                    (OamAttributePriority::FRONT, false, _)
-                    | (OamAttributePriority::BACK, false, true) => sprite_pixel.color,
-                    _ => background_pixel.color,
+                let sprite_pixel = self.get_sprite_pixel(pixel_x, pixel_y, false);
+
+                let palette_entry = if self.display_options == PpuDisplayOptions::default() {
+                    Ppu::mix_pixels(&background_pixel, &sprite_pixel)
+                } else {
+                    self.mix_display_pixels(pixel_x, pixel_y, &background_pixel)
                 };
 
-                let is_sprite_0_hit = sprite_pixel.sprite_index == 0
+                let is_sprite_0_hit = sprite_pixel.is_sprite_0
                     && !sprite_pixel.is_transparent
                     && !background_pixel.is_transparent;
                 if is_sprite_0_hit {
                     self.reg_status.set(PpuStatusReg::IS_SPRITE_0_HIT, true);
                 }
 
-                self.curr_frame.data[pixel_y * 256 + pixel_x] = output_color;
+                self.output_pixel(pixel_y * 256 + pixel_x, palette_entry);
             }
 
             if is_rendering_enabled && (curr_scanline < 240 || curr_scanline == 261) {
+                self.step_sprite_evaluation(curr_scanline_cycle % 341);
+
                 if (curr_scanline_cycle >= 1 && curr_scanline_cycle <= 256)
                     || (curr_scanline_cycle >= 321 && curr_scanline_cycle <= 336)
                 {
@@ -703,8 +822,8 @@ impl Ppu {
                     let mask = 0b0000_0100_0001_1111;
                     self.reg_v = (self.reg_v & !mask) | (self.reg_t & mask);
 
-                    // We perform sprite evaluation here, to fill secondary OAM
-                    self.evaluate_sprites();
+                    // Sprites found by the evaluation on dots 65-256
+                    self.load_secondary_oam();
                     // We fill the sprite output units based on the sprite evaluation that was previously performed
                     self.prepare_sprite_units();
                 }
@@ -721,8 +840,13 @@ impl Ppu {
                 }
             }
 
-            if curr_scanline_cycle == 257 {
-                //&& self.curr_scanline_cycle <= 320 {
+            // OAMADDR is cleared during sprite tile loading, which only happens on rendering
+            // scanlines. OAM DMA writes through OAMDATA, so clearing it during vblank would
+            // break a transfer that crosses a scanline.
+            if (257..=320).contains(&curr_scanline_cycle)
+                && is_rendering_enabled
+                && (curr_scanline < 240 || curr_scanline == 261)
+            {
                 self.reg_oam_addr = 0;
             }
 
@@ -735,40 +859,37 @@ impl Ppu {
                     std::mem::swap(&mut self.output_frame, &mut self.curr_frame)
                 }
                 self.is_frame_ready = true;
-                if self.reg_ctrl.contains(PpuCtrlReg::IS_NMI_ENABLED) && !self.should_skip_vbl {
-                    self.nmi_pending = true;
-                }
+            }
+
+            if curr_scanline == 261 && curr_scanline_cycle == 1 && is_rendering_enabled {
+                self.start_rendering_oam();
             }
 
             if curr_scanline == 261 && curr_scanline_cycle == 1 {
                 self.reg_status = PpuStatusReg::empty();
                 self.is_odd_frame = !self.is_odd_frame;
                 self.should_skip_vbl = false;
-                self.nmi_pending = false;
                 self.is_frame_ready = false;
             }
 
-            if curr_scanline_cycle == 341
-                || (curr_scanline == 261
-                    && curr_scanline_cycle == 340
-                    && self.is_odd_frame
-                    && self.is_rendering_enabled())
-            {
+            let is_dot_skipped = curr_scanline == 261
+                && curr_scanline_cycle == 340
+                && self.is_odd_frame
+                && self.is_rendering_enabled();
+            if curr_scanline_cycle == 341 || is_dot_skipped {
                 self.curr_scanline_cycle = 0;
                 self.curr_scanline += 1;
             }
             if self.curr_scanline == 262 {
                 self.curr_scanline = 0;
+                self.burst_phase = (self.burst_phase + if is_dot_skipped { 2 } else { 1 }) % 3;
+                self.curr_frame.burst_phase = self.burst_phase;
+                self.event_recorder.finish_frame();
             }
             self.curr_scanline_cycle += 1;
         }
 
-        if tracer.is_enabled() {
-            tracer.add_ppu_trace(&self);
-        }
-
         self.cpu_cycles = cpu_cycles;
-        self.reg_status.contains(PpuStatusReg::IS_IN_VBLANK) && self.nmi_pending
     }
 
     fn get_background_pixel(&mut self, pixel_x: usize, _pixel_y: usize) -> BackgroundPixel {
@@ -777,9 +898,9 @@ impl Ppu {
                 .reg_mask
                 .contains(PpuMaskReg::IS_SHOW_BACKGROUND_ENABLED_LEFTMOST)
         {
-            let color = self.ppu_mem_map.palette.get_background_color(0, 0);
+            let palette_entry = self.ppu_mem_map.palette.get_background_entry(0, 0);
             BackgroundPixel {
-                color,
+                palette_entry,
                 is_transparent: true,
             }
         } else {
@@ -791,23 +912,66 @@ impl Ppu {
             let palette_index_low = (self.shift_regs.palette_index_low >> pixel_index_x % 8) & 0b1;
             let palette_index = palette_index_high << 1 | palette_index_low;
             let color_index = (pattern_bit_plane_high << 1 | pattern_bit_plane_low) as u8;
-            let color = self
+            let palette_entry = self
                 .ppu_mem_map
                 .palette
-                .get_background_color(palette_index, color_index);
+                .get_background_entry(palette_index, color_index);
 
             BackgroundPixel {
-                color,
+                palette_entry,
                 is_transparent: color_index == 0,
             }
         }
     }
 
+    #[inline(always)]
+    fn mix_pixels(background_pixel: &BackgroundPixel, sprite_pixel: &SpritePixel) -> u8 {
+        match (
+            sprite_pixel.priority,
+            sprite_pixel.is_transparent,
+            background_pixel.is_transparent,
+        ) {
+            (OamAttributePriority::FRONT, false, _) | (OamAttributePriority::BACK, false, true) => {
+                sprite_pixel.palette_entry
+            }
+            _ => background_pixel.palette_entry,
+        }
+    }
+
+    // The pixel as drawn with the display options
+    fn mix_display_pixels(
+        &self,
+        pixel_x: usize,
+        pixel_y: usize,
+        background_pixel: &BackgroundPixel,
+    ) -> u8 {
+        let transparent_entry = self.ppu_mem_map.palette.get_transparent_entry();
+        let background_pixel = if self.display_options.is_background_hidden {
+            BackgroundPixel {
+                palette_entry: transparent_entry,
+                is_transparent: true,
+            }
+        } else {
+            *background_pixel
+        };
+        let sprite_pixel = if self.display_options.are_sprites_hidden {
+            SpritePixel {
+                palette_entry: transparent_entry,
+                is_transparent: true,
+                ..SpritePixel::default()
+            }
+        } else {
+            self.get_sprite_pixel(pixel_x, pixel_y, true)
+        };
+        Ppu::mix_pixels(&background_pixel, &sprite_pixel)
+    }
+
+    // Sprites drawn with the display options are the ones past the limit and the ones not hidden
     #[inline]
-    fn get_sprite_pixel(&self, pixel_x: usize, pixel_y: usize) -> SpritePixel {
-        let mut color = self.ppu_mem_map.palette.get_transparent_color();
+    fn get_sprite_pixel(&self, pixel_x: usize, pixel_y: usize, is_displayed: bool) -> SpritePixel {
+        let mut palette_entry = self.ppu_mem_map.palette.get_transparent_entry();
         let mut priority = OamAttributePriority::default();
-        let mut sprite_index = 0;
+        let mut is_sprite_0 = false;
         let mut is_transparent = true;
 
         let sprite_height_pixels = if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
@@ -816,16 +980,32 @@ impl Ppu {
             8
         };
 
-        for index in (0..self.sprite_output_units.count).rev() {
-            let unit = self.sprite_output_units.units[index];
+        let sprite_output_units = &self.sprite_output_units;
+        let extra_units: &[SpriteOutputUnit] = if is_displayed {
+            &sprite_output_units.extra_units
+        } else {
+            &[]
+        };
+        let units = sprite_output_units.units[..sprite_output_units.count]
+            .iter()
+            .chain(extra_units);
+
+        for unit in units.rev() {
+            if is_displayed
+                && self.display_options.hidden_sprites
+                    & (1_u64 << unit.secondary_oam_entry.sprite_index)
+                    != 0
+            {
+                continue;
+            }
             if pixel_x < 8
                 && !self
                     .reg_mask
                     .contains(PpuMaskReg::IS_SHOW_SPRITES_ENABLED_LEFTMOST)
             {
-                color = self.ppu_mem_map.palette.get_sprite_color(0, 0);
+                palette_entry = self.ppu_mem_map.palette.get_sprite_entry(0, 0);
                 priority = unit.secondary_oam_entry.oam_entry.attributes.priority;
-                sprite_index = unit.secondary_oam_entry.sprite_index;
+                is_sprite_0 = unit.secondary_oam_entry.is_sprite_0;
                 is_transparent = true;
             } else {
                 let sprite_first_pixel_x = unit.secondary_oam_entry.oam_entry.sprite_x as usize;
@@ -849,172 +1029,418 @@ impl Ppu {
                 let palette_index = unit.secondary_oam_entry.oam_entry.attributes.palette_index;
                 let color_index = (pattern_bit_plane_high << 1) | pattern_bit_plane_low;
                 if color_index > 0 {
-                    color = self
+                    palette_entry = self
                         .ppu_mem_map
                         .palette
-                        .get_sprite_color(palette_index, color_index);
+                        .get_sprite_entry(palette_index, color_index);
                     priority = unit.secondary_oam_entry.oam_entry.attributes.priority;
-                    sprite_index = unit.secondary_oam_entry.sprite_index;
+                    is_sprite_0 = unit.secondary_oam_entry.is_sprite_0;
                     is_transparent = false;
                 }
             }
         }
         SpritePixel {
-            color,
+            palette_entry,
             priority,
-            sprite_index,
+            is_sprite_0,
             is_transparent,
         }
     }
 
-    fn evaluate_sprites(&mut self) {
-        self.secondary_oam.count = 0;
-
-        let sprite_height_pixels = if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
+    #[inline(always)]
+    fn sprite_height(&self) -> u16 {
+        if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
             16
         } else {
             8
-        };
+        }
+    }
 
-        let next_scanline_index = ((self.curr_scanline + 1) % 262) as usize;
-        let mut num_found_sprites = 0;
-        for (sprite_index, oam_entry) in self.ppu_mem_map.oam_table.oam_entries.iter().enumerate() {
-            let sprite_y_first_pixel = oam_entry.sprite_y.saturating_add(1) as usize;
-            let sprite_y_last_pixel = sprite_y_first_pixel + sprite_height_pixels - 1;
-            let is_overflowing_y = sprite_y_last_pixel >= 240;
-            if next_scanline_index > 0
-                && next_scanline_index >= sprite_y_first_pixel
-                && (next_scanline_index <= sprite_y_last_pixel || is_overflowing_y)
-            {
-                if num_found_sprites < 8 {
-                    self.secondary_oam.oam_entries[num_found_sprites] = SecondaryOamEntry {
-                        oam_entry: *oam_entry,
-                        sprite_index,
+    // Sprite evaluation, a dot at a time like the PPU does it. Secondary OAM is cleared to $FF
+    // on dots 1-64, then on dots 65-256 OAM is read at OAMADDR on odd dots and the byte is
+    // written to secondary OAM on even dots, advancing to the next byte of a sprite in range of
+    // the next scanline or to the next sprite otherwise. Dots 257-320 read secondary OAM back
+    // for the sprite fetches. There's no evaluation on the pre-render scanline.
+    fn step_sprite_evaluation(&mut self, dot: u16) {
+        match dot {
+            1..=64 if self.curr_scanline < 240 => {
+                let evaluation = &mut self.sprite_evaluation;
+                evaluation.oam_latch = 0xFF;
+                if dot & 1 == 0 {
+                    evaluation.secondary_oam[(dot as usize - 1) / 2] = 0xFF;
+                }
+            }
+            65..=256 if self.curr_scanline < 240 => {
+                if dot == 65 {
+                    self.sprite_evaluation = SpriteEvaluation {
+                        secondary_oam: self.sprite_evaluation.secondary_oam,
+                        ..SpriteEvaluation::default()
                     };
-                    num_found_sprites += 1;
+                }
+                if dot & 1 == 1 {
+                    self.sprite_evaluation.oam_latch =
+                        self.ppu_mem_map.oam_table.read(self.reg_oam_addr);
                 } else {
-                    if sprite_y_first_pixel > 0 && sprite_y_first_pixel <= 240 {
-                        self.reg_status.set(PpuStatusReg::IS_SPRITE_OVERFLOW, true);
-                    }
+                    self.evaluate_sprite_byte(dot);
                 }
             }
+            257..=320 => {
+                // Each fetch reads Y, tile, attributes and X, then X again for the last 4 dots
+                let fetch_dot = (dot - 257) as usize;
+                let index = (fetch_dot / 8) * 4 + (fetch_dot % 8).min(3);
+                self.sprite_evaluation.oam_latch = self.sprite_evaluation.secondary_oam[index];
+            }
+            0 | 321..=340 => {
+                self.sprite_evaluation.oam_latch = self.sprite_evaluation.secondary_oam[0];
+            }
+            _ => (),
         }
-        self.secondary_oam.count = num_found_sprites;
     }
 
-    fn prepare_sprite_units(&mut self) {
-        self.sprite_output_units.count = self.secondary_oam.count;
+    // Once 8 sprites are found, writes to secondary OAM turn into reads and the next sprites are
+    // still checked for overflow, but a sprite out of range advances both the sprite and the byte
+    // address, so bytes other than Y are checked as Y, giving false positives and negatives.
+    fn evaluate_sprite_byte(&mut self, dot: u16) {
+        let row = self
+            .curr_scanline
+            .wrapping_sub(self.sprite_evaluation.oam_latch as u16);
+        let is_in_range = row < self.sprite_height();
+
+        let evaluation = &mut self.sprite_evaluation;
+        let mut sprite_addr = self.reg_oam_addr >> 2;
+        let mut byte_addr = self.reg_oam_addr & 0b11;
+
+        if evaluation.is_done {
+            sprite_addr = (sprite_addr + 1) & 0x3F;
+            if evaluation.secondary_oam_addr >= 32 {
+                evaluation.oam_latch =
+                    evaluation.secondary_oam[evaluation.secondary_oam_addr & 0x1F];
+            }
+        } else {
+            if !evaluation.is_sprite_in_range && is_in_range {
+                evaluation.is_sprite_in_range = true;
+            }
+            if dot == 66 {
+                evaluation.is_sprite_0_in_range = evaluation.is_sprite_in_range;
+            }
 
-        for index in 0..self.secondary_oam.count {
-            let secondary_oam_entry = self.secondary_oam.oam_entries[index];
+            if evaluation.secondary_oam_addr < 32 {
+                evaluation.secondary_oam[evaluation.secondary_oam_addr] = evaluation.oam_latch;
 
-            let mut pattern_data_bitplanes: [[u8; 2]; 16] = [[0; 2]; 16];
+                if evaluation.is_sprite_in_range {
+                    if evaluation.secondary_oam_addr & 0b11 == 0 {
+                        evaluation.sprite_indices[evaluation.secondary_oam_addr / 4] = sprite_addr;
+                    }
+                    byte_addr += 1;
+                    evaluation.secondary_oam_addr += 1;
+
+                    if evaluation.secondary_oam_addr & 0b11 == 0 {
+                        // All 4 bytes copied
+                        evaluation.is_sprite_in_range = false;
+                        byte_addr = 0;
+                        sprite_addr = (sprite_addr + 1) & 0x3F;
+                        evaluation.is_done = sprite_addr == 0;
+                    }
+                } else {
+                    sprite_addr = (sprite_addr + 1) & 0x3F;
+                    evaluation.is_done = sprite_addr == 0;
+                }
+            } else {
+                evaluation.oam_latch =
+                    evaluation.secondary_oam[evaluation.secondary_oam_addr & 0x1F];
 
-            if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
-                // 8x16 sprites
-                let pattern_entry_byte = secondary_oam_entry.oam_entry.tile_bank_index;
-                let pattern_table_index = pattern_entry_byte & 0b1;
-
-                let pattern_entry_index_top = pattern_entry_byte & 0xFE;
-                let pattern_entry_index_bottom = pattern_entry_index_top + 1;
-
-                let mut pattern_data_top = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, pattern_entry_index_top);
-                let mut pattern_data_bottom = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, pattern_entry_index_bottom);
-
-                if secondary_oam_entry
-                    .oam_entry
-                    .attributes
-                    .is_flipped_vertically
-                {
-                    let temp = pattern_data_top;
-                    pattern_data_top = pattern_data_bottom;
-                    pattern_data_bottom = temp;
+                if evaluation.is_sprite_in_range {
+                    self.reg_status.set(PpuStatusReg::IS_SPRITE_OVERFLOW, true);
 
-                    pattern_data_top = Self::flip_pattern_data_vertically(pattern_data_top);
-                    pattern_data_bottom = Self::flip_pattern_data_vertically(pattern_data_bottom);
-                }
+                    byte_addr += 1;
+                    if byte_addr == 4 {
+                        sprite_addr = (sprite_addr + 1) & 0x3F;
+                        byte_addr = 0;
+                    }
 
-                if secondary_oam_entry
-                    .oam_entry
-                    .attributes
-                    .is_flipped_horizontally
-                {
-                    pattern_data_top = Self::flip_pattern_data_horizontally(pattern_data_top);
-                    pattern_data_bottom = Self::flip_pattern_data_horizontally(pattern_data_bottom);
+                    // The overflowing sprite's other 3 bytes are read, then evaluation stops
+                    if evaluation.overflow_bytes_left == 0 {
+                        evaluation.overflow_bytes_left = 3;
+                    } else {
+                        evaluation.overflow_bytes_left -= 1;
+                        if evaluation.overflow_bytes_left == 0 {
+                            evaluation.is_done = true;
+                            byte_addr = 0;
+                        }
+                    }
+                } else {
+                    // The hardware bug
+                    sprite_addr = (sprite_addr + 1) & 0x3F;
+                    byte_addr = (byte_addr + 1) & 0b11;
+                    evaluation.is_done = sprite_addr == 0;
                 }
+            }
+        }
 
-                for index in 0..8 {
-                    pattern_data_bitplanes[index][0] = pattern_data_top[index];
-                    pattern_data_bitplanes[index][1] = pattern_data_top[index + 8];
-                }
+        self.reg_oam_addr = sprite_addr << 2 | (byte_addr & 0b11);
+    }
 
-                for index in 8..16 {
-                    pattern_data_bitplanes[index][0] = pattern_data_bottom[index - 8];
-                    pattern_data_bitplanes[index][1] = pattern_data_bottom[index];
-                }
-            } else {
-                // 8x8 sprites
-                let pattern_table_index =
-                    self.reg_ctrl
-                        .contains(PpuCtrlReg::SPRITE_PATTERN_TABLE_INDEX) as u8;
-                let pattern_entry_index = secondary_oam_entry.oam_entry.tile_bank_index;
-                let mut pattern_data = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, pattern_entry_index);
-
-                if secondary_oam_entry
-                    .oam_entry
-                    .attributes
-                    .is_flipped_vertically
-                {
-                    pattern_data = Self::flip_pattern_data_vertically(pattern_data);
-                }
+    fn load_secondary_oam(&mut self) {
+        let evaluation = &self.sprite_evaluation;
+        self.secondary_oam.count = if self.curr_scanline < 240 {
+            (evaluation.secondary_oam_addr / 4).min(8)
+        } else {
+            0
+        };
 
-                if secondary_oam_entry
-                    .oam_entry
-                    .attributes
-                    .is_flipped_horizontally
-                {
-                    pattern_data = Self::flip_pattern_data_horizontally(pattern_data);
-                }
+        for index in 0..self.secondary_oam.count {
+            let bytes = &evaluation.secondary_oam[index * 4..index * 4 + 4];
+            self.secondary_oam.oam_entries[index] = SecondaryOamEntry {
+                oam_entry: OamEntry::from(bytes),
+                sprite_index: evaluation.sprite_indices[index] as usize,
+                is_sprite_0: index == 0 && evaluation.is_sprite_0_in_range,
+            };
+        }
+    }
 
-                for index in 0..8 {
-                    pattern_data_bitplanes[index][0] = pattern_data[index];
-                    pattern_data_bitplanes[index][1] = pattern_data[index + 8];
-                }
+    // If OAMADDR isn't below 8 when rendering starts, the 8 bytes at OAMADDR & $F8 are
+    // copied over the first 8
+    fn start_rendering_oam(&mut self) {
+        self.process_oam_corruption();
+
+        if self.reg_oam_addr >= 8 {
+            let oam_table = &mut self.ppu_mem_map.oam_table;
+            let row_addr = self.reg_oam_addr & 0xF8;
+            for index in 0..8 {
+                let byte = oam_table.read(row_addr + index);
+                oam_table.write_u8(index, byte);
             }
+        }
+    }
 
-            self.sprite_output_units.units[index] = SpriteOutputUnit {
-                secondary_oam_entry: secondary_oam_entry,
-                pattern_data: pattern_data_bitplanes,
-            };
+    // Disabling rendering while secondary OAM is cleared (dots 0-63) or read by the sprite
+    // fetches (dots 256-319) leaves an OAM row to be overwritten with row 0 once rendering is
+    // enabled again. This follows Mesen's model of which row gets corrupted.
+    fn set_oam_corruption(&mut self) {
+        let dot = self.curr_scanline_cycle % 341;
+        let row = match dot {
+            0..=63 => dot / 2,
+            256..=319 => {
+                let fetch_dot = dot - 256;
+                (fetch_dot / 8) * 4 + (fetch_dot % 8).min(3)
+            }
+            _ => return,
+        };
+        self.corrupted_oam_rows |= 1 << row;
+    }
+
+    fn process_oam_corruption(&mut self) {
+        let oam_entries = &mut self.ppu_mem_map.oam_table.oam_entries;
+        for row in 1..32 {
+            if self.corrupted_oam_rows & (1 << row) != 0 {
+                oam_entries[row * 2] = oam_entries[0];
+                oam_entries[row * 2 + 1] = oam_entries[1];
+            }
+        }
+        self.corrupted_oam_rows = 0;
+    }
+
+    fn prepare_sprite_units(&mut self) {
+        self.sprite_output_units.count = self.secondary_oam.count;
+
+        for index in 0..self.secondary_oam.count {
+            let secondary_oam_entry = self.secondary_oam.oam_entries[index];
+            self.sprite_output_units.units[index] =
+                self.sprite_output_unit(secondary_oam_entry, true);
+        }
+
+        // Sprites past the 8th are only drawn, their patterns are peeked so mappers watching
+        // the PPU bus don't see the extra fetches
+        self.sprite_output_units.extra_units.clear();
+        if self.display_options.is_sprite_limit_disabled {
+            for secondary_oam_entry in self.extra_sprites() {
+                let unit = self.sprite_output_unit(secondary_oam_entry, false);
+                self.sprite_output_units.extra_units.push(unit);
+            }
         }
 
         for _ in self.secondary_oam.count..8 {
             // We must fetch pattern data even if no sprite exists to toggle A12.
             // The PPU typically fetches the pattern for tile 0xFF in this case.
+            // Which row of it doesn't matter, as no mapper watches for the rows of tile 0xFF.
             let dummy_tile_index = 0xFF;
             if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
                 let pattern_table_index = dummy_tile_index & 0b1;
-                let _ = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, dummy_tile_index & 0xFE);
-                let _ = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, (dummy_tile_index & 0xFE) + 1);
+                let _ = self.ppu_mem_map.fetch_sprite_pattern(
+                    pattern_table_index,
+                    dummy_tile_index & 0xFE,
+                    0,
+                );
+                let _ = self.ppu_mem_map.fetch_sprite_pattern(
+                    pattern_table_index,
+                    (dummy_tile_index & 0xFE) + 1,
+                    0,
+                );
             } else {
                 let pattern_table_index =
                     self.reg_ctrl
                         .contains(PpuCtrlReg::SPRITE_PATTERN_TABLE_INDEX) as u8;
-                let _ = self
-                    .ppu_mem_map
-                    .fetch_sprite_pattern(pattern_table_index, dummy_tile_index);
+                let _ =
+                    self.ppu_mem_map
+                        .fetch_sprite_pattern(pattern_table_index, dummy_tile_index, 0);
+            }
+        }
+    }
+
+    fn sprite_output_unit(
+        &mut self,
+        secondary_oam_entry: SecondaryOamEntry,
+        is_fetched: bool,
+    ) -> SpriteOutputUnit {
+        let mut pattern_data_bitplanes: [[u8; 2]; 16] = [[0; 2]; 16];
+        let fetched_row = self.sprite_row(secondary_oam_entry);
+
+        if self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16) {
+            // 8x16 sprites
+            let pattern_entry_byte = secondary_oam_entry.oam_entry.tile_bank_index;
+            let pattern_table_index = pattern_entry_byte & 0b1;
+
+            let pattern_entry_index_top = pattern_entry_byte & 0xFE;
+            let pattern_entry_index_bottom = pattern_entry_index_top + 1;
+
+            // Only the tile with the row for the next scanline is fetched
+            let (fetched_row_top, fetched_row_bottom) = match fetched_row {
+                _ if !is_fetched => (None, None),
+                0..=7 => (Some(fetched_row), None),
+                _ => (None, Some(fetched_row - 8)),
+            };
+            let mut pattern_data_top = self.sprite_pattern(
+                pattern_table_index,
+                pattern_entry_index_top,
+                fetched_row_top,
+            );
+            let mut pattern_data_bottom = self.sprite_pattern(
+                pattern_table_index,
+                pattern_entry_index_bottom,
+                fetched_row_bottom,
+            );
+
+            if secondary_oam_entry
+                .oam_entry
+                .attributes
+                .is_flipped_vertically
+            {
+                let temp = pattern_data_top;
+                pattern_data_top = pattern_data_bottom;
+                pattern_data_bottom = temp;
+
+                pattern_data_top = Self::flip_pattern_data_vertically(pattern_data_top);
+                pattern_data_bottom = Self::flip_pattern_data_vertically(pattern_data_bottom);
             }
+
+            if secondary_oam_entry
+                .oam_entry
+                .attributes
+                .is_flipped_horizontally
+            {
+                pattern_data_top = Self::flip_pattern_data_horizontally(pattern_data_top);
+                pattern_data_bottom = Self::flip_pattern_data_horizontally(pattern_data_bottom);
+            }
+
+            for index in 0..8 {
+                pattern_data_bitplanes[index][0] = pattern_data_top[index];
+                pattern_data_bitplanes[index][1] = pattern_data_top[index + 8];
+            }
+
+            for index in 8..16 {
+                pattern_data_bitplanes[index][0] = pattern_data_bottom[index - 8];
+                pattern_data_bitplanes[index][1] = pattern_data_bottom[index];
+            }
+        } else {
+            // 8x8 sprites
+            let pattern_table_index =
+                self.reg_ctrl
+                    .contains(PpuCtrlReg::SPRITE_PATTERN_TABLE_INDEX) as u8;
+            let pattern_entry_index = secondary_oam_entry.oam_entry.tile_bank_index;
+            let fetched_row = if is_fetched { Some(fetched_row) } else { None };
+            let mut pattern_data =
+                self.sprite_pattern(pattern_table_index, pattern_entry_index, fetched_row);
+
+            if secondary_oam_entry
+                .oam_entry
+                .attributes
+                .is_flipped_vertically
+            {
+                pattern_data = Self::flip_pattern_data_vertically(pattern_data);
+            }
+
+            if secondary_oam_entry
+                .oam_entry
+                .attributes
+                .is_flipped_horizontally
+            {
+                pattern_data = Self::flip_pattern_data_horizontally(pattern_data);
+            }
+
+            for index in 0..8 {
+                pattern_data_bitplanes[index][0] = pattern_data[index];
+                pattern_data_bitplanes[index][1] = pattern_data[index + 8];
+            }
+        }
+
+        SpriteOutputUnit {
+            secondary_oam_entry,
+            pattern_data: pattern_data_bitplanes,
+        }
+    }
+
+    // Fetches the tile if the row fetched from it is given, peeks it otherwise
+    #[inline]
+    fn sprite_pattern(
+        &mut self,
+        pattern_table_index: u8,
+        pattern_entry_index: u8,
+        fetched_row: Option<u16>,
+    ) -> [u8; 16] {
+        match fetched_row {
+            Some(pixel_y) => self.ppu_mem_map.fetch_sprite_pattern(
+                pattern_table_index,
+                pattern_entry_index,
+                pixel_y,
+            ),
+            None => self
+                .ppu_mem_map
+                .peek_sprite_pattern(pattern_table_index, pattern_entry_index),
+        }
+    }
+
+    // Row of a sprite (0-15 for 8x16 sprites) that the PPU fetches for the next scanline
+    fn sprite_row(&self, secondary_oam_entry: SecondaryOamEntry) -> u16 {
+        let sprite_height = self.sprite_height();
+        let oam_entry = secondary_oam_entry.oam_entry;
+        let row = self.curr_scanline.wrapping_sub(oam_entry.sprite_y as u16) & (sprite_height - 1);
+        if oam_entry.attributes.is_flipped_vertically {
+            sprite_height - 1 - row
+        } else {
+            row
+        }
+    }
+
+    // The sprites in range of the next scanline after the 8 found by the evaluation
+    fn extra_sprites(&self) -> Vec<SecondaryOamEntry> {
+        let secondary_oam = &self.secondary_oam;
+        if secondary_oam.count < 8 {
+            return Vec::new();
         }
+        let sprite_height = self.sprite_height();
+        let first_index = secondary_oam.oam_entries[7].sprite_index + 1;
+        self.ppu_mem_map.oam_table.oam_entries[first_index.min(64)..]
+            .iter()
+            .enumerate()
+            .filter(|(_, oam_entry)| {
+                self.curr_scanline.wrapping_sub(oam_entry.sprite_y as u16) < sprite_height
+            })
+            .map(|(index, oam_entry)| SecondaryOamEntry {
+                oam_entry: *oam_entry,
+                sprite_index: first_index + index,
+                is_sprite_0: false,
+            })
+            .collect()
     }
 
     fn flip_pattern_data_vertically(pattern_data: [u8; 16]) -> [u8; 16] {
@@ -1049,6 +1475,94 @@ impl Ppu {
         &self.output_frame.data.as_slice()
     }
 
+    // Only filled in PaletteIndex output mode
+    #[inline(always)]
+    pub fn get_index_frame(&mut self) -> PpuIndexFrame<'_> {
+        self.is_frame_ready = false;
+        self.output_frame.indices.as_slice()
+    }
+
+    // Color subcarrier phase of the output frame, for NtscFilter
+    pub fn frame_burst_phase(&self) -> u8 {
+        self.output_frame.burst_phase
+    }
+
+    pub fn is_odd_frame(&self) -> bool {
+        self.is_odd_frame
+    }
+
+    pub fn output_mode(&self) -> PpuOutputMode {
+        self.output_mode
+    }
+
+    pub fn set_output_mode(&mut self, output_mode: PpuOutputMode) {
+        self.output_mode = output_mode;
+    }
+
+    pub fn display_options(&self) -> PpuDisplayOptions {
+        self.display_options
+    }
+
+    pub fn set_display_options(&mut self, display_options: PpuDisplayOptions) {
+        self.display_options = display_options;
+    }
+
+    // Greyscale keeps the column of grey colors of the palette entry,
+    // and the emphasis bits select one of the 8 sets of 64 colors
+    #[inline(always)]
+    fn output_value(&self, palette_entry: u8) -> u16 {
+        let mask = self.reg_mask.bits();
+        let palette_entry = if mask & PpuMaskReg::IS_GREYSCALE_ENABLED.bits() != 0 {
+            palette_entry & 0x30
+        } else {
+            palette_entry
+        };
+        ((mask as u16 & 0b1110_0000) << 1) | palette_entry as u16
+    }
+
+    #[inline(always)]
+    fn output_pixel(&mut self, index: usize, palette_entry: u8) {
+        let value = self.output_value(palette_entry);
+        match self.output_mode {
+            PpuOutputMode::Rgb => {
+                self.curr_frame.data[index] = self.ppu_mem_map.palette.get_color(value)
+            }
+            PpuOutputMode::PaletteIndex => self.curr_frame.indices[index] = value,
+        }
+    }
+
+    fn register_state(&self) -> PpuRegisterState {
+        PpuRegisterState {
+            reg_v: self.reg_v,
+            reg_t: self.reg_t,
+            reg_x: self.reg_x,
+            ctrl: self.reg_ctrl.bits(),
+            mask: self.reg_mask.bits(),
+        }
+    }
+
+    fn record_scanline_event(&mut self) {
+        let event = PpuScanlineEvent {
+            scanline: self.curr_scanline,
+            registers: self.register_state(),
+            mapper_banks: self.ppu_mem_map.mapper.bank_state(),
+        };
+        self.event_recorder.record_scanline(event);
+    }
+
+    // The write lands between the dot the PPU was last clocked on and the next one,
+    // so it's seen from curr_scanline_cycle on. Cycle 341 is the line's dot 0.
+    fn record_write_event(&mut self, index: u16, byte: u8) {
+        let event = PpuWriteEvent {
+            scanline: self.curr_scanline,
+            dot: self.curr_scanline_cycle % 341,
+            address: 0x2000 + index,
+            value: byte,
+            registers: Some(self.register_state()),
+        };
+        self.event_recorder.record_write(event);
+    }
+
     #[inline(always)]
     fn clock_mapper_irq(&mut self) {
         self.ppu_mem_map.mapper.clock_irq(self.reg_v);
@@ -1058,12 +1572,19 @@ impl Ppu {
 //
 
 impl MemMapped for Ppu {
+    // Bits a register doesn't drive come from the I/O latch, see io_latch
     fn read(&mut self, index: u16) -> u8 {
-        match index {
-            0 | 1 | 3 | 5 | 6 => 0, // Err(MemoryAccess(format!("Attempted read from write-only PPU register with index {}.", index))),
+        let (value, driven_bits) = match index {
+            // Write-only
+            0 | 1 | 3 | 5 | 6 => (0, 0),
             2 => {
-                // PPUSTATUS
-                let value = self.reg_status.bits();
+                // PPUSTATUS, with the ID of RC2C05 PPUs in the low bits
+                let value = self.reg_status.bits() | self.status_id;
+                let driven_bits = if self.is_ctrl_mask_swapped {
+                    0xFF
+                } else {
+                    0b1110_0000
+                };
 
                 // Reading $2002 within a few PPU clocks of when VBL is set results in special-case behavior.
                 // Reading one PPU clock before reads it as clear and never sets the flag or generates NMI for that frame.
@@ -1071,18 +1592,11 @@ impl MemMapped for Ppu {
                 // Reading two or more PPU clocks before/after it's set behaves normally (reads flag's value, clears it, and doesn't affect NMI operation).
                 // This suppression behavior is due to the $2002 read pulling the NMI line back up too quickly after it drops (NMI is active low) for the CPU to see it.
                 // (CPU inputs like NMI are sampled each clock.)
+                // The PPU is clocked before each CPU access and the CPU samples NMI after it,
+                // so a read that clears the flag on the cycle it was set is never seen as an NMI.
                 if self.is_mutating_read() {
-                    if self.curr_scanline == 241 {
-                        if self.curr_scanline_cycle == 0 {
-                            self.should_skip_vbl = true;
-                            self.nmi_pending = false;
-                        } else if self.curr_scanline_cycle == 1
-                            || self.curr_scanline_cycle == 2
-                            || self.curr_scanline_cycle == 3
-                        {
-                            self.should_skip_vbl = true;
-                            self.nmi_pending = false;
-                        }
+                    if self.curr_scanline == 241 && self.curr_scanline_cycle == 1 {
+                        self.should_skip_vbl = true;
                     }
 
                     // Reading from this register also resets the write latch and vblank active flag
@@ -1090,22 +1604,26 @@ impl MemMapped for Ppu {
                     self.reset_vblank_status();
                 }
 
-                value
+                (value, driven_bits)
             }
             4 => {
                 // OAMDATA
                 if self.is_mutating_read() {
-                    self.reg_oam_data = self.ppu_mem_map.oam_table.read(self.reg_oam_addr);
+                    self.reg_oam_data = if self.is_rendering_oam() {
+                        self.sprite_evaluation.oam_latch
+                    } else {
+                        self.ppu_mem_map.oam_table.read(self.reg_oam_addr)
+                    };
                 }
-                self.reg_oam_data
+                (self.reg_oam_data, 0xFF)
             }
             7 => {
                 // PPUDATA
                 let data = if (0x3F00..=0x3FFF).contains(&self.reg_v) {
-                    // Reads from palette RAM are not buffered
-                    self.ppu_mem_map.read(self.reg_v)
+                    // Reads from palette RAM are not buffered, and only drive the low 6 bits
+                    (self.ppu_mem_map.read(self.reg_v), 0b0011_1111)
                 } else {
-                    self.read_buffer
+                    (self.read_buffer, 0xFF)
                 };
                 if self.is_mutating_read() {
                     self.read_buffer = self.ppu_mem_map.read(self.reg_v);
@@ -1115,40 +1633,56 @@ impl MemMapped for Ppu {
                 data
             }
             _ => unreachable!(),
+        };
+
+        let value = (value & driven_bits) | (self.io_latch() & !driven_bits);
+        if self.is_mutating_read() {
+            self.refresh_io_latch(value, driven_bits);
         }
+        value
     }
 
     fn write(&mut self, index: u16, byte: u8) {
-        match index {
+        self.refresh_io_latch(byte, 0xFF);
+
+        let register_index = match index {
+            0 | 1 if self.is_ctrl_mask_swapped => index ^ 1,
+            _ => index,
+        };
+        match register_index {
             0 => {
-                // TODO: For better accuracy, replace old_is_nmi_enabled check with PPU cycle count
-                let old_is_nmi_enabled = self.reg_ctrl.contains(PpuCtrlReg::IS_NMI_ENABLED);
+                // Enabling NMI during vblank asserts the NMI line right away,
+                // so it can fire again without a new vblank
                 self.reg_ctrl = PpuCtrlReg::from_bits_truncate(byte);
 
-                if !old_is_nmi_enabled
-                    && self.reg_ctrl.contains(PpuCtrlReg::IS_NMI_ENABLED)
-                    && self.reg_status.contains(PpuStatusReg::IS_IN_VBLANK)
-                    && self.curr_scanline_cycle > 3
-                {
-                    self.nmi_pending = true;
-                }
-                if !self.reg_ctrl.contains(PpuCtrlReg::IS_NMI_ENABLED) {
-                    self.nmi_pending = false;
-                }
-
                 // reg_t: ....GH.. ........ <- byte: ......GH
                 let name_table_index = ((byte & BIT_MASK_2) as u16) << 10;
                 let mask: u16 = 0b0000_1100_0000_0000;
                 self.reg_t = (self.reg_t & !mask) | (name_table_index & mask);
             }
-            1 => self.reg_mask = PpuMaskReg::from_bits_truncate(byte),
+            1 => {
+                let was_rendering_enabled = self.is_rendering_enabled();
+                self.reg_mask = PpuMaskReg::from_bits_truncate(byte);
+                if self.curr_scanline < 240 || self.curr_scanline == 261 {
+                    match (was_rendering_enabled, self.is_rendering_enabled()) {
+                        (true, false) => self.set_oam_corruption(),
+                        (false, true) => self.process_oam_corruption(),
+                        _ => (),
+                    }
+                }
+            }
             2 => (),
             3 => {
                 self.reg_oam_addr = byte;
             }
             4 => {
-                self.ppu_mem_map.oam_table.write_u8(self.reg_oam_addr, byte);
-                self.reg_oam_addr = self.reg_oam_addr.wrapping_add(1);
+                if self.is_rendering_oam() {
+                    // OAM isn't written, but OAMADDR is bumped to the next sprite
+                    self.reg_oam_addr = self.reg_oam_addr.wrapping_add(4);
+                } else {
+                    self.ppu_mem_map.oam_table.write_u8(self.reg_oam_addr, byte);
+                    self.reg_oam_addr = self.reg_oam_addr.wrapping_add(1);
+                }
             }
             5 => {
                 if !self.is_address_latch_on {
@@ -1208,6 +1742,10 @@ impl MemMapped for Ppu {
             }
             _ => unreachable!(),
         }
+
+        if self.event_recorder.is_enabled() {
+            self.record_write_event(index, byte);
+        }
     }
 
     fn is_mutating_read(&self) -> bool {
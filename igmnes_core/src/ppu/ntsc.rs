@@ -0,0 +1,365 @@
+// NTSC video filter, in the spirit of blargg's nes_ntsc.
+//
+// Instead of looking up colors, the PPU's composite signal is generated from the 9-bit
+// output values and decoded the way a TV would, which gives the dot crawl, color fringing
+// and horizontal blur of the real thing. The signal model follows the nesdev wiki
+// (https://www.nesdev.org/wiki/NTSC_video):
+//
+// - Every PPU dot lasts 8 samples of the signal, and a color subcarrier cycle lasts 12
+// - A color is a square wave between a low and a high voltage of its luma level,
+//   with its hue (1-12) picking which 6 of the 12 phases are high
+// - Colors 0 and 13-15 have no carrier, only their high or low voltage
+// - Each emphasis bit attenuates the signal during the 6 phases of its color
+//
+// A scanline is 341 dots, or 2728 samples, so each scanline starts 4 samples (120 degrees)
+// later in the subcarrier cycle than the one above it. A frame is 4 samples longer than a
+// whole number of cycles, or 8 samples shorter when the PPU skips the last dot of odd frames,
+// which is what makes the dot crawl move between frames. The PPU keeps track of this as the
+// burst phase (0-2, in 4 sample steps) of its output frame.
+use crate::ppu::palette::{PpuPaletteColor, PALETTE_COLOR_COUNT};
+
+use std::array;
+use std::str::FromStr;
+
+pub const NTSC_FRAME_WIDTH: usize = 602;
+pub const NTSC_FRAME_HEIGHT: usize = 240;
+
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const SAMPLES_PER_SCANLINE: usize = 256 * SAMPLES_PER_PIXEL;
+
+// Output pixels are 12/7 of a PPU pixel apart (7 output pixels for every 3 input pixels),
+// starting a bit to the left so that the blur past the edges of the picture isn't cut off
+const SAMPLES_PER_OUTPUT_PIXEL: f32 = 24.0 / 7.0;
+const OUTPUT_SAMPLE_OFFSET: f32 =
+    (NTSC_FRAME_WIDTH as f32 * SAMPLES_PER_OUTPUT_PIXEL - SAMPLES_PER_SCANLINE as f32) / 2.0;
+
+// Blank signal on both sides of the scanline, wide enough for the widest filter
+const SCANLINE_BORDER: usize = 3 * SAMPLES_PER_CYCLE;
+
+// Signal voltages, normalized to black and white
+const SIGNAL_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const SIGNAL_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const SIGNAL_BLACK: f32 = 0.312;
+const SIGNAL_WHITE: f32 = 1.100;
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// Line up the decoded colors with the default palette, which is what a TV's hue and color
+// knobs end up being set to
+const HUE_OFFSET_DEGREES: f32 = 115.0;
+const CHROMA_GAIN: f32 = 1.4;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum NtscFilterPreset {
+    #[default]
+    Composite,
+    SVideo,
+    Rgb,
+    Monochrome,
+}
+
+impl FromStr for NtscFilterPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "composite" => Ok(NtscFilterPreset::Composite),
+            "svideo" | "s-video" => Ok(NtscFilterPreset::SVideo),
+            "rgb" => Ok(NtscFilterPreset::Rgb),
+            "monochrome" | "mono" => Ok(NtscFilterPreset::Monochrome),
+            _ => Err(format!("Unknown NTSC filter preset: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NtscFilterSettings {
+    // Hue rotation in degrees
+    pub hue: f32,
+    // 0 is black and white, 1 is normal
+    pub saturation: f32,
+    // -1 blurs the picture further, 1 sharpens it
+    pub sharpness: f32,
+    // 0-1, how much of the color subcarrier the TV's notch filter leaves in luma, which
+    // shows up as dot crawl on the edges between colors. Also sets the notch filter's
+    // width, so 0 gives a crisper picture
+    pub artifacts: f32,
+    // 0-1, how much luma ends up in the decoded color, which shows up as colored fringes
+    // on the edges between brightness levels
+    pub fringing: f32,
+    // 0-1, how much the color is blurred compared to luma
+    pub bleed: f32,
+}
+
+impl NtscFilterSettings {
+    pub fn from_preset(preset: NtscFilterPreset) -> NtscFilterSettings {
+        let composite = NtscFilterSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            sharpness: 0.0,
+            artifacts: 1.0,
+            fringing: 1.0,
+            bleed: 1.0,
+        };
+
+        match preset {
+            NtscFilterPreset::Composite => composite,
+            NtscFilterPreset::SVideo => NtscFilterSettings {
+                sharpness: 0.2,
+                artifacts: 0.0,
+                fringing: 0.0,
+                ..composite
+            },
+            NtscFilterPreset::Rgb => NtscFilterSettings {
+                sharpness: 0.2,
+                artifacts: 0.0,
+                fringing: 0.0,
+                bleed: 0.0,
+                ..composite
+            },
+            NtscFilterPreset::Monochrome => NtscFilterSettings {
+                saturation: 0.0,
+                fringing: 0.0,
+                bleed: 0.0,
+                ..composite
+            },
+        }
+    }
+}
+
+impl Default for NtscFilterSettings {
+    fn default() -> Self {
+        NtscFilterSettings::from_preset(NtscFilterPreset::default())
+    }
+}
+
+impl From<NtscFilterPreset> for NtscFilterSettings {
+    fn from(preset: NtscFilterPreset) -> Self {
+        NtscFilterSettings::from_preset(preset)
+    }
+}
+
+// Running sums of one scanline's luma and demodulated color, so that the box filters
+// of any width cost the same
+#[derive(Default)]
+struct ScanlineSums {
+    luma: Vec<f32>,
+    i: Vec<f32>,
+    q: Vec<f32>,
+}
+
+impl ScanlineSums {
+    fn average(sums: &[f32], center: usize, width: usize) -> f32 {
+        let half_width = width / 2;
+        (sums[center + half_width] - sums[center - half_width]) / width as f32
+    }
+}
+
+pub struct NtscFilter {
+    settings: NtscFilterSettings,
+
+    // Signal of every 9-bit output value at each of the 12 phases, along with its average,
+    // the luma a TV with perfect Y/C separation would see
+    signal_levels: Box<[[f32; SAMPLES_PER_CYCLE]; PALETTE_COLOR_COUNT]>,
+    luma_levels: Box<[f32; PALETTE_COLOR_COUNT]>,
+
+    // Subcarrier the color is demodulated with, hue adjusted
+    carrier_cos: [f32; SAMPLES_PER_CYCLE],
+    carrier_sin: [f32; SAMPLES_PER_CYCLE],
+
+    sums: ScanlineSums,
+}
+
+impl NtscFilter {
+    pub fn new(settings: NtscFilterSettings) -> NtscFilter {
+        let mut filter = NtscFilter {
+            settings,
+            signal_levels: Box::new([[0.0; SAMPLES_PER_CYCLE]; PALETTE_COLOR_COUNT]),
+            luma_levels: Box::new([0.0; PALETTE_COLOR_COUNT]),
+            carrier_cos: [0.0; SAMPLES_PER_CYCLE],
+            carrier_sin: [0.0; SAMPLES_PER_CYCLE],
+            sums: ScanlineSums::default(),
+        };
+        filter.generate_signal_levels();
+        filter.generate_carrier();
+        filter
+    }
+
+    pub fn settings(&self) -> NtscFilterSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: NtscFilterSettings) {
+        self.settings = settings;
+        self.generate_carrier();
+    }
+
+    pub fn signal_level(value: u16, phase: usize) -> f32 {
+        let color = (value & 0x0F) as usize;
+        let emphasis = (value >> 6) & 0b111;
+        let level = if color > 13 {
+            1
+        } else {
+            ((value >> 4) & 0b11) as usize
+        };
+
+        let low = if color == 0 {
+            SIGNAL_HIGH[level]
+        } else {
+            SIGNAL_LOW[level]
+        };
+        let high = if color > 12 { low } else { SIGNAL_HIGH[level] };
+
+        let is_in_color_phase = |color: usize| (color + phase) % SAMPLES_PER_CYCLE < 6;
+
+        let mut signal = if is_in_color_phase(color) { high } else { low };
+        if (emphasis & 0b001 != 0 && is_in_color_phase(0))
+            || (emphasis & 0b010 != 0 && is_in_color_phase(4))
+            || (emphasis & 0b100 != 0 && is_in_color_phase(8))
+        {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+
+        (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+    }
+
+    fn generate_signal_levels(&mut self) {
+        for value in 0..PALETTE_COLOR_COUNT {
+            let levels = &mut self.signal_levels[value];
+            for (phase, level) in levels.iter_mut().enumerate() {
+                *level = NtscFilter::signal_level(value as u16, phase);
+            }
+            self.luma_levels[value] = levels.iter().sum::<f32>() / SAMPLES_PER_CYCLE as f32;
+        }
+    }
+
+    fn generate_carrier(&mut self) {
+        (self.carrier_cos, self.carrier_sin) = NtscFilter::carrier(self.settings.hue);
+    }
+
+    fn carrier(hue: f32) -> ([f32; SAMPLES_PER_CYCLE], [f32; SAMPLES_PER_CYCLE]) {
+        let hue = (hue + HUE_OFFSET_DEGREES).to_radians();
+        let angle = |phase: usize| std::f32::consts::PI * phase as f32 / 6.0 + hue;
+        (
+            array::from_fn(|phase| angle(phase).cos()),
+            array::from_fn(|phase| angle(phase).sin()),
+        )
+    }
+
+    // Decodes a flat area of a 9-bit output value to YIQ, which is all the palette
+    // generator needs
+    pub(crate) fn decode_flat(value: u16, hue: f32) -> (f32, f32, f32) {
+        let (carrier_cos, carrier_sin) = NtscFilter::carrier(hue);
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for phase in 0..SAMPLES_PER_CYCLE {
+            let level = NtscFilter::signal_level(value, phase);
+            y += level;
+            i += level * carrier_cos[phase];
+            q += level * carrier_sin[phase];
+        }
+
+        let cycle = SAMPLES_PER_CYCLE as f32;
+        (y / cycle, i / cycle * CHROMA_GAIN, q / cycle * CHROMA_GAIN)
+    }
+
+    pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [f32; 3] {
+        [
+            y + 0.956 * i + 0.621 * q,
+            y - 0.272 * i - 0.647 * q,
+            y - 1.106 * i + 1.703 * q,
+        ]
+    }
+
+    // Filters a frame of 9-bit output values (see PpuOutputMode::PaletteIndex) into
+    // NTSC_FRAME_WIDTH * NTSC_FRAME_HEIGHT colors
+    pub fn apply(&mut self, frame: &[u16], burst_phase: u8, output: &mut [PpuPaletteColor]) {
+        let sample_count = SAMPLES_PER_SCANLINE + 2 * SCANLINE_BORDER;
+        self.sums.luma.resize(sample_count + 1, 0.0);
+        self.sums.i.resize(sample_count + 1, 0.0);
+        self.sums.q.resize(sample_count + 1, 0.0);
+
+        let NtscFilterSettings {
+            saturation,
+            sharpness,
+            artifacts,
+            fringing,
+            bleed,
+            ..
+        } = self.settings;
+
+        for (scanline, (input, output)) in frame
+            .chunks_exact(256)
+            .zip(output.chunks_exact_mut(NTSC_FRAME_WIDTH))
+            .enumerate()
+        {
+            // The border is a whole number of cycles, so it doesn't shift the phase
+            let scanline_phase = (burst_phase as usize + scanline) * 4;
+
+            let (mut luma_sum, mut i_sum, mut q_sum) = (0.0, 0.0, 0.0);
+            for sample in 0..sample_count {
+                let phase = (scanline_phase + sample) % SAMPLES_PER_CYCLE;
+                let pixel = sample.wrapping_sub(SCANLINE_BORDER) / SAMPLES_PER_PIXEL;
+
+                let (signal, luma) = match input.get(pixel) {
+                    Some(&value) => {
+                        let value = value as usize & (PALETTE_COLOR_COUNT - 1);
+                        (self.signal_levels[value][phase], self.luma_levels[value])
+                    }
+                    None => (0.0, 0.0),
+                };
+                let chroma = signal - luma;
+
+                luma_sum += luma + artifacts * chroma;
+                let color_signal = chroma + fringing * luma;
+                i_sum += color_signal * self.carrier_cos[phase];
+                q_sum += color_signal * self.carrier_sin[phase];
+
+                self.sums.luma[sample + 1] = luma_sum;
+                self.sums.i[sample + 1] = i_sum;
+                self.sums.q[sample + 1] = q_sum;
+            }
+
+            for (x, color) in output.iter_mut().enumerate() {
+                let center = (x as f32 * SAMPLES_PER_OUTPUT_PIXEL - OUTPUT_SAMPLE_OFFSET).round()
+                    as isize
+                    + SCANLINE_BORDER as isize;
+                let center = center.clamp(
+                    SAMPLES_PER_CYCLE as isize + SAMPLES_PER_PIXEL as isize,
+                    (sample_count - SAMPLES_PER_CYCLE - SAMPLES_PER_PIXEL) as isize,
+                ) as usize;
+
+                // The notch filter removing the subcarrier from luma has to span a whole
+                // cycle to remove it completely, a pixel wide one lets some of it through
+                let luma_at = |center: usize| {
+                    let sums = &self.sums.luma;
+                    let narrow = ScanlineSums::average(sums, center, SAMPLES_PER_PIXEL);
+                    let wide = ScanlineSums::average(sums, center, SAMPLES_PER_CYCLE);
+                    narrow + (wide - narrow) * artifacts
+                };
+                let y = luma_at(center);
+                let neighbors = (luma_at(center - SAMPLES_PER_PIXEL)
+                    + luma_at(center + SAMPLES_PER_PIXEL))
+                    / 2.0;
+                let y = y + (y - neighbors) * sharpness;
+
+                // Demodulating over whole cycles cancels out flat luma, and averaging over
+                // two of them halves the color's bandwidth
+                let color_at = |sums: &[f32]| {
+                    let sharp = ScanlineSums::average(sums, center, SAMPLES_PER_CYCLE);
+                    let blurred = ScanlineSums::average(sums, center, 2 * SAMPLES_PER_CYCLE);
+                    (sharp + (blurred - sharp) * bleed) * CHROMA_GAIN * saturation
+                };
+                let i = color_at(&self.sums.i);
+                let q = color_at(&self.sums.q);
+
+                *color = PpuPaletteColor::from_rgb(NtscFilter::yiq_to_rgb(y, i, q));
+            }
+        }
+    }
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        NtscFilter::new(NtscFilterSettings::default())
+    }
+}
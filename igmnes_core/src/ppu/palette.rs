@@ -1,12 +1,20 @@
 use crate::memory::MemMapped;
+use crate::ppu::ntsc::NtscFilter;
+use crate::vs_system::VsPpuType;
 use std::array;
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{ErrorKind, Read};
 use std::ops::Index;
 use std::path::Path;
+use std::str::FromStr;
 
 const PALETTE_COLOR_BYTE_LEN: usize = 3;
+pub const PALETTE_COLOR_COUNT: usize = 512;
+// How much each emphasis bit darkens the other channels, measured on a 2C02
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+// The 2C07 generates its colors half a hue step (15 degrees) away from the 2C02
+const PAL_HUE_OFFSET_DEGREES: f32 = -15.0;
 const DEFAULT_PALETTE: &'static [u8] = include_bytes!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/../palette/DigitalPrime.pal"
@@ -33,6 +41,18 @@ impl Index<usize> for PpuPaletteColor {
     }
 }
 
+impl PpuPaletteColor {
+    // Channels from 0 to 1, clamped
+    pub fn from_rgb(rgb: [f32; 3]) -> PpuPaletteColor {
+        let to_u8 = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+        PpuPaletteColor {
+            red: to_u8(rgb[0]),
+            green: to_u8(rgb[1]),
+            blue: to_u8(rgb[2]),
+        }
+    }
+}
+
 impl From<&[u8]> for PpuPaletteColor {
     fn from(triplet: &[u8]) -> Self {
         PpuPaletteColor {
@@ -43,40 +63,274 @@ impl From<&[u8]> for PpuPaletteColor {
     }
 }
 
+// PPUs the palette generator can produce the colors of. The 2C02 and 2C07 colors are decoded
+// from their video signal, the RGB PPUs have theirs in a ROM
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PpuPalettePreset {
+    // NTSC
+    #[default]
+    Rp2c02,
+    Rp2c03,
+    // RP2C04-0001 to -0004
+    Rp2c04(u8),
+    Rc2c05,
+    // PAL
+    Rp2c07,
+}
+
+// Accepts names like 2c02, rp2c03, 2c04-0002 or 2c04-2 (2c04 is the -0001)
+impl FromStr for PpuPalettePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let name = name.trim_start_matches("rp").trim_start_matches("rc");
+        match name {
+            "2c02" => Ok(PpuPalettePreset::Rp2c02),
+            "2c03" => Ok(PpuPalettePreset::Rp2c03),
+            "2c04" => Ok(PpuPalettePreset::Rp2c04(1)),
+            "2c05" => Ok(PpuPalettePreset::Rc2c05),
+            "2c07" => Ok(PpuPalettePreset::Rp2c07),
+            _ => name
+                .strip_prefix("2c04-")
+                .and_then(|number| number.parse::<u8>().ok())
+                .filter(|number| (1..=4).contains(number))
+                .map(PpuPalettePreset::Rp2c04)
+                .ok_or_else(|| format!("Unknown palette preset: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PpuPaletteSettings {
+    // Hue rotation in degrees, only for the 2C02 and 2C07
+    pub hue: f32,
+    // 0 is black and white, 1 is normal, only for the 2C02 and 2C07
+    pub saturation: f32,
+    // 1 is normal
+    pub contrast: f32,
+    // Added to every channel, 0 is normal
+    pub brightness: f32,
+    // Applied as channel^(1/gamma), so 1 is normal and higher values brighten the midtones
+    pub gamma: f32,
+}
+
+impl Default for PpuPaletteSettings {
+    fn default() -> Self {
+        PpuPaletteSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl PpuPaletteSettings {
+    fn adjust(&self, rgb: [f32; 3]) -> PpuPaletteColor {
+        PpuPaletteColor::from_rgb(rgb.map(|channel| {
+            let channel = (channel * self.contrast + self.brightness).clamp(0.0, 1.0);
+            channel.powf(1.0 / self.gamma)
+        }))
+    }
+}
+
+// The 512 colors the PPU can output: the 64 palette entries for each combination of the
+// three emphasis bits of PPUMASK. A 9-bit output value indexes it directly, with the palette
+// entry in bits 0-5 and the emphasis bits in bits 6-8 (red, green and blue on the 2C02).
 #[derive(Clone, Debug)]
-pub struct PpuPalette {
-    colors: Box<[PpuPaletteColor; 64]>,
-    mapping: [usize; 32],
+pub struct PpuPaletteColors {
+    colors: Box<[PpuPaletteColor; PALETTE_COLOR_COUNT]>,
 }
 
-impl Default for PpuPalette {
+impl Default for PpuPaletteColors {
     fn default() -> Self {
-        PpuPalette::load_default().unwrap()
+        PpuPaletteColors::load_default().unwrap()
     }
 }
 
-impl TryFrom<&[u8]> for PpuPalette {
+// Files with 64 colors get the emphasized colors generated, files with 512 colors have them
+impl TryFrom<&[u8]> for PpuPaletteColors {
     type Error = std::io::Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, std::io::Error> {
-        if bytes.len() < 64 * PALETTE_COLOR_BYTE_LEN {
-            Err(std::io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "PpuPalette needs at least 64 color triplets (192 bytes)",
-            ))
-        } else {
-            let colors: [PpuPaletteColor; 64] = array::from_fn(|index| {
+        if bytes.len() >= PALETTE_COLOR_COUNT * PALETTE_COLOR_BYTE_LEN {
+            let colors: [PpuPaletteColor; PALETTE_COLOR_COUNT] = array::from_fn(|index| {
                 PpuPaletteColor::from(&bytes[index * 3..(index * 3) + PALETTE_COLOR_BYTE_LEN])
             });
 
-            Ok(PpuPalette {
+            Ok(PpuPaletteColors {
                 colors: Box::new(colors),
-                mapping: [0; 32],
             })
+        } else if bytes.len() >= 64 * PALETTE_COLOR_BYTE_LEN {
+            let colors: [PpuPaletteColor; 64] = array::from_fn(|index| {
+                PpuPaletteColor::from(&bytes[index * 3..(index * 3) + PALETTE_COLOR_BYTE_LEN])
+            });
+
+            Ok(PpuPaletteColors::with_generated_emphasis(&colors))
+        } else {
+            Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "PpuPaletteColors needs at least 64 color triplets (192 bytes)",
+            ))
         }
     }
 }
 
+impl PpuPaletteColors {
+    pub fn load(file_path: &Path) -> Result<PpuPaletteColors, std::io::Error> {
+        let mut file = File::open(file_path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        PpuPaletteColors::try_from(bytes.iter().as_ref())
+    }
+
+    pub fn load_default() -> Result<PpuPaletteColors, std::io::Error> {
+        Ok(PpuPaletteColors::try_from(DEFAULT_PALETTE).unwrap())
+    }
+
+    pub fn generate(preset: PpuPalettePreset, settings: &PpuPaletteSettings) -> PpuPaletteColors {
+        let rgb_ppu_type = match preset {
+            PpuPalettePreset::Rp2c02 => {
+                return PpuPaletteColors::generate_from_signal(
+                    |value| NtscFilter::decode_flat(value, settings.hue),
+                    settings,
+                );
+            }
+            // The 2C07 has the red and green emphasis bits swapped. PAL TVs flip the
+            // phase of every other line to cancel out phase errors, so a flat area of
+            // color decodes the same as it would on NTSC
+            PpuPalettePreset::Rp2c07 => {
+                return PpuPaletteColors::generate_from_signal(
+                    |value| {
+                        let value = (value & !0b0_1100_0000)
+                            | ((value & 0b0_0100_0000) << 1)
+                            | ((value & 0b0_1000_0000) >> 1);
+                        NtscFilter::decode_flat(value, settings.hue + PAL_HUE_OFFSET_DEGREES)
+                    },
+                    settings,
+                );
+            }
+            PpuPalettePreset::Rp2c03 => VsPpuType::Rp2c03,
+            PpuPalettePreset::Rp2c04(number) => VsPpuType::Rp2c04(number),
+            PpuPalettePreset::Rc2c05 => VsPpuType::Rc2c05(1),
+        };
+
+        let rgb_colors = PpuPaletteColors::with_rgb_emphasis(&rgb_ppu_type.palette_colors());
+        let colors = rgb_colors.colors.map(|color| {
+            let channel = |channel: u8| channel as f32 / 255.0;
+            settings.adjust([
+                channel(color.red),
+                channel(color.green),
+                channel(color.blue),
+            ])
+        });
+
+        PpuPaletteColors {
+            colors: Box::new(colors),
+        }
+    }
+
+    fn generate_from_signal<F>(decode: F, settings: &PpuPaletteSettings) -> PpuPaletteColors
+    where
+        F: Fn(u16) -> (f32, f32, f32),
+    {
+        let colors: [PpuPaletteColor; PALETTE_COLOR_COUNT] = array::from_fn(|value| {
+            let (y, i, q) = decode(value as u16);
+            let (i, q) = (i * settings.saturation, q * settings.saturation);
+            settings.adjust(NtscFilter::yiq_to_rgb(y, i, q))
+        });
+
+        PpuPaletteColors {
+            colors: Box::new(colors),
+        }
+    }
+
+    // On the 2C02, each emphasis bit darkens the two other color channels,
+    // so with all three set the whole picture is darker
+    pub fn with_generated_emphasis(colors: &[PpuPaletteColor; 64]) -> PpuPaletteColors {
+        PpuPaletteColors::with_emphasis(colors, |channel, _, other_emphasis_count| {
+            let attenuation = EMPHASIS_ATTENUATION.powi(other_emphasis_count as i32);
+            (channel as f32 * attenuation).round() as u8
+        })
+    }
+
+    // The RGB PPUs of the Vs. System and PlayChoice-10 turn emphasized channels fully on
+    pub fn with_rgb_emphasis(colors: &[PpuPaletteColor; 64]) -> PpuPaletteColors {
+        PpuPaletteColors::with_emphasis(
+            colors,
+            |channel, is_emphasized, _| {
+                if is_emphasized {
+                    0xFF
+                } else {
+                    channel
+                }
+            },
+        )
+    }
+
+    fn with_emphasis<F>(colors: &[PpuPaletteColor; 64], emphasize: F) -> PpuPaletteColors
+    where
+        F: Fn(u8, bool, u32) -> u8,
+    {
+        let colors: [PpuPaletteColor; PALETTE_COLOR_COUNT] = array::from_fn(|index| {
+            let color = colors[index % 64];
+            let emphasis = (index / 64) as u8;
+            let emphasize_channel = |channel: u8, bit: u8| {
+                let is_emphasized = emphasis & bit != 0;
+                let other_emphasis_count = (emphasis & !bit).count_ones();
+                emphasize(channel, is_emphasized, other_emphasis_count)
+            };
+
+            PpuPaletteColor {
+                red: emphasize_channel(color.red, 0b001),
+                green: emphasize_channel(color.green, 0b010),
+                blue: emphasize_channel(color.blue, 0b100),
+            }
+        });
+
+        PpuPaletteColors {
+            colors: Box::new(colors),
+        }
+    }
+
+    #[inline(always)]
+    pub fn color(&self, value: u16) -> PpuPaletteColor {
+        self.colors[value as usize & (PALETTE_COLOR_COUNT - 1)]
+    }
+
+    // Converts a frame of 9-bit output values to colors
+    pub fn convert_frame(&self, frame: &[u16], output: &mut [PpuPaletteColor]) {
+        for (color, &value) in output.iter_mut().zip(frame) {
+            *color = self.color(value);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PpuPalette {
+    colors: PpuPaletteColors,
+    mapping: [usize; 32],
+}
+
+impl Default for PpuPalette {
+    fn default() -> Self {
+        PpuPalette::load_default().unwrap()
+    }
+}
+
+impl TryFrom<&[u8]> for PpuPalette {
+    type Error = std::io::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        Ok(PpuPalette {
+            colors: PpuPaletteColors::try_from(bytes)?,
+            mapping: [0; 32],
+        })
+    }
+}
+
 impl PpuPalette {
     pub fn load(file_path: &Path) -> Result<PpuPalette, std::io::Error> {
         let mut file = File::open(file_path)?;
@@ -89,10 +343,20 @@ impl PpuPalette {
         Ok(PpuPalette::try_from(DEFAULT_PALETTE).unwrap())
     }
 
+    pub fn colors(&self) -> &PpuPaletteColors {
+        &self.colors
+    }
+
+    // Replaces the colors, keeping the palette RAM contents
+    pub fn set_colors(&mut self, colors: PpuPaletteColors) {
+        self.colors = colors;
+    }
+
+    // Palette RAM entries are the 6-bit index of a color
     #[inline(always)]
-    pub fn get_background_color(&self, palette_index: u8, color_index: u8) -> PpuPaletteColor {
+    pub fn get_background_entry(&self, palette_index: u8, color_index: u8) -> u8 {
         if color_index == 0 {
-            self.get_transparent_color()
+            self.get_transparent_entry()
         } else {
             let base_mapping_index = match palette_index {
                 0 => 0x1,
@@ -102,15 +366,14 @@ impl PpuPalette {
                 _ => unreachable!(),
             };
             let mapping_index = base_mapping_index + color_index as usize - 1;
-            let color_index = self.mapping[mapping_index];
-            self.colors[color_index]
+            self.mapping[mapping_index] as u8
         }
     }
 
     #[inline(always)]
-    pub fn get_sprite_color(&self, palette_index: u8, color_index: u8) -> PpuPaletteColor {
+    pub fn get_sprite_entry(&self, palette_index: u8, color_index: u8) -> u8 {
         if color_index == 0 {
-            self.get_transparent_color()
+            self.get_transparent_entry()
         } else {
             let base_mapping_index = match palette_index {
                 0 => 0x11,
@@ -120,19 +383,29 @@ impl PpuPalette {
                 _ => unreachable!(),
             };
             let mapping_index = base_mapping_index + color_index as usize - 1;
-            let color_index = self.mapping[mapping_index];
-            self.colors[color_index]
+            self.mapping[mapping_index] as u8
         }
     }
 
+    // Entry at one of the 32 palette RAM addresses
+    pub fn get_entry(&self, index: u8) -> u8 {
+        self.mapping[index as usize & 0x1F] as u8
+    }
+
+    #[inline(always)]
+    pub fn get_transparent_entry(&self) -> u8 {
+        self.mapping[0] as u8
+    }
+
     #[inline(always)]
     pub fn get_transparent_color(&self) -> PpuPaletteColor {
-        self.colors[self.mapping[0]]
+        self.colors.color(self.mapping[0] as u16)
     }
 
+    // Color of a 9-bit output value
     #[inline(always)]
-    pub fn is_transparent_color(&self, color: &PpuPaletteColor) -> bool {
-        *color == self.colors[self.mapping[0]]
+    pub fn get_color(&self, value: u16) -> PpuPaletteColor {
+        self.colors.color(value)
     }
 }
 
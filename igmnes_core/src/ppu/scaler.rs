@@ -0,0 +1,732 @@
+// Software pixel art scalers, for machines without a GPU to run shaders on.
+//
+// - Scale2x/Scale3x (AdvanceMAME's) copy a neighbor's color into the corners of a pixel that
+//   sits on the inside of an edge, which keeps the picture sharp and its colors exact
+// - xBRZ weighs the color distances around every 2x2 block of pixels to find the dominant
+//   diagonals, then blends the corners, diagonals and shallow and steep lines along them
+//
+// A CRT effect can be put on top: darker gaps between scanlines and an aperture grille or
+// slot mask in front of the phosphors. It needs a few output pixels per input pixel, so
+// frames that aren't scaled otherwise are scaled 3x for it.
+use crate::ppu::palette::PpuPaletteColor;
+
+use std::str::FromStr;
+
+// xBRZ's default configuration
+const XBRZ_EQUAL_COLOR_TOLERANCE: f32 = 30.0;
+const XBRZ_DOMINANT_DIRECTION_THRESHOLD: f32 = 3.6;
+const XBRZ_STEEP_DIRECTION_THRESHOLD: f32 = 2.2;
+
+// The scaler's pixels are the output of CRT_MASK_WIDTH phosphor columns
+const CRT_MASK_WIDTH: usize = 3;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Scaler {
+    #[default]
+    None,
+    Scale2x,
+    Scale3x,
+    Xbrz2x,
+    Xbrz3x,
+    Xbrz4x,
+}
+
+impl FromStr for Scaler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Scaler::None),
+            "scale2x" => Ok(Scaler::Scale2x),
+            "scale3x" => Ok(Scaler::Scale3x),
+            "xbrz2x" => Ok(Scaler::Xbrz2x),
+            "xbrz3x" => Ok(Scaler::Xbrz3x),
+            "xbrz4x" => Ok(Scaler::Xbrz4x),
+            _ => Err(format!("Unknown scaler: {}", s)),
+        }
+    }
+}
+
+impl Scaler {
+    pub fn scale_factor(&self) -> usize {
+        match self {
+            Scaler::None => 1,
+            Scaler::Scale2x | Scaler::Xbrz2x => 2,
+            Scaler::Scale3x | Scaler::Xbrz3x => 3,
+            Scaler::Xbrz4x => 4,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum CrtMask {
+    #[default]
+    None,
+    // Vertical red, green and blue stripes, like a Trinitron
+    ApertureGrille,
+    // Red, green and blue slots, staggered from one column of them to the next
+    SlotMask,
+}
+
+impl FromStr for CrtMask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(CrtMask::None),
+            "aperture" | "aperture-grille" => Ok(CrtMask::ApertureGrille),
+            "slot" | "slot-mask" => Ok(CrtMask::SlotMask),
+            _ => Err(format!("Unknown CRT mask: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScalerSettings {
+    pub scaler: Scaler,
+    // 0-1, how much darker the gaps between scanlines are, 0 turns them off
+    pub scanlines: f32,
+    pub mask: CrtMask,
+    // 0-1, how much of the other two colors each phosphor filters out. The picture is
+    // brightened to make up for it
+    pub mask_strength: f32,
+}
+
+impl Default for ScalerSettings {
+    fn default() -> Self {
+        ScalerSettings {
+            scaler: Scaler::None,
+            scanlines: 0.0,
+            mask: CrtMask::None,
+            mask_strength: 0.3,
+        }
+    }
+}
+
+impl ScalerSettings {
+    pub fn has_crt_effect(&self) -> bool {
+        self.scanlines > 0.0 || self.mask != CrtMask::None
+    }
+
+    pub fn scale_factor(&self) -> usize {
+        match self.scaler.scale_factor() {
+            1 if self.has_crt_effect() => 3,
+            scale_factor => scale_factor,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FrameScaler {
+    settings: ScalerSettings,
+    // xBRZ's blending of each pixel's corners, see XbrzCorner
+    xbrz_blends: Vec<[XbrzBlend; 4]>,
+}
+
+impl FrameScaler {
+    pub fn new(settings: ScalerSettings) -> FrameScaler {
+        FrameScaler {
+            settings,
+            xbrz_blends: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> ScalerSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ScalerSettings) {
+        self.settings = settings;
+    }
+
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let scale_factor = self.settings.scale_factor();
+        (width * scale_factor, height * scale_factor)
+    }
+
+    // Scales a frame of width * height colors, resizing the output to output_size
+    pub fn apply(
+        &mut self,
+        frame: &[PpuPaletteColor],
+        width: usize,
+        height: usize,
+        output: &mut Vec<PpuPaletteColor>,
+    ) {
+        let frame = Frame {
+            pixels: frame,
+            width,
+            height,
+        };
+        let scale_factor = self.settings.scale_factor();
+        let (output_width, output_height) = self.output_size(width, height);
+        output.clear();
+        output.resize(output_width * output_height, PpuPaletteColor::default());
+
+        match self.settings.scaler {
+            Scaler::None => frame.scale(scale_factor, output, |x, y, block| {
+                block.fill(frame.at(x, y));
+            }),
+            Scaler::Scale2x => {
+                frame.scale(2, output, |x, y, block| scale2x(&frame.kernel(x, y), block))
+            }
+            Scaler::Scale3x => {
+                frame.scale(3, output, |x, y, block| scale3x(&frame.kernel(x, y), block))
+            }
+            Scaler::Xbrz2x | Scaler::Xbrz3x | Scaler::Xbrz4x => {
+                xbrz_detect_blends(&frame, &mut self.xbrz_blends);
+                let blends = &self.xbrz_blends;
+                frame.scale(scale_factor, output, |x, y, block| {
+                    xbrz(
+                        &frame.kernel(x, y),
+                        &blends[y * width + x],
+                        scale_factor,
+                        block,
+                    )
+                });
+            }
+        }
+
+        if self.settings.has_crt_effect() {
+            self.apply_crt_effect(output, output_width, scale_factor);
+        }
+    }
+
+    fn apply_crt_effect(&self, output: &mut [PpuPaletteColor], width: usize, scale_factor: usize) {
+        let ScalerSettings {
+            scanlines,
+            mask,
+            mask_strength,
+            ..
+        } = self.settings;
+        let mask_strength = mask_strength.clamp(0.0, 1.0);
+
+        // Each phosphor keeps its own color and filters out some of the other two, and the
+        // slot mask has a dark row below each slot as well
+        let mask_brightness = match mask {
+            CrtMask::None => 1.0,
+            CrtMask::ApertureGrille => 1.0 - mask_strength * 2.0 / 3.0,
+            CrtMask::SlotMask => (1.0 - mask_strength * 2.0 / 3.0) * (1.0 - mask_strength / 4.0),
+        };
+        let gain = 1.0 / mask_brightness;
+
+        for (y, row) in output.chunks_exact_mut(width).enumerate() {
+            let scanline_gain = if y % scale_factor == scale_factor - 1 {
+                1.0 - scanlines.clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+
+            for (x, color) in row.iter_mut().enumerate() {
+                let mut channel_gains = [gain * scanline_gain; 3];
+                if mask != CrtMask::None {
+                    for (channel, channel_gain) in channel_gains.iter_mut().enumerate() {
+                        if channel != x % CRT_MASK_WIDTH {
+                            *channel_gain *= 1.0 - mask_strength;
+                        }
+                    }
+                }
+                if mask == CrtMask::SlotMask {
+                    let slot_offset = (x / CRT_MASK_WIDTH) % 2 * 2;
+                    if (y + slot_offset) % 4 == 3 {
+                        for channel_gain in channel_gains.iter_mut() {
+                            *channel_gain *= 1.0 - mask_strength;
+                        }
+                    }
+                }
+
+                let channel = |value: u8, gain: f32| (value as f32 * gain).min(255.0) as u8;
+                *color = PpuPaletteColor {
+                    red: channel(color.red, channel_gains[0]),
+                    green: channel(color.green, channel_gains[1]),
+                    blue: channel(color.blue, channel_gains[2]),
+                };
+            }
+        }
+    }
+}
+
+struct Frame<'a> {
+    pixels: &'a [PpuPaletteColor],
+    width: usize,
+    height: usize,
+}
+
+impl Frame<'_> {
+    // Pixels past the edges repeat the ones on the edges
+    fn at(&self, x: usize, y: usize) -> PpuPaletteColor {
+        self.pixels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    fn at_offset(&self, x: usize, y: usize, dx: isize, dy: isize) -> PpuPaletteColor {
+        let offset = |value: usize, delta: isize| (value as isize + delta).max(0) as usize;
+        self.at(offset(x, dx), offset(y, dy))
+    }
+
+    // The 3x3 pixels around a pixel, row by row:
+    // A B C
+    // D E F
+    // G H I
+    fn kernel(&self, x: usize, y: usize) -> [PpuPaletteColor; 9] {
+        let mut kernel = [PpuPaletteColor::default(); 9];
+        for (index, pixel) in kernel.iter_mut().enumerate() {
+            let (dx, dy) = (index as isize % 3 - 1, index as isize / 3 - 1);
+            *pixel = self.at_offset(x, y, dx, dy);
+        }
+        kernel
+    }
+
+    // Scales every pixel into a block of scale_factor * scale_factor output pixels
+    fn scale<F>(&self, scale_factor: usize, output: &mut [PpuPaletteColor], mut scale_pixel: F)
+    where
+        F: FnMut(usize, usize, &mut [PpuPaletteColor]),
+    {
+        let output_width = self.width * scale_factor;
+        let mut block = vec![PpuPaletteColor::default(); scale_factor * scale_factor];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                scale_pixel(x, y, &mut block);
+                for (row, block_row) in block.chunks_exact(scale_factor).enumerate() {
+                    let start = (y * scale_factor + row) * output_width + x * scale_factor;
+                    output[start..start + scale_factor].copy_from_slice(block_row);
+                }
+            }
+        }
+    }
+}
+
+// Weighted average of colors
+fn mix(colors: &[(PpuPaletteColor, u32)]) -> PpuPaletteColor {
+    let total: u32 = colors.iter().map(|&(_, weight)| weight).sum();
+    let channel = |get: fn(&PpuPaletteColor) -> u8| {
+        let sum: u32 = colors
+            .iter()
+            .map(|(color, weight)| get(color) as u32 * weight)
+            .sum();
+        ((sum + total / 2) / total) as u8
+    };
+
+    PpuPaletteColor {
+        red: channel(|color| color.red),
+        green: channel(|color| color.green),
+        blue: channel(|color| color.blue),
+    }
+}
+
+fn scale2x(kernel: &[PpuPaletteColor; 9], block: &mut [PpuPaletteColor]) {
+    let [_, b, _, d, e, f, _, h, _] = *kernel;
+    block.fill(e);
+    if b != h && d != f {
+        block[0] = if d == b { d } else { e };
+        block[1] = if b == f { f } else { e };
+        block[2] = if d == h { d } else { e };
+        block[3] = if h == f { f } else { e };
+    }
+}
+
+fn scale3x(kernel: &[PpuPaletteColor; 9], block: &mut [PpuPaletteColor]) {
+    let [a, b, c, d, e, f, g, h, i] = *kernel;
+    block.fill(e);
+    if b != h && d != f {
+        block[0] = if d == b { d } else { e };
+        block[1] = if (d == b && e != c) || (b == f && e != a) {
+            b
+        } else {
+            e
+        };
+        block[2] = if b == f { f } else { e };
+        block[3] = if (d == b && e != g) || (d == h && e != a) {
+            d
+        } else {
+            e
+        };
+        block[5] = if (b == f && e != i) || (h == f && e != c) {
+            f
+        } else {
+            e
+        };
+        block[6] = if d == h { d } else { e };
+        block[7] = if (d == h && e != i) || (h == f && e != g) {
+            h
+        } else {
+            e
+        };
+        block[8] = if h == f { f } else { e };
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum XbrzBlend {
+    #[default]
+    None,
+    Normal,
+    // The diagonal is much more pronounced than the other one
+    Dominant,
+}
+
+// Corners of a pixel, clockwise so that rotating the kernel by 90 degrees moves to the next
+#[derive(Copy, Clone)]
+enum XbrzCorner {
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+}
+
+// Blending of the bottom right corner, along a line through it or just the corner itself
+#[derive(Copy, Clone)]
+enum XbrzLine {
+    Shallow,
+    Steep,
+    SteepAndShallow,
+    Diagonal,
+    Corner,
+}
+
+// Distance in YCbCr (BT.2020)
+fn xbrz_distance(color_1: PpuPaletteColor, color_2: PpuPaletteColor) -> f32 {
+    let r = color_1.red as f32 - color_2.red as f32;
+    let g = color_1.green as f32 - color_2.green as f32;
+    let b = color_1.blue as f32 - color_2.blue as f32;
+
+    let (k_b, k_r) = (0.0593, 0.2627);
+    let k_g = 1.0 - k_b - k_r;
+    let y = k_r * r + k_g * g + k_b * b;
+    let c_b = 0.5 / (1.0 - k_b) * (b - y);
+    let c_r = 0.5 / (1.0 - k_r) * (r - y);
+
+    (y * y + c_b * c_b + c_r * c_r).sqrt()
+}
+
+fn xbrz_equal(color_1: PpuPaletteColor, color_2: PpuPaletteColor) -> bool {
+    xbrz_distance(color_1, color_2) < XBRZ_EQUAL_COLOR_TOLERANCE
+}
+
+// Looks at the 4x4 pixels around every 2x2 block F G / J K to decide which of the block's
+// inner corners get blended:
+// A B C D
+// E F G H
+// I J K L
+// M N O P
+fn xbrz_detect_blends(frame: &Frame, blends: &mut Vec<[XbrzBlend; 4]>) {
+    blends.clear();
+    blends.resize(frame.width * frame.height, [XbrzBlend::None; 4]);
+
+    // Blocks start one pixel before the frame so that its edges get all of their corners
+    for y in -1..frame.height as isize {
+        for x in -1..frame.width as isize {
+            let at = |dx: isize, dy: isize| {
+                let (x, y) = ((x + dx).max(0) as usize, (y + dy).max(0) as usize);
+                frame.at(x, y)
+            };
+            let (b, c) = (at(0, -1), at(1, -1));
+            let (e, f, g, h) = (at(-1, 0), at(0, 0), at(1, 0), at(2, 0));
+            let (i, j, k, l) = (at(-1, 1), at(0, 1), at(1, 1), at(2, 1));
+            let (n, o) = (at(0, 2), at(1, 2));
+
+            if (f == g && j == k) || (f == j && g == k) {
+                continue;
+            }
+
+            let distance = xbrz_distance;
+            let jg = distance(i, f)
+                + distance(f, c)
+                + distance(n, k)
+                + distance(k, h)
+                + 4.0 * distance(j, g);
+            let fk = distance(e, j)
+                + distance(j, o)
+                + distance(b, g)
+                + distance(g, l)
+                + 4.0 * distance(f, k);
+
+            let blend = |is_dominant: bool| {
+                if is_dominant {
+                    XbrzBlend::Dominant
+                } else {
+                    XbrzBlend::Normal
+                }
+            };
+            let mut set_blend = |dx: isize, dy: isize, corner: XbrzCorner, value: XbrzBlend| {
+                let (x, y) = (x + dx, y + dy);
+                if x >= 0 && y >= 0 && (x as usize) < frame.width && (y as usize) < frame.height {
+                    blends[y as usize * frame.width + x as usize][corner as usize] = value;
+                }
+            };
+
+            if jg < fk {
+                let value = blend(XBRZ_DOMINANT_DIRECTION_THRESHOLD * jg < fk);
+                if f != g && f != j {
+                    set_blend(0, 0, XbrzCorner::BottomRight, value);
+                }
+                if k != j && k != g {
+                    set_blend(1, 1, XbrzCorner::TopLeft, value);
+                }
+            } else if fk < jg {
+                let value = blend(XBRZ_DOMINANT_DIRECTION_THRESHOLD * fk < jg);
+                if j != f && j != k {
+                    set_blend(0, 1, XbrzCorner::TopRight, value);
+                }
+                if g != f && g != k {
+                    set_blend(1, 0, XbrzCorner::BottomLeft, value);
+                }
+            }
+        }
+    }
+}
+
+// Output pixels (row, column) of the bottom right corner blended with a line's color, and
+// how much of it (numerator, denominator), for each scale factor. Steep lines are the same
+// as shallow ones with rows and columns swapped.
+fn xbrz_line_blends(line: XbrzLine, scale_factor: usize) -> &'static [(usize, usize, u32, u32)] {
+    match (scale_factor, line) {
+        (2, XbrzLine::Shallow) => &[(1, 0, 1, 4), (1, 1, 3, 4)],
+        (2, XbrzLine::SteepAndShallow) => &[(1, 0, 1, 4), (0, 1, 1, 4), (1, 1, 5, 6)],
+        (2, XbrzLine::Diagonal) => &[(1, 1, 1, 2)],
+        (2, _) => &[(1, 1, 21, 100)],
+        (3, XbrzLine::Shallow) => &[(2, 0, 1, 4), (1, 2, 1, 4), (2, 1, 3, 4), (2, 2, 1, 1)],
+        (3, XbrzLine::SteepAndShallow) => &[
+            (2, 0, 3, 4),
+            (0, 2, 3, 4),
+            (2, 1, 1, 2),
+            (1, 2, 1, 2),
+            (2, 2, 1, 1),
+        ],
+        (3, XbrzLine::Diagonal) => &[(1, 2, 1, 8), (2, 1, 1, 8), (2, 2, 7, 8)],
+        (3, _) => &[(2, 2, 45, 100)],
+        (_, XbrzLine::Shallow) => &[
+            (3, 0, 1, 4),
+            (2, 2, 1, 4),
+            (3, 1, 3, 4),
+            (2, 3, 3, 4),
+            (3, 2, 1, 1),
+            (3, 3, 1, 1),
+        ],
+        (_, XbrzLine::SteepAndShallow) => &[
+            (3, 1, 3, 4),
+            (1, 3, 3, 4),
+            (3, 0, 1, 4),
+            (0, 3, 1, 4),
+            (2, 2, 1, 3),
+            (3, 3, 1, 1),
+            (3, 2, 1, 1),
+            (2, 3, 1, 1),
+        ],
+        (_, XbrzLine::Diagonal) => &[(3, 2, 1, 2), (2, 3, 1, 2), (3, 3, 1, 1)],
+        (_, _) => &[(3, 3, 68, 100), (3, 2, 9, 100), (2, 3, 9, 100)],
+    }
+}
+
+fn xbrz(
+    kernel: &[PpuPaletteColor; 9],
+    blends: &[XbrzBlend; 4],
+    scale_factor: usize,
+    block: &mut [PpuPaletteColor],
+) {
+    let e = kernel[4];
+    block.fill(e);
+    if blends.iter().all(|&blend| blend == XbrzBlend::None) {
+        return;
+    }
+
+    // Each corner is blended as the bottom right one of the kernel rotated clockwise by
+    // 90 degrees that many times
+    for rotation in 0..4 {
+        let rotate = |(mut row, mut column): (usize, usize), size: usize| {
+            for _ in 0..rotation {
+                let rotated = (column, size - 1 - row);
+                row = rotated.0;
+                column = rotated.1;
+            }
+            (row, column)
+        };
+        let at = |row: usize, column: usize| {
+            let (row, column) = rotate((row, column), 3);
+            kernel[row * 3 + column]
+        };
+        let (b, c) = (at(0, 1), at(0, 2));
+        let (d, f) = (at(1, 0), at(1, 2));
+        let (g, h, i) = (at(2, 0), at(2, 1), at(2, 2));
+        let blend = |corner: XbrzCorner| blends[(corner as usize + rotation) % 4];
+
+        if blend(XbrzCorner::BottomRight) < XbrzBlend::Normal {
+            continue;
+        }
+
+        let is_line_blend = if blend(XbrzCorner::BottomRight) >= XbrzBlend::Dominant {
+            true
+        } else if blend(XbrzCorner::TopRight) != XbrzBlend::None && !xbrz_equal(e, g) {
+            // Another blend in a neighboring corner, like with single pixels
+            false
+        } else if blend(XbrzCorner::BottomLeft) != XbrzBlend::None && !xbrz_equal(e, c) {
+            false
+        } else {
+            // Only the corner of L shapes
+            !(!xbrz_equal(e, i)
+                && xbrz_equal(g, h)
+                && xbrz_equal(h, i)
+                && xbrz_equal(i, f)
+                && xbrz_equal(f, c))
+        };
+
+        let color = if xbrz_distance(e, f) <= xbrz_distance(e, h) {
+            f
+        } else {
+            h
+        };
+        let fg = xbrz_distance(f, g);
+        let hc = xbrz_distance(h, c);
+        let is_shallow = XBRZ_STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+        let is_steep = XBRZ_STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+
+        let line = match (is_line_blend, is_shallow, is_steep) {
+            (false, _, _) => XbrzLine::Corner,
+            (true, true, true) => XbrzLine::SteepAndShallow,
+            (true, true, false) => XbrzLine::Shallow,
+            (true, false, true) => XbrzLine::Steep,
+            (true, false, false) => XbrzLine::Diagonal,
+        };
+
+        let (line_blends, is_transposed) = match line {
+            XbrzLine::Steep => (xbrz_line_blends(XbrzLine::Shallow, scale_factor), true),
+            _ => (xbrz_line_blends(line, scale_factor), false),
+        };
+        for &(row, column, numerator, denominator) in line_blends {
+            let (row, column) = if is_transposed {
+                (column, row)
+            } else {
+                (row, column)
+            };
+            let (row, column) = rotate((row, column), scale_factor);
+            let pixel = &mut block[row * scale_factor + column];
+            *pixel = mix(&[(color, numerator), (*pixel, denominator - numerator)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames of gray levels, one row per string
+    fn gray_frame(rows: &[&str]) -> (Vec<PpuPaletteColor>, usize, usize) {
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.split_whitespace())
+            .map(|level| {
+                let level = u8::from_str_radix(level, 16).unwrap();
+                PpuPaletteColor {
+                    red: level,
+                    green: level,
+                    blue: level,
+                }
+            })
+            .collect();
+        let width = rows[0].split_whitespace().count();
+        (pixels, width, rows.len())
+    }
+
+    fn scale_gray_frame(scaler: Scaler, rows: &[&str]) -> Vec<String> {
+        let (frame, width, height) = gray_frame(rows);
+        let mut scaler = FrameScaler::new(ScalerSettings {
+            scaler,
+            ..ScalerSettings::default()
+        });
+        let mut output = Vec::new();
+        scaler.apply(&frame, width, height, &mut output);
+
+        let output_width = width * scaler.settings().scale_factor();
+        output
+            .chunks_exact(output_width)
+            .map(|row| {
+                row.iter()
+                    .map(|color| {
+                        assert!(color.red == color.green && color.green == color.blue);
+                        format!("{:02X}", color.red)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    const STAIRCASE: [&str; 4] = ["FF FF FF FF", "FF FF FF 00", "FF FF 00 00", "FF 00 00 00"];
+
+    #[test]
+    fn scale2x_fills_the_inside_corners_of_edges() {
+        #[rustfmt::skip]
+        let expected = [
+            "FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF 00",
+            "FF FF FF FF FF 00 00 00",
+            "FF FF FF FF FF 00 00 00",
+            "FF FF FF 00 00 00 00 00",
+            "FF FF FF 00 00 00 00 00",
+            "FF FF 00 00 00 00 00 00",
+        ];
+        assert_eq!(scale_gray_frame(Scaler::Scale2x, &STAIRCASE), expected);
+    }
+
+    #[test]
+    fn scale3x_fills_the_inside_corners_and_sides_of_edges() {
+        #[rustfmt::skip]
+        let expected = [
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF FF 00",
+            "FF FF FF FF FF FF FF FF FF 00 00 00",
+            "FF FF FF FF FF FF FF FF 00 00 00 00",
+            "FF FF FF FF FF FF FF 00 00 00 00 00",
+            "FF FF FF FF FF FF 00 00 00 00 00 00",
+            "FF FF FF FF FF 00 00 00 00 00 00 00",
+            "FF FF FF FF 00 00 00 00 00 00 00 00",
+            "FF FF FF FF 00 00 00 00 00 00 00 00",
+            "FF FF FF 00 00 00 00 00 00 00 00 00",
+        ];
+        assert_eq!(scale_gray_frame(Scaler::Scale3x, &STAIRCASE), expected);
+    }
+
+    #[test]
+    fn xbrz_blends_along_diagonals() {
+        #[rustfmt::skip]
+        let expected = [
+            "FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF BF 40",
+            "FF FF FF FF FF 80 00 00",
+            "FF FF FF FF 80 00 00 00",
+            "FF FF FF 80 00 00 00 00",
+            "FF FF BF 00 00 00 00 00",
+            "FF FF 40 00 00 00 00 00",
+        ];
+        assert_eq!(scale_gray_frame(Scaler::Xbrz2x, &STAIRCASE), expected);
+
+        #[rustfmt::skip]
+        let expected = [
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF FF FF",
+            "FF FF FF FF FF FF FF FF FF FF BF 40",
+            "FF FF FF FF FF FF FF FF DF 40 00 00",
+            "FF FF FF FF FF FF FF DF 20 00 00 00",
+            "FF FF FF FF FF FF DF 20 00 00 00 00",
+            "FF FF FF FF FF DF 20 00 00 00 00 00",
+            "FF FF FF FF DF 20 00 00 00 00 00 00",
+            "FF FF FF FF 40 00 00 00 00 00 00 00",
+            "FF FF FF BF 00 00 00 00 00 00 00 00",
+            "FF FF FF 40 00 00 00 00 00 00 00 00",
+        ];
+        assert_eq!(scale_gray_frame(Scaler::Xbrz3x, &STAIRCASE), expected);
+    }
+
+    #[test]
+    fn xbrz_keeps_straight_edges_sharp() {
+        let output = scale_gray_frame(Scaler::Xbrz4x, &["FF FF 00 00"; 3]);
+        assert_eq!(output.len(), 12);
+        for row in output {
+            assert_eq!(row, "FF FF FF FF FF FF FF FF 00 00 00 00 00 00 00 00");
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// PPU viewers, rendering the PPU's memory for debugging graphics.
+//
+// They only peek at memory: pattern data is read with read_chr, which doesn't clock mapper
+// IRQ counters (MMC3 watches A12) or CHR latches (MMC2/MMC4), and nametables are read with
+// is_mutating_read off. Colors are the plain palette entries, without greyscale or emphasis.
+use crate::mappers::PpuMapper;
+use crate::memory::MemMapped;
+use crate::ppu::palette::PpuPaletteColor;
+use crate::ppu::{OamAttributePriority, Ppu, PpuCtrlReg};
+
+// The four nametables as laid out in PPU memory, 2x2
+pub const NAMETABLES_VIEW_WIDTH: usize = 512;
+pub const NAMETABLES_VIEW_HEIGHT: usize = 480;
+// Both pattern tables side by side, 16x16 tiles each
+pub const PATTERN_TABLES_VIEW_WIDTH: usize = 256;
+pub const PATTERN_TABLES_VIEW_HEIGHT: usize = 128;
+// The 64 sprites in 8 rows of 8, with room for 8x16 sprites
+pub const OAM_VIEW_WIDTH: usize = 64;
+pub const OAM_VIEW_HEIGHT: usize = 128;
+
+// A sprite's OAM entry, decoded
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct OamSprite {
+    pub index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub tile_index: u8,
+    // 4 to 7
+    pub palette_index: u8,
+    pub is_behind_background: bool,
+    pub is_flipped_horizontally: bool,
+    pub is_flipped_vertically: bool,
+}
+
+impl Ppu {
+    #[inline(always)]
+    fn peek_pattern_row(&self, pattern_table_index: u8, tile_index: u8, row: u16) -> [u8; 2] {
+        let addr = (pattern_table_index as u16) << 12 | (tile_index as u16) << 4 | row;
+        let mapper = &self.ppu_mem_map.mapper;
+        [mapper.read_chr(addr), mapper.read_chr(addr | 1 << 3)]
+    }
+
+    #[inline(always)]
+    fn pattern_color_index(pattern_row: [u8; 2], column: u16) -> u8 {
+        let shift = 7 - column;
+        (pattern_row[0] >> shift) & 1 | ((pattern_row[1] >> shift) & 1) << 1
+    }
+
+    // Palettes 0-3 are the background palettes, 4-7 the sprite palettes
+    fn palette_color(&self, palette_index: u8, color_index: u8) -> PpuPaletteColor {
+        let palette = &self.ppu_mem_map.palette;
+        let entry = if palette_index < 4 {
+            palette.get_background_entry(palette_index, color_index)
+        } else {
+            palette.get_sprite_entry(palette_index - 4, color_index)
+        };
+        palette.get_color(entry as u16)
+    }
+
+    // Renders the four nametables, with the current mirroring, and outlines the screen at
+    // the scroll position in t. Games that change the scroll mid-frame will show the last one.
+    pub fn render_nametables_view(&mut self, output: &mut [PpuPaletteColor]) {
+        let pattern_table_index =
+            self.reg_ctrl
+                .contains(PpuCtrlReg::BACKGROUND_PATTERN_TABLE_INDEX) as u8;
+
+        for nametable_index in 0..4_u16 {
+            let nametable_addr = 0x2000 | nametable_index << 10;
+            let view_x = (nametable_index as usize & 1) * 256;
+            let view_y = (nametable_index as usize >> 1) * 240;
+
+            for tile_y in 0..30_u16 {
+                for tile_x in 0..32_u16 {
+                    let tile_index = self.ppu_mem_map.read(nametable_addr | tile_y << 5 | tile_x);
+                    let attribute = self
+                        .ppu_mem_map
+                        .read(nametable_addr | 0x3C0 | (tile_y >> 2) << 3 | tile_x >> 2);
+                    let attribute_shift = ((tile_y & 0b10) << 1) | (tile_x & 0b10);
+                    let palette_index = (attribute >> attribute_shift) & 0b11;
+
+                    for row in 0..8_u16 {
+                        let pattern_row =
+                            self.peek_pattern_row(pattern_table_index, tile_index, row);
+                        let y = view_y + (tile_y * 8 + row) as usize;
+                        for column in 0..8_u16 {
+                            let color_index = Ppu::pattern_color_index(pattern_row, column);
+                            let x = view_x + (tile_x * 8 + column) as usize;
+                            output[y * NAMETABLES_VIEW_WIDTH + x] =
+                                self.palette_color(palette_index, color_index);
+                        }
+                    }
+                }
+            }
+        }
+
+        // t: yyy NN YYYYY XXXXX, see reg_v
+        let reg_t = self.reg_t as usize;
+        let scroll_x = ((reg_t >> 10) & 1) * 256 + (reg_t & 0x1F) * 8 + self.reg_x as usize;
+        let scroll_y = ((reg_t >> 11) & 1) * 240 + ((reg_t >> 5) & 0x1F) * 8 + (reg_t >> 12);
+
+        let mut invert = |x: usize, y: usize| {
+            let x = (scroll_x + x) % NAMETABLES_VIEW_WIDTH;
+            let y = (scroll_y + y) % NAMETABLES_VIEW_HEIGHT;
+            let color = &mut output[y * NAMETABLES_VIEW_WIDTH + x];
+            *color = PpuPaletteColor {
+                red: !color.red,
+                green: !color.green,
+                blue: !color.blue,
+            };
+        };
+        for x in 0..256 {
+            invert(x, 0);
+            invert(x, 239);
+        }
+        for y in 1..239 {
+            invert(0, y);
+            invert(255, y);
+        }
+    }
+
+    // Renders both pattern tables with one of the 8 palettes, see palette_color
+    pub fn render_pattern_tables_view(
+        &mut self,
+        palette_index: u8,
+        output: &mut [PpuPaletteColor],
+    ) {
+        let palette_index = palette_index & 0b111;
+        for pattern_table_index in 0..2_u8 {
+            for tile_index in 0..=255_u8 {
+                let view_x = pattern_table_index as usize * 128 + (tile_index as usize % 16) * 8;
+                let view_y = (tile_index as usize / 16) * 8;
+
+                for row in 0..8_u16 {
+                    let pattern_row = self.peek_pattern_row(pattern_table_index, tile_index, row);
+                    for column in 0..8_u16 {
+                        let color_index = Ppu::pattern_color_index(pattern_row, column);
+                        let index = (view_y + row as usize) * PATTERN_TABLES_VIEW_WIDTH
+                            + view_x
+                            + column as usize;
+                        output[index] = self.palette_color(palette_index, color_index);
+                    }
+                }
+            }
+        }
+    }
+
+    // Renders the 64 sprites as they'd be drawn, flipped and with their palettes,
+    // on the backdrop color
+    pub fn render_oam_view(&mut self, output: &mut [PpuPaletteColor]) {
+        let backdrop_color = self.ppu_mem_map.palette.get_transparent_color();
+        output[..OAM_VIEW_WIDTH * OAM_VIEW_HEIGHT].fill(backdrop_color);
+
+        let is_sprite_height_16 = self.reg_ctrl.contains(PpuCtrlReg::IS_SPRITE_HEIGHT_16);
+        let sprite_height = if is_sprite_height_16 { 16 } else { 8 };
+
+        for sprite in self.oam_sprites() {
+            let view_x = (sprite.index as usize % 8) * 8;
+            let view_y = (sprite.index as usize / 8) * 16;
+
+            for sprite_row in 0..sprite_height {
+                let row = if sprite.is_flipped_vertically {
+                    sprite_height - 1 - sprite_row
+                } else {
+                    sprite_row
+                };
+                // 8x16 sprites take their pattern table from bit 0 of the tile index,
+                // with the bottom half in the next tile
+                let (pattern_table_index, tile_index) = if is_sprite_height_16 {
+                    (
+                        sprite.tile_index & 1,
+                        (sprite.tile_index & 0xFE) + (row / 8) as u8,
+                    )
+                } else {
+                    (
+                        self.reg_ctrl
+                            .contains(PpuCtrlReg::SPRITE_PATTERN_TABLE_INDEX)
+                            as u8,
+                        sprite.tile_index,
+                    )
+                };
+                let pattern_row = self.peek_pattern_row(pattern_table_index, tile_index, row % 8);
+
+                for sprite_column in 0..8_u16 {
+                    let column = if sprite.is_flipped_horizontally {
+                        7 - sprite_column
+                    } else {
+                        sprite_column
+                    };
+                    let color_index = Ppu::pattern_color_index(pattern_row, column);
+                    if color_index != 0 {
+                        let index = (view_y + sprite_row as usize) * OAM_VIEW_WIDTH
+                            + view_x
+                            + sprite_column as usize;
+                        output[index] = self.palette_color(sprite.palette_index, color_index);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn oam_sprites(&self) -> [OamSprite; 64] {
+        let oam_entries = &self.ppu_mem_map.oam_table.oam_entries;
+        std::array::from_fn(|index| {
+            let oam_entry = &oam_entries[index];
+            let attributes = &oam_entry.attributes;
+            OamSprite {
+                index: index as u8,
+                x: oam_entry.sprite_x,
+                y: oam_entry.sprite_y,
+                tile_index: oam_entry.tile_bank_index,
+                palette_index: (attributes.palette_index & 0b11) + 4,
+                is_behind_background: matches!(attributes.priority, OamAttributePriority::BACK),
+                is_flipped_horizontally: attributes.is_flipped_horizontally,
+                is_flipped_vertically: attributes.is_flipped_vertically,
+            }
+        })
+    }
+
+    // The colors of the 32 palette RAM entries, background palettes first
+    pub fn palette_view(&self) -> [PpuPaletteColor; 32] {
+        let palette = &self.ppu_mem_map.palette;
+        std::array::from_fn(|index| palette.get_color(palette.get_entry(index as u8) as u16))
+    }
+}
@@ -1,12 +1,77 @@
+use crate::archive;
+use crate::fds_disk::{self, FDS_SIDE_SIZE, QD_SIDE_SIZE};
+use crate::nsf::{self, Nsf};
+use crate::patch::{self, PatchError};
+use crate::rom_db::{self, RomDbEntry};
+use crate::vs_system::{self, VsGame, VsHardwareType, VsPpuType};
 use nom::*;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 const PRG_ROM_BYTES_PER_CHUNK: usize = 16384;
 const CHR_ROM_BYTES_PER_CHUNK: usize = 8192;
 const PRG_RAM_BYTES_PER_CHUNK: usize = 8192;
+const TRAINER_SIZE: usize = 512;
+
+// Famicom Disk System images, with or without the fwNES header
+const FDS_HEADER_TAG: &[u8] = b"FDS\x1A";
+const FDS_HEADER_SIZE: usize = 16;
+const FDS_DISK_TAG: &[u8] = b"\x01*NINTENDO-HVC*";
+const FDS_MAPPER_NUMBER: u16 = 20;
+const FDS_BIOS_SIZE: usize = 8192;
+const FDS_BIOS_FILE_NAME: &str = "disksys.rom";
+const FDS_PRG_RAM_SIZE: usize = 32768;
+
+// UNIF images, with board names instead of mapper numbers
+const UNIF_HEADER_TAG: &[u8] = b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+const UNIF_CHUNK_HEADER_SIZE: usize = 8;
+const UNIF_BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "AVE-"];
+
+// UNIF board names, without their prefix, of the boards that have a mapper implementation
+const UNIF_BOARDS: &[(&str, u16)] = &[
+    ("NROM", 0),
+    ("NROM-128", 0),
+    ("NROM-256", 0),
+    ("HROM", 0),
+    ("RROM", 0),
+    ("RROM-128", 0),
+    ("UNROM", 2),
+    ("UOROM", 2),
+    ("CNROM", 3),
+    ("B4", 4),
+    ("TBROM", 4),
+    ("TEROM", 4),
+    ("TFROM", 4),
+    ("TGROM", 4),
+    ("TKROM", 4),
+    ("TLROM", 4),
+    ("TL1ROM", 4),
+    ("TR1ROM", 4),
+    ("TSROM", 4),
+    ("TVROM", 4),
+    ("AMROM", 7),
+    ("ANROM", 7),
+    ("AN1ROM", 7),
+    ("AOROM", 7),
+    ("PNROM", 9),
+    ("PEEOROM", 9),
+    ("FJROM", 10),
+    ("FKROM", 10),
+    ("CPROM", 13),
+    ("UNROM-512-8", 30),
+    ("UNROM-512-16", 30),
+    ("UNROM-512-32", 30),
+    ("BNROM", 34),
+    ("NINA-01", 34),
+    ("GNROM", 66),
+    ("MHROM", 66),
+    ("NINA-03", 79),
+    ("NINA-06", 79),
+    ("TQROM", 119),
+];
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TVSystem {
@@ -37,6 +102,11 @@ impl Default for HeaderType {
 pub enum MirroringMode {
     Horizontal,
     Vertical,
+    // Every nametable maps to the first (A) or second (B) page of CIRAM
+    SingleScreenA,
+    SingleScreenB,
+    // Every nametable has its own page of VRAM on the cart
+    FourScreen,
 }
 
 impl Default for MirroringMode {
@@ -57,6 +127,8 @@ pub struct Header {
     pub prg_rom_size: usize,
     pub chr_rom_size: usize,
     pub prg_ram_size: usize,
+    // CHR RAM size specified by a NES 2.0 header, 0 if unspecified
+    pub chr_ram_size: usize,
     pub mapper_number: u16,
     pub four_screen_mode: bool,
     pub trainer_present: bool,
@@ -64,16 +136,52 @@ pub struct Header {
     pub mirroring_mode: MirroringMode,
     pub is_playchoice_10: bool,
     pub is_vs_unisystem: bool,
+    // PPU and protection hardware of Vs. UniSystem games, from NES 2.0 byte 13
+    pub vs_ppu_type: VsPpuType,
+    pub vs_hardware_type: VsHardwareType,
     pub tv_system: TVSystem,
     pub extension: Option<HeaderExtension>,
 }
 
+impl Header {
+    // NES 2.0 submapper number, 0 (default behaviour) for iNES headers
+    pub fn submapper_number(&self) -> u8 {
+        self.extension
+            .as_ref()
+            .map_or(0, |extension| extension.submapper_number)
+    }
+
+    // Arcade boards use an RGB PPU instead of the composite one, the PlayChoice-10 a 2C03
+    pub fn rgb_ppu_type(&self) -> Option<VsPpuType> {
+        if self.is_vs_unisystem {
+            Some(self.vs_ppu_type)
+        } else if self.is_playchoice_10 {
+            Some(VsPpuType::Rp2c03)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Rom {
     pub header: Header,
     pub trainer_bytes: Option<Vec<u8>>,
     pub prg_rom_bytes: Vec<u8>,
     pub chr_rom_bytes: Vec<u8>,
+    // Famicom Disk System disk sides, in .fds format. Empty for cartridges
+    pub disk_sides: Vec<Vec<u8>>,
+    // NSF/NSFe music rip, whose code and data are kept in prg_rom_bytes. None for cartridges
+    pub nsf: Option<Nsf>,
+    // Game title, for formats that store one
+    pub title: Option<String>,
+    // Patch that was applied to the file at load time
+    pub patch_path: Option<PathBuf>,
+    // Game database entry that the header was corrected with
+    pub db_entry: Option<&'static RomDbEntry>,
+    // Vs. UniSystem game that the PPU and protection hardware were looked up for, when the
+    // header and the game database didn't have them
+    pub vs_game: Option<&'static VsGame>,
 }
 
 #[derive(Error, Debug)]
@@ -83,16 +191,141 @@ pub enum RomError {
 
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
+
+    #[error("FDS BIOS not found: {0}")]
+    FdsBiosNotFound(String),
+
+    #[error("Error extracting ROM: {0}")]
+    ArchiveError(String),
+
+    #[error("Error applying patch {0}: {1}")]
+    PatchError(String, PatchError),
+}
+
+// Optional inputs for loading a ROM
+#[derive(Debug, Default, Clone)]
+pub struct RomLoadOptions {
+    // FDS BIOS, instead of disksys.rom next to the image or in the working directory
+    pub fds_bios_path: Option<PathBuf>,
+    // Patch to apply, instead of a .ips/.bps/.ups patch with the same name next to the ROM
+    pub patch_path: Option<PathBuf>,
+    // Zip archive entry to load, instead of the first one with a ROM extension
+    pub archive_entry: Option<String>,
+    // Trust the header even if the game is in the game database
+    pub ignore_rom_db: bool,
+}
+
+// Checks that a ROM failed to load with the expected error
+#[cfg(test)]
+pub fn assert_rom_error<T>(result: Result<T, RomError>, expected: RomError) {
+    match result {
+        Ok(_) => panic!("Expected {:?}, the ROM was loaded", expected),
+        Err(error) => assert_eq!(format!("{:?}", error), format!("{:?}", expected)),
+    }
 }
 
 impl Rom {
     pub fn load_rom(file_path: &Path) -> Result<Rom, RomError> {
+        Rom::load_rom_with_options(file_path, &RomLoadOptions::default())
+    }
+
+    // Archives are extracted and patches are applied in memory, the file itself is left untouched
+    pub fn load_rom_with_options(
+        file_path: &Path,
+        options: &RomLoadOptions,
+    ) -> Result<Rom, RomError> {
         let mut file = File::open(file_path)?;
         let mut bytes = Vec::new();
 
         file.read_to_end(&mut bytes)?;
 
-        let rom = parse_rom(&bytes).unwrap().1;
+        if archive::is_gzip(&bytes) {
+            bytes = archive::extract_gzip(&bytes)?;
+        } else if archive::is_zip(&bytes) {
+            bytes = archive::extract_zip(&bytes, options.archive_entry.as_deref())?.1;
+        }
+
+        let patch_path = options
+            .patch_path
+            .clone()
+            .or_else(|| patch::find_patch_for_rom(file_path));
+        if let Some(patch_path) = &patch_path {
+            let patch_bytes = std::fs::read(patch_path)?;
+            bytes = patch::apply_patch(&bytes, &patch_bytes)
+                .map_err(|e| RomError::PatchError(patch_path.display().to_string(), e))?;
+        }
+
+        let mut rom = Rom::parse(&bytes, file_path, options)?;
+        rom.patch_path = patch_path;
+        Ok(rom)
+    }
+
+    // Names of the ROMs in a zip archive, to pick one of them with RomLoadOptions::archive_entry
+    pub fn list_archive_entries(file_path: &Path) -> Result<Vec<String>, RomError> {
+        let bytes = std::fs::read(file_path)?;
+        if archive::is_zip(&bytes) {
+            archive::zip_rom_entries(&bytes)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn is_fds(&self) -> bool {
+        !self.disk_sides.is_empty()
+    }
+
+    fn parse(bytes: &[u8], file_path: &Path, options: &RomLoadOptions) -> Result<Rom, RomError> {
+        if is_fds_image(bytes) {
+            let bios_bytes = match &options.fds_bios_path {
+                Some(fds_bios_path) => std::fs::read(fds_bios_path)
+                    .map_err(|_| RomError::FdsBiosNotFound(fds_bios_path.display().to_string()))?,
+                None => load_default_fds_bios(file_path)?,
+            };
+            return parse_fds(bytes, bios_bytes);
+        }
+
+        if nsf::is_nsf_file(bytes) {
+            let (nsf, data) = nsf::parse_nsf_file(bytes)?;
+            return Ok(Rom {
+                prg_rom_bytes: data,
+                title: Some(nsf.title.clone()).filter(|title| !title.is_empty()),
+                nsf: Some(nsf),
+                ..Rom::default()
+            });
+        }
+
+        let mut rom = if is_unif_image(bytes) {
+            parse_unif(bytes)?
+        } else {
+            parse_ines(bytes)?
+        };
+
+        if !options.ignore_rom_db {
+            rom.db_entry = rom_db::find_entry(&rom);
+            if let Some(db_entry) = rom.db_entry {
+                db_entry.apply(&mut rom.header);
+                rom.title = Some(db_entry.title.to_string());
+            } else if rom.header.is_vs_unisystem
+                && matches!(rom.header.header_type, HeaderType::Standard)
+            {
+                let name = rom.title.clone().or_else(|| {
+                    file_path
+                        .file_stem()
+                        .map(|file_stem| file_stem.to_string_lossy().into_owned())
+                });
+                rom.vs_game = name.and_then(|name| vs_system::find_vs_game(&name));
+                if let Some(vs_game) = rom.vs_game {
+                    rom.header.vs_ppu_type = vs_game.ppu_type;
+                    rom.header.vs_hardware_type = vs_game.hardware_type;
+                }
+            }
+        }
+
+        // The trainer gets loaded at $7000-$71FF, so the cart needs PRG RAM even if the header
+        // doesn't ask for any
+        if rom.trainer_bytes.is_some() {
+            rom.header.prg_ram_size = rom.header.prg_ram_size.max(PRG_RAM_BYTES_PER_CHUNK);
+        }
 
         if rom.header.tv_system != TVSystem::PAL {
             Ok(rom)
@@ -115,9 +348,9 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
         byte_8: le_u8                   >>
         flags_9: le_u8                  >>
         _flags_10: le_u8                 >>
-        _flags_11: le_u8                 >>
+        flags_11: le_u8                 >>
         flags_12: le_u8                 >>
-        _flags_13: le_u8                 >>
+        flags_13: le_u8                 >>
         _rest: take!(2)                  >>
         (
             {
@@ -134,7 +367,7 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
                     true => MirroringMode::Vertical,
                 };
 
-                let (mut prg_ram_chunk_count, mapper_number, _submapper_number) = match header_type {
+                let (mut prg_ram_chunk_count, mapper_number, submapper_number) = match header_type {
                     HeaderType::Standard => {
                         (
                             byte_8,
@@ -157,9 +390,37 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
                 }
                 let prg_ram_size = prg_ram_chunk_count as usize * PRG_RAM_BYTES_PER_CHUNK;
 
+                // NES 2.0 byte 11: CHR NVRAM (high nibble) and CHR RAM (low nibble) sizes,
+                // as shift counts of 64 bytes
+                let chr_ram_size = match header_type {
+                    HeaderType::Standard => 0,
+                    HeaderType::Extended => [flags_11 & 0x0F, flags_11 >> 4]
+                        .iter()
+                        .filter(|&&shift| shift != 0)
+                        .map(|&shift| 64usize << shift)
+                        .sum(),
+                };
+
                 let is_playchoice_10 = (flags_7 >> 1) & 0b1 == 0b1;
                 let is_vs_unisystem = flags_7 & 0b1 == 0b1;
 
+                // NES 2.0 byte 13: Vs. hardware type (high nibble) and PPU type (low nibble)
+                let (vs_ppu_type, vs_hardware_type) = match header_type {
+                    HeaderType::Extended if is_vs_unisystem => (
+                        VsPpuType::from_nes2(flags_13 & 0x0F),
+                        VsHardwareType::from_nes2(flags_13 >> 4),
+                    ),
+                    _ => Default::default(),
+                };
+
+                let extension = match header_type {
+                    HeaderType::Standard => None,
+                    HeaderType::Extended => Some(HeaderExtension {
+                        mapper_number,
+                        submapper_number,
+                    }),
+                };
+
                 let tv_system = {
                     let byte_to_check = match header_type {
                         HeaderType::Standard => flags_9,
@@ -178,6 +439,7 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
                     prg_rom_size,
                     chr_rom_size,
                     prg_ram_size,
+                    chr_ram_size,
                     mapper_number,
                     four_screen_mode,
                     trainer_present,
@@ -185,8 +447,10 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
                     mirroring_mode,
                     is_playchoice_10,
                     is_vs_unisystem,
+                    vs_ppu_type,
+                    vs_hardware_type,
                     tv_system,
-                    extension: None,
+                    extension,
                 }
                 // TODO support NES 2.0 file format (Extended)
             }
@@ -197,7 +461,7 @@ fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
 fn parse_trainer(input: &[u8], trainer_present: bool) -> IResult<&[u8], Option<Vec<u8>>> {
     if trainer_present {
         do_parse!(input,
-            bytes: take!(512) >>
+            bytes: take!(TRAINER_SIZE) >>
             ( Some(bytes.to_vec()) )
         )
     } else {
@@ -217,11 +481,26 @@ fn parse_rom(input: &[u8]) -> IResult<&[u8], Rom> {
                 trainer_bytes,
                 prg_rom_bytes: prg_rom_bytes.to_vec(),
                 chr_rom_bytes: chr_rom_bytes.to_vec(),
+                disk_sides: Vec::new(),
+                nsf: None,
+                title: None,
+                patch_path: None,
+                db_entry: None,
+                vs_game: None,
             }
         )
     )
 }
 
+// Files that are cut short or aren't ROMs at all can come out of a bad patch or archive
+fn parse_ines(bytes: &[u8]) -> Result<Rom, RomError> {
+    match parse_rom(bytes) {
+        IResult::Done(_, rom) => Ok(rom),
+        IResult::Incomplete(_) => Err(RomError::ParseError("iNES image is truncated".to_string())),
+        IResult::Error(_) => Err(RomError::ParseError("Unsupported ROM format".to_string())),
+    }
+}
+
 fn detect_header_type(flags_7: u8) -> HeaderType {
     if flags_7 & 0b00001100 == 0b00001000 {
         HeaderType::Extended
@@ -229,3 +508,469 @@ fn detect_header_type(flags_7: u8) -> HeaderType {
         HeaderType::Standard
     }
 }
+
+fn is_fds_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(FDS_HEADER_TAG) || bytes.starts_with(FDS_DISK_TAG)
+}
+
+// QD images keep the CRC after each block, so the 56 byte disk info block is followed by 2
+// bytes of CRC rather than by the file amount block (type 2)
+fn is_qd_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(FDS_DISK_TAG) && bytes.get(56) != Some(&2) && bytes.get(58) == Some(&2)
+}
+
+fn load_default_fds_bios(file_path: &Path) -> Result<Vec<u8>, RomError> {
+    let image_dir_path = file_path.with_file_name(FDS_BIOS_FILE_NAME);
+    std::fs::read(&image_dir_path)
+        .or_else(|_| std::fs::read(FDS_BIOS_FILE_NAME))
+        .map_err(|_| RomError::FdsBiosNotFound(image_dir_path.display().to_string()))
+}
+
+// The fwNES header only holds the side count, which can be derived from the image size.
+// QD images are converted to .fds sides, after checking their CRCs.
+fn parse_fds(bytes: &[u8], bios_bytes: Vec<u8>) -> Result<Rom, RomError> {
+    if bios_bytes.len() != FDS_BIOS_SIZE {
+        return Err(RomError::ParseError(format!(
+            "Invalid FDS BIOS size: {} bytes",
+            bios_bytes.len()
+        )));
+    }
+
+    let disk_bytes = if bytes.starts_with(FDS_HEADER_TAG) {
+        &bytes[FDS_HEADER_SIZE.min(bytes.len())..]
+    } else {
+        bytes
+    };
+
+    let disk_sides: Vec<Vec<u8>> = if is_qd_image(disk_bytes) {
+        disk_bytes
+            .chunks(QD_SIDE_SIZE)
+            .enumerate()
+            .filter(|(_, side)| side.starts_with(FDS_DISK_TAG))
+            .map(|(side_index, side)| {
+                fds_disk::qd_to_fds_side(side).map_err(|block_index| {
+                    RomError::ParseError(format!(
+                        "QD image has a bad CRC in block {} of side {}",
+                        block_index, side_index
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        disk_bytes
+            .chunks(FDS_SIDE_SIZE)
+            .filter(|side| side.starts_with(FDS_DISK_TAG))
+            .map(|side| {
+                let mut side = side.to_vec();
+                side.resize(FDS_SIDE_SIZE, 0);
+                side
+            })
+            .collect()
+    };
+
+    if disk_sides.is_empty() {
+        return Err(RomError::ParseError(
+            "FDS image contains no disk sides".to_string(),
+        ));
+    }
+
+    let header = Header {
+        prg_rom_size: FDS_BIOS_SIZE,
+        prg_ram_size: FDS_PRG_RAM_SIZE,
+        mapper_number: FDS_MAPPER_NUMBER,
+        ..Header::default()
+    };
+
+    Ok(Rom {
+        header,
+        trainer_bytes: None,
+        prg_rom_bytes: bios_bytes,
+        chr_rom_bytes: Vec::new(),
+        disk_sides,
+        nsf: None,
+        title: None,
+        patch_path: None,
+        db_entry: None,
+        vs_game: None,
+    })
+}
+
+fn is_unif_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(UNIF_HEADER_TAG)
+}
+
+// The 32 byte header (tag, revision and padding) is followed by chunks of a 4 byte ID,
+// a 4 byte little endian length and the chunk data. PRG0-PRGF and CHR0-CHRF hold the PRG ROM
+// and CHR ROM, concatenated in order of their number.
+fn parse_unif(bytes: &[u8]) -> Result<Rom, RomError> {
+    let mut board_name: Option<String> = None;
+    let mut title: Option<String> = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring: Option<u8> = None;
+    let mut has_battery = false;
+    let mut tv_system = TVSystem::NTSC;
+
+    let mut position = UNIF_HEADER_SIZE;
+    while position + UNIF_CHUNK_HEADER_SIZE <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let length = u32::from_le_bytes([
+            bytes[position + 4],
+            bytes[position + 5],
+            bytes[position + 6],
+            bytes[position + 7],
+        ]) as usize;
+        let chunk_start = position + UNIF_CHUNK_HEADER_SIZE;
+        let chunk_end = chunk_start
+            .checked_add(length)
+            .filter(|&chunk_end| chunk_end <= bytes.len())
+            .ok_or_else(|| {
+                RomError::ParseError(format!(
+                    "UNIF chunk {} is truncated",
+                    String::from_utf8_lossy(id)
+                ))
+            })?;
+        let chunk = &bytes[chunk_start..chunk_end];
+
+        match id {
+            b"MAPR" => board_name = Some(read_unif_string(chunk)),
+            b"NAME" => title = Some(read_unif_string(chunk)).filter(|title| !title.is_empty()),
+            [b'P', b'R', b'G', number] | [b'C', b'H', b'R', number] => {
+                let number = (*number as char).to_digit(16).ok_or_else(|| {
+                    RomError::ParseError(format!(
+                        "Invalid UNIF chunk: {}",
+                        String::from_utf8_lossy(id)
+                    ))
+                })? as usize;
+                if id[0] == b'P' {
+                    prg_chunks[number] = Some(chunk);
+                } else {
+                    chr_chunks[number] = Some(chunk);
+                }
+            }
+            b"MIRR" => mirroring = chunk.first().copied(),
+            b"BATR" => has_battery = chunk.first() != Some(&0),
+            b"TVCI" => {
+                tv_system = match chunk.first() {
+                    Some(1) => TVSystem::PAL,
+                    Some(2) => TVSystem::DualCompatible,
+                    _ => TVSystem::NTSC,
+                }
+            }
+            _ => (),
+        }
+
+        position = chunk_end;
+    }
+
+    let board_name = board_name
+        .ok_or_else(|| RomError::ParseError("UNIF image has no MAPR chunk".to_string()))?;
+    let mapper_number = get_unif_board_mapper(&board_name)
+        .ok_or_else(|| RomError::ParseError(format!("Unsupported UNIF board: {}", board_name)))?;
+
+    let prg_rom_bytes: Vec<u8> = prg_chunks
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter())
+        .copied()
+        .collect();
+    let chr_rom_bytes: Vec<u8> = chr_chunks
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter())
+        .copied()
+        .collect();
+    if prg_rom_bytes.is_empty() {
+        return Err(RomError::ParseError(
+            "UNIF image has no PRG ROM".to_string(),
+        ));
+    }
+
+    // 0: horizontal, 1: vertical, 2: single screen A, 3: single screen B,
+    // 4: four-screen, 5: controlled by the mapper
+    let mirroring_mode = match mirroring {
+        Some(1) => MirroringMode::Vertical,
+        Some(2) => MirroringMode::SingleScreenA,
+        Some(3) => MirroringMode::SingleScreenB,
+        _ => MirroringMode::Horizontal,
+    };
+
+    let header = Header {
+        prg_rom_size: prg_rom_bytes.len(),
+        chr_rom_size: chr_rom_bytes.len(),
+        prg_ram_size: PRG_RAM_BYTES_PER_CHUNK,
+        mapper_number,
+        four_screen_mode: mirroring == Some(4),
+        sram_present: has_battery,
+        mirroring_mode,
+        tv_system,
+        ..Header::default()
+    };
+
+    Ok(Rom {
+        header,
+        trainer_bytes: None,
+        prg_rom_bytes,
+        chr_rom_bytes,
+        disk_sides: Vec::new(),
+        nsf: None,
+        title,
+        patch_path: None,
+        db_entry: None,
+        vs_game: None,
+    })
+}
+
+fn get_unif_board_mapper(board_name: &str) -> Option<u16> {
+    let board_name = UNIF_BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| board_name.strip_prefix(prefix))
+        .unwrap_or(board_name);
+
+    UNIF_BOARDS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(board_name))
+        .map(|&(_, mapper_number)| mapper_number)
+}
+
+fn read_unif_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unif_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn unif_image(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = UNIF_HEADER_TAG.to_vec();
+        // Revision
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.resize(UNIF_HEADER_SIZE, 0);
+        for chunk in chunks {
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    fn uxrom_db_entry(rom: &Rom) -> RomDbEntry {
+        let (crc32, _) = rom_db::rom_checksums(rom);
+        RomDbEntry {
+            crc32,
+            sha1: None,
+            title: "Test (USA)",
+            mapper_number: 2,
+            submapper_number: 0,
+            mirroring_mode: Some(MirroringMode::Vertical),
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            has_battery: false,
+            console: rom_db::RomDbConsole::Nes,
+            vs_ppu_type: VsPpuType::Rp2c03,
+            vs_hardware_type: VsHardwareType::UniSystem,
+            tv_system: TVSystem::NTSC,
+        }
+    }
+
+    // A UxROM dump whose header bytes 7-15 were overwritten with "DiskDude!" by an old
+    // dumping tool, and that's missing its vertical mirroring bit
+    #[test]
+    fn bad_ines_header_is_corrected_from_rom_db() {
+        let mut bytes = b"NES\x1A\x02\x01\x00DiskDude!".to_vec();
+        bytes.extend_from_slice(&[0xEA; 2 * PRG_ROM_BYTES_PER_CHUNK]);
+        bytes.extend_from_slice(&[0x55; CHR_ROM_BYTES_PER_CHUNK]);
+
+        let mut rom = parse_rom(&bytes).unwrap().1;
+        assert!(matches!(rom.header.header_type, HeaderType::Standard));
+        assert_eq!(rom.header.mapper_number, 64);
+        assert_eq!(rom.header.mirroring_mode, MirroringMode::Horizontal);
+        assert_eq!(rom.header.tv_system, TVSystem::DualCompatible);
+
+        let entries = [uxrom_db_entry(&rom)];
+        rom_db::find_entry_in(&entries, &rom)
+            .unwrap()
+            .apply(&mut rom.header);
+
+        assert_eq!(rom.header.mapper_number, 2);
+        assert_eq!(rom.header.submapper_number(), 0);
+        assert_eq!(rom.header.mirroring_mode, MirroringMode::Vertical);
+        assert_eq!(rom.header.tv_system, TVSystem::NTSC);
+        assert_eq!(rom.header.prg_ram_size, 0);
+        assert!(!rom.header.sram_present);
+    }
+
+    // The Vs. bit of an iNES header doesn't say which PPU and protection chip the game needs
+    #[test]
+    fn vs_hardware_is_set_from_rom_db() {
+        let mut bytes = b"NES\x1A\x02\x01\x00\x01".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&[0xEA; 2 * PRG_ROM_BYTES_PER_CHUNK]);
+        bytes.extend_from_slice(&[0x55; CHR_ROM_BYTES_PER_CHUNK]);
+
+        let mut rom = parse_rom(&bytes).unwrap().1;
+        assert!(rom.header.is_vs_unisystem);
+        assert_eq!(rom.header.vs_ppu_type, VsPpuType::Rp2c03);
+        assert_eq!(rom.header.vs_hardware_type, VsHardwareType::UniSystem);
+
+        let entries = [RomDbEntry {
+            console: rom_db::RomDbConsole::VsUniSystem,
+            vs_ppu_type: VsPpuType::from_nes2(3),
+            vs_hardware_type: VsHardwareType::from_nes2(1),
+            ..uxrom_db_entry(&rom)
+        }];
+        rom_db::find_entry_in(&entries, &rom)
+            .unwrap()
+            .apply(&mut rom.header);
+
+        assert!(rom.header.is_vs_unisystem);
+        assert_eq!(rom.header.vs_ppu_type, VsPpuType::Rp2c04(2));
+        assert_eq!(
+            rom.header.vs_hardware_type,
+            VsHardwareType::RbiBaseballProtection
+        );
+    }
+
+    #[test]
+    fn vs_hardware_is_looked_up_by_file_name() {
+        let mut bytes = b"NES\x1A\x02\x01\x00\x01".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&[0xEA; 2 * PRG_ROM_BYTES_PER_CHUNK]);
+        bytes.extend_from_slice(&[0x55; CHR_ROM_BYTES_PER_CHUNK]);
+
+        let options = RomLoadOptions::default();
+        let rom = Rom::parse(&bytes, Path::new("Vs. Gumshoe (USA).nes"), &options).unwrap();
+        assert_eq!(rom.vs_game.unwrap().title, "Gumshoe");
+        assert_eq!(rom.header.vs_ppu_type, VsPpuType::Rc2c05(3));
+        assert_eq!(rom.header.vs_hardware_type, VsHardwareType::UniSystem);
+
+        let rom = Rom::parse(&bytes, Path::new("Vs. Unknown.nes"), &options).unwrap();
+        assert!(rom.vs_game.is_none());
+        assert_eq!(rom.header.vs_ppu_type, VsPpuType::Rp2c03);
+
+        let options = RomLoadOptions {
+            ignore_rom_db: true,
+            ..RomLoadOptions::default()
+        };
+        let rom = Rom::parse(&bytes, Path::new("Vs. Gumshoe (USA).nes"), &options).unwrap();
+        assert!(rom.vs_game.is_none());
+    }
+
+    #[test]
+    fn rom_db_entry_with_different_sha1_is_ignored() {
+        let mut bytes = b"NES\x1A\x01\x00\x00\x00".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&[0xEA; PRG_ROM_BYTES_PER_CHUNK]);
+        let rom = parse_rom(&bytes).unwrap().1;
+
+        let entries = [RomDbEntry {
+            sha1: Some("0000000000000000000000000000000000000000"),
+            ..uxrom_db_entry(&rom)
+        }];
+        assert!(rom_db::find_entry_in(&entries, &rom).is_none());
+    }
+
+    #[test]
+    fn truncated_ines_image_is_rejected() {
+        let mut bytes = b"NES\x1A\x02\x01".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&[0xEA; PRG_ROM_BYTES_PER_CHUNK]);
+
+        let options = RomLoadOptions::default();
+        assert_rom_error(
+            Rom::parse(&bytes, Path::new("game.nes"), &options),
+            RomError::ParseError("iNES image is truncated".to_string()),
+        );
+        assert_rom_error(
+            Rom::parse(&bytes[..8], Path::new("game.nes"), &options),
+            RomError::ParseError("iNES image is truncated".to_string()),
+        );
+    }
+
+    #[test]
+    fn file_without_ines_header_is_rejected() {
+        let bytes = vec![0xFF; 0x4010];
+        assert_rom_error(
+            Rom::parse(&bytes, Path::new("game.nes"), &RomLoadOptions::default()),
+            RomError::ParseError("Unsupported ROM format".to_string()),
+        );
+    }
+
+    #[test]
+    fn unif_image_is_parsed() {
+        let bytes = unif_image(&[
+            unif_chunk(b"MAPR", b"NES-UNROM\0"),
+            unif_chunk(b"NAME", b"Test\0"),
+            unif_chunk(b"PRG1", &[2; 0x4000]),
+            unif_chunk(b"PRG0", &[1; 0x4000]),
+            unif_chunk(b"CHR0", &[3; 0x2000]),
+            unif_chunk(b"MIRR", &[1]),
+            unif_chunk(b"BATR", &[1]),
+        ]);
+
+        let rom = parse_unif(&bytes).unwrap();
+        assert_eq!(rom.header.mapper_number, 2);
+        assert_eq!(rom.header.mirroring_mode, MirroringMode::Vertical);
+        assert!(rom.header.sram_present);
+        assert_eq!(rom.title.as_deref(), Some("Test"));
+
+        // PRG chunks are concatenated in order of their number, not of the file
+        assert_eq!(rom.header.prg_rom_size, 0x8000);
+        assert_eq!(rom.prg_rom_bytes[0], 1);
+        assert_eq!(rom.prg_rom_bytes[0x4000], 2);
+        assert_eq!(rom.header.chr_rom_size, 0x2000);
+        assert_eq!(rom.chr_rom_bytes[0], 3);
+    }
+
+    #[test]
+    fn unif_board_prefix_and_case_are_ignored() {
+        assert_eq!(get_unif_board_mapper("NES-TLROM"), Some(4));
+        assert_eq!(get_unif_board_mapper("HVC-tlrom"), Some(4));
+        assert_eq!(get_unif_board_mapper("TLROM"), Some(4));
+    }
+
+    #[test]
+    fn unknown_unif_board_is_rejected() {
+        let bytes = unif_image(&[
+            unif_chunk(b"MAPR", b"UNL-NOT-A-BOARD\0"),
+            unif_chunk(b"PRG0", &[0; 0x8000]),
+        ]);
+        assert_rom_error(
+            parse_unif(&bytes),
+            RomError::ParseError("Unsupported UNIF board: UNL-NOT-A-BOARD".to_string()),
+        );
+    }
+
+    #[test]
+    fn unif_image_without_board_is_rejected() {
+        let bytes = unif_image(&[unif_chunk(b"PRG0", &[0; 0x8000])]);
+        assert_rom_error(
+            parse_unif(&bytes),
+            RomError::ParseError("UNIF image has no MAPR chunk".to_string()),
+        );
+    }
+
+    #[test]
+    fn truncated_unif_chunk_is_rejected() {
+        let mut bytes = unif_image(&[
+            unif_chunk(b"MAPR", b"NES-NROM-256\0"),
+            unif_chunk(b"PRG0", &[0; 0x8000]),
+        ]);
+        bytes.truncate(bytes.len() - 1);
+        assert_rom_error(
+            parse_unif(&bytes),
+            RomError::ParseError("UNIF chunk PRG0 is truncated".to_string()),
+        );
+    }
+}
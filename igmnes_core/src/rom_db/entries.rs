@@ -0,0 +1,10 @@
+// Generated by `romdb generate` from the NES 2.0 XML database, do not edit
+
+use super::{RomDbConsole, RomDbEntry};
+use crate::rom::{MirroringMode, TVSystem};
+use crate::vs_system::{VsHardwareType, VsPpuType};
+
+#[rustfmt::skip]
+pub const ROM_DB_ENTRIES: &[RomDbEntry] = &[
+    RomDbEntry { crc32: 0x3337EC46, sha1: None, title: "Super Mario Bros. (World)", mapper_number: 0, submapper_number: 0, mirroring_mode: Some(MirroringMode::Vertical), prg_ram_size: 0, prg_nvram_size: 0, chr_ram_size: 0, chr_nvram_size: 0, has_battery: false, console: RomDbConsole::Nes, vs_ppu_type: VsPpuType::from_nes2(0), vs_hardware_type: VsHardwareType::from_nes2(0), tv_system: TVSystem::NTSC },
+];
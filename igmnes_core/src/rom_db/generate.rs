@@ -0,0 +1,127 @@
+// Generates the source of the database entries from the NES 2.0 XML database
+//
+// Used by the build script as well as by `romdb generate`, so this only depends on std.
+
+use std::fmt::Write;
+
+// Source of a module with the entries of every game in the database
+pub fn generate_rom_db_entries(xml: &str) -> String {
+    let mut source = String::new();
+    source.push_str(
+        "// Generated by `romdb generate` from the NES 2.0 XML database, do not edit\n\n",
+    );
+    source.push_str("use super::{RomDbConsole, RomDbEntry};\n");
+    source.push_str("use crate::rom::{MirroringMode, TVSystem};\n");
+    source.push_str("use crate::vs_system::{VsHardwareType, VsPpuType};\n\n");
+    source.push_str("#[rustfmt::skip]\n");
+    source.push_str("pub const ROM_DB_ENTRIES: &[RomDbEntry] = &[\n");
+    for game in xml.split("<game>").skip(1) {
+        let game = game.split("</game>").next().unwrap_or_default();
+        if let Some(entry) = generate_entry(game) {
+            let _ = writeln!(source, "    {},", entry);
+        }
+    }
+    source.push_str("];\n");
+
+    source
+}
+
+//  <game>
+//    <!-- path\to\Title (Region).nes -->
+//    <rom size="..." crc32="..." sha1="..."/>
+//    <pcb mapper="..." submapper="..." mirroring="H|V|4|1" battery="0|1"/>
+//    <prgram size="..."/> <prgnvram size="..."/> <chrram size="..."/> <chrnvram size="..."/>
+//    <console type="..." region="..."/>
+//    <vs hardware="..." ppu="..."/>
+//  </game>
+fn generate_entry(game: &str) -> Option<String> {
+    let crc32 = attribute(game, "rom", "crc32")?;
+    let sha1 = match attribute(game, "rom", "sha1") {
+        Some(sha1) => format!("Some(\"{}\")", sha1.to_uppercase()),
+        None => "None".to_string(),
+    };
+
+    let title = game
+        .split("<!--")
+        .nth(1)
+        .and_then(|comment| comment.split("-->").next())
+        .map(|path| path.trim().rsplit(['\\', '/']).next().unwrap_or_default())
+        .map(|file_name| file_name.trim_end_matches(".nes"))
+        .unwrap_or_default();
+
+    let mirroring_mode = match attribute(game, "pcb", "mirroring") {
+        Some("H") => "Some(MirroringMode::Horizontal)",
+        Some("V") => "Some(MirroringMode::Vertical)",
+        Some("4") => "Some(MirroringMode::FourScreen)",
+        _ => "None",
+    };
+    let console = match attribute(game, "console", "type") {
+        Some("1") => "RomDbConsole::VsUniSystem",
+        Some("2") => "RomDbConsole::PlayChoice10",
+        _ => "RomDbConsole::Nes",
+    };
+    // Dendy clones run at PAL timings as well
+    let tv_system = match attribute(game, "console", "region") {
+        Some("1") | Some("3") => "TVSystem::PAL",
+        Some("2") => "TVSystem::DualCompatible",
+        _ => "TVSystem::NTSC",
+    };
+
+    let number = |element: &str, name: &str| attribute(game, element, name).unwrap_or("0");
+
+    Some(format!(
+        "RomDbEntry {{ crc32: 0x{}, sha1: {}, title: {:?}, mapper_number: {}, submapper_number: {}, mirroring_mode: {}, prg_ram_size: {}, prg_nvram_size: {}, chr_ram_size: {}, chr_nvram_size: {}, has_battery: {}, console: {}, vs_ppu_type: VsPpuType::from_nes2({}), vs_hardware_type: VsHardwareType::from_nes2({}), tv_system: {} }}",
+        crc32.to_uppercase(),
+        sha1,
+        title,
+        number("pcb", "mapper"),
+        number("pcb", "submapper"),
+        mirroring_mode,
+        number("prgram", "size"),
+        number("prgnvram", "size"),
+        number("chrram", "size"),
+        number("chrnvram", "size"),
+        number("pcb", "battery") == "1",
+        console,
+        number("vs", "ppu"),
+        number("vs", "hardware"),
+        tv_system,
+    ))
+}
+
+// Value of an attribute of the first element with the given name
+fn attribute<'a>(xml: &'a str, element: &str, name: &str) -> Option<&'a str> {
+    let element_start = xml.find(&format!("<{} ", element))?;
+    let element = xml[element_start..].split('>').next()?;
+    let value_start = element.find(&format!(" {}=\"", name))? + name.len() + 3;
+    element[value_start..].split('"').next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vs_game_entry_is_generated() {
+        let xml = r#"<nes20db>
+  <game>
+    <!-- \NES\Vs. System\Vs. Test (USA).nes -->
+    <rom size="49152" crc32="0a1b2c3d" sha1="0123456789abcdef0123456789abcdef01234567"/>
+    <pcb mapper="99" submapper="0" mirroring="4" battery="0"/>
+    <prgram size="2048"/>
+    <console type="1" region="0"/>
+    <vs hardware="1" ppu="3"/>
+  </game>
+</nes20db>"#;
+
+        let source = generate_rom_db_entries(xml);
+        let entry = source
+            .lines()
+            .find(|line| line.trim_start().starts_with("RomDbEntry"))
+            .unwrap();
+        assert_eq!(
+            entry.trim(),
+            "RomDbEntry { crc32: 0x0A1B2C3D, sha1: Some(\"0123456789ABCDEF0123456789ABCDEF01234567\"), title: \"Vs. Test (USA)\", mapper_number: 99, submapper_number: 0, mirroring_mode: Some(MirroringMode::FourScreen), prg_ram_size: 2048, prg_nvram_size: 0, chr_ram_size: 0, chr_nvram_size: 0, has_battery: false, console: RomDbConsole::VsUniSystem, vs_ppu_type: VsPpuType::from_nes2(3), vs_hardware_type: VsHardwareType::from_nes2(1), tv_system: TVSystem::NTSC },"
+        );
+    }
+}
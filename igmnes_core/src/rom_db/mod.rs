@@ -0,0 +1,101 @@
+use crate::checksum::{crc32, sha1};
+use crate::rom::{Header, HeaderExtension, MirroringMode, Rom, TVSystem};
+use crate::vs_system::{VsHardwareType, VsPpuType};
+
+mod generate;
+
+pub use self::generate::generate_rom_db_entries;
+
+// Generated by the build script, from the NES 2.0 XML database if NES20DB_PATH is set when
+// building, otherwise copied from entries.rs
+mod entries {
+    include!(concat!(env!("OUT_DIR"), "/rom_db_entries.rs"));
+}
+
+// Game database, for fixing up the headers of known dumps
+//
+// Entries are keyed by the CRC-32 and SHA-1 of the PRG and CHR ROM, without the header and
+// trainer, and are generated from the NES 2.0 XML database, either at build time or with
+// `romdb generate`, whose output can be checked in as entries.rs.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RomDbConsole {
+    Nes,
+    VsUniSystem,
+    PlayChoice10,
+}
+
+#[derive(Debug, Clone)]
+pub struct RomDbEntry {
+    pub crc32: u32,
+    // Uppercase hex, None for entries that are only known by CRC-32
+    pub sha1: Option<&'static str>,
+    pub title: &'static str,
+    pub mapper_number: u16,
+    pub submapper_number: u8,
+    // None if the mapper controls mirroring
+    pub mirroring_mode: Option<MirroringMode>,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub has_battery: bool,
+    pub console: RomDbConsole,
+    // Vs. UniSystem PPU and protection hardware, defaults for other consoles
+    pub vs_ppu_type: VsPpuType,
+    pub vs_hardware_type: VsHardwareType,
+    pub tv_system: TVSystem,
+}
+
+impl RomDbEntry {
+    pub fn apply(&self, header: &mut Header) {
+        header.mapper_number = self.mapper_number;
+        header.extension = Some(HeaderExtension {
+            mapper_number: self.mapper_number,
+            submapper_number: self.submapper_number,
+        });
+
+        match self.mirroring_mode {
+            Some(MirroringMode::FourScreen) => header.four_screen_mode = true,
+            Some(mirroring_mode) => {
+                header.four_screen_mode = false;
+                header.mirroring_mode = mirroring_mode;
+            }
+            None => (),
+        }
+
+        header.prg_ram_size = self.prg_ram_size + self.prg_nvram_size;
+        header.chr_ram_size = self.chr_ram_size + self.chr_nvram_size;
+        header.sram_present = self.has_battery || self.prg_nvram_size > 0;
+
+        header.is_vs_unisystem = self.console == RomDbConsole::VsUniSystem;
+        header.is_playchoice_10 = self.console == RomDbConsole::PlayChoice10;
+        header.vs_ppu_type = self.vs_ppu_type;
+        header.vs_hardware_type = self.vs_hardware_type;
+        header.tv_system = self.tv_system.clone();
+    }
+}
+
+// CRC-32 and uppercase hex SHA-1 of the PRG and CHR ROM
+pub fn rom_checksums(rom: &Rom) -> (u32, String) {
+    let mut bytes = rom.prg_rom_bytes.clone();
+    bytes.extend_from_slice(&rom.chr_rom_bytes);
+
+    let sha1 = sha1(&bytes)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    (crc32(&bytes), sha1)
+}
+
+pub fn find_entry(rom: &Rom) -> Option<&'static RomDbEntry> {
+    find_entry_in(entries::ROM_DB_ENTRIES, rom)
+}
+
+pub fn find_entry_in<'a>(entries: &'a [RomDbEntry], rom: &Rom) -> Option<&'a RomDbEntry> {
+    let (crc32, sha1) = rom_checksums(rom);
+    entries
+        .iter()
+        .filter(|entry| entry.crc32 == crc32)
+        .find(|entry| entry.sha1.is_none_or(|entry_sha1| entry_sha1 == sha1))
+}
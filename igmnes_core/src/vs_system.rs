@@ -0,0 +1,417 @@
+use crate::memory::{MemMapConfig, MemMapped};
+use crate::ppu::palette::PpuPaletteColor;
+
+// Vs. UniSystem arcade hardware
+//
+// The Vs. UniSystem runs NES games with an RGB PPU, coin slots and DIP switches. Games were
+// tied to a particular PPU through its palette, and some carts add copy protection chips.
+//
+// $4016 read
+//     7  bit  0
+//     ---- ----
+//     PCCD DSxB
+//     |||| || |
+//     |||| || +- Controller 1 serial data
+//     |||| |+--- Service button
+//     |||+-+---- DIP switches 2 and 1
+//     |++------- Coins inserted in slots 2 and 1
+//     +--------- 0: main CPU, 1: secondary CPU of a dual system
+//
+// $4017 read
+//     7  bit  0
+//     ---- ----
+//     DDDD DDxB
+//     |||| || |
+//     |||| || +- Controller 2 serial data
+//     ++++-++--- DIP switches 8 to 3
+//
+// $4016 write, bit 2 selects the CHR bank of mapper 99 carts. $4020 write, bit 0 drives the
+// coin counter, a mechanical counter that counts one coin each time it's energized.
+
+// NES 2.0 Vs. PPU types
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VsPpuType {
+    // RP2C03B/G and RC2C03B/C, also used by the PlayChoice-10
+    #[default]
+    Rp2c03,
+    // RP2C04-0001 to -0004, with scrambled palettes
+    Rp2c04(u8),
+    // RC2C05-01 to -05, with $2000/$2001 swapped and an ID in the low bits of $2002
+    Rc2c05(u8),
+}
+
+// NES 2.0 Vs. hardware types
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VsHardwareType {
+    #[default]
+    UniSystem,
+    RbiBaseballProtection,
+    TkoBoxingProtection,
+    SuperXeviousProtection,
+    IceClimberJapanProtection,
+    // Dual systems run the main side only
+    DualSystem,
+    DualSystemRaidOnBungelingBay,
+}
+
+// 3 bits per channel, in octal
+#[rustfmt::skip]
+const RP2C03_PALETTE: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+#[rustfmt::skip]
+const RP2C04_PALETTES: [[u16; 64]; 4] = [
+    // RP2C04-0001
+    [
+        0o755, 0o637, 0o700, 0o447, 0o044, 0o120, 0o222, 0o704, 0o777, 0o333, 0o750, 0o503, 0o403, 0o660, 0o320, 0o777,
+        0o357, 0o653, 0o310, 0o360, 0o467, 0o657, 0o764, 0o027, 0o760, 0o276, 0o000, 0o200, 0o666, 0o444, 0o707, 0o014,
+        0o003, 0o567, 0o757, 0o070, 0o077, 0o022, 0o053, 0o507, 0o000, 0o420, 0o747, 0o510, 0o407, 0o006, 0o740, 0o000,
+        0o000, 0o140, 0o555, 0o031, 0o572, 0o326, 0o770, 0o630, 0o020, 0o036, 0o040, 0o111, 0o773, 0o737, 0o430, 0o473,
+    ],
+    // RP2C04-0002
+    [
+        0o000, 0o750, 0o430, 0o572, 0o473, 0o737, 0o044, 0o567, 0o700, 0o407, 0o773, 0o747, 0o777, 0o637, 0o467, 0o040,
+        0o020, 0o357, 0o510, 0o666, 0o053, 0o360, 0o200, 0o447, 0o222, 0o707, 0o003, 0o276, 0o657, 0o320, 0o000, 0o326,
+        0o403, 0o764, 0o740, 0o757, 0o036, 0o310, 0o555, 0o006, 0o507, 0o760, 0o333, 0o120, 0o027, 0o000, 0o660, 0o777,
+        0o653, 0o111, 0o070, 0o630, 0o022, 0o014, 0o704, 0o140, 0o000, 0o077, 0o420, 0o770, 0o755, 0o503, 0o031, 0o444,
+    ],
+    // RP2C04-0003
+    [
+        0o507, 0o737, 0o473, 0o555, 0o040, 0o777, 0o567, 0o120, 0o014, 0o000, 0o764, 0o320, 0o704, 0o666, 0o653, 0o467,
+        0o447, 0o044, 0o503, 0o027, 0o140, 0o430, 0o630, 0o053, 0o333, 0o326, 0o000, 0o006, 0o700, 0o510, 0o747, 0o755,
+        0o637, 0o020, 0o003, 0o770, 0o111, 0o750, 0o740, 0o777, 0o360, 0o403, 0o357, 0o707, 0o036, 0o444, 0o000, 0o310,
+        0o077, 0o200, 0o572, 0o757, 0o420, 0o070, 0o660, 0o222, 0o031, 0o000, 0o657, 0o773, 0o407, 0o276, 0o760, 0o022,
+    ],
+    // RP2C04-0004
+    [
+        0o430, 0o326, 0o044, 0o660, 0o000, 0o755, 0o014, 0o630, 0o555, 0o310, 0o070, 0o003, 0o764, 0o770, 0o040, 0o572,
+        0o737, 0o200, 0o027, 0o747, 0o000, 0o222, 0o510, 0o740, 0o653, 0o053, 0o447, 0o140, 0o403, 0o000, 0o473, 0o357,
+        0o503, 0o031, 0o420, 0o006, 0o407, 0o507, 0o333, 0o704, 0o022, 0o666, 0o036, 0o020, 0o111, 0o773, 0o444, 0o707,
+        0o757, 0o777, 0o320, 0o700, 0o760, 0o276, 0o777, 0o467, 0o000, 0o750, 0o637, 0o567, 0o360, 0o657, 0o077, 0o120,
+    ],
+];
+
+// Low bits of $2002 on the RC2C05-01 to -05
+const RC2C05_STATUS_IDS: [u8; 5] = [0x1B, 0x3D, 0x1C, 0x1B, 0x00];
+
+// Values returned from $5E01 by the RBI Baseball and TKO Boxing protection chips,
+// in sequence after a read from $5E00
+const RBI_BASEBALL_PROTECTION_DATA: [u8; 32] = [
+    0xFF, 0xBF, 0xB7, 0x97, 0x97, 0x17, 0x57, 0x4F, 0x6F, 0x6B, 0xEB, 0xA9, 0xB1, 0x90, 0x94, 0x14,
+    0x56, 0x4E, 0x6F, 0x6B, 0xEB, 0xA9, 0xB1, 0x90, 0xD4, 0x5C, 0x3E, 0x26, 0x87, 0x83, 0x13, 0x00,
+];
+const TKO_BOXING_PROTECTION_DATA: [u8; 32] = [
+    0x00, 0x00, 0x00, 0x00, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x6F, 0x00, 0x00, 0x00, 0x00, 0x94, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// A Vs. UniSystem game and the hardware it needs
+#[derive(Debug)]
+pub struct VsGame {
+    pub title: &'static str,
+    pub ppu_type: VsPpuType,
+    pub hardware_type: VsHardwareType,
+}
+
+// Vs. UniSystem games, from the Vs. System game list on the NESdev wiki. iNES headers only
+// have the Vs. bit, so games that aren't in the game database are looked up here by title.
+// The 2C05 number also gives the ID the game checks for in $2002.
+#[rustfmt::skip]
+pub const VS_GAMES: &[VsGame] = &[
+    VsGame { title: "Castlevania", ppu_type: VsPpuType::Rp2c04(2), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Clu Clu Land", ppu_type: VsPpuType::Rp2c04(4), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Dr. Mario", ppu_type: VsPpuType::Rp2c04(3), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Duck Hunt", ppu_type: VsPpuType::Rp2c03, hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Excitebike", ppu_type: VsPpuType::Rp2c04(3), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Freedom Force", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Goonies", ppu_type: VsPpuType::Rp2c04(3), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Gradius", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Gumshoe", ppu_type: VsPpuType::Rc2c05(3), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Hogan's Alley", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Ice Climber", ppu_type: VsPpuType::Rp2c04(4), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Mach Rider", ppu_type: VsPpuType::Rp2c04(2), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Mighty Bomb Jack", ppu_type: VsPpuType::Rc2c05(2), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Ninja Jajamaru-kun", ppu_type: VsPpuType::Rc2c05(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Pinball", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Platoon", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "R.B.I. Baseball", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::RbiBaseballProtection },
+    VsGame { title: "Slalom", ppu_type: VsPpuType::Rp2c04(2), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Soccer", ppu_type: VsPpuType::Rp2c04(3), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Stroke & Match Golf", ppu_type: VsPpuType::Rp2c04(2), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Super Mario Bros.", ppu_type: VsPpuType::Rp2c04(4), hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "Super Xevious", ppu_type: VsPpuType::Rp2c04(1), hardware_type: VsHardwareType::SuperXeviousProtection },
+    VsGame { title: "Tetris", ppu_type: VsPpuType::Rp2c03, hardware_type: VsHardwareType::UniSystem },
+    VsGame { title: "TKO Boxing", ppu_type: VsPpuType::Rp2c04(3), hardware_type: VsHardwareType::TkoBoxingProtection },
+    VsGame { title: "Top Gun", ppu_type: VsPpuType::Rc2c05(4), hardware_type: VsHardwareType::UniSystem },
+];
+
+// Game whose title is in the given name, a file name or the title stored in the ROM. Case,
+// spaces and punctuation are ignored, and the longest title wins, so "Vs. Super Xevious"
+// doesn't match a shorter title that's part of it
+pub fn find_vs_game(name: &str) -> Option<&'static VsGame> {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+
+    let name = normalize(name);
+    VS_GAMES
+        .iter()
+        .map(|vs_game| (vs_game, normalize(vs_game.title)))
+        .filter(|(_, title)| name.contains(title.as_str()))
+        .max_by_key(|(_, title)| title.len())
+        .map(|(vs_game, _)| vs_game)
+}
+
+impl VsPpuType {
+    // NES 2.0 header byte 13, low nibble
+    pub const fn from_nes2(value: u8) -> VsPpuType {
+        match value {
+            2..=5 => VsPpuType::Rp2c04(value - 1),
+            8..=12 => VsPpuType::Rc2c05(value - 7),
+            _ => VsPpuType::Rp2c03,
+        }
+    }
+
+    pub fn palette_colors(&self) -> [PpuPaletteColor; 64] {
+        let palette = match self {
+            VsPpuType::Rp2c04(number) => &RP2C04_PALETTES[(*number as usize - 1) % 4],
+            _ => &RP2C03_PALETTE,
+        };
+
+        // Scales 0-7 to 0-255
+        let channel = |rgb: u16, shift: u16| (((rgb >> shift) & 0b111) * 255 / 7) as u8;
+        std::array::from_fn(|index| PpuPaletteColor {
+            red: channel(palette[index], 6),
+            green: channel(palette[index], 3),
+            blue: channel(palette[index], 0),
+        })
+    }
+
+    pub fn is_ctrl_mask_swapped(&self) -> bool {
+        matches!(self, VsPpuType::Rc2c05(_))
+    }
+
+    pub fn status_id(&self) -> u8 {
+        match self {
+            VsPpuType::Rc2c05(number) => RC2C05_STATUS_IDS[(*number as usize - 1) % 5],
+            _ => 0,
+        }
+    }
+}
+
+impl VsHardwareType {
+    // NES 2.0 header byte 13, high nibble
+    pub const fn from_nes2(value: u8) -> VsHardwareType {
+        match value {
+            1 => VsHardwareType::RbiBaseballProtection,
+            2 => VsHardwareType::TkoBoxingProtection,
+            3 => VsHardwareType::SuperXeviousProtection,
+            4 => VsHardwareType::IceClimberJapanProtection,
+            5 => VsHardwareType::DualSystem,
+            6 => VsHardwareType::DualSystemRaidOnBungelingBay,
+            _ => VsHardwareType::UniSystem,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VsSystem {
+    hardware_type: VsHardwareType,
+
+    // Switches 1 to 8 in bits 0 to 7, kept across resets like the physical switches
+    dip_switches: u8,
+    is_coin_inserted: [bool; 2],
+    is_service_button_pressed: bool,
+
+    protection_index: usize,
+    is_xevious_protection_flipped: bool,
+
+    // Last value written to $4020
+    coin_counter: u8,
+    // Coins counted by the coin counter, kept across resets like the physical counter
+    coin_count: u32,
+
+    mem_map_config: MemMapConfig,
+}
+
+impl VsSystem {
+    pub fn new(hardware_type: VsHardwareType) -> VsSystem {
+        VsSystem {
+            hardware_type,
+            dip_switches: 0,
+            is_coin_inserted: [false; 2],
+            is_service_button_pressed: false,
+            protection_index: 0,
+            is_xevious_protection_flipped: false,
+            coin_counter: 0,
+            coin_count: 0,
+            mem_map_config: MemMapConfig::default(),
+        }
+    }
+
+    pub fn hard_reset(&mut self) {
+        self.protection_index = 0;
+        self.is_xevious_protection_flipped = false;
+        self.coin_counter = 0;
+    }
+
+    pub fn dip_switches(&self) -> u8 {
+        self.dip_switches
+    }
+
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches;
+    }
+
+    // Slot 0 or 1, held while the coin is going through the slot
+    pub fn set_coin_inserted(&mut self, slot: usize, is_inserted: bool) {
+        self.is_coin_inserted[slot % 2] = is_inserted;
+    }
+
+    pub fn set_service_button_pressed(&mut self, is_pressed: bool) {
+        self.is_service_button_pressed = is_pressed;
+    }
+
+    pub fn coin_counter(&self) -> u8 {
+        self.coin_counter
+    }
+
+    pub fn coin_count(&self) -> u32 {
+        self.coin_count
+    }
+
+    pub fn is_protection_address(&self, index: u16) -> bool {
+        match self.hardware_type {
+            VsHardwareType::RbiBaseballProtection | VsHardwareType::TkoBoxingProtection => {
+                matches!(index, 0x5E00 | 0x5E01)
+            }
+            VsHardwareType::SuperXeviousProtection => {
+                matches!(index, 0x54FF | 0x5567 | 0x5678 | 0x578F)
+            }
+            _ => false,
+        }
+    }
+
+    fn read_protection(&mut self, index: u16) -> u8 {
+        match (self.hardware_type, index) {
+            (_, 0x5E00) => {
+                if self.is_mutating_read() {
+                    self.protection_index = 0;
+                }
+                0
+            }
+            (hardware_type, 0x5E01) => {
+                let data = if hardware_type == VsHardwareType::RbiBaseballProtection {
+                    &RBI_BASEBALL_PROTECTION_DATA
+                } else {
+                    &TKO_BOXING_PROTECTION_DATA
+                };
+                let byte = data[self.protection_index % data.len()];
+                if self.is_mutating_read() {
+                    self.protection_index += 1;
+                }
+                byte
+            }
+            (_, 0x54FF) => 0x05,
+            (_, 0x5567) => {
+                let is_flipped = !self.is_xevious_protection_flipped;
+                if self.is_mutating_read() {
+                    self.is_xevious_protection_flipped = is_flipped;
+                }
+                if is_flipped {
+                    0x37
+                } else {
+                    0x3E
+                }
+            }
+            (_, 0x5678) => !self.is_xevious_protection_flipped as u8,
+            (_, 0x578F) => {
+                if self.is_xevious_protection_flipped {
+                    0xD1
+                } else {
+                    0x89
+                }
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl MemMapped for VsSystem {
+    // Only the Vs. bits of $4016/$4017, the controller bits come from the controllers
+    fn read(&mut self, index: u16) -> u8 {
+        match index {
+            0x4016 => {
+                ((self.is_coin_inserted[1] as u8) << 6)
+                    | ((self.is_coin_inserted[0] as u8) << 5)
+                    | ((self.dip_switches & 0b11) << 3)
+                    | ((self.is_service_button_pressed as u8) << 2)
+            }
+            0x4017 => self.dip_switches & 0b1111_1100,
+            _ => self.read_protection(index),
+        }
+    }
+
+    // Only $4020, the coin counter
+    fn write(&mut self, _index: u16, byte: u8) {
+        if byte & 1 != 0 && self.coin_counter & 1 == 0 {
+            self.coin_count += 1;
+        }
+        self.coin_counter = byte;
+    }
+
+    fn read_range(&mut self, _range: std::ops::Range<u16>) -> &[u8] {
+        unimplemented!()
+    }
+
+    fn is_mutating_read(&self) -> bool {
+        self.mem_map_config.is_mutating_read
+    }
+
+    fn set_is_mutating_read(&mut self, is_mutating_read: bool) {
+        self.mem_map_config.is_mutating_read = is_mutating_read;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coin_counter_counts_each_pulse() {
+        let mut vs_system = VsSystem::new(VsHardwareType::UniSystem);
+        for byte in [0x01, 0x01, 0x00, 0x03, 0x02, 0x01] {
+            vs_system.write(0x4020, byte);
+        }
+        assert_eq!(vs_system.coin_counter(), 0x01);
+        assert_eq!(vs_system.coin_count(), 3);
+
+        vs_system.hard_reset();
+        assert_eq!(vs_system.coin_counter(), 0);
+        assert_eq!(vs_system.coin_count(), 3);
+    }
+
+    #[test]
+    fn vs_games_are_found_by_title() {
+        let vs_game = find_vs_game("VS Super Mario Bros (VS) [!]").unwrap();
+        assert_eq!(vs_game.title, "Super Mario Bros.");
+        assert_eq!(vs_game.ppu_type, VsPpuType::Rp2c04(4));
+
+        let vs_game = find_vs_game("Atari RBI Baseball").unwrap();
+        assert_eq!(vs_game.hardware_type, VsHardwareType::RbiBaseballProtection);
+
+        let vs_game = find_vs_game("vs_top_gun").unwrap();
+        assert_eq!(vs_game.ppu_type, VsPpuType::Rc2c05(4));
+        assert!(vs_game.ppu_type.is_ctrl_mask_swapped());
+        assert_eq!(vs_game.ppu_type.status_id(), 0x1B);
+
+        assert!(find_vs_game("Vs. Unknown Game").is_none());
+    }
+}
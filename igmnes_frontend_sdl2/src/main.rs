@@ -1,36 +1,69 @@
 use igmnes_core::debug::Tracer;
 use igmnes_core::debugger::Debugger;
 use igmnes_core::ppu::palette::PpuPaletteColor;
-use igmnes_core::Core;
+use igmnes_core::{
+    crop_frame, AspectRatio, Core, CrtMask, FrameScaler, NsfExpansionChips, NtscFilter,
+    NtscFilterPreset, Overscan, PpuDisplayOptions, PpuOutputMode, PpuPaletteColors,
+    PpuPalettePreset, PpuPaletteSettings, RomLoadOptions, Scaler, ScalerSettings,
+    NAMETABLES_VIEW_HEIGHT, NAMETABLES_VIEW_WIDTH, NTSC_FRAME_HEIGHT, NTSC_FRAME_WIDTH,
+    OAM_VIEW_HEIGHT, OAM_VIEW_WIDTH, PATTERN_TABLES_VIEW_HEIGHT, PATTERN_TABLES_VIEW_WIDTH,
+};
 use rfd::FileDialog;
 use sdl2::rect::Rect;
 use std::path::{Path, PathBuf};
-use std::ptr;
 use std::time::{Duration, Instant};
 
 use sdl2::audio::AudioSpecDesired;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Texture, WindowCanvas};
-use sdl2::video::FullscreenType;
+use sdl2::video::{FullscreenType, VideoSubsystem};
 
 const WINDOW_SCALING: u32 = 3;
 const PIXELS_PER_SCANLINE: usize = 256_usize;
-const BYTES_PER_SCANLINE: usize = PIXELS_PER_SCANLINE * 3;
 const SCANLINES: usize = 240;
-const SCANLINES_OFFSET: usize = 8;
+
+// F2 cycles through the scalers, Shift+F2 through the CRT effects (scanlines, mask)
+const SCALERS: [Scaler; 6] = [
+    Scaler::None,
+    Scaler::Scale2x,
+    Scaler::Scale3x,
+    Scaler::Xbrz2x,
+    Scaler::Xbrz3x,
+    Scaler::Xbrz4x,
+];
+const CRT_EFFECTS: [(f32, CrtMask); 4] = [
+    (0.0, CrtMask::None),
+    (0.4, CrtMask::None),
+    (0.4, CrtMask::ApertureGrille),
+    (0.4, CrtMask::SlotMask),
+];
+// Largest scale factor of the scalers, which the texture is sized for
+const MAX_SCALE_FACTOR: usize = 4;
+
+// Nametables on the left, pattern tables, sprites (at 2x) and palettes on the right
+const PPU_VIEWER_WIDTH: usize = NAMETABLES_VIEW_WIDTH + PATTERN_TABLES_VIEW_WIDTH;
+const PPU_VIEWER_HEIGHT: usize = NAMETABLES_VIEW_HEIGHT;
 
 const NANOS_PER_FRAME: u128 = 16_666_667;
 
+const AUDIO_SAMPLE_RATE: u32 = 44_100;
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     let mut rom_path: Option<PathBuf> = None;
+    let mut rom_load_options = RomLoadOptions::default();
 
     let mut attach_debugger = false;
     let mut enable_tracing = false;
     let mut entry_point: Option<u16> = None;
+    let mut vs_dip_switches: Option<u8> = None;
+    let mut video_options = VideoOptions::default();
+    let mut show_ppu_viewer = false;
+    let mut palette: Option<String> = None;
+    let mut is_sprite_limit_disabled = false;
 
     let mut arg_index = 1;
     while arg_index < args.len() {
@@ -47,8 +80,54 @@ fn main() {
             let entry_point_addr = u16::from_str_radix(without_prefix, 16).unwrap();
             entry_point = Some(entry_point_addr);
             arg_index += 2;
+        } else if arg == "--dip" {
+            let dip_switches_hex = args[arg_index + 1].trim_start_matches("0x");
+            vs_dip_switches = Some(u8::from_str_radix(dip_switches_hex, 16).unwrap());
+            arg_index += 2;
+        } else if arg == "--fds-bios" {
+            rom_load_options.fds_bios_path = Some(PathBuf::from(&args[arg_index + 1]));
+            arg_index += 2;
+        } else if arg == "--archive-entry" {
+            rom_load_options.archive_entry = Some(args[arg_index + 1].clone());
+            arg_index += 2;
+        } else if arg == "--no-rom-db" {
+            rom_load_options.ignore_rom_db = true;
+            arg_index += 1;
+        } else if arg == "--ntsc" {
+            video_options.ntsc_preset = Some(args[arg_index + 1].parse().unwrap());
+            arg_index += 2;
+        } else if arg == "--overscan" {
+            video_options.overscan = args[arg_index + 1].parse().unwrap();
+            arg_index += 2;
+        } else if arg == "--aspect" {
+            video_options.aspect_ratio = args[arg_index + 1].parse().unwrap();
+            arg_index += 2;
+        } else if arg == "--scaler" {
+            video_options.scaler_settings.scaler = args[arg_index + 1].parse().unwrap();
+            arg_index += 2;
+        } else if arg == "--scanlines" {
+            video_options.scaler_settings.scanlines = args[arg_index + 1].parse().unwrap();
+            arg_index += 2;
+        } else if arg == "--crt-mask" {
+            video_options.scaler_settings.mask = args[arg_index + 1].parse().unwrap();
+            arg_index += 2;
+        } else if arg == "--integer-scale" {
+            video_options.is_integer_scaled = true;
+            arg_index += 1;
+        } else if arg == "--ppu-viewer" {
+            show_ppu_viewer = true;
+            arg_index += 1;
+        } else if arg == "--no-sprite-limit" {
+            is_sprite_limit_disabled = true;
+            arg_index += 1;
+        } else if arg == "--palette" {
+            palette = Some(args[arg_index + 1].clone());
+            arg_index += 2;
+        } else if arg == "--patch" {
+            rom_load_options.patch_path = Some(PathBuf::from(&args[arg_index + 1]));
+            arg_index += 2;
         } else {
-            rom_path = Some(PathBuf::from(&args[1]));
+            rom_path = Some(PathBuf::from(arg));
             arg_index += 1;
         }
     }
@@ -56,7 +135,10 @@ fn main() {
     if rom_path.is_none() {
         let working_dir = std::env::current_dir().unwrap();
         let file = FileDialog::new()
-            .add_filter("ROM", &["nes"])
+            .add_filter(
+                "ROM",
+                &["nes", "unf", "unif", "fds", "nsf", "nsfe", "zip", "gz"],
+            )
             .set_directory(working_dir)
             .pick_file();
 
@@ -66,26 +148,64 @@ fn main() {
     }
 
     if let Some(rom_path) = rom_path {
-        let core = Core::load_rom(rom_path.as_path()).unwrap();
-        start(core, attach_debugger, enable_tracing, entry_point);
+        let archive_entries = Core::list_archive_entries(rom_path.as_path()).unwrap();
+        if archive_entries.len() > 1 && rom_load_options.archive_entry.is_none() {
+            println!(
+                "Archive contains several ROMs, loading {} (pick one with --archive-entry):",
+                archive_entries[0]
+            );
+            for entry in &archive_entries {
+                println!("  {}", entry);
+            }
+        }
+
+        let mut core = Core::load_rom_with_options(rom_path.as_path(), &rom_load_options).unwrap();
+        if let Some(dip_switches) = vs_dip_switches {
+            core.vs_set_dip_switches(dip_switches);
+        }
+        // Either a PPU to generate the palette of, or a .pal file
+        if let Some(palette) = palette {
+            let colors = match palette.parse::<PpuPalettePreset>() {
+                Ok(preset) => PpuPaletteColors::generate(preset, &PpuPaletteSettings::default()),
+                Err(_) => PpuPaletteColors::load(Path::new(&palette)).unwrap(),
+            };
+            core.set_palette_colors(colors);
+        }
+        core.set_ppu_display_options(PpuDisplayOptions {
+            is_sprite_limit_disabled,
+            ..PpuDisplayOptions::default()
+        });
+        let save_path = rom_path.with_extension("sav");
+        start(
+            core,
+            &save_path,
+            attach_debugger,
+            enable_tracing,
+            entry_point,
+            &video_options,
+            show_ppu_viewer,
+        );
     } else {
-        println!("Usage: igmnes path_to_rom");
+        println!("Usage: igmnes [--fds-bios path_to_bios] [--archive-entry name] [--patch path_to_patch] [--no-rom-db] [--dip hex] [--ntsc composite|svideo|rgb|monochrome] [--overscan top,bottom,left,right] [--aspect 1:1|8:7|4:3] [--integer-scale] [--scaler none|scale2x|scale3x|xbrz2x|xbrz3x|xbrz4x] [--scanlines strength] [--crt-mask none|aperture|slot] [--palette path_to_pal|preset] [--ppu-viewer] [--no-sprite-limit] path_to_rom");
         std::process::exit(1);
     }
 }
 
 pub fn start(
     mut core: Core,
+    save_path: &Path,
     attach_debugger: bool,
     enable_tracing: bool,
     entry_point: Option<u16>,
+    video_options: &VideoOptions,
+    show_ppu_viewer: bool,
 ) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let audio_subsystem = sdl_context.audio().unwrap();
 
     let audio_spec_desired = AudioSpecDesired {
-        freq: Some(44_100),
+        freq: Some(AUDIO_SAMPLE_RATE as i32),
         channels: Some(1),
         samples: Some(1),
     };
@@ -97,20 +217,55 @@ pub fn start(
 
     let mut events = sdl_context.event_pump().unwrap();
 
+    // The cropped picture, stretched to its aspect ratio and letterboxed to fit the window
+    let (display_width, display_height) = video_options
+        .aspect_ratio
+        .display_size(&video_options.overscan);
     let window = video_subsystem
-        .window("IGMNes", 256 * WINDOW_SCALING, 240 * WINDOW_SCALING)
+        .window(
+            "IGMNes",
+            display_width as u32 * WINDOW_SCALING,
+            display_height as u32 * WINDOW_SCALING,
+        )
         .resizable()
         .position_centered()
         .build()
         .unwrap();
 
     let mut renderer = window.into_canvas().build().unwrap();
-    renderer.set_logical_size(256, 232).unwrap();
+    renderer
+        .set_logical_size(display_width as u32, display_height as u32)
+        .unwrap();
+    renderer
+        .set_integer_scale(video_options.is_integer_scaled)
+        .unwrap();
+
+    // The NTSC filter decodes palette indices into a wider frame of its own
+    let mut ntsc_output = video_options.ntsc_preset.map(|preset| {
+        core.set_ppu_output_mode(PpuOutputMode::PaletteIndex);
+        NtscOutput {
+            filter: NtscFilter::new(preset.into()),
+            frame: vec![PpuPaletteColor::default(); NTSC_FRAME_WIDTH * NTSC_FRAME_HEIGHT],
+        }
+    });
+    let frame_width = if ntsc_output.is_some() {
+        NTSC_FRAME_WIDTH
+    } else {
+        PIXELS_PER_SCANLINE
+    };
 
     let texture_creator = renderer.texture_creator();
     let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, 256, 240)
+        .create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            (frame_width * MAX_SCALE_FACTOR) as u32,
+            (SCANLINES * MAX_SCALE_FACTOR) as u32,
+        )
         .unwrap();
+    let mut scaler_output = ScalerOutput {
+        scaler: FrameScaler::new(video_options.scaler_settings),
+        frame: Vec::new(),
+    };
 
     if attach_debugger {
         let debugger = core.attach_debugger();
@@ -125,6 +280,32 @@ pub fn start(
     }
 
     core.hard_reset();
+    load_battery(&mut core, save_path);
+
+    if let Some(patch_path) = core.patch_path() {
+        println!("Applied patch: {}", patch_path.display());
+    }
+    for warning in core.rom_warnings() {
+        println!("Warning: {}", warning);
+    }
+
+    let mut nsf_playback = NsfPlayback::default();
+    if core.nsf().is_some() {
+        show_nsf_track(&mut core, &mut renderer);
+    } else if let Some(title) = core.rom_title() {
+        println!("{}", title);
+        renderer
+            .window_mut()
+            .set_title(&format!("IGMNes - {}", title))
+            .unwrap();
+    }
+
+    let mut ppu_viewer = if show_ppu_viewer {
+        Some(PpuViewer::new(&video_subsystem))
+    } else {
+        None
+    };
+
     let start_time = Instant::now();
 
     'running: loop {
@@ -146,6 +327,131 @@ pub fn start(
                     core.hard_reset();
                     println!("{}", core.cpu_cycles());
                 }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Left | Keycode::Right)),
+                    ..
+                } => {
+                    let song_count = core.nsf().map(|nsf| nsf.song_count());
+                    if let (Some(song), Some(song_count)) = (core.nsf_song(), song_count) {
+                        let song = if keycode == Keycode::Right {
+                            (song + 1) % song_count
+                        } else {
+                            (song + song_count - 1) % song_count
+                        };
+                        select_nsf_track(&mut core, song, &mut renderer, &mut nsf_playback);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    keymod,
+                    ..
+                } => {
+                    let mut settings = scaler_output.scaler.settings();
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let index = CRT_EFFECTS
+                            .iter()
+                            .position(|&(scanlines, mask)| {
+                                scanlines == settings.scanlines && mask == settings.mask
+                            })
+                            .map_or(0, |index| (index + 1) % CRT_EFFECTS.len());
+                        let (scanlines, mask) = CRT_EFFECTS[index];
+                        settings.scanlines = scanlines;
+                        settings.mask = mask;
+                        println!("Scanlines {}, mask {:?}", scanlines, mask);
+                    } else {
+                        let index = SCALERS
+                            .iter()
+                            .position(|&scaler| scaler == settings.scaler)
+                            .map_or(0, |index| (index + 1) % SCALERS.len());
+                        settings.scaler = SCALERS[index];
+                        println!("Scaler {:?}", settings.scaler);
+                    }
+                    scaler_output.scaler.set_settings(settings);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => {
+                    if let Some(side_count) = core.fds_disk_side_count() {
+                        let side = core
+                            .fds_disk_side()
+                            .map_or(0, |side| (side + 1) % side_count);
+                        core.fds_insert_disk_side(side);
+                        println!(
+                            "Disk {} side {}",
+                            side / 2 + 1,
+                            if side % 2 == 0 { "A" } else { "B" }
+                        );
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => {
+                    if core.fds_disk_side_count().is_some() {
+                        core.fds_eject_disk();
+                        println!("Disk ejected");
+                    }
+                }
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if ppu_viewer.as_ref().map(|viewer| viewer.window_id()) == Some(window_id) {
+                        ppu_viewer = None;
+                    } else {
+                        break 'running;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    ppu_viewer = match ppu_viewer {
+                        Some(_) => None,
+                        None => Some(PpuViewer::new(&video_subsystem)),
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    if let Some(ppu_viewer) = &mut ppu_viewer {
+                        ppu_viewer.palette_index = (ppu_viewer.palette_index + 1) % 8;
+                        println!("Pattern tables palette {}", ppu_viewer.palette_index);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    if ppu_viewer.is_some() {
+                        print_oam_sprites(&mut core);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F8 | Keycode::F10 | Keycode::F11)),
+                    ..
+                } => {
+                    let mut display_options = core.ppu_display_options();
+                    let (name, is_enabled) = match keycode {
+                        Keycode::F8 => {
+                            display_options.is_sprite_limit_disabled ^= true;
+                            ("Sprite limit", !display_options.is_sprite_limit_disabled)
+                        }
+                        Keycode::F10 => {
+                            display_options.is_background_hidden ^= true;
+                            ("Background", !display_options.is_background_hidden)
+                        }
+                        _ => {
+                            display_options.are_sprites_hidden ^= true;
+                            ("Sprites", !display_options.are_sprites_hidden)
+                        }
+                    };
+                    core.set_ppu_display_options(display_options);
+                    println!("{} {}", name, if is_enabled { "on" } else { "off" });
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::F9),
                     ..
@@ -184,6 +490,9 @@ pub fn start(
             .collect();
 
         set_controllers_state(&mut core, keys.iter());
+        if core.is_vs_system() {
+            set_vs_system_state(&mut core, &keys);
+        }
 
         // Run emulation until PPU frame ready
         while !core.is_ppu_frame_ready() {
@@ -191,14 +500,27 @@ pub fn start(
         }
 
         // Render frame
-        render_frame(&mut core, &mut renderer, &mut texture);
+        render_frame(
+            &mut core,
+            &mut renderer,
+            &mut texture,
+            &mut ntsc_output,
+            &mut scaler_output,
+            &video_options.overscan,
+        );
+        if let Some(ppu_viewer) = &mut ppu_viewer {
+            ppu_viewer.render(&mut core);
+        }
 
         // Audio
         while !core.is_apu_output_ready() {
             // Keep running (if necessary) until we have audio enough samples for this frame
             core.step(&mut tracer);
         }
-        let samples = core.apu_output_samples();
+        let mut samples = core.apu_output_samples();
+        if core.nsf().is_some() {
+            play_nsf_track(&mut core, &mut samples, &mut renderer, &mut nsf_playback);
+        }
         audio_queue.queue_audio(&samples).unwrap();
 
         // Sleep
@@ -217,6 +539,12 @@ pub fn start(
         }
     }
 
+    save_battery(&mut core, save_path);
+
+    if core.is_vs_system() {
+        println!("Coin counter: {}", core.vs_coin_count());
+    }
+
     if tracer.has_traces() {
         tracer.write_to_file(Path::new("./trace.log"));
     }
@@ -233,6 +561,109 @@ pub fn start(
     }
 }
 
+// Playback position of the current NSF track, used for NSFe track lengths and fades
+#[derive(Default)]
+struct NsfPlayback {
+    elapsed_samples: u64,
+}
+
+fn show_nsf_track(core: &mut Core, renderer: &mut WindowCanvas) {
+    let song = core.nsf_song().unwrap_or(0);
+    let nsf = match core.nsf() {
+        Some(nsf) => nsf,
+        None => return,
+    };
+
+    let mut title = format!("{} - {}", nsf.title, nsf.artist);
+    title.push_str(&format!(" - Track {}/{}", song + 1, nsf.song_count()));
+    if let Some(label) = &nsf.tracks[song].label {
+        title.push_str(&format!(": {}", label));
+    }
+    println!("{}", title);
+
+    let unsupported_chips = nsf.expansion_chips - NsfExpansionChips::supported();
+    if !unsupported_chips.is_empty() {
+        println!(
+            "Unsupported expansion audio: {}",
+            unsupported_chips.names().join(", ")
+        );
+    }
+
+    renderer
+        .window_mut()
+        .set_title(&format!("IGMNes - {}", title))
+        .unwrap();
+}
+
+fn select_nsf_track(
+    core: &mut Core,
+    song: usize,
+    renderer: &mut WindowCanvas,
+    nsf_playback: &mut NsfPlayback,
+) {
+    core.nsf_select_song(song);
+    *nsf_playback = NsfPlayback::default();
+    show_nsf_track(core, renderer);
+}
+
+// Fades out tracks with a known length, and moves on to the next track once the fade is over
+fn play_nsf_track(
+    core: &mut Core,
+    samples: &mut [f32],
+    renderer: &mut WindowCanvas,
+    nsf_playback: &mut NsfPlayback,
+) {
+    let song = core.nsf_song().unwrap_or(0);
+    let (song_count, track) = match core.nsf() {
+        Some(nsf) => (nsf.song_count(), nsf.tracks[song].clone()),
+        None => return,
+    };
+    let length_ms = match track.length_ms {
+        Some(length_ms) => length_ms as u64,
+        None => return,
+    };
+    let fade_ms = track.fade_ms.unwrap_or(0) as u64;
+
+    // Converted as a whole, as the 44.1 samples per millisecond don't divide evenly
+    let ms_to_samples = |ms: u64| ms * AUDIO_SAMPLE_RATE as u64 / 1000;
+    let fade_start = ms_to_samples(length_ms);
+    let fade_end = ms_to_samples(length_ms + fade_ms);
+
+    for sample in samples.iter_mut() {
+        let position = nsf_playback.elapsed_samples;
+        if position >= fade_end {
+            *sample = 0.0;
+        } else if position >= fade_start {
+            *sample *= (fade_end - position) as f32 / (fade_end - fade_start) as f32;
+        }
+        nsf_playback.elapsed_samples += 1;
+    }
+
+    if nsf_playback.elapsed_samples >= fade_end {
+        select_nsf_track(core, (song + 1) % song_count, renderer, nsf_playback);
+    }
+}
+
+fn load_battery(core: &mut Core, save_path: &Path) {
+    if !core.has_battery() {
+        return;
+    }
+
+    match std::fs::read(save_path) {
+        Ok(data) => core.load_battery_data(&data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => println!("Failed to load {}: {}", save_path.display(), e),
+    }
+}
+
+fn save_battery(core: &mut Core, save_path: &Path) {
+    if let Some(data) = core.battery_data() {
+        if let Err(e) = std::fs::write(save_path, data) {
+            println!("Failed to save {}: {}", save_path.display(), e);
+        }
+    }
+}
+
 pub fn set_controllers_state<'a, I>(core: &mut Core, state: I)
 where
     I: Iterator<Item = &'a Keycode>,
@@ -261,36 +692,217 @@ where
     core.set_controller_button_state(ControllerIndex::First, &controller_1_state);
 }
 
-fn render_frame(core: &mut Core, renderer: &mut WindowCanvas, texture: &mut Texture) {
+// Coins are held while the key is down, games count them when released
+fn set_vs_system_state(core: &mut Core, keys: &[Keycode]) {
+    core.vs_set_coin_inserted(0, keys.contains(&Keycode::Num5));
+    core.vs_set_coin_inserted(1, keys.contains(&Keycode::Num6));
+    core.vs_set_service_button(keys.contains(&Keycode::Num9));
+}
+
+#[derive(Default)]
+pub struct VideoOptions {
+    pub ntsc_preset: Option<NtscFilterPreset>,
+    pub overscan: Overscan,
+    pub aspect_ratio: AspectRatio,
+    pub is_integer_scaled: bool,
+    pub scaler_settings: ScalerSettings,
+}
+
+struct NtscOutput {
+    filter: NtscFilter,
+    frame: Vec<PpuPaletteColor>,
+}
+
+struct ScalerOutput {
+    scaler: FrameScaler,
+    frame: Vec<PpuPaletteColor>,
+}
+
+fn render_frame(
+    core: &mut Core,
+    renderer: &mut WindowCanvas,
+    texture: &mut Texture,
+    ntsc_output: &mut Option<NtscOutput>,
+    scaler_output: &mut ScalerOutput,
+    overscan: &Overscan,
+) {
     let background_color = to_sdl_color(core.get_background_color());
     renderer.set_draw_color(background_color);
     renderer.clear();
 
-    let frame = core.ppu_frame();
+    let (frame, frame_width) = match ntsc_output {
+        Some(ntsc_output) => {
+            let burst_phase = core.ppu_frame_burst_phase();
+            let frame = core.ppu_index_frame();
+            ntsc_output
+                .filter
+                .apply(frame, burst_phase, &mut ntsc_output.frame);
+            (ntsc_output.frame.as_slice(), NTSC_FRAME_WIDTH)
+        }
+        None => (core.ppu_frame(), PIXELS_PER_SCANLINE),
+    };
+    let crop_rect = overscan.crop_rect(frame_width);
+
+    // Scalers only see the cropped picture, so its edges are scaled the same as in
+    // screenshots. Unscaled frames are cropped when they're copied instead.
+    let (frame, width, height, src_rect) = if scaler_output.scaler.settings().scale_factor() > 1 {
+        let cropped = crop_frame(frame, frame_width, overscan);
+        let ScalerOutput { scaler, frame } = scaler_output;
+        scaler.apply(&cropped, crop_rect.width, crop_rect.height, frame);
+        let (width, height) = scaler.output_size(crop_rect.width, crop_rect.height);
+        let src_rect = Rect::new(0, 0, width as u32, height as u32);
+        (frame.as_slice(), width, height, src_rect)
+    } else {
+        let src_rect = Rect::new(
+            crop_rect.x as i32,
+            crop_rect.y as i32,
+            crop_rect.width as u32,
+            crop_rect.height as u32,
+        );
+        (frame, frame_width, SCANLINES, src_rect)
+    };
+    let bytes_per_scanline = width * 3;
+
     unsafe {
-        let pointer = ptr::addr_of!(*frame);
-        let pointer_arr = pointer as *mut [u8; BYTES_PER_SCANLINE * SCANLINES];
-        let data = &mut *pointer_arr;
+        let pointer = frame.as_ptr() as *const u8;
+        let data = std::slice::from_raw_parts(pointer, bytes_per_scanline * height);
 
-        let offset = BYTES_PER_SCANLINE * SCANLINES_OFFSET;
-        let data_slice = &mut data[offset..];
         texture
             .update(
-                Some(Rect::new(
-                    0,
-                    SCANLINES_OFFSET as i32,
-                    256,
-                    240 - (SCANLINES_OFFSET as u32 * 2),
-                )),
-                data_slice,
-                BYTES_PER_SCANLINE,
+                Some(Rect::new(0, 0, width as u32, height as u32)),
+                data,
+                bytes_per_scanline,
             )
             .unwrap();
-        renderer.copy(texture, None, None).unwrap();
+        renderer.copy(texture, Some(src_rect), None).unwrap();
         renderer.present();
     }
 }
 
+struct PpuViewer {
+    canvas: WindowCanvas,
+    // Palette the pattern tables are shown with, 0-3 background and 4-7 sprites
+    palette_index: u8,
+    nametables: Vec<PpuPaletteColor>,
+    pattern_tables: Vec<PpuPaletteColor>,
+    oam: Vec<PpuPaletteColor>,
+    frame: Vec<PpuPaletteColor>,
+}
+
+impl PpuViewer {
+    fn new(video_subsystem: &VideoSubsystem) -> PpuViewer {
+        let window = video_subsystem
+            .window(
+                "IGMNes - PPU",
+                PPU_VIEWER_WIDTH as u32,
+                PPU_VIEWER_HEIGHT as u32,
+            )
+            .resizable()
+            .build()
+            .unwrap();
+        let mut canvas = window.into_canvas().build().unwrap();
+        canvas
+            .set_logical_size(PPU_VIEWER_WIDTH as u32, PPU_VIEWER_HEIGHT as u32)
+            .unwrap();
+
+        PpuViewer {
+            canvas,
+            palette_index: 0,
+            nametables: vec![
+                PpuPaletteColor::default();
+                NAMETABLES_VIEW_WIDTH * NAMETABLES_VIEW_HEIGHT
+            ],
+            pattern_tables: vec![
+                PpuPaletteColor::default();
+                PATTERN_TABLES_VIEW_WIDTH * PATTERN_TABLES_VIEW_HEIGHT
+            ],
+            oam: vec![PpuPaletteColor::default(); OAM_VIEW_WIDTH * OAM_VIEW_HEIGHT],
+            frame: vec![PpuPaletteColor::default(); PPU_VIEWER_WIDTH * PPU_VIEWER_HEIGHT],
+        }
+    }
+
+    fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn render(&mut self, core: &mut Core) {
+        core.render_nametables_view(&mut self.nametables);
+        core.render_pattern_tables_view(self.palette_index, &mut self.pattern_tables);
+        core.render_oam_view(&mut self.oam);
+        let palette = core.palette_view();
+
+        self.frame.fill(PpuPaletteColor::default());
+        let right = NAMETABLES_VIEW_WIDTH;
+        let oam_y = PATTERN_TABLES_VIEW_HEIGHT + 8;
+        let palette_y = oam_y + OAM_VIEW_HEIGHT * 2 + 8;
+        let frame = &mut self.frame;
+        blit(frame, &self.nametables, NAMETABLES_VIEW_WIDTH, 0, 0, 1);
+        blit(
+            frame,
+            &self.pattern_tables,
+            PATTERN_TABLES_VIEW_WIDTH,
+            right,
+            0,
+            1,
+        );
+        blit(frame, &self.oam, OAM_VIEW_WIDTH, right, oam_y, 2);
+        // Background palettes on the top row, sprite palettes on the bottom one
+        blit(frame, &palette, 16, right, palette_y, 16);
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                PPU_VIEWER_WIDTH as u32,
+                PPU_VIEWER_HEIGHT as u32,
+            )
+            .unwrap();
+        let data = unsafe {
+            std::slice::from_raw_parts(self.frame.as_ptr() as *const u8, self.frame.len() * 3)
+        };
+        texture.update(None, data, PPU_VIEWER_WIDTH * 3).unwrap();
+        self.canvas.copy(&texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
+
+// Copies a view into the PPU viewer's frame, scaled up
+fn blit(
+    frame: &mut [PpuPaletteColor],
+    source: &[PpuPaletteColor],
+    width: usize,
+    x: usize,
+    y: usize,
+    scale: usize,
+) {
+    for (source_y, row) in source.chunks_exact(width).enumerate() {
+        for (source_x, &color) in row.iter().enumerate() {
+            for offset in 0..scale * scale {
+                let frame_x = x + source_x * scale + offset % scale;
+                let frame_y = y + source_y * scale + offset / scale;
+                frame[frame_y * PPU_VIEWER_WIDTH + frame_x] = color;
+            }
+        }
+    }
+}
+
+fn print_oam_sprites(core: &mut Core) {
+    println!("  #   X   Y Tile Pal Behind FlipH FlipV");
+    for sprite in core.oam_sprites() {
+        println!(
+            "{:3} {:3} {:3}   {:02X}   {} {:6} {:5} {:5}",
+            sprite.index,
+            sprite.x,
+            sprite.y,
+            sprite.tile_index,
+            sprite.palette_index,
+            sprite.is_behind_background,
+            sprite.is_flipped_horizontally,
+            sprite.is_flipped_vertically
+        );
+    }
+}
+
 fn to_sdl_color(ppu_color: PpuPaletteColor) -> sdl2::pixels::Color {
     sdl2::pixels::Color::RGB(ppu_color.red, ppu_color.green, ppu_color.blue)
 }